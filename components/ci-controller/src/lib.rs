@@ -167,13 +167,13 @@ mod component_impl {
     fn make_job_toml(name: &str, platform: &str, repo_full: &str, tag_name: &str, asset_name: &str) -> String {
         format!(
             "name = \"{name}\"\n\n[runtime]\ntype = \"native\"\nbinary = \"/usr/bin/bash\"\nargs = [\"-c\", \"set -e; \
-mkdir -p /tmp/workspace; \
-tar -xzf /tmp/assets/workspace.tar.gz -C /tmp/workspace; \
-cd /tmp/workspace; \
+mkdir -p src; \
+tar -xzf \\\"$REALM_ASSETS/workspace.tar.gz\\\" -C src; \
+cd src; \
 cargo build --release --bin realm; \
-ASSET=/{asset_path}; \
-if [ -f /tmp/assets/gh_token ]; then \
-  TOKEN=$(cat /tmp/assets/gh_token); \
+ASSET={asset_path}; \
+if [ -f \\\"$REALM_ASSETS/gh_token\\\" ]; then \
+  TOKEN=$(cat \\\"$REALM_ASSETS/gh_token\\\"); \
   if [ -n \"$TOKEN\" ]; then \
     echo 'Uploading asset to GitHub release...'; \
     REL=$(curl -s -H \"Authorization: Bearer $TOKEN\" https://api.github.com/repos/{repo}/releases/tags/{tag}); \
@@ -184,13 +184,13 @@ if [ -f /tmp/assets/gh_token ]; then \
       echo 'Release ID not found for tag'; \
     fi; \
   fi; \
-fi\"]\nmemory_mb = 4096\n\n[execution]\nworking_dir = \"/tmp\"\ntimeout_minutes = 45\nartifacts = [ {{ path = \"/tmp/workspace/target/release/realm\", name = \"{asset_name}\" }} ]\n\n[targeting]\nplatform = \"{platform}\"\n",
+fi\"]\nmemory_mb = 4096\n\n[execution]\ntimeout_minutes = 45\nartifacts = [ {{ path = \"src/target/release/realm\", name = \"{asset_name}\" }} ]\n\n[targeting]\nplatform = \"{platform}\"\n",
             name = name,
             platform = platform,
             repo = repo_full,
             tag = tag_name,
             asset_name = asset_name,
-            asset_path = "tmp/workspace/target/release/realm"
+            asset_path = "target/release/realm"
         )
     }
 
@@ -0,0 +1 @@
+// Generated by `cargo component build --features component` from wit/world.wit.
@@ -0,0 +1,160 @@
+// Sample HTTP component calling the agent through the realm:host interfaces.
+// Default build (workspace `cargo build`) does not compile the component bindings.
+// Enable the `component` feature and use `cargo component build` to build as a WASI HTTP component.
+
+#![cfg_attr(feature = "component", no_main)]
+
+#[cfg(feature = "component")]
+mod bindings;
+
+#[cfg(feature = "component")]
+mod component_impl {
+    use crate::bindings;
+
+    use bindings::exports::realm::host::event_handler;
+    use bindings::exports::wasi::http::incoming_handler::Guest;
+    use bindings::realm::host::types::Error;
+    use bindings::realm::host::{cas, events, jobs, kv, log};
+    use bindings::wasi::http::types as http;
+
+    const MAX_BODY_BYTES: usize = 8 * 1024 * 1024;
+    const USAGE: &str = "routes: GET /count, POST /jobs, GET /jobs/<id>, PUT /blobs, GET /blobs/<digest>, POST /events/<topic>, GET /events/<topic>\n";
+
+    struct HostDemo;
+
+    fn read_body(req: &http::IncomingRequest) -> Vec<u8> {
+        let mut out = Vec::new();
+        if let Ok(body) = req.consume() {
+            if let Ok(stream) = body.stream() {
+                while let Ok(chunk) = stream.blocking_read(64 * 1024) {
+                    if chunk.is_empty() || out.len() + chunk.len() > MAX_BODY_BYTES {
+                        break;
+                    }
+                    out.extend_from_slice(&chunk);
+                }
+                drop(stream);
+            }
+            let _ = http::IncomingBody::finish(body);
+        }
+        out
+    }
+
+    fn job_status_name(status: jobs::JobStatus) -> &'static str {
+        match status {
+            jobs::JobStatus::Pending => "pending",
+            jobs::JobStatus::Running => "running",
+            jobs::JobStatus::Completed => "completed",
+            jobs::JobStatus::Failed => "failed",
+            jobs::JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn route(method: &http::Method, path: &str, body: Vec<u8>) -> Result<(u16, Vec<u8>), Error> {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (method, segments.as_slice()) {
+            // Page hit counter kept in the component's KV store
+            (http::Method::Get, ["count"]) => {
+                let hits = kv::increment("hits", 1)?;
+                Ok((200, format!("{hits}\n").into_bytes()))
+            }
+            // Body is a job TOML, as accepted by `realm job submit`
+            (http::Method::Post, ["jobs"]) => {
+                let spec = String::from_utf8(body)
+                    .map_err(|_| Error::Invalid("job TOML must be UTF-8".to_string()))?;
+                let id = jobs::submit_job(&spec, &[])?;
+                log::log(log::Level::Info, &format!("submitted job {id}"));
+                Ok((202, format!("{id}\n").into_bytes()))
+            }
+            (http::Method::Get, ["jobs", id]) => {
+                let job = jobs::get_job_status(id)?;
+                let mut out = format!("{} {} {}\n", job.id, job.name, job_status_name(job.status));
+                for artifact in job.artifacts {
+                    out.push_str(&format!(
+                        "artifact {} {}\n",
+                        artifact.name,
+                        artifact.digest.unwrap_or_default()
+                    ));
+                }
+                Ok((200, out.into_bytes()))
+            }
+            (http::Method::Put, ["blobs"]) => {
+                let digest = cas::put(&body)?;
+                Ok((201, format!("{digest}\n").into_bytes()))
+            }
+            (http::Method::Get, ["blobs", digest]) => Ok((200, cas::get(digest)?)),
+            (http::Method::Post, ["events", topic]) => {
+                events::publish_event(topic, &body)?;
+                Ok((202, Vec::new()))
+            }
+            // Events received by the handler below, per topic
+            (http::Method::Get, ["events", topic]) => {
+                let seen = kv::get(&format!("events/{topic}"))?
+                    .and_then(|v| String::from_utf8(v).ok())
+                    .unwrap_or_else(|| "0".to_string());
+                Ok((200, format!("{seen}\n").into_bytes()))
+            }
+            _ => Ok((404, USAGE.as_bytes().to_vec())),
+        }
+    }
+
+    impl Guest for HostDemo {
+        fn handle(req: http::IncomingRequest, out: http::ResponseOutparam) {
+            let method = req.method();
+            let path = req
+                .path_with_query()
+                .map(|p| p.split('?').next().unwrap_or("/").to_string())
+                .unwrap_or_else(|| "/".to_string());
+            let body = read_body(&req);
+
+            let (status, payload) = match route(&method, &path, body) {
+                Ok(result) => result,
+                Err(Error::Denied(msg)) => (403, msg.into_bytes()),
+                Err(Error::NotFound) => (404, b"not found\n".to_vec()),
+                Err(Error::Invalid(msg)) => (400, msg.into_bytes()),
+                Err(Error::Unavailable(msg)) => (503, msg.into_bytes()),
+            };
+            if status >= 400 {
+                log::log(log::Level::Warn, &format!("{path}: {status}"));
+            }
+
+            let resp = http::OutgoingResponse::new(http::Fields::new());
+            let _ = resp.set_status_code(status);
+            let body = resp.body().expect("body");
+            http::ResponseOutparam::set(out, Ok(resp));
+            {
+                let w = body.write().expect("write");
+                for chunk in payload.chunks(4096) {
+                    if w.blocking_write_and_flush(chunk).is_err() {
+                        break;
+                    }
+                }
+            }
+            let _ = http::OutgoingBody::finish(body, None);
+        }
+    }
+
+    // Runs for events matching the component's `subscriptions`
+    impl event_handler::Guest for HostDemo {
+        fn handle(event: events::Event) -> Result<(), String> {
+            kv::increment(&format!("events/{}", event.topic), 1).map_err(|e| format!("{e:?}"))?;
+            log::log(
+                log::Level::Info,
+                &format!(
+                    "event {} from {}@{} ({} bytes)",
+                    event.topic,
+                    event.source,
+                    event.origin_node,
+                    event.payload.len()
+                ),
+            );
+            Ok(())
+        }
+    }
+
+    bindings::export!(HostDemo with_types_in bindings);
+}
+
+#[cfg(not(feature = "component"))]
+mod non_component_stub {
+    // No-op library to keep workspace `cargo build` happy when not building the component target.
+}
@@ -130,6 +130,18 @@ pub async fn deploy_component(
         mounts: None,
         ports: None,
         visibility: Some(Visibility::Local),
+        request_timeout_ms: None,
+        routes: Vec::new(),
+        http_limits: None,
+        egress: None,
+        args: Vec::new(),
+        env: Default::default(),
+        secret_env: Default::default(),
+        stdin: None,
+        capabilities: Vec::new(),
+        kv: None,
+        subscriptions: Vec::new(),
+        schedule: None,
     };
     let unsigned_bytes = serde_json::to_vec(&unsigned)?;
     let sig = sign_bytes_ed25519(&kp.private_hex, &unsigned_bytes)?;
@@ -37,6 +37,9 @@ pub async fn submit_job(
 
     let text = tokio::fs::read_to_string(&job_toml_path).await?;
     let mut spec: JobSpec = toml::from_str(&text)?;
+    if let Some(expr) = &spec.schedule {
+        crate::schedule::parse_schedule(expr).map_err(anyhow::Error::msg)?;
+    }
 
     // Inline-upload small assets via P2P and inject pre_stage entries
     // Each asset flag format: name=local_path or just local_path
@@ -96,8 +99,8 @@ pub async fn submit_job(
         // Also store locally
         let store = crate::storage::ContentStore::open();
         let _ = store.put_bytes(&bytes);
-        // Inject pre_stage to write to /tmp/assets/<name>
-        let dest = format!("/tmp/assets/{}", name);
+        // Inject pre_stage to write to $REALM_ASSETS/<name>
+        let dest = format!("assets/{}", name);
         spec.execution.pre_stage.push(PreStageSpec {
             source: format!("cas:{}", digest),
             dest,
@@ -115,7 +118,7 @@ pub async fn submit_job(
                     if let Some(d) = &art.sha256_hex {
                         spec.execution.pre_stage.push(PreStageSpec {
                             source: format!("cas:{}", d),
-                            dest: format!("/tmp/assets/{}", name),
+                            dest: format!("assets/{}", name),
                         });
                     } else {
                         // If digest missing, compute and add
@@ -125,7 +128,7 @@ pub async fn submit_job(
                             let _ = store.put_bytes(&bytes);
                             spec.execution.pre_stage.push(PreStageSpec {
                                 source: format!("cas:{}", digest),
-                                dest: format!("/tmp/assets/{}", name),
+                                dest: format!("assets/{}", name),
                             });
                         }
                     }
@@ -197,6 +200,7 @@ pub async fn list_jobs(
         println!("No jobs found");
     } else {
         print_job_table(&jobs);
+        print_job_queue(&jobs);
     }
 
     Ok(())
@@ -677,8 +681,8 @@ pub async fn job_logs(job_id: String, tail: usize, follow: bool) -> anyhow::Resu
     Ok(())
 }
 
-// Helper function for web API
-pub async fn submit_job_from_spec(spec: JobSpec) -> anyhow::Result<()> {
+// Helper function for web API and the realm:host jobs interface; returns the job id
+pub async fn submit_job_from_spec(spec: JobSpec) -> anyhow::Result<String> {
     let (mut swarm, topic_cmd, _topic_status) = new_swarm().await?;
     libp2p::Swarm::listen_on(
         &mut swarm,
@@ -721,7 +725,7 @@ pub async fn submit_job_from_spec(spec: JobSpec) -> anyhow::Result<()> {
     // drive the swarm to flush the message (wait up to 2s for gossip propagation)
     let _ = tokio::time::timeout(Duration::from_secs(2), swarm.select_next_some()).await;
     println!("Job '{}' submitted successfully", job_id);
-    Ok(())
+    Ok(job_id)
 }
 
 // Helper functions for formatting output
@@ -756,6 +760,42 @@ fn print_job_table(jobs: &[JobInstance]) {
     }
 }
 
+/// Jobs waiting for a slot, per node in the order they will start.
+fn print_job_queue(jobs: &[JobInstance]) {
+    let mut queued: Vec<&JobInstance> = jobs
+        .iter()
+        .filter(|j| j.status == common::JobStatus::Queued)
+        .collect();
+    if queued.is_empty() {
+        return;
+    }
+    queued.sort_by_key(|j| {
+        (
+            j.assigned_node.clone(),
+            j.spec.runtime.kind(),
+            std::cmp::Reverse(j.spec.priority),
+            j.queued_at,
+        )
+    });
+    println!();
+    println!("Queued:");
+    for job in queued {
+        println!(
+            "  {:<10} {:<12} {:<20} priority {:<4} since {}",
+            job.assigned_node
+                .as_deref()
+                .map(|n| &n[..n.len().min(10)])
+                .unwrap_or("-"),
+            job.spec.runtime.kind(),
+            job.id,
+            job.spec.priority,
+            job.queued_at
+                .map(format_timestamp)
+                .unwrap_or_else(|| "-".to_string()),
+        );
+    }
+}
+
 fn print_job_details(job: &JobInstance) {
     println!("Job Details:");
     println!("  ID: {}", job.id);
@@ -763,12 +803,45 @@ fn print_job_details(job: &JobInstance) {
     println!("  Status: {:?}", job.status);
     println!("  Type: {:?}", job.spec.job_type);
 
+    if job.spec.priority != 0 {
+        println!("  Priority: {}", job.spec.priority);
+    }
+
+    if job.spec.preemptible {
+        println!("  Preemptible: yes");
+    }
+
     if let Some(schedule) = &job.spec.schedule {
         println!("  Schedule: {}", schedule);
     }
 
+    if let Some(tz) = job
+        .spec
+        .recurring
+        .as_ref()
+        .and_then(|r| r.timezone.as_ref())
+    {
+        println!("  Timezone: {}", tz);
+    }
+
+    if let Some(next) = job.schedule_next_at {
+        println!("  Next Run: {}", format_timestamp(next));
+    }
+
+    if let Some(parent) = &job.parent_id {
+        println!("  Parent: {}", parent);
+    }
+
+    if !job.children.is_empty() {
+        println!("  Runs: {}", job.children.join(", "));
+    }
+
     println!("  Submitted: {}", format_timestamp(job.submitted_at));
 
+    if let (common::JobStatus::Queued, Some(queued)) = (&job.status, job.queued_at) {
+        println!("  Queued: {}", format_timestamp(queued));
+    }
+
     if let Some(started) = job.started_at {
         println!("  Started: {}", format_timestamp(started));
     }
@@ -781,6 +854,28 @@ fn print_job_details(job: &JobInstance) {
         println!("  Node: {}", node);
     }
 
+    if job.restarts > 0 {
+        println!("  Restarts: {}", job.restarts);
+    }
+
+    if !job.attempts.is_empty() {
+        println!("  Attempts:");
+        for (i, attempt) in job.attempts.iter().enumerate() {
+            let started = attempt
+                .started_at
+                .map(format_timestamp)
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "    {}. {} {} - {}: {}",
+                i + 1,
+                attempt.node,
+                started,
+                format_timestamp(attempt.ended_at),
+                attempt.reason
+            );
+        }
+    }
+
     if let Some(exit_code) = job.exit_code {
         println!("  Exit Code: {}", exit_code);
     }
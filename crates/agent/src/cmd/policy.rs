@@ -6,7 +6,13 @@ pub async fn policy_show() -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn policy_set(native: Option<bool>, qemu: Option<bool>) -> anyhow::Result<()> {
+pub async fn policy_set(
+    native: Option<bool>,
+    qemu: Option<bool>,
+    native_sandbox: Option<bool>,
+    containers: Option<bool>,
+    slots: Vec<String>,
+) -> anyhow::Result<()> {
     let mut pol = load_policy();
     if let Some(n) = native {
         pol.allow_native_execution = n;
@@ -14,6 +20,15 @@ pub async fn policy_set(native: Option<bool>, qemu: Option<bool>) -> anyhow::Res
     if let Some(q) = qemu {
         pol.allow_emulation = q;
     }
+    if let Some(s) = native_sandbox {
+        pol.require_native_sandbox = s;
+    }
+    if let Some(c) = containers {
+        pol.allow_containers = c;
+    }
+    for entry in &slots {
+        pol.job_slots.set(entry).map_err(|e| anyhow::anyhow!(e))?;
+    }
     save_policy(&pol).map_err(|e| anyhow::anyhow!(e))?;
     println!("policy saved");
     Ok(())
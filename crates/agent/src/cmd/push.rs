@@ -2,8 +2,9 @@ use anyhow::Context;
 use base64::Engine;
 
 use common::{
-    serialize_message, sha256_hex, sign_bytes_ed25519, Command, MountSpec, OwnerKeypair, Protocol,
-    PushPackage, PushUnsigned, ServicePort, Visibility,
+    parse_capabilities, parse_env_entries, parse_subscriptions, serialize_message, sha256_hex,
+    sign_bytes_ed25519, Command, EgressPolicy, HttpLimits, KvSpec, MountSpec, OwnerKeypair,
+    PushPackage, PushUnsigned, RouteSpec, ServicePort, Visibility,
 };
 
 use super::util::{mdns_warmup, new_swarm, owner_dir};
@@ -15,9 +16,22 @@ pub async fn push(
     memory_max_mb: u64,
     fuel: u64,
     epoch_ms: u64,
+    request_timeout_ms: Option<u64>,
+    http_limits: HttpLimits,
     mounts_cli: Vec<String>,
     ports_cli: Vec<String>,
     _routes_static_cli: Vec<String>,
+    routes_cli: Vec<String>,
+    egress_cli: Vec<String>,
+    args: Vec<String>,
+    env_cli: Vec<String>,
+    secret_env_cli: Vec<String>,
+    stdin: Option<String>,
+    capabilities_cli: Vec<String>,
+    kv_store: Option<String>,
+    kv_replicate: bool,
+    subscriptions_cli: Vec<String>,
+    schedule: Option<String>,
     visibility_cli: Option<String>,
     target_peers: Vec<String>,
     target_tags: Vec<String>,
@@ -74,37 +88,42 @@ pub async fn push(
         }
     }
 
-    // Parse ports 8080/tcp style
+    // Parse ports [HOST:]PORT[/PROTO], e.g. 8080/tcp or 18080:8080/tcp
     let ports: Option<Vec<ServicePort>> = if ports_cli.is_empty() {
         None
     } else {
         let mut out = Vec::new();
         for p in ports_cli.iter() {
-            let mut it = p.split('/');
-            if let (Some(num), Some(proto)) = (it.next(), it.next()) {
-                if let Ok(port) = num.parse::<u16>() {
-                    let protocol = if proto.eq_ignore_ascii_case("udp") {
-                        Protocol::Udp
-                    } else {
-                        Protocol::Tcp
-                    };
-                    out.push(ServicePort {
-                        name: None,
-                        port,
-                        protocol,
-                    });
-                }
-            }
-        }
-        if out.is_empty() {
-            None
-        } else {
-            Some(out)
+            out.push(p.parse::<ServicePort>().map_err(|e| anyhow::anyhow!(e))?);
         }
+        Some(out)
     };
 
     // Static routes removed; WASI HTTP handles requests inside components now.
 
+    // Parse gateway routes host=...,prefix=...[,strip=true][,priority=N]
+    let routes = routes_cli
+        .iter()
+        .map(|r| r.parse::<RouteSpec>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!(e))?;
+
+    // Outbound allowlist [SCHEME://]HOST[:PORT], or `none` to deny all egress
+    let egress = EgressPolicy::from_entries(&egress_cli).map_err(|e| anyhow::anyhow!(e))?;
+
+    // Guest environment KEY=VALUE; secret env values are file:/cas: references
+    let env = parse_env_entries(&env_cli).map_err(|e| anyhow::anyhow!(e))?;
+    let secret_env = parse_env_entries(&secret_env_cli).map_err(|e| anyhow::anyhow!(e))?;
+    if let Some(reference) = &stdin {
+        crate::secrets::stdin_digest(reference).map_err(|e| anyhow::anyhow!(e))?;
+    }
+    let capabilities = parse_capabilities(&capabilities_cli).map_err(|e| anyhow::anyhow!(e))?;
+    let kv =
+        KvSpec::from_options(kv_store.as_deref(), kv_replicate).map_err(|e| anyhow::anyhow!(e))?;
+    let subscriptions = parse_subscriptions(&subscriptions_cli).map_err(|e| anyhow::anyhow!(e))?;
+    let schedule =
+        crate::schedule::normalize_schedule(schedule.as_deref()).map_err(|e| anyhow::anyhow!(e))?;
+
     let visibility = visibility_cli.and_then(|v| match v.as_str() {
         "local" | "Local" => Some(Visibility::Local),
         "public" | "Public" => Some(Visibility::Public),
@@ -126,6 +145,18 @@ pub async fn push(
         mounts,
         ports,
         visibility,
+        request_timeout_ms,
+        routes,
+        http_limits: http_limits.non_empty(),
+        egress,
+        args,
+        env,
+        secret_env,
+        stdin,
+        capabilities,
+        kv,
+        subscriptions,
+        schedule,
     };
     let unsigned_bytes = serde_json::to_vec(&unsigned)?;
     let sig = sign_bytes_ed25519(&kp.private_hex, &unsigned_bytes)?;
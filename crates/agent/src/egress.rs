@@ -0,0 +1,160 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use common::{EgressPolicy, Protocol};
+use tracing::warn;
+
+use crate::p2p::metrics::{push_log, Metrics, SharedLogs};
+
+/// Socket-level view of an allowlist entry. Hostnames are resolved up front since
+/// socket checks only see addresses.
+struct SocketRule {
+    tcp: bool,
+    udp: bool,
+    /// `None` matches any address (`*` rules)
+    ip: Option<IpAddr>,
+    port: Option<u16>,
+}
+
+/// Enforces one component's egress policy and reports denials to its log buffer
+/// and the `egress_denied_total` metric.
+pub struct EgressGuard {
+    component: String,
+    policy: EgressPolicy,
+    sockets: Vec<SocketRule>,
+    logs: SharedLogs,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl EgressGuard {
+    /// Guard for outgoing HTTP and for socket rules naming IP addresses. Call
+    /// [`EgressGuard::resolve_hosts`] to also allow sockets to named hosts.
+    pub fn new(
+        component: &str,
+        policy: EgressPolicy,
+        logs: SharedLogs,
+        metrics: Option<Arc<Metrics>>,
+    ) -> Self {
+        let sockets = policy
+            .allow
+            .iter()
+            .filter_map(|r| {
+                let (tcp, udp) = socket_protocols(r.scheme.as_deref())?;
+                let ip = match r.host.as_str() {
+                    "*" => None,
+                    host => Some(host.parse::<IpAddr>().ok()?),
+                };
+                Some(SocketRule {
+                    tcp,
+                    udp,
+                    ip,
+                    port: r.port,
+                })
+            })
+            .collect();
+        Self {
+            component: component.to_string(),
+            policy,
+            sockets,
+            logs,
+            metrics,
+        }
+    }
+
+    /// Resolve named hosts of socket rules to addresses. Wildcard domains cannot be
+    /// resolved and only apply to outgoing HTTP.
+    pub async fn resolve_hosts(mut self) -> Self {
+        for rule in &self.policy.allow {
+            let Some((tcp, udp)) = socket_protocols(rule.scheme.as_deref()) else {
+                continue;
+            };
+            if rule.host == "*"
+                || rule.host.starts_with("*.")
+                || rule.host.parse::<IpAddr>().is_ok()
+            {
+                continue;
+            }
+            match tokio::net::lookup_host((rule.host.as_str(), rule.port.unwrap_or(0))).await {
+                Ok(addrs) => {
+                    for addr in addrs {
+                        self.sockets.push(SocketRule {
+                            tcp,
+                            udp,
+                            ip: Some(addr.ip()),
+                            port: rule.port,
+                        });
+                    }
+                }
+                Err(e) => {
+                    push_log(
+                        &self.logs,
+                        &self.component,
+                        format!("egress: cannot resolve {}: {}", rule.host, e),
+                    )
+                    .await
+                }
+            }
+        }
+        self
+    }
+
+    /// Whether outbound sockets are possible at all under this policy.
+    pub fn has_socket_rules(&self) -> bool {
+        !self.sockets.is_empty()
+    }
+
+    /// Check an outgoing wasi-http request.
+    pub fn allow_http(&self, use_tls: bool, host: &str, port: u16) -> bool {
+        let scheme = if use_tls { "https" } else { "http" };
+        let allowed = self.policy.allows(scheme, host, port);
+        if !allowed {
+            self.denied(format!("{scheme}://{host}:{port}"));
+        }
+        allowed
+    }
+
+    /// Check an outbound TCP connect or UDP send.
+    pub fn allow_socket(&self, protocol: Protocol, addr: SocketAddr) -> bool {
+        let allowed = self.sockets.iter().any(|r| {
+            let proto_ok = match protocol {
+                Protocol::Tcp => r.tcp,
+                Protocol::Udp => r.udp,
+            };
+            proto_ok
+                && r.ip.map(|ip| ip == addr.ip()).unwrap_or(true)
+                && r.port.map(|p| p == addr.port()).unwrap_or(true)
+        });
+        if !allowed {
+            let scheme = match protocol {
+                Protocol::Tcp => "tcp",
+                Protocol::Udp => "udp",
+            };
+            self.denied(format!("{scheme}://{addr}"));
+        }
+        allowed
+    }
+
+    fn denied(&self, target: String) {
+        warn!(component=%self.component, target=%target, "Egress denied");
+        if let Some(m) = &self.metrics {
+            m.observe_egress_denied(&self.component);
+        }
+        // Checks run synchronously inside wasi hooks; log without blocking them
+        let logs = self.logs.clone();
+        let component = self.component.clone();
+        tokio::spawn(async move {
+            push_log(&logs, &component, format!("egress denied: {target}")).await;
+        });
+    }
+}
+
+/// Protocols a rule opens at the socket level; http/https rules only apply to
+/// outgoing wasi-http requests.
+fn socket_protocols(scheme: Option<&str>) -> Option<(bool, bool)> {
+    match scheme {
+        None => Some((true, true)),
+        Some("tcp") => Some((true, false)),
+        Some("udp") => Some((false, true)),
+        Some(_) => None,
+    }
+}
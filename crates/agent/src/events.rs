@@ -0,0 +1,283 @@
+//! Component event bus behind `realm:host/events`. Events are gossiped on the
+//! realm's events topic and, on every node, handed to running instances that
+//! subscribed and to components whose spec `subscriptions` match. A node acks
+//! an event once it was delivered everywhere locally; until then the
+//! publisher keeps resending it, and redeliveries are dropped by id.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use common::Command;
+use tokio::sync::mpsc;
+
+use crate::p2p::metrics::push_log;
+use crate::supervisor::Supervisor;
+
+/// Events queued for one subscribed instance before delivery counts as failed.
+const SUBSCRIBER_QUEUE: usize = 256;
+/// How long delivered event ids are remembered to drop redeliveries.
+const DELIVERED_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone)]
+pub struct BusEvent {
+    pub id: String,
+    pub topic: String,
+    pub source: String,
+    pub origin_node: String,
+    pub payload: Vec<u8>,
+}
+
+impl BusEvent {
+    pub fn from_command(cmd: &Command) -> Option<Self> {
+        let Command::ComponentEvent {
+            topic,
+            source_component,
+            origin_node_id,
+            payload_b64,
+            message_id,
+        } = cmd
+        else {
+            return None;
+        };
+        Some(Self {
+            id: message_id.clone(),
+            topic: topic.clone(),
+            source: source_component.clone(),
+            origin_node: origin_node_id.clone(),
+            payload: base64::engine::general_purpose::STANDARD
+                .decode(payload_b64.as_bytes())
+                .ok()?,
+        })
+    }
+}
+
+/// `pattern` is a topic, or a topic prefix ending in `*`.
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => topic.starts_with(prefix),
+        None => pattern == topic,
+    }
+}
+
+struct Registration {
+    patterns: Vec<String>,
+    tx: mpsc::Sender<BusEvent>,
+}
+
+fn registry() -> &'static Mutex<HashMap<u64, Registration>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<u64, Registration>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Event queue of one running instance; unregistered when dropped.
+pub struct Subscriber {
+    id: u64,
+    rx: mpsc::Receiver<BusEvent>,
+}
+
+impl Subscriber {
+    pub fn register() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_QUEUE);
+        if let Ok(mut reg) = registry().lock() {
+            reg.insert(
+                id,
+                Registration {
+                    patterns: Vec::new(),
+                    tx,
+                },
+            );
+        }
+        Self { id, rx }
+    }
+
+    pub fn subscribe(&self, pattern: &str) {
+        if let Ok(mut reg) = registry().lock() {
+            if let Some(r) = reg.get_mut(&self.id) {
+                if !r.patterns.iter().any(|p| p == pattern) {
+                    r.patterns.push(pattern.to_string());
+                }
+            }
+        }
+    }
+
+    pub fn unsubscribe(&self, pattern: &str) {
+        if let Ok(mut reg) = registry().lock() {
+            if let Some(r) = reg.get_mut(&self.id) {
+                r.patterns.retain(|p| p != pattern);
+            }
+        }
+    }
+
+    pub async fn next(&mut self, timeout: Duration) -> Option<BusEvent> {
+        tokio::time::timeout(timeout, self.rx.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        if let Ok(mut reg) = registry().lock() {
+            reg.remove(&self.id);
+        }
+    }
+}
+
+enum Delivery {
+    InFlight,
+    Delivered,
+}
+
+fn deliveries() -> &'static Mutex<HashMap<String, (Instant, Delivery)>> {
+    static DELIVERIES: OnceLock<Mutex<HashMap<String, (Instant, Delivery)>>> = OnceLock::new();
+    DELIVERIES.get_or_init(Default::default)
+}
+
+/// Deliver an event on this node once. Returns true when it should be acked:
+/// it was delivered now or earlier. A failed delivery is forgotten so the
+/// publisher's next resend retries it.
+pub async fn receive(supervisor: &Supervisor, event: BusEvent) -> bool {
+    {
+        let Ok(mut seen) = deliveries().lock() else {
+            return false;
+        };
+        let now = Instant::now();
+        seen.retain(|_, (at, _)| now.duration_since(*at) < DELIVERED_TTL);
+        match seen.get(&event.id) {
+            Some((_, Delivery::Delivered)) => return true,
+            Some((_, Delivery::InFlight)) => return false,
+            None => {
+                seen.insert(event.id.clone(), (now, Delivery::InFlight));
+            }
+        }
+    }
+    let result = deliver(supervisor, &event).await;
+    let logs = supervisor.logs();
+    if let Ok(mut seen) = deliveries().lock() {
+        match &result {
+            Ok(_) => {
+                seen.insert(event.id.clone(), (Instant::now(), Delivery::Delivered));
+            }
+            Err(_) => {
+                seen.remove(&event.id);
+            }
+        }
+    }
+    match result {
+        Ok(0) => true,
+        Ok(n) => {
+            push_log(
+                &logs,
+                "events",
+                format!(
+                    "{} from {}@{} delivered to {n} subscriber(s)",
+                    event.topic, event.source, event.origin_node
+                ),
+            )
+            .await;
+            true
+        }
+        Err(e) => {
+            push_log(
+                &logs,
+                "events",
+                format!("{} ({}) not delivered: {e}", event.topic, event.id),
+            )
+            .await;
+            false
+        }
+    }
+}
+
+/// Hand the event to subscribed instances and run matching event handlers.
+/// Returns how many received it.
+async fn deliver(supervisor: &Supervisor, event: &BusEvent) -> Result<usize, String> {
+    let mut delivered = 0;
+    let mut failures = Vec::new();
+    let queues: Vec<mpsc::Sender<BusEvent>> = match registry().lock() {
+        Ok(reg) => reg
+            .values()
+            .filter(|r| r.patterns.iter().any(|p| topic_matches(p, &event.topic)))
+            .map(|r| r.tx.clone())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    for tx in queues {
+        match tx.try_send(event.clone()) {
+            Ok(()) => delivered += 1,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                failures.push("a subscriber's queue is full".to_string())
+            }
+            // The instance exited between lookup and send
+            Err(mpsc::error::TrySendError::Closed(_)) => {}
+        }
+    }
+
+    let handlers: Vec<_> = supervisor
+        .get_desired_snapshot()
+        .await
+        .into_values()
+        .filter(|d| {
+            d.spec.start
+                && d.spec
+                    .subscriptions
+                    .iter()
+                    .any(|p| topic_matches(p, &event.topic))
+        })
+        .collect();
+    if !handlers.is_empty() {
+        let Some(cache) = supervisor.http_cache() else {
+            return Err("component cache unavailable".to_string());
+        };
+        let logs = supervisor.logs();
+        let runs = handlers.iter().map(|desired| {
+            let cache = cache.clone();
+            let logs = logs.clone();
+            async move {
+                let res = crate::runner::invoke_event_handler(&cache, desired, event).await;
+                match &res {
+                    Ok(false) => {
+                        push_log(
+                            &logs,
+                            &desired.name,
+                            format!(
+                                "subscribed to {} but does not export realm:host/event-handler",
+                                event.topic
+                            ),
+                        )
+                        .await
+                    }
+                    Err(e) => {
+                        push_log(
+                            &logs,
+                            &desired.name,
+                            format!("event handler failed for {}: {e}", event.topic),
+                        )
+                        .await
+                    }
+                    Ok(true) => {}
+                }
+                res.map_err(|e| format!("{}: {e}", desired.name))
+            }
+        });
+        for res in futures::future::join_all(runs).await {
+            match res {
+                Ok(true) => delivered += 1,
+                Ok(false) => {}
+                Err(e) => failures.push(e),
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(delivered)
+    } else {
+        Err(failures.join("; "))
+    }
+}
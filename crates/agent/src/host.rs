@@ -0,0 +1,495 @@
+//! Host side of the `realm:host` WIT package (`wit/realm-host`): typed agent
+//! APIs for components, checked against the capabilities in their spec.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use base64::Engine;
+use common::{Capability, Command, JobSpec, KvEntry, KvSpec, PreStageSpec};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::debug;
+use wasmtime::component::Linker;
+use wasmtime::Store;
+
+use crate::events::{BusEvent, Subscriber};
+use crate::kv::KvError;
+use crate::p2p::metrics::{push_log, SharedLogs};
+use crate::p2p::storage::P2PStorage;
+use crate::p2p::{accept_job, JobDispatch};
+use crate::storage::ContentStore;
+
+wasmtime::component::bindgen!({
+    path: "../../wit/realm-host",
+    world: "triggered",
+    async: true,
+});
+
+use realm::host::types::Error;
+use realm::host::{cas, events, jobs, kv, log, types};
+
+const MAX_KV_KEY_BYTES: usize = 512;
+const MAX_KV_VALUE_BYTES: usize = 1024 * 1024;
+/// Replicated writes are gossiped like events.
+const MAX_REPLICATED_KV_VALUE_BYTES: usize = 32 * 1024;
+const MAX_TOPIC_BYTES: usize = 256;
+/// Events are gossiped base64-encoded to every node of the realm and resent
+/// until acked, so they stay small.
+const MAX_EVENT_BYTES: usize = 32 * 1024;
+/// Longest a `next-event` call may wait.
+const MAX_EVENT_WAIT: Duration = Duration::from_secs(60);
+const CAS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The running agent's side of the mesh, available once the p2p loop is up.
+struct MeshLink {
+    node_id: String,
+    commands: UnboundedSender<Command>,
+    storage: P2PStorage,
+    /// The agent's job manager and dispatch, shared with the p2p loop
+    jobs: JobDispatch,
+}
+
+static MESH: OnceLock<MeshLink> = OnceLock::new();
+
+/// Connect host calls to the p2p loop: `commands` are published to peers and
+/// `storage` fetches blobs missing from the local CAS and `jobs` records and
+/// runs jobs like the agent's own submissions. Until this is called, calls
+/// that need the agent fail with `unavailable`.
+pub fn attach_mesh(
+    node_id: String,
+    commands: UnboundedSender<Command>,
+    storage: P2PStorage,
+    jobs: JobDispatch,
+) {
+    let _ = MESH.set(MeshLink {
+        node_id,
+        commands,
+        storage,
+        jobs,
+    });
+}
+
+/// Origin recorded on kv writes and events: this node's peer id once the mesh is up.
+pub fn node_id() -> String {
+    MESH.get()
+        .map(|m| m.node_id.clone())
+        .unwrap_or_else(|| "local".to_string())
+}
+
+/// Register `realm:host` imports; `get` selects the store's [`HostState`].
+pub fn add_to_linker<T: Send + 'static>(
+    linker: &mut Linker<T>,
+    get: fn(&mut T) -> &mut HostState,
+) -> anyhow::Result<()> {
+    Triggered::add_to_linker(linker, get)
+}
+
+/// Instantiate a component from `pre` and pass `event` to its
+/// `realm:host/event-handler` export.
+pub async fn call_event_handler<T: Send>(
+    pre: &TriggeredPre<T>,
+    store: &mut Store<T>,
+    event: &BusEvent,
+) -> anyhow::Result<Result<(), String>> {
+    let instance = pre.instantiate_async(&mut *store).await?;
+    instance
+        .realm_host_event_handler()
+        .call_handle(&mut *store, &events::Event::from(event))
+        .await
+}
+
+impl From<&BusEvent> for events::Event {
+    fn from(e: &BusEvent) -> Self {
+        Self {
+            id: e.id.clone(),
+            topic: e.topic.clone(),
+            source: e.source.clone(),
+            origin_node: e.origin_node.clone(),
+            payload: e.payload.clone(),
+        }
+    }
+}
+
+/// Per-instance state behind the `realm:host` imports.
+pub struct HostState {
+    component: String,
+    capabilities: Vec<Capability>,
+    logs: SharedLogs,
+    kv_store: String,
+    kv_replicate: bool,
+    /// Created on the first `subscribe` call
+    subscriber: Option<Subscriber>,
+}
+
+impl HostState {
+    pub fn new(component: &str, capabilities: &[Capability], logs: SharedLogs) -> Self {
+        Self {
+            component: component.to_string(),
+            capabilities: capabilities.to_vec(),
+            logs,
+            kv_store: component.to_string(),
+            kv_replicate: false,
+            subscriber: None,
+        }
+    }
+
+    /// Use the store named in the component's `kv` settings.
+    pub fn with_kv(mut self, kv: Option<&KvSpec>) -> Self {
+        self.kv_store = crate::kv::store_name(&self.component, kv);
+        self.kv_replicate = kv.is_some_and(|k| k.replicate);
+        self
+    }
+
+    fn require(&self, capability: Capability) -> Result<(), Error> {
+        if self.capabilities.contains(&capability) {
+            Ok(())
+        } else {
+            Err(Error::Denied(format!(
+                "component '{}' does not have the '{}' capability",
+                self.component, capability
+            )))
+        }
+    }
+}
+
+fn not_attached() -> Error {
+    Error::Unavailable("agent is not connected to the mesh".to_string())
+}
+
+fn check_digest(digest: &str) -> Result<(), Error> {
+    if digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(Error::Invalid(format!("invalid sha256 digest '{digest}'")))
+    }
+}
+
+impl types::Host for HostState {}
+
+#[async_trait::async_trait]
+impl jobs::Host for HostState {
+    async fn submit_job(
+        &mut self,
+        spec_toml: String,
+        attachments: Vec<jobs::Attachment>,
+    ) -> Result<String, Error> {
+        self.require(Capability::Jobs)?;
+        let mut spec: JobSpec = toml::from_str(&spec_toml)
+            .map_err(|e| Error::Invalid(format!("invalid job TOML: {e}")))?;
+        if let Some(expr) = &spec.schedule {
+            crate::schedule::parse_schedule(expr).map_err(Error::Invalid)?;
+        }
+        for attachment in attachments {
+            check_digest(&attachment.digest)?;
+            let name = attachment.name.trim();
+            if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+                return Err(Error::Invalid(format!(
+                    "invalid attachment name '{}'",
+                    attachment.name
+                )));
+            }
+            spec.execution.pre_stage.push(PreStageSpec {
+                source: format!("cas:{}", attachment.digest.to_ascii_lowercase()),
+                dest: format!("assets/{name}"),
+            });
+        }
+        let mesh = MESH.get().ok_or_else(not_attached)?;
+        let job_name = spec.name.clone();
+        let job_id = mesh
+            .jobs
+            .job_mgr
+            .submit_job(spec.clone(), Some(mesh.node_id.clone()), None)
+            .await
+            .map_err(|e| Error::Unavailable(format!("submit failed: {e}")))?;
+        // Gossip never loops back, so accept the job here as well
+        let _ = mesh.commands.send(Command::SubmitJob {
+            origin_node_id: mesh.node_id.clone(),
+            job_id: job_id.clone(),
+            spec: spec.clone(),
+        });
+        tokio::spawn(accept_job(
+            mesh.jobs.clone(),
+            mesh.node_id.clone(),
+            job_id.clone(),
+            spec,
+        ));
+        push_log(
+            &self.logs,
+            &self.component,
+            format!("submitted job {job_name} ({job_id})"),
+        )
+        .await;
+        Ok(job_id)
+    }
+
+    async fn get_job_status(&mut self, id: String) -> Result<jobs::JobInfo, Error> {
+        self.require(Capability::Jobs)?;
+        let mesh = MESH.get().ok_or_else(not_attached)?;
+        let job = mesh
+            .jobs
+            .job_mgr
+            .get_job(&id)
+            .await
+            .ok_or(Error::NotFound)?;
+        Ok(jobs::JobInfo {
+            id: job.id,
+            name: job.spec.name,
+            status: match job.status {
+                common::JobStatus::Pending | common::JobStatus::Queued => jobs::JobStatus::Pending,
+                common::JobStatus::Running => jobs::JobStatus::Running,
+                common::JobStatus::Completed => jobs::JobStatus::Completed,
+                common::JobStatus::Failed => jobs::JobStatus::Failed,
+                common::JobStatus::Cancelled => jobs::JobStatus::Cancelled,
+            },
+            assigned_node: job.assigned_node,
+            exit_code: job.exit_code,
+            error: job.error_message,
+            artifacts: job
+                .artifacts
+                .into_iter()
+                .map(|a| jobs::Artifact {
+                    name: a.name,
+                    size_bytes: a.size_bytes,
+                    digest: a.sha256_hex,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl cas::Host for HostState {
+    async fn get(&mut self, digest: String) -> Result<Vec<u8>, Error> {
+        self.require(Capability::Cas)?;
+        check_digest(&digest)?;
+        let digest = digest.to_ascii_lowercase();
+        let store = ContentStore::open();
+        if let Some(path) = store.get_path(&digest) {
+            return tokio::fs::read(path)
+                .await
+                .map_err(|e| Error::Unavailable(format!("cas read failed: {e}")));
+        }
+        let mesh = MESH.get().ok_or(Error::NotFound)?;
+        let bytes = mesh
+            .storage
+            .get(digest.clone(), CAS_FETCH_TIMEOUT)
+            .await
+            .ok_or(Error::NotFound)?;
+        if common::sha256_hex(&bytes) != digest {
+            return Err(Error::Unavailable("digest mismatch from peer".to_string()));
+        }
+        let _ = store.put_bytes(&bytes);
+        Ok(bytes)
+    }
+
+    async fn put(&mut self, data: Vec<u8>) -> Result<String, Error> {
+        self.require(Capability::Cas)?;
+        ContentStore::open()
+            .put_bytes(&data)
+            .map_err(|e| Error::Unavailable(format!("cas put failed: {e}")))
+    }
+}
+
+fn check_key(key: &str) -> Result<(), Error> {
+    if key.is_empty() || key.len() > MAX_KV_KEY_BYTES {
+        return Err(Error::Invalid(format!(
+            "keys must be 1-{MAX_KV_KEY_BYTES} bytes"
+        )));
+    }
+    Ok(())
+}
+
+impl From<KvError> for Error {
+    fn from(e: KvError) -> Self {
+        match e {
+            KvError::Invalid(msg) => Error::Invalid(msg),
+            KvError::Io(msg) => Error::Unavailable(msg),
+        }
+    }
+}
+
+impl HostState {
+    fn check_value(&self, value: &[u8]) -> Result<(), Error> {
+        let limit = if self.kv_replicate {
+            MAX_REPLICATED_KV_VALUE_BYTES
+        } else {
+            MAX_KV_VALUE_BYTES
+        };
+        if value.len() > limit {
+            return Err(Error::Invalid(format!(
+                "values in this store are limited to {limit} bytes"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Send a local write to peers hosting the same replicated store.
+    fn replicate(&self, key: &str, entry: Option<KvEntry>) {
+        let (Some(entry), true, Some(mesh)) = (entry, self.kv_replicate, MESH.get()) else {
+            return;
+        };
+        let _ = mesh.commands.send(Command::KvReplicate {
+            store: self.kv_store.clone(),
+            key: key.to_string(),
+            entry,
+        });
+    }
+}
+
+#[async_trait::async_trait]
+impl kv::Host for HostState {
+    async fn get(&mut self, key: String) -> Result<Option<Vec<u8>>, Error> {
+        self.require(Capability::Kv)?;
+        check_key(&key)?;
+        Ok(crate::kv::open(&self.kv_store).await.get(&key).await)
+    }
+
+    async fn set(&mut self, key: String, value: Vec<u8>, ttl_ms: Option<u64>) -> Result<(), Error> {
+        self.require(Capability::Kv)?;
+        check_key(&key)?;
+        self.check_value(&value)?;
+        let entry = crate::kv::open(&self.kv_store)
+            .await
+            .set(&key, value, ttl_ms, &node_id())
+            .await?;
+        self.replicate(&key, Some(entry));
+        Ok(())
+    }
+
+    async fn delete(&mut self, key: String) -> Result<(), Error> {
+        self.require(Capability::Kv)?;
+        check_key(&key)?;
+        let entry = crate::kv::open(&self.kv_store)
+            .await
+            .delete(&key, &node_id())
+            .await?;
+        self.replicate(&key, entry);
+        Ok(())
+    }
+
+    async fn increment(&mut self, key: String, delta: i64) -> Result<i64, Error> {
+        self.require(Capability::Kv)?;
+        check_key(&key)?;
+        let (value, entry) = crate::kv::open(&self.kv_store)
+            .await
+            .increment(&key, delta, &node_id())
+            .await?;
+        self.replicate(&key, Some(entry));
+        Ok(value)
+    }
+
+    async fn compare_and_swap(
+        &mut self,
+        key: String,
+        expected: Option<Vec<u8>>,
+        value: Option<Vec<u8>>,
+        ttl_ms: Option<u64>,
+    ) -> Result<bool, Error> {
+        self.require(Capability::Kv)?;
+        check_key(&key)?;
+        if let Some(v) = &value {
+            self.check_value(v)?;
+        }
+        let (swapped, entry) = crate::kv::open(&self.kv_store)
+            .await
+            .compare_and_swap(&key, expected.as_deref(), value, ttl_ms, &node_id())
+            .await?;
+        self.replicate(&key, entry);
+        Ok(swapped)
+    }
+
+    async fn list_keys(&mut self, prefix: String) -> Result<Vec<String>, Error> {
+        self.require(Capability::Kv)?;
+        Ok(crate::kv::open(&self.kv_store)
+            .await
+            .list_keys(&prefix)
+            .await)
+    }
+}
+
+fn check_topic(topic: &str) -> Result<(), Error> {
+    if topic.is_empty() || topic.len() > MAX_TOPIC_BYTES {
+        return Err(Error::Invalid(format!(
+            "topics must be 1-{MAX_TOPIC_BYTES} bytes"
+        )));
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl events::Host for HostState {
+    async fn publish_event(&mut self, topic: String, payload: Vec<u8>) -> Result<(), Error> {
+        self.require(Capability::Events)?;
+        check_topic(&topic)?;
+        if topic.contains(['*', ',']) {
+            return Err(Error::Invalid(
+                "topics may not contain '*' or ','".to_string(),
+            ));
+        }
+        if payload.len() > MAX_EVENT_BYTES {
+            return Err(Error::Invalid(format!(
+                "event payloads are limited to {MAX_EVENT_BYTES} bytes"
+            )));
+        }
+        let mesh = MESH.get().ok_or_else(not_attached)?;
+        let size = payload.len();
+        mesh.commands
+            .send(Command::ComponentEvent {
+                topic: topic.clone(),
+                source_component: self.component.clone(),
+                origin_node_id: mesh.node_id.clone(),
+                payload_b64: base64::engine::general_purpose::STANDARD.encode(payload),
+                message_id: uuid::Uuid::new_v4().to_string(),
+            })
+            .map_err(|_| Error::Unavailable("agent is shutting down".to_string()))?;
+        push_log(
+            &self.logs,
+            &self.component,
+            format!("published event {topic} ({size} bytes)"),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn subscribe(&mut self, pattern: String) -> Result<(), Error> {
+        self.require(Capability::Events)?;
+        check_topic(&pattern)?;
+        let patterns = common::parse_subscriptions(&[pattern.as_str()]).map_err(Error::Invalid)?;
+        let sub = self.subscriber.get_or_insert_with(Subscriber::register);
+        for p in &patterns {
+            sub.subscribe(p);
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, pattern: String) -> Result<(), Error> {
+        self.require(Capability::Events)?;
+        if let Some(sub) = &self.subscriber {
+            sub.unsubscribe(&pattern);
+        }
+        Ok(())
+    }
+
+    async fn next_event(&mut self, timeout_ms: u64) -> Result<Option<events::Event>, Error> {
+        self.require(Capability::Events)?;
+        let Some(sub) = self.subscriber.as_mut() else {
+            return Err(Error::Invalid("no subscriptions".to_string()));
+        };
+        let wait = Duration::from_millis(timeout_ms).min(MAX_EVENT_WAIT);
+        Ok(sub.next(wait).await.map(|e| events::Event::from(&e)))
+    }
+}
+
+#[async_trait::async_trait]
+impl log::Host for HostState {
+    async fn log(&mut self, level: log::Level, message: String) {
+        let level = match level {
+            log::Level::Trace => "trace",
+            log::Level::Debug => "debug",
+            log::Level::Info => "info",
+            log::Level::Warn => "warn",
+            log::Level::Error => "error",
+        };
+        debug!(component=%self.component, level = %level, "{}", message);
+        push_log(&self.logs, &self.component, format!("{level}: {message}")).await;
+    }
+}
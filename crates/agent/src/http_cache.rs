@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tracing::{info, warn};
+use wasmtime::{
+    component::{Component, Linker as CLinker},
+    Config, Engine, InstanceAllocationStrategy, PoolingAllocationConfig,
+};
+use wasmtime_wasi_http::bindings::ProxyPre;
+
+use crate::egress::EgressGuard;
+use crate::host::{HostState, TriggeredPre};
+use crate::p2p::metrics::{Metrics, SharedLogs};
+use crate::p2p::state::agent_data_dir;
+use crate::runner::{HttpStore, HTTP_EPOCH_TICK_MS};
+use crate::supervisor::DesiredComponent;
+use common::EgressPolicy;
+
+/// Default number of concurrently live HTTP component instances in the pool.
+const DEFAULT_POOL_INSTANCES: u32 = 256;
+/// Default per-memory reservation for pooled instances, in MiB.
+const DEFAULT_POOL_MAX_MEMORY_MB: usize = 256;
+/// How long the resolved hosts of an egress guard are reused before resolving again.
+const EGRESS_RESOLVE_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Egress guard shared by the requests of one component.
+struct CachedGuard {
+    policy: EgressPolicy,
+    resolved_at: std::time::Instant,
+    guard: Arc<EgressGuard>,
+}
+
+/// A compiled, pre-linked on-demand component ready for instantiation.
+pub struct CachedHttpComponent {
+    pub engine: Engine,
+    /// Set when the component exports `wasi:http/incoming-handler`
+    pub pre: Option<ProxyPre<HttpStore>>,
+    /// Set when the component exports `realm:host/event-handler`
+    pub events_pre: Option<TriggeredPre<HttpStore>>,
+}
+
+/// Cache of compiled on-demand (HTTP and event handler) components keyed by
+/// artifact digest.
+///
+/// All entries share one engine configured with the pooling allocator so that
+/// per-request instantiation is cheap. Compiled artifacts are also serialized
+/// under `agent_data_dir()/cache/compiled` so restarts skip compilation.
+pub struct HttpComponentCache {
+    engine: Engine,
+    compat_tag: String,
+    dir: PathBuf,
+    entries: tokio::sync::Mutex<HashMap<String, Arc<CachedHttpComponent>>>,
+    ticker_started: AtomicBool,
+    /// Where egress denials and `realm:host` log calls from requests are reported
+    logs: SharedLogs,
+    metrics: Arc<Metrics>,
+    /// Egress guards by component name, with socket rule hosts resolved
+    guards: tokio::sync::Mutex<HashMap<String, CachedGuard>>,
+}
+
+impl HttpComponentCache {
+    pub fn new(logs: SharedLogs, metrics: Arc<Metrics>) -> anyhow::Result<Self> {
+        let engine = build_engine()?;
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+        let compat_tag = format!("{:016x}", hasher.finish());
+        Ok(Self {
+            engine,
+            compat_tag,
+            dir: agent_data_dir().join("cache").join("compiled"),
+            entries: tokio::sync::Mutex::new(HashMap::new()),
+            ticker_started: AtomicBool::new(false),
+            logs,
+            metrics,
+            guards: tokio::sync::Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Return the cached component for `digest`, compiling (or loading the serialized
+    /// artifact for) `wasm_path` on a miss.
+    pub async fn get_or_load(
+        &self,
+        digest: &str,
+        wasm_path: &Path,
+    ) -> anyhow::Result<Arc<CachedHttpComponent>> {
+        self.ensure_epoch_ticker();
+        if let Some(hit) = self.entries.lock().await.get(digest) {
+            return Ok(hit.clone());
+        }
+
+        let engine = self.engine.clone();
+        let serialized = self.serialized_path(digest);
+        let wasm_path = wasm_path.to_path_buf();
+        let component =
+            tokio::task::spawn_blocking(move || load_or_compile(&engine, &wasm_path, &serialized))
+                .await??;
+
+        let mut linker = CLinker::<HttpStore>::new(&self.engine);
+        linker.allow_shadowing(true);
+        wasmtime_wasi::add_to_linker_async(&mut linker)?;
+        wasmtime_wasi_http::add_to_linker_async(&mut linker)?;
+        crate::host::add_to_linker(&mut linker, HttpStore::host)?;
+        let instance_pre = linker.instantiate_pre(&component)?;
+        let pre = ProxyPre::new(instance_pre.clone()).ok();
+        let events_pre = TriggeredPre::new(instance_pre).ok();
+        if pre.is_none() && events_pre.is_none() {
+            anyhow::bail!(
+                "component exports neither wasi:http/incoming-handler nor realm:host/event-handler"
+            );
+        }
+
+        let entry = Arc::new(CachedHttpComponent {
+            engine: self.engine.clone(),
+            pre,
+            events_pre,
+        });
+        // Another request may have raced us; keep whichever landed first.
+        let mut entries = self.entries.lock().await;
+        Ok(entries.entry(digest.to_string()).or_insert(entry).clone())
+    }
+
+    /// Drop the entry and serialized artifact for a digest (e.g. after a component
+    /// update).
+    pub async fn invalidate(&self, digest: &str) {
+        if self.entries.lock().await.remove(digest).is_some() {
+            info!(digest=%digest, "Evicted HTTP component from cache");
+        }
+        self.remove_serialized(|d| d == digest).await;
+    }
+
+    /// Drop entries and serialized artifacts whose digest is not in `keep`.
+    pub async fn retain_digests(&self, keep: &[String]) {
+        self.entries
+            .lock()
+            .await
+            .retain(|digest, _| keep.iter().any(|k| k == digest));
+        self.remove_serialized(|d| !keep.iter().any(|k| k == d))
+            .await;
+    }
+
+    /// Delete serialized artifacts whose digest matches `remove`, for any engine
+    /// compatibility tag.
+    async fn remove_serialized(&self, remove: impl Fn(&str) -> bool) {
+        let Ok(mut dir) = tokio::fs::read_dir(&self.dir).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = dir.next_entry().await {
+            let name = entry.file_name();
+            let Some(digest) = name
+                .to_str()
+                .and_then(|n| n.strip_suffix(".cwasm"))
+                .and_then(|n| n.split_once('-'))
+                .map(|(digest, _)| digest)
+            else {
+                continue;
+            };
+            if remove(digest) {
+                if let Err(e) = tokio::fs::remove_file(entry.path()).await {
+                    warn!(path=%entry.path().display(), error=%e, "Failed to remove compiled artifact");
+                }
+            }
+        }
+    }
+
+    /// `realm:host` state for one request to `desired`.
+    pub fn host_state(&self, desired: &DesiredComponent) -> HostState {
+        HostState::new(&desired.name, &desired.spec.capabilities, self.logs.clone())
+            .with_kv(desired.spec.kv.as_ref())
+    }
+
+    /// Egress guard for one request to `desired`, when its spec sets a policy.
+    /// Hosts named by socket rules are resolved once and reused across requests
+    /// until the policy changes or `EGRESS_RESOLVE_TTL` passes.
+    pub async fn egress_guard(&self, desired: &DesiredComponent) -> Option<Arc<EgressGuard>> {
+        let Some(policy) = desired.spec.egress.clone() else {
+            self.guards.lock().await.remove(&desired.name);
+            return None;
+        };
+        if let Some(cached) = self.guards.lock().await.get(&desired.name) {
+            if cached.policy == policy && cached.resolved_at.elapsed() < EGRESS_RESOLVE_TTL {
+                return Some(cached.guard.clone());
+            }
+        }
+        let guard = Arc::new(
+            EgressGuard::new(
+                &desired.name,
+                policy.clone(),
+                self.logs.clone(),
+                Some(self.metrics.clone()),
+            )
+            .resolve_hosts()
+            .await,
+        );
+        self.guards.lock().await.insert(
+            desired.name.clone(),
+            CachedGuard {
+                policy,
+                resolved_at: std::time::Instant::now(),
+                guard: guard.clone(),
+            },
+        );
+        Some(guard)
+    }
+
+    /// Start the single epoch ticker shared by every store on this engine.
+    fn ensure_epoch_ticker(&self) {
+        if self.ticker_started.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let engine = self.engine.clone();
+        tokio::spawn(async move {
+            let mut ticker =
+                tokio::time::interval(std::time::Duration::from_millis(HTTP_EPOCH_TICK_MS));
+            loop {
+                ticker.tick().await;
+                engine.increment_epoch();
+            }
+        });
+    }
+
+    fn serialized_path(&self, digest: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}-{}.cwasm", digest, self.compat_tag))
+    }
+}
+
+fn load_or_compile(
+    engine: &Engine,
+    wasm_path: &Path,
+    serialized: &Path,
+) -> anyhow::Result<Component> {
+    if serialized.exists() {
+        // SAFETY: the file is only ever written by `Component::serialize` below for an
+        // engine with the same compatibility hash (encoded in the file name), and lives
+        // in the agent's private data directory.
+        match unsafe { Component::deserialize_file(engine, serialized) } {
+            Ok(component) => return Ok(component),
+            Err(e) => {
+                warn!(path=%serialized.display(), error=%e, "Discarding unusable compiled artifact");
+                let _ = std::fs::remove_file(serialized);
+            }
+        }
+    }
+
+    let component = Component::from_file(engine, wasm_path)?;
+    match component.serialize() {
+        Ok(bytes) => {
+            if let Some(parent) = serialized.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            // Unique per writer so concurrent compiles of one digest do not interleave
+            let tmp =
+                serialized.with_extension(format!("cwasm.{}.tmp", uuid::Uuid::new_v4().simple()));
+            if std::fs::write(&tmp, &bytes).is_err() || std::fs::rename(&tmp, serialized).is_err() {
+                let _ = std::fs::remove_file(&tmp);
+            }
+        }
+        Err(e) => warn!(error=%e, "Failed to serialize compiled component"),
+    }
+    info!(path=%wasm_path.display(), "Compiled HTTP component");
+    Ok(component)
+}
+
+fn base_config() -> Config {
+    let mut cfg = Config::new();
+    cfg.wasm_component_model(true)
+        .async_support(true)
+        .epoch_interruption(true)
+        .consume_fuel(true);
+    cfg
+}
+
+fn build_engine() -> anyhow::Result<Engine> {
+    let instances = std::env::var("REALM_HTTP_POOL_INSTANCES")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_POOL_INSTANCES);
+    let max_memory_mb = std::env::var("REALM_HTTP_POOL_MAX_MEMORY_MB")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_POOL_MAX_MEMORY_MB);
+
+    let mut pool = PoolingAllocationConfig::default();
+    pool.total_component_instances(instances)
+        .total_core_instances(instances * 4)
+        .total_memories(instances * 2)
+        .total_tables(instances * 2)
+        .max_memory_size(max_memory_mb * 1024 * 1024);
+
+    let mut cfg = base_config();
+    cfg.allocation_strategy(InstanceAllocationStrategy::Pooling(pool));
+    match Engine::new(&cfg) {
+        Ok(engine) => Ok(engine),
+        Err(e) => {
+            // Pooling reserves virtual memory up front; fall back when the host refuses.
+            warn!(error=%e, "Pooling allocator unavailable; using on-demand allocation");
+            Engine::new(&base_config())
+        }
+    }
+}
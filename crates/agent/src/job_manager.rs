@@ -1,9 +1,9 @@
 use anyhow::Result;
-use common::{JobArtifact, JobInstance, JobSpec, JobStatus, JobType};
-use cron::Schedule;
+use common::{
+    CatchUp, Concurrency, JobArtifact, JobAttempt, JobInstance, JobSpec, JobStatus, JobType,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::str::FromStr;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{
     atomic::{AtomicU64, Ordering},
     Arc,
@@ -14,6 +14,23 @@ use tracing::{info, warn};
 
 type JobId = String;
 
+/// Time a cancelled job has to stop its processes before its task is aborted.
+const STOP_GRACE: std::time::Duration = std::time::Duration::from_secs(45);
+/// Ticks older than this when evaluated count as missed (schedules are
+/// evaluated once a minute).
+const MISSED_AFTER: chrono::Duration = chrono::Duration::seconds(120);
+/// Upper bound on missed runs created for one recurring job in one
+/// evaluation; the latest ones are kept.
+const MAX_CATCH_UP_RUNS: usize = 100;
+
+/// A run of a recurring job that came due, already recorded as a child job.
+pub struct ScheduledRun {
+    pub child_id: String,
+    pub spec: JobSpec,
+    /// Active runs to cancel first (`concurrency = "replace"`)
+    pub replaces: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JobManagerState {
     pub jobs: HashMap<JobId, JobInstance>,
@@ -71,6 +88,10 @@ impl JobManager {
         self.last_update.load(Ordering::Relaxed)
     }
 
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
     pub async fn load_from_disk(&self) -> Result<()> {
         let state_file = self.data_dir.join("jobs.json");
         if state_file.exists() {
@@ -120,6 +141,17 @@ impl JobManager {
         };
 
         let mut job = JobInstance::new(id.clone(), origin.clone(), spec);
+        // A resubmitted job (relocation) is claimed afresh but keeps its history
+        if let Some(previous) = state.jobs.remove(&id) {
+            job.submitted_at = previous.submitted_at;
+            job.logs = previous.logs;
+            job.restarts = previous.restarts;
+            job.attempts = previous.attempts;
+            job.last_scheduled_at = previous.last_scheduled_at;
+            job.schedule_next_at = previous.schedule_next_at;
+            job.parent_id = previous.parent_id;
+            job.children = previous.children;
+        }
         job.add_log("info".to_string(), "Job submitted".to_string());
 
         state.jobs.insert(id.clone(), job);
@@ -162,6 +194,23 @@ impl JobManager {
         self.assign_job(job_id, node_id).await
     }
 
+    /// Mark a job claimed by this node as waiting for a job slot.
+    pub async fn queue_job(&self, job_id: &str, reason: String) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(job) = state.jobs.get_mut(job_id) {
+            job.queue(self.node_id.clone());
+            job.add_log("info".to_string(), reason);
+        }
+        drop(state);
+
+        self.mark_update();
+
+        if let Err(e) = self.save_to_disk().await {
+            warn!("Failed to save job state: {}", e);
+        }
+        Ok(())
+    }
+
     pub async fn start_job(&self, job_id: &str) -> Result<()> {
         let mut state = self.state.lock().await;
         if let Some(job) = state.jobs.get_mut(job_id) {
@@ -220,16 +269,21 @@ impl JobManager {
     pub async fn cancel_job(&self, job_id: &str) -> Result<bool> {
         let mut state = self.state.lock().await;
         if let Some(job) = state.jobs.get_mut(job_id) {
-            if matches!(job.status, JobStatus::Pending | JobStatus::Running) {
+            if job.is_active() {
                 job.cancel();
                 job.add_log("warn".to_string(), "Job cancelled".to_string());
                 drop(state);
 
                 // Send cancellation signal to running job if it exists
+                // Let the job stop its processes; abort the task only if it hangs
                 let mut running_jobs = self.running_jobs.lock().await;
                 if let Some(running_job) = running_jobs.remove(job_id) {
                     let _ = running_job.cancel_tx.send(());
-                    running_job.handle.abort();
+                    let handle = running_job.handle;
+                    tokio::spawn(async move {
+                        tokio::time::sleep(STOP_GRACE).await;
+                        handle.abort();
+                    });
                     info!("Sent cancellation signal to running job: {}", job_id);
                 }
 
@@ -256,6 +310,7 @@ impl JobManager {
         if let Some(status_str) = status_filter {
             let filter_status = match status_str.to_lowercase().as_str() {
                 "pending" => Some(JobStatus::Pending),
+                "queued" => Some(JobStatus::Queued),
                 "running" => Some(JobStatus::Running),
                 "completed" => Some(JobStatus::Completed),
                 "failed" => Some(JobStatus::Failed),
@@ -307,56 +362,308 @@ impl JobManager {
         Ok(())
     }
 
-    /// For recurring jobs, decide if they are due and create a new pending instance
-    pub async fn evaluate_schedules(&self) -> Result<Vec<JobSpec>> {
+    /// Count a service restart and log why it happened.
+    pub async fn record_restart(&self, job_id: &str, reason: String) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(job) = state.jobs.get_mut(job_id) {
+            job.restarts = job.restarts.saturating_add(1);
+            job.add_log(
+                "warn".to_string(),
+                format!("Service restart #{}: {}", job.restarts, reason),
+            );
+        }
+        drop(state);
+
+        self.mark_update();
+
+        if let Err(e) = self.save_to_disk().await {
+            warn!("Failed to save job state: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Record that the current run was stopped before finishing and the job
+    /// goes back into this node's queue.
+    pub async fn record_attempt(&self, job_id: &str, reason: String) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(job) = state.jobs.get_mut(job_id) {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            job.attempts.push(JobAttempt {
+                node: self.node_id.clone(),
+                started_at: job.started_at,
+                ended_at: now,
+                reason: reason.clone(),
+            });
+            job.add_log(
+                "warn".to_string(),
+                format!(
+                    "Attempt {} stopped: {}; requeued",
+                    job.attempts.len(),
+                    reason
+                ),
+            );
+        }
+        drop(state);
+
+        self.mark_update();
+
+        if let Err(e) = self.save_to_disk().await {
+            warn!("Failed to save job state: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Service jobs running under supervision on this node.
+    pub async fn local_services(&self) -> Vec<JobInstance> {
+        let running = self.running_jobs.lock().await;
+        let state = self.state.lock().await;
+        state
+            .jobs
+            .values()
+            .filter(|j| matches!(j.spec.job_type, JobType::Service) && running.contains_key(&j.id))
+            .cloned()
+            .collect()
+    }
+
+    /// Jobs marked running or queued that need a new home: service jobs
+    /// assigned here with no supervisor (the agent restarted), and relocatable service
+    /// jobs and recurring schedules whose node `is_live` no longer reports.
+    pub async fn stranded_jobs(&self, is_live: impl Fn(&str) -> bool) -> Vec<JobInstance> {
+        let running = self.running_jobs.lock().await;
+        let state = self.state.lock().await;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        state
+            .jobs
+            .values()
+            // Leave jobs that are still being started alone
+            .filter(|j| {
+                let since = match j.status {
+                    JobStatus::Running => j.started_at,
+                    JobStatus::Queued => j.queued_at,
+                    _ => None,
+                };
+                since.is_some_and(|t| now.saturating_sub(t) >= 10)
+            })
+            .filter(|j| match (&j.spec.job_type, j.assigned_node.as_deref()) {
+                (JobType::Service, Some(node)) if node == self.node_id => {
+                    !running.contains_key(&j.id)
+                }
+                (JobType::Service, Some(node)) => {
+                    j.spec.service.as_ref().map(|s| s.relocate).unwrap_or(true) && !is_live(node)
+                }
+                (JobType::Recurring, Some(node)) => node != self.node_id && !is_live(node),
+                _ => false,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Create the runs that came due for the recurring jobs this node
+    /// schedules. Only the node that claimed a recurring job evaluates it, so
+    /// each run is submitted once for the whole mesh.
+    pub async fn evaluate_schedules(&self) -> Result<Vec<ScheduledRun>> {
         use chrono::{DateTime, Utc};
         let now = Utc::now();
-        let mut due: Vec<JobSpec> = Vec::new();
+        let mut runs: Vec<ScheduledRun> = Vec::new();
+        let mut changed = false;
 
         let mut state = self.state.lock().await;
-        for (_id, job) in state.jobs.iter_mut() {
-            if matches!(&job.spec.job_type, JobType::Recurring) {
-                if let Some(expr) = &job.spec.schedule {
-                    match Schedule::from_str(expr) {
-                        Ok(schedule) => {
-                            let last_run = job
-                                .last_scheduled_at
-                                .map(|ts| DateTime::from_timestamp(ts as i64, 0).unwrap_or(now));
-
-                            // Check if the job is due to run based on the cron schedule
-                            if let Some(next_run) = schedule.upcoming(Utc).next() {
-                                let should_run = if let Some(last) = last_run {
-                                    next_run > last && next_run <= now
-                                } else {
-                                    next_run <= now
-                                };
-
-                                if should_run {
-                                    job.last_scheduled_at = Some(now.timestamp() as u64);
-                                    job.updated_at = now.timestamp() as u64;
-                                    due.push(job.spec.clone());
-                                    info!(
-                                        "Job '{}' is due for execution based on schedule '{}'",
-                                        job.spec.name, expr
-                                    );
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            warn!(
-                                "Invalid cron expression '{}' for job '{}': {}",
-                                expr, job.spec.name, e
-                            );
+        let parents: Vec<JobInstance> = state
+            .jobs
+            .values()
+            .filter(|j| {
+                matches!(j.spec.job_type, JobType::Recurring)
+                    && j.status == JobStatus::Running
+                    && j.assigned_node.as_deref() == Some(self.node_id.as_str())
+            })
+            .cloned()
+            .collect();
+        for parent in parents {
+            let Some(expr) = &parent.spec.schedule else {
+                continue;
+            };
+            let policy = parent.spec.recurring.clone().unwrap_or_default();
+            let schedule = match crate::schedule::parse_schedule(expr) {
+                Ok(schedule) => schedule,
+                Err(e) => {
+                    warn!("Job '{}': {}", parent.spec.name, e);
+                    continue;
+                }
+            };
+            let tz = match policy.timezone.as_deref() {
+                Some(name) => match name.parse::<chrono_tz::Tz>() {
+                    Ok(tz) => tz,
+                    Err(e) => {
+                        warn!(
+                            "Invalid timezone '{}' for job '{}': {}",
+                            name, parent.spec.name, e
+                        );
+                        continue;
+                    }
+                },
+                None => chrono_tz::UTC,
+            };
+
+            // Ticks since the last one handled, or since the job was claimed
+            let since = parent
+                .last_scheduled_at
+                .or(parent.started_at)
+                .and_then(|ts| DateTime::from_timestamp(ts as i64, 0))
+                .unwrap_or(now)
+                .with_timezone(&tz);
+            // Walk every tick up to now, so the schedule always moves past them,
+            // but only keep the latest missed ones
+            let mut last_due: Option<DateTime<Utc>> = None;
+            let mut missed: VecDeque<DateTime<Utc>> = VecDeque::new();
+            let mut missed_total = 0usize;
+            let mut on_time: Vec<DateTime<Utc>> = Vec::new();
+            for tick in schedule
+                .after(&since)
+                .map(|t| t.with_timezone(&Utc))
+                .take_while(|t| *t <= now)
+            {
+                last_due = Some(tick);
+                // Ticks that already have a run (submitted by a previous scheduler) are done
+                if state
+                    .jobs
+                    .contains_key(&format!("{}-{}", parent.id, tick.timestamp()))
+                {
+                    continue;
+                }
+                if now - tick > MISSED_AFTER {
+                    missed_total += 1;
+                    if missed.len() == MAX_CATCH_UP_RUNS {
+                        missed.pop_front();
+                    }
+                    missed.push_back(tick);
+                } else {
+                    on_time.push(tick);
+                }
+            }
+            let next_at = schedule
+                .after(&now.with_timezone(&tz))
+                .next()
+                .map(|t| t.timestamp() as u64);
+
+            let mut ticks: Vec<DateTime<Utc>> = match policy.catch_up {
+                CatchUp::Skip => Vec::new(),
+                CatchUp::RunOnce => missed.back().copied().into_iter().collect(),
+                CatchUp::RunAll => missed.iter().copied().collect(),
+            };
+            ticks.extend(on_time);
+            // Runs that may not overlap collapse to the latest one
+            if policy.concurrency != Concurrency::Allow && ticks.len() > 1 {
+                ticks.drain(..ticks.len() - 1);
+            }
+
+            let mut notes: Vec<String> = Vec::new();
+            match policy.catch_up {
+                CatchUp::Skip if missed_total > 0 => {
+                    notes.push(format!("Skipped {missed_total} missed run(s)"));
+                }
+                CatchUp::RunAll if missed_total > missed.len() => {
+                    notes.push(format!(
+                        "Skipped {} missed run(s) beyond the catch-up limit of {}",
+                        missed_total - missed.len(),
+                        MAX_CATCH_UP_RUNS
+                    ));
+                }
+                _ => {}
+            }
+            let active: Vec<String> = parent
+                .children
+                .iter()
+                .filter(|id| state.jobs.get(*id).is_some_and(|c| c.is_active()))
+                .cloned()
+                .collect();
+            let mut spawned: Vec<String> = Vec::new();
+            for tick in ticks {
+                let child_id = format!("{}-{}", parent.id, tick.timestamp());
+                if state.jobs.contains_key(&child_id) {
+                    continue;
+                }
+                let mut replaces = Vec::new();
+                if !active.is_empty() {
+                    match policy.concurrency {
+                        Concurrency::Allow => {}
+                        Concurrency::Forbid => {
+                            notes.push(format!(
+                                "Run due {} skipped: {} still active",
+                                tick.with_timezone(&tz),
+                                active.join(", ")
+                            ));
                             continue;
                         }
+                        Concurrency::Replace => replaces = active.clone(),
                     }
                 }
+                let mut spec = parent.spec.clone();
+                spec.job_type = JobType::OneShot;
+                spec.schedule = None;
+                spec.recurring = None;
+                let mut child =
+                    JobInstance::new(child_id.clone(), self.node_id.clone(), spec.clone());
+                child.parent_id = Some(parent.id.clone());
+                child.add_log(
+                    "info".to_string(),
+                    format!("Run of {} due {}", parent.id, tick.with_timezone(&tz)),
+                );
+                state.jobs.insert(child_id.clone(), child);
+                info!(
+                    "Job '{}' is due for execution based on schedule '{}'",
+                    parent.spec.name, expr
+                );
+                spawned.push(child_id.clone());
+                runs.push(ScheduledRun {
+                    child_id,
+                    spec,
+                    replaces,
+                });
+            }
+
+            let last_due = last_due.map(|t| t.timestamp() as u64);
+            if last_due.is_none() && next_at == parent.schedule_next_at && notes.is_empty() {
+                continue;
+            }
+            changed = true;
+            let mut dropped: Vec<String> = Vec::new();
+            if let Some(job) = state.jobs.get_mut(&parent.id) {
+                if last_due.is_some() {
+                    job.last_scheduled_at = last_due;
+                }
+                job.schedule_next_at = next_at;
+                job.children.extend(spawned);
+                if job.children.len() > policy.history {
+                    let excess = job.children.len() - policy.history;
+                    dropped = job.children.drain(..excess).collect();
+                }
+                for note in notes {
+                    job.add_log("warn".to_string(), note);
+                }
+                job.updated_at = now.timestamp() as u64;
+            }
+            // Finished runs that fell out of the history are forgotten
+            for id in dropped {
+                let finished = state.jobs.get(&id).is_some_and(|c| !c.is_active());
+                if finished {
+                    state.jobs.remove(&id);
+                }
             }
         }
-        if !due.is_empty() {
+        drop(state);
+        if changed {
             self.mark_update();
+            if let Err(e) = self.save_to_disk().await {
+                warn!("Failed to save job state: {}", e);
+            }
         }
-        Ok(due)
+        Ok(runs)
     }
 
     /// Add methods for tracking running jobs and artifact management
@@ -375,6 +682,10 @@ impl JobManager {
         running_jobs.remove(job_id)
     }
 
+    pub async fn is_running_here(&self, job_id: &str) -> bool {
+        self.running_jobs.lock().await.contains_key(job_id)
+    }
+
     /// Copy artifacts to job-specific directory
     pub async fn stage_artifacts(
         &self,
@@ -392,6 +703,10 @@ impl JobManager {
 
         for artifact in artifacts {
             let dest_path = artifacts_dir.join(&artifact.name);
+            if std::path::Path::new(&artifact.stored_path) == dest_path {
+                // Staged by an earlier run
+                continue;
+            }
             if let Err(e) = tokio::fs::copy(&artifact.stored_path, &dest_path).await {
                 warn!(
                     "Failed to copy artifact '{}' to {}: {}",
@@ -405,14 +720,16 @@ impl JobManager {
                     artifact.name,
                     dest_path.display()
                 );
-                // Compute digest for downstream reuse (best-effort)
-                if let Ok(bytes) = tokio::fs::read(&artifact.stored_path).await {
+                // Compute digest for downstream reuse (best-effort); the
+                // staged copy outlives the job workspace it came from
+                if let Ok(bytes) = tokio::fs::read(&dest_path).await {
                     let digest = common::sha256_hex(&bytes);
                     let mut state = self.state.lock().await;
                     if let Some(job) = state.jobs.get_mut(job_id) {
                         if let Some(a) = job.artifacts.iter_mut().find(|a| a.name == artifact.name)
                         {
                             a.sha256_hex = Some(digest);
+                            a.stored_path = dest_path.display().to_string();
                             job.updated_at = std::time::SystemTime::now()
                                 .duration_since(std::time::UNIX_EPOCH)
                                 .unwrap()
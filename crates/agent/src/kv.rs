@@ -0,0 +1,345 @@
+//! Embedded key-value stores behind `realm:host/kv`. Each named store is held
+//! in memory, with every write appended to `<agent data>/kv/<store>.log`
+//! before it is applied and the log folded into the `<store>.json` snapshot
+//! once it outgrows the store. Operations on one store are serialized, so
+//! increments and compare-and-swap are atomic on the node that runs them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use base64::Engine;
+use common::{KvEntry, KvSpec};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::p2p::state::agent_data_dir;
+
+/// Deleted keys are remembered this long so a late replicated write cannot
+/// bring them back.
+const TOMBSTONE_RETENTION_MS: u64 = 24 * 60 * 60 * 1000;
+/// The log is folded into the snapshot once it holds more records than the
+/// store has keys, and never below this many.
+const MIN_COMPACT_RECORDS: usize = 1024;
+
+#[derive(Debug)]
+pub enum KvError {
+    /// The stored value does not suit the operation
+    Invalid(String),
+    /// The write could not be saved
+    Io(String),
+}
+
+/// Store used by a component: the configured name, else the component name.
+pub fn store_name(component: &str, kv: Option<&KvSpec>) -> String {
+    kv.and_then(|k| k.store.clone())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| component.to_string())
+}
+
+fn store_path(store: &str) -> PathBuf {
+    let file: String = store
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    agent_data_dir().join("kv").join(format!("{file}.json"))
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn live_value(entry: Option<&KvEntry>, now: u64) -> Option<Vec<u8>> {
+    let entry = entry?;
+    if entry.expires_at_ms.is_some_and(|t| t <= now) {
+        return None;
+    }
+    entry
+        .value_b64
+        .as_ref()
+        .and_then(|v| base64::engine::general_purpose::STANDARD.decode(v).ok())
+}
+
+/// One line of a store's write log.
+#[derive(Serialize, Deserialize)]
+struct LogRecord {
+    key: String,
+    entry: KvEntry,
+}
+
+/// Replay the write log over the snapshot. A torn last line from a crash is
+/// skipped. Returns how many records were applied.
+async fn replay(log: &Path, entries: &mut BTreeMap<String, KvEntry>) -> usize {
+    let Ok(bytes) = tokio::fs::read(log).await else {
+        return 0;
+    };
+    let mut applied = 0;
+    for line in bytes.split(|b| *b == b'\n') {
+        if let Ok(record) = serde_json::from_slice::<LogRecord>(line) {
+            entries.insert(record.key, record.entry);
+            applied += 1;
+        }
+    }
+    applied
+}
+
+/// Open (or load from disk) the store called `store`.
+pub async fn open(store: &str) -> Arc<KvStore> {
+    static STORES: OnceLock<Mutex<HashMap<String, Arc<KvStore>>>> = OnceLock::new();
+    let mut stores = STORES.get_or_init(Default::default).lock().await;
+    if let Some(existing) = stores.get(store) {
+        return existing.clone();
+    }
+    let path = store_path(store);
+    let mut entries = match tokio::fs::read(&path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+        Err(_) => BTreeMap::new(),
+    };
+    let logged = replay(&path.with_extension("log"), &mut entries).await;
+    let opened = Arc::new(KvStore {
+        path,
+        entries: Mutex::new(entries),
+        logged: AtomicUsize::new(logged),
+    });
+    stores.insert(store.to_string(), opened.clone());
+    opened
+}
+
+pub struct KvStore {
+    /// Snapshot file; the write log sits next to it
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, KvEntry>>,
+    /// Records in the write log since the last snapshot
+    logged: AtomicUsize,
+}
+
+impl KvStore {
+    pub async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let entries = self.entries.lock().await;
+        live_value(entries.get(key), now_ms())
+    }
+
+    /// Live keys starting with `prefix`, in order.
+    pub async fn list_keys(&self, prefix: &str) -> Vec<String> {
+        let now = now_ms();
+        let entries = self.entries.lock().await;
+        entries
+            .range(prefix.to_string()..)
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .filter(|(_, e)| live_value(Some(e), now).is_some())
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+
+    /// Returns the written entry, for replication.
+    pub async fn set(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl_ms: Option<u64>,
+        origin: &str,
+    ) -> Result<KvEntry, KvError> {
+        let mut entries = self.entries.lock().await;
+        let now = now_ms();
+        let entry = local_entry(
+            &entries,
+            key,
+            Some(value),
+            ttl_ms.map(|t| now.saturating_add(t)),
+            origin,
+            now,
+        );
+        self.commit(&mut entries, key, entry.clone()).await?;
+        Ok(entry)
+    }
+
+    /// Returns the tombstone when the key existed.
+    pub async fn delete(&self, key: &str, origin: &str) -> Result<Option<KvEntry>, KvError> {
+        let mut entries = self.entries.lock().await;
+        let now = now_ms();
+        if live_value(entries.get(key), now).is_none() {
+            return Ok(None);
+        }
+        let entry = local_entry(&entries, key, None, None, origin, now);
+        self.commit(&mut entries, key, entry.clone()).await?;
+        Ok(Some(entry))
+    }
+
+    /// Add `delta` to the decimal integer at `key` (0 when missing), keeping
+    /// its expiry. Returns the new value and the written entry.
+    pub async fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+        origin: &str,
+    ) -> Result<(i64, KvEntry), KvError> {
+        let mut entries = self.entries.lock().await;
+        let now = now_ms();
+        let current = match live_value(entries.get(key), now) {
+            Some(bytes) => std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.trim().parse::<i64>().ok())
+                .ok_or_else(|| KvError::Invalid(format!("value of '{key}' is not an integer")))?,
+            None => 0,
+        };
+        let next = current
+            .checked_add(delta)
+            .ok_or_else(|| KvError::Invalid(format!("increment of '{key}' overflows")))?;
+        let expires_at_ms = entries
+            .get(key)
+            .filter(|e| e.value_b64.is_some())
+            .and_then(|e| e.expires_at_ms);
+        let entry = local_entry(
+            &entries,
+            key,
+            Some(next.to_string().into_bytes()),
+            expires_at_ms,
+            origin,
+            now,
+        );
+        self.commit(&mut entries, key, entry.clone()).await?;
+        Ok((next, entry))
+    }
+
+    /// Replace the value at `key` if it currently equals `expected` (`None`:
+    /// the key must be absent). A `value` of `None` deletes the key. Returns
+    /// whether the swap happened and the entry written, if any.
+    pub async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: Option<Vec<u8>>,
+        ttl_ms: Option<u64>,
+        origin: &str,
+    ) -> Result<(bool, Option<KvEntry>), KvError> {
+        let mut entries = self.entries.lock().await;
+        let now = now_ms();
+        let current = live_value(entries.get(key), now);
+        if current.as_deref() != expected {
+            return Ok((false, None));
+        }
+        if current.is_none() && value.is_none() {
+            return Ok((true, None));
+        }
+        let expires_at_ms = match value {
+            Some(_) => ttl_ms.map(|t| now.saturating_add(t)),
+            None => None,
+        };
+        let entry = local_entry(&entries, key, value, expires_at_ms, origin, now);
+        self.commit(&mut entries, key, entry.clone()).await?;
+        Ok((true, Some(entry)))
+    }
+
+    /// Apply a write replicated from a peer if it is newer than ours.
+    pub async fn apply_remote(&self, key: String, entry: KvEntry) -> Result<bool, KvError> {
+        let mut entries = self.entries.lock().await;
+        let newer = match entries.get(&key) {
+            Some(ours) => (entry.updated_ms, &entry.origin) > (ours.updated_ms, &ours.origin),
+            None => true,
+        };
+        if !newer {
+            return Ok(false);
+        }
+        self.commit(&mut entries, &key, entry).await?;
+        Ok(true)
+    }
+
+    /// Append the write to the log and only then apply it, so a failed write
+    /// leaves the store as it was.
+    async fn commit(
+        &self,
+        entries: &mut BTreeMap<String, KvEntry>,
+        key: &str,
+        entry: KvEntry,
+    ) -> Result<(), KvError> {
+        let record = LogRecord {
+            key: key.to_string(),
+            entry,
+        };
+        let mut line = serde_json::to_vec(&record).map_err(|e| KvError::Io(e.to_string()))?;
+        line.push(b'\n');
+        if let Some(parent) = self.path.parent() {
+            let _ = tokio::fs::create_dir_all(parent).await;
+        }
+        let mut log = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.with_extension("log"))
+            .await
+            .map_err(|e| KvError::Io(format!("kv write failed: {e}")))?;
+        log.write_all(&line)
+            .await
+            .map_err(|e| KvError::Io(format!("kv write failed: {e}")))?;
+        log.flush()
+            .await
+            .map_err(|e| KvError::Io(format!("kv write failed: {e}")))?;
+        entries.insert(record.key, record.entry);
+
+        let logged = self.logged.fetch_add(1, Ordering::Relaxed) + 1;
+        if logged > entries.len().max(MIN_COMPACT_RECORDS) {
+            // The write is already saved in the log; compaction can retry later
+            if let Err(e) = self.compact(entries).await {
+                warn!(path = %self.path.display(), error = ?e, "kv compaction failed");
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop expired keys and old tombstones, write the snapshot and start a
+    /// new log. Replaying a log that survived a crash after the rename only
+    /// reapplies writes the snapshot already holds.
+    async fn compact(&self, entries: &mut BTreeMap<String, KvEntry>) -> Result<(), KvError> {
+        let now = now_ms();
+        entries.retain(|_, e| match &e.value_b64 {
+            Some(_) => e.expires_at_ms.is_none_or(|t| t > now),
+            None => e.updated_ms.saturating_add(TOMBSTONE_RETENTION_MS) > now,
+        });
+        let bytes = serde_json::to_vec(&*entries).map_err(|e| KvError::Io(e.to_string()))?;
+        let tmp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, bytes)
+            .await
+            .map_err(|e| KvError::Io(format!("kv snapshot failed: {e}")))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| KvError::Io(format!("kv snapshot failed: {e}")))?;
+        tokio::fs::write(self.path.with_extension("log"), b"")
+            .await
+            .map_err(|e| KvError::Io(format!("kv snapshot failed: {e}")))?;
+        self.logged.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// The entry for a local write. Versions only move forward, even if the
+/// clock does.
+fn local_entry(
+    entries: &BTreeMap<String, KvEntry>,
+    key: &str,
+    value: Option<Vec<u8>>,
+    expires_at_ms: Option<u64>,
+    origin: &str,
+    now: u64,
+) -> KvEntry {
+    let updated_ms = entries
+        .get(key)
+        .map(|e| now.max(e.updated_ms.saturating_add(1)))
+        .unwrap_or(now);
+    KvEntry {
+        value_b64: value.map(|v| base64::engine::general_purpose::STANDARD.encode(v)),
+        expires_at_ms,
+        updated_ms,
+        origin: origin.to_string(),
+    }
+}
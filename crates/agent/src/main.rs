@@ -1,8 +1,17 @@
 mod cmd;
+mod egress;
+mod events;
+mod host;
+mod http_cache;
 mod job_manager;
+mod kv;
+mod oci;
 mod p2p;
 mod policy;
 mod runner;
+mod sandbox;
+mod schedule;
+mod secrets;
 mod storage;
 mod supervisor;
 mod web;
@@ -102,15 +111,63 @@ enum Commands {
         fuel: u64,
         #[arg(long, default_value_t = 100)]
         epoch_ms: u64,
+        /// Per-request deadline for HTTP components in ms (default 30000)
+        #[arg(long)]
+        request_timeout_ms: Option<u64>,
+        /// Gateway rate limit across all clients, requests per second
+        #[arg(long)]
+        rate_limit_rps: Option<u32>,
+        /// Gateway rate limit per client IP, requests per second
+        #[arg(long)]
+        client_rate_limit_rps: Option<u32>,
+        /// Largest accepted request body in bytes (default: node limit, 16 MiB)
+        #[arg(long)]
+        max_body_bytes: Option<u64>,
+        /// Requests served at once before the gateway answers 503
+        #[arg(long)]
+        max_concurrent_requests: Option<u32>,
         /// Preopen mounts (repeatable): host=/abs/path,guest=/www[,ro=true]
         #[arg(long = "mount")]
         mounts: Vec<String>,
-        /// Declare service ports (repeatable), e.g. 8080/tcp or 9090/udp
+        /// Declare service ports (repeatable): [HOST_PORT:]PORT[/tcp|/udp], e.g. 8080/tcp or 18080:8080/tcp
         #[arg(long = "port")]
         ports: Vec<String>,
         /// [deprecated] Static routes removed; HTTP is handled via WASI HTTP inside components.
         #[arg(long = "route-static", hide = true)]
         routes_static: Vec<String>,
+        /// Gateway route (repeatable): host=ci.example.internal,prefix=/hook[,strip=true][,priority=N]
+        #[arg(long = "route")]
+        routes: Vec<String>,
+        /// Outbound allowlist (repeatable): [SCHEME://]HOST[:PORT], e.g. https://api.github.com; `none` denies all
+        #[arg(long = "egress")]
+        egress: Vec<String>,
+        /// Guest argument after the program name (repeatable, in order)
+        #[arg(long = "arg", allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// Environment variable (repeatable): KEY=VALUE
+        #[arg(long = "env")]
+        env: Vec<String>,
+        /// Environment variable read from a secret (repeatable): KEY=file:/abs/path or KEY=cas:<sha256>
+        #[arg(long = "secret-env")]
+        secret_env: Vec<String>,
+        /// Feed stdin of command components from cas:<sha256>
+        #[arg(long)]
+        stdin: Option<String>,
+        /// realm:host capability to grant (repeatable): jobs|cas|kv|events
+        #[arg(long = "capability")]
+        capabilities: Vec<String>,
+        /// Name of the realm:host/kv store (default: the component name)
+        #[arg(long = "kv-store")]
+        kv_store: Option<String>,
+        /// Replicate kv writes to other nodes hosting the same store
+        #[arg(long = "kv-replicate", default_value_t = false)]
+        kv_replicate: bool,
+        /// Event topic that triggers the component's realm:host/event-handler (repeatable; `prefix*` matches a prefix)
+        #[arg(long = "subscribe")]
+        subscriptions: Vec<String>,
+        /// Cron expression to invoke the component on instead of running replicas (5 fields, or 6-7 with seconds)
+        #[arg(long)]
+        schedule: Option<String>,
         /// Gateway bind policy: local|public
         #[arg(long)]
         visibility: Option<String>,
@@ -209,7 +266,7 @@ enum Commands {
         #[arg(long, default_value_t = 30)]
         timeout: u64,
     },
-    /// Show current runtime policy (native/QEMU)
+    /// Show current runtime policy (native/QEMU/containers)
     PolicyShow,
     /// Set runtime policy flags
     PolicySet {
@@ -219,6 +276,15 @@ enum Commands {
         /// Allow QEMU emulation (true/false)
         #[arg(long)]
         qemu: Option<bool>,
+        /// Run all native jobs in the Linux sandbox (true/false)
+        #[arg(long)]
+        native_sandbox: Option<bool>,
+        /// Allow OCI container jobs (true/false)
+        #[arg(long)]
+        containers: Option<bool>,
+        /// Job slots for a runtime, e.g. native=2 (repeatable)
+        #[arg(long = "slots")]
+        slots: Vec<String>,
     },
     /// List stored blobs (CAS)
     StorageLs,
@@ -347,8 +413,23 @@ enum P2pCommands {
     Watch,
 }
 
-#[tokio::main(flavor = "multi_thread")]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
+    // Sandboxed native jobs re-execute this binary as their namespace helper,
+    // which has to fork before any runtime threads exist
+    #[cfg(target_os = "linux")]
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|a| a == sandbox::HELPER_ARG)
+    {
+        sandbox::linux::helper_main();
+    }
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run())
+}
+
+async fn run() -> anyhow::Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .with_target(false)
@@ -425,9 +506,25 @@ async fn main() -> anyhow::Result<()> {
             memory_max_mb,
             fuel,
             epoch_ms,
+            request_timeout_ms,
+            rate_limit_rps,
+            client_rate_limit_rps,
+            max_body_bytes,
+            max_concurrent_requests,
             mounts,
             ports,
             routes_static,
+            routes,
+            egress,
+            args,
+            env,
+            secret_env,
+            stdin,
+            capabilities,
+            kv_store,
+            kv_replicate,
+            subscriptions,
+            schedule,
             visibility,
             target_peers,
             target_tags,
@@ -440,9 +537,27 @@ async fn main() -> anyhow::Result<()> {
                 memory_max_mb,
                 fuel,
                 epoch_ms,
+                request_timeout_ms,
+                common::HttpLimits {
+                    rate_limit_rps,
+                    client_rate_limit_rps,
+                    max_body_bytes,
+                    max_concurrent_requests,
+                },
                 mounts,
                 ports,
                 routes_static,
+                routes,
+                egress,
+                args,
+                env,
+                secret_env,
+                stdin,
+                capabilities,
+                kv_store,
+                kv_replicate,
+                subscriptions,
+                schedule,
                 visibility,
                 target_peers,
                 target_tags,
@@ -563,7 +678,13 @@ async fn main() -> anyhow::Result<()> {
             P2pCommands::Watch => cmd::watch().await,
         },
         Some(Commands::PolicyShow) => cmd::policy_show().await,
-        Some(Commands::PolicySet { native, qemu }) => cmd::policy_set(native, qemu).await,
+        Some(Commands::PolicySet {
+            native,
+            qemu,
+            native_sandbox,
+            containers,
+            slots,
+        }) => cmd::policy_set(native, qemu, native_sandbox, containers, slots).await,
         Some(Commands::StorageLs) => cmd::storage_ls().await,
         Some(Commands::StoragePin { digest, pinned }) => cmd::storage_pin(digest, pinned).await,
         Some(Commands::StorageGc { target_total_bytes }) => {
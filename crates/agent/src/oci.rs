@@ -0,0 +1,803 @@
+//! OCI images for container jobs. Images come from a registry (distribution
+//! API v2 with anonymous bearer tokens) or from an OCI image layout tarball
+//! in CAS. Manifests and layers are kept in CAS under their sha256, so peers
+//! can serve them to each other, and unpacked roots are cached under the
+//! digests of the layers they were built from.
+
+use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use reqwest::header::{ACCEPT, WWW_AUTHENTICATE};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::p2p::storage::P2PStorage;
+use crate::storage::ContentStore;
+
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+application/vnd.oci.image.manifest.v1+json, \
+application/vnd.docker.distribution.manifest.list.v2+json, \
+application/vnd.docker.distribution.manifest.v2+json";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+const WHITEOUT_PREFIX: &str = ".wh.";
+const P2P_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// `[registry/]repository[:tag|@digest]`, with Docker Hub defaults.
+#[derive(Debug, Clone)]
+pub struct ImageRef {
+    pub registry: String,
+    pub repository: String,
+    /// Tag or `sha256:` digest
+    pub reference: String,
+}
+
+impl ImageRef {
+    pub fn parse(image: &str) -> Result<Self, String> {
+        let (name, reference) = match image.split_once('@') {
+            Some((name, digest)) => {
+                digest_hex(digest)?;
+                (name, digest.to_string())
+            }
+            None => match image.rsplit_once(':') {
+                Some((name, tag)) if !tag.contains('/') => (name, tag.to_string()),
+                _ => (image, "latest".to_string()),
+            },
+        };
+        if name.is_empty() || reference.is_empty() {
+            return Err(format!("invalid image reference '{image}'"));
+        }
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                (first.to_string(), rest.to_string())
+            }
+            _ => ("docker.io".to_string(), name.to_string()),
+        };
+        let repository = if registry == "docker.io" && !repository.contains('/') {
+            format!("library/{repository}")
+        } else {
+            repository
+        };
+        Ok(Self {
+            registry,
+            repository,
+            reference,
+        })
+    }
+
+    fn base_url(&self) -> String {
+        let host = if self.registry == "docker.io" {
+            "registry-1.docker.io"
+        } else {
+            self.registry.as_str()
+        };
+        let hostname = host.rsplit_once(':').map_or(host, |(h, _)| h);
+        let insecure = matches!(hostname, "localhost" | "127.0.0.1" | "[::1]")
+            || std::env::var("REALM_INSECURE_REGISTRIES")
+                .map(|list| list.split(',').any(|r| r.trim() == self.registry))
+                .unwrap_or(false);
+        let scheme = if insecure { "http" } else { "https" };
+        format!("{scheme}://{host}")
+    }
+}
+
+/// The parts of an image config a container starts from.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageConfig {
+    #[serde(default)]
+    pub env: Option<Vec<String>>,
+    #[serde(default)]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(default)]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    /// sha256 hex of the compressed blob
+    pub digest: String,
+    pub media_type: String,
+}
+
+/// A resolved image whose blobs are all in the local CAS.
+#[derive(Debug, Clone)]
+pub struct Image {
+    /// Digest of the platform manifest
+    pub digest: String,
+    pub config: ImageConfig,
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+    #[serde(rename = "mediaType", default)]
+    media_type: String,
+    digest: String,
+    #[serde(default)]
+    platform: Option<Platform>,
+}
+
+#[derive(Deserialize)]
+struct Platform {
+    os: String,
+    architecture: String,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    config: Option<Descriptor>,
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+    /// Set for indexes and manifest lists
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    config: Option<ImageConfig>,
+}
+
+/// Resolve `image` for this node's platform and make sure all its blobs are
+/// in CAS, fetching them from peers or the registry when missing.
+pub async fn pull(image: &str, storage: Option<&P2PStorage>) -> Result<Image, String> {
+    let (mut registry, mut manifest_bytes) = match image.strip_prefix("cas:") {
+        Some(hex) => {
+            let tarball = blob(hex, storage, None).await?;
+            let index = tokio::task::spawn_blocking(move || import_layout(&tarball))
+                .await
+                .map_err(|e| e.to_string())??;
+            (None, index)
+        }
+        None => {
+            let image_ref = ImageRef::parse(image)?;
+            let mut registry = Registry::new(image_ref.clone());
+            let bytes = registry
+                .get(
+                    &format!("manifests/{}", image_ref.reference),
+                    MANIFEST_TYPES,
+                )
+                .await?;
+            if let Ok(hex) = digest_hex(&image_ref.reference) {
+                verify(&bytes, hex)?;
+            }
+            (Some(registry), bytes)
+        }
+    };
+    let mut digest = common::sha256_hex(&manifest_bytes);
+    // Indexes point at one manifest per platform; nested indexes are allowed
+    for _ in 0..4 {
+        let manifest: Manifest =
+            serde_json::from_slice(&manifest_bytes).map_err(|e| format!("manifest: {e}"))?;
+        if manifest.manifests.is_empty() {
+            let config = manifest
+                .config
+                .ok_or_else(|| "manifest has no config".to_string())?;
+            let config_bytes =
+                blob(digest_hex(&config.digest)?, storage, registry.as_mut()).await?;
+            let config_file: ConfigFile =
+                serde_json::from_slice(&config_bytes).map_err(|e| format!("image config: {e}"))?;
+            let mut layers = Vec::with_capacity(manifest.layers.len());
+            for layer in manifest.layers {
+                let hex = digest_hex(&layer.digest)?.to_string();
+                ensure_blob(&hex, storage, registry.as_mut()).await?;
+                layers.push(Layer {
+                    digest: hex,
+                    media_type: layer.media_type,
+                });
+            }
+            let _ = ContentStore::open().put_bytes(&manifest_bytes);
+            return Ok(Image {
+                digest,
+                config: config_file.config.unwrap_or_default(),
+                layers,
+            });
+        }
+        let chosen = select_platform(&manifest.manifests)?;
+        let hex = digest_hex(&chosen.digest)?.to_string();
+        manifest_bytes = match registry.as_mut() {
+            Some(registry) => {
+                let bytes = registry
+                    .get(&format!("manifests/{}", chosen.digest), MANIFEST_TYPES)
+                    .await?;
+                verify(&bytes, &hex)?;
+                bytes
+            }
+            None => blob(&hex, storage, None).await?,
+        };
+        digest = hex;
+    }
+    Err("image index nesting is too deep".to_string())
+}
+
+/// Unpack the image's layers into the rootfs cache and return the root.
+/// Blocking; images with the same layers share one root.
+pub fn unpack(image: &Image) -> Result<PathBuf, String> {
+    let chain: Vec<&str> = image.layers.iter().map(|l| l.digest.as_str()).collect();
+    let key = common::sha256_hex(chain.join("\n").as_bytes());
+    let cache = rootfs_dir();
+    let root = cache.join(&key);
+    if root.exists() {
+        return Ok(root);
+    }
+    let tmp = cache.join(format!(".tmp-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&tmp).map_err(|e| format!("rootfs {}: {e}", tmp.display()))?;
+    let store = ContentStore::open();
+    let built = image.layers.iter().try_for_each(|layer| {
+        let path = store
+            .get_path(&layer.digest)
+            .ok_or_else(|| format!("layer {} missing from CAS", layer.digest))?;
+        let file =
+            std::fs::File::open(&path).map_err(|e| format!("layer {}: {e}", layer.digest))?;
+        apply_layer(&tmp, file, &layer.media_type)
+            .map_err(|e| format!("layer {}: {e}", layer.digest))
+    });
+    if let Err(e) = built {
+        let _ = std::fs::remove_dir_all(&tmp);
+        return Err(e);
+    }
+    match std::fs::rename(&tmp, &root) {
+        Ok(()) => Ok(root),
+        // Another run built the same root meanwhile
+        Err(_) if root.exists() => {
+            let _ = std::fs::remove_dir_all(&tmp);
+            Ok(root)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&tmp);
+            Err(format!("rootfs {}: {e}", root.display()))
+        }
+    }
+}
+
+fn rootfs_dir() -> PathBuf {
+    crate::p2p::state::agent_data_dir()
+        .join("oci")
+        .join("rootfs")
+}
+
+/// Extract a plain (optionally gzipped) tarball into `root`, with the same
+/// path checks as image layers.
+pub fn unpack_tarball(root: &Path, bytes: &[u8]) -> Result<(), String> {
+    apply_layer(root, std::io::Cursor::new(bytes), "application/x-tar")
+}
+
+/// Extract one layer over `root`, applying its whiteouts to the layers below.
+/// The layer is read twice, so it is streamed from a seekable reader rather
+/// than held in memory.
+fn apply_layer<R: Read + Seek>(root: &Path, mut layer: R, media_type: &str) -> Result<(), String> {
+    // Whiteouts only hide lower layers, so they go first
+    let mut archive = tar::Archive::new(layer_reader(&mut layer, media_type)?);
+    for entry in archive.entries().map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path().map_err(|e| e.to_string())?;
+        let Some(rel) = clean_path(&path) else {
+            continue;
+        };
+        let Some(name) = rel.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let parent = rel.parent().unwrap_or(Path::new(""));
+        if name == OPAQUE_WHITEOUT {
+            if let Some(dir) = contained(root, parent) {
+                if let Ok(children) = std::fs::read_dir(&dir) {
+                    for child in children.flatten() {
+                        remove_path(&child.path());
+                    }
+                }
+            }
+        } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            if let Some(target) = contained(root, &parent.join(hidden)) {
+                remove_path(&target);
+            }
+        }
+    }
+
+    drop(archive);
+
+    layer.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+    let mut archive = tar::Archive::new(layer_reader(&mut layer, media_type)?);
+    archive.set_preserve_permissions(true);
+    archive.set_overwrite(true);
+    archive.set_unpack_xattrs(false);
+    for entry in archive.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path().map_err(|e| e.to_string())?.into_owned();
+        let Some(rel) = clean_path(&path) else {
+            continue;
+        };
+        let name = rel.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if name.starts_with(WHITEOUT_PREFIX) {
+            continue;
+        }
+        let kind = entry.header().entry_type();
+        // Device nodes cannot be created without privileges; /dev is
+        // provided by the sandbox anyway
+        if kind.is_block_special() || kind.is_character_special() || kind.is_fifo() {
+            continue;
+        }
+        let Some(target) = contained(root, &rel) else {
+            continue;
+        };
+        // A lower layer may have a directory where this one has a file, or
+        // the other way round
+        if let Ok(meta) = target.symlink_metadata() {
+            if meta.is_dir() != kind.is_dir() {
+                remove_path(&target);
+            }
+        }
+        entry
+            .unpack_in(root)
+            .map_err(|e| format!("{}: {e}", rel.display()))?;
+    }
+    make_owner_accessible(root);
+    Ok(())
+}
+
+fn layer_reader<'a, R: Read + 'a>(
+    reader: R,
+    media_type: &str,
+) -> Result<Box<dyn Read + 'a>, String> {
+    if media_type.contains("zstd") {
+        return Err("zstd-compressed layers are not supported".to_string());
+    }
+    let mut reader = std::io::BufReader::new(reader);
+    let gzipped = reader
+        .fill_buf()
+        .map_err(|e| e.to_string())?
+        .starts_with(&[0x1f, 0x8b]);
+    if gzipped {
+        Ok(Box::new(flate2::read::GzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Archive path relative to the root; `None` if it tries to leave it.
+fn clean_path(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!out.as_os_str().is_empty()).then_some(out)
+}
+
+/// `root/rel`, unless one of its parent directories is a symlink (which
+/// could point anywhere on the host).
+fn contained(root: &Path, rel: &Path) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    let mut components = rel.components().peekable();
+    while let Some(component) = components.next() {
+        path.push(component);
+        if components.peek().is_some()
+            && path
+                .symlink_metadata()
+                .is_ok_and(|m| m.file_type().is_symlink())
+        {
+            return None;
+        }
+    }
+    Some(path)
+}
+
+fn remove_path(path: &Path) {
+    match path.symlink_metadata() {
+        Ok(meta) if meta.is_dir() => {
+            let _ = std::fs::remove_dir_all(path);
+        }
+        Ok(_) => {
+            let _ = std::fs::remove_file(path);
+        }
+        Err(_) => {}
+    }
+}
+
+/// Image files belong to the agent's user once unpacked; make sure it can
+/// still traverse, write and remove every directory and read every file.
+#[cfg(unix)]
+fn make_owner_accessible(dir: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(meta) = path.symlink_metadata() else {
+            continue;
+        };
+        let mode = meta.permissions().mode();
+        if meta.is_dir() {
+            if mode & 0o700 != 0o700 {
+                let _ =
+                    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode | 0o700));
+            }
+            make_owner_accessible(&path);
+        } else if meta.is_file() && mode & 0o400 == 0 {
+            let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode | 0o400));
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn make_owner_accessible(_dir: &Path) {}
+
+/// Copy the blobs of an OCI image layout tarball into CAS and return its
+/// `index.json`.
+fn import_layout(tarball: &[u8]) -> Result<Vec<u8>, String> {
+    let store = ContentStore::open();
+    let mut index = None;
+    let mut archive = tar::Archive::new(layer_reader(tarball, "")?);
+    for entry in archive.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path().map_err(|e| e.to_string())?;
+        let Some(rel) = clean_path(&path) else {
+            continue;
+        };
+        let is_index = rel == Path::new("index.json");
+        let blob_hex = rel
+            .strip_prefix("blobs/sha256")
+            .ok()
+            .and_then(|p| p.to_str())
+            .map(str::to_string);
+        if !is_index && blob_hex.is_none() {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        entry
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("{}: {e}", rel.display()))?;
+        if is_index {
+            index = Some(bytes);
+        } else if let Some(hex) = blob_hex {
+            verify(&bytes, &hex)?;
+            store.put_bytes(&bytes)?;
+        }
+    }
+    index.ok_or_else(|| "not an OCI image layout (index.json missing)".to_string())
+}
+
+fn select_platform(manifests: &[Descriptor]) -> Result<&Descriptor, String> {
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "powerpc64" => "ppc64le",
+        other => other,
+    };
+    manifests
+        .iter()
+        .find(|m| {
+            m.platform
+                .as_ref()
+                .is_some_and(|p| p.os == "linux" && p.architecture == arch)
+        })
+        // Single-image layouts often carry no platform at all
+        .or(match manifests {
+            [only] if only.platform.is_none() => Some(only),
+            _ => None,
+        })
+        .ok_or_else(|| format!("image has no linux/{arch} variant"))
+}
+
+/// Read blob `hex` from CAS, fetching it into CAS first when needed.
+async fn blob(
+    hex: &str,
+    storage: Option<&P2PStorage>,
+    registry: Option<&mut Registry>,
+) -> Result<Vec<u8>, String> {
+    let path = ensure_blob(hex, storage, registry).await?;
+    tokio::fs::read(&path)
+        .await
+        .map_err(|e| format!("cas read failed: {e}"))
+}
+
+async fn ensure_blob(
+    hex: &str,
+    storage: Option<&P2PStorage>,
+    registry: Option<&mut Registry>,
+) -> Result<PathBuf, String> {
+    let store = ContentStore::open();
+    if let Some(path) = store.get_path(hex) {
+        return Ok(path);
+    }
+    let from_peer = match storage {
+        Some(sto) => sto
+            .get(hex.to_string(), P2P_TIMEOUT)
+            .await
+            .filter(|b| common::sha256_hex(b) == hex),
+        None => None,
+    };
+    let digest = match (from_peer, registry) {
+        (Some(bytes), _) => store.put_bytes(&bytes)?,
+        // Layers can be large: stream them to disk instead of buffering
+        (None, Some(registry)) => {
+            registry
+                .download(&format!("blobs/sha256:{hex}"), hex, &store)
+                .await?
+        }
+        (None, None) => return Err(format!("blob sha256:{hex} not available")),
+    };
+    store
+        .get_path(&digest)
+        .ok_or_else(|| "cas path missing".to_string())
+}
+
+fn digest_hex(digest: &str) -> Result<&str, String> {
+    match digest.strip_prefix("sha256:") {
+        Some(hex) if hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => Ok(hex),
+        _ => Err(format!(
+            "unsupported digest '{digest}' (expected sha256:<hex>)"
+        )),
+    }
+}
+
+fn verify(bytes: &[u8], hex: &str) -> Result<(), String> {
+    if common::sha256_hex(bytes).eq_ignore_ascii_case(hex) {
+        Ok(())
+    } else {
+        Err(format!("digest mismatch for sha256:{hex}"))
+    }
+}
+
+struct Registry {
+    image: ImageRef,
+    http: reqwest::Client,
+    token: Option<String>,
+}
+
+impl Registry {
+    fn new(image: ImageRef) -> Self {
+        Self {
+            image,
+            http: reqwest::Client::new(),
+            token: None,
+        }
+    }
+
+    async fn get(&mut self, path: &str, accept: &str) -> Result<Vec<u8>, String> {
+        let (url, res) = self.send(path, accept).await?;
+        let bytes = res.bytes().await.map_err(|e| format!("{url}: {e}"))?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Write `/v2/<repository>/<path>` into CAS chunk by chunk, hashing as it
+    /// goes, and return its digest once it matches `hex`.
+    async fn download(
+        &mut self,
+        path: &str,
+        hex: &str,
+        store: &ContentStore,
+    ) -> Result<String, String> {
+        let (url, mut res) = self.send(path, "*/*").await?;
+        let staged = store.staging_path();
+        let written = async {
+            let mut file = tokio::fs::File::create(&staged)
+                .await
+                .map_err(|e| format!("cas write failed: {e}"))?;
+            let mut hasher = Sha256::new();
+            while let Some(chunk) = res.chunk().await.map_err(|e| format!("{url}: {e}"))? {
+                hasher.update(&chunk);
+                file.write_all(&chunk)
+                    .await
+                    .map_err(|e| format!("cas write failed: {e}"))?;
+            }
+            file.flush()
+                .await
+                .map_err(|e| format!("cas write failed: {e}"))?;
+            let digest = hex::encode(hasher.finalize());
+            if !digest.eq_ignore_ascii_case(hex) {
+                return Err(format!("digest mismatch for sha256:{hex}"));
+            }
+            store.put_file(&staged, &digest)?;
+            Ok::<_, String>(digest)
+        }
+        .await;
+        if written.is_err() {
+            let _ = tokio::fs::remove_file(&staged).await;
+        }
+        written
+    }
+
+    /// GET `/v2/<repository>/<path>`, fetching a pull token on the first 401.
+    async fn send(
+        &mut self,
+        path: &str,
+        accept: &str,
+    ) -> Result<(String, reqwest::Response), String> {
+        let url = format!(
+            "{}/v2/{}/{}",
+            self.image.base_url(),
+            self.image.repository,
+            path
+        );
+        for _ in 0..2 {
+            let mut req = self.http.get(&url).header(ACCEPT, accept);
+            if let Some(token) = &self.token {
+                req = req.bearer_auth(token);
+            }
+            let res = req.send().await.map_err(|e| format!("{url}: {e}"))?;
+            if res.status() == reqwest::StatusCode::UNAUTHORIZED && self.token.is_none() {
+                let challenge = res
+                    .headers()
+                    .get(WWW_AUTHENTICATE)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                self.token = Some(self.fetch_token(&challenge).await?);
+                continue;
+            }
+            if !res.status().is_success() {
+                return Err(format!("{url}: {}", res.status()));
+            }
+            return Ok((url, res));
+        }
+        Err(format!("{url}: unauthorized"))
+    }
+
+    /// Anonymous token from the `Bearer realm=...` challenge.
+    async fn fetch_token(&self, challenge: &str) -> Result<String, String> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            #[serde(default)]
+            token: Option<String>,
+            #[serde(default)]
+            access_token: Option<String>,
+        }
+        let params = challenge
+            .strip_prefix("Bearer ")
+            .map(parse_challenge)
+            .ok_or_else(|| format!("registry requires unsupported auth: {challenge}"))?;
+        let param = |key: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v.clone())
+        };
+        let realm = param("realm").ok_or_else(|| "auth challenge without realm".to_string())?;
+        let scope =
+            param("scope").unwrap_or_else(|| format!("repository:{}:pull", self.image.repository));
+        let mut query = vec![("scope", scope)];
+        if let Some(service) = param("service") {
+            query.push(("service", service));
+        }
+        let res = self
+            .http
+            .get(&realm)
+            .query(&query)
+            .send()
+            .await
+            .map_err(|e| format!("token: {e}"))?;
+        if !res.status().is_success() {
+            return Err(format!("token: {}", res.status()));
+        }
+        let bytes = res.bytes().await.map_err(|e| format!("token: {e}"))?;
+        let token: TokenResponse =
+            serde_json::from_slice(&bytes).map_err(|e| format!("token: {e}"))?;
+        token
+            .token
+            .or(token.access_token)
+            .ok_or_else(|| "token response without token".to_string())
+    }
+}
+
+/// `key="value",key2="value2"` pairs; quoted values may contain commas.
+fn parse_challenge(params: &str) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, remaining)) => (value.to_string(), remaining),
+                None => (quoted.to_string(), ""),
+            },
+            None => match after.split_once(',') {
+                Some((value, remaining)) => (value.trim().to_string(), remaining),
+                None => (after.trim().to_string(), ""),
+            },
+        };
+        out.push((key, value));
+        rest = remaining.trim_start_matches(',').trim();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(s: &str) -> (String, String, String) {
+        let r = ImageRef::parse(s).unwrap();
+        (r.registry, r.repository, r.reference)
+    }
+
+    fn owned(registry: &str, repository: &str, reference: &str) -> (String, String, String) {
+        (registry.into(), repository.into(), reference.into())
+    }
+
+    #[test]
+    fn parses_docker_hub_defaults() {
+        assert_eq!(
+            image("alpine"),
+            owned("docker.io", "library/alpine", "latest")
+        );
+        assert_eq!(
+            image("alpine:3.20"),
+            owned("docker.io", "library/alpine", "3.20")
+        );
+        assert_eq!(
+            image("grafana/grafana:11.0.0"),
+            owned("docker.io", "grafana/grafana", "11.0.0")
+        );
+        assert_eq!(
+            image("docker.io/nginx"),
+            owned("docker.io", "library/nginx", "latest")
+        );
+    }
+
+    #[test]
+    fn parses_registries_tags_and_digests() {
+        let digest = format!("sha256:{}", "ab".repeat(32));
+        assert_eq!(
+            image(&format!("ghcr.io/acme/api@{digest}")),
+            owned("ghcr.io", "acme/api", &digest)
+        );
+        assert_eq!(
+            image("localhost:5000/app:1.2"),
+            owned("localhost:5000", "app", "1.2")
+        );
+        assert_eq!(
+            image("registry.example.com:8443/team/app"),
+            owned("registry.example.com:8443", "team/app", "latest")
+        );
+        assert_eq!(image("localhost/app"), owned("localhost", "app", "latest"));
+        assert!(ImageRef::parse("alpine@sha256:abc").is_err());
+        assert!(ImageRef::parse("alpine@latest").is_err());
+        assert!(ImageRef::parse(":tag").is_err());
+    }
+
+    #[test]
+    fn selects_this_platform_from_a_manifest_list() {
+        let arch = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            "powerpc64" => "ppc64le",
+            other => other,
+        };
+        let other = if arch == "amd64" { "arm64" } else { "amd64" };
+        let list: Manifest = serde_json::from_value(serde_json::json!({
+            "manifests": [
+                { "digest": "sha256:windows", "platform": { "os": "windows", "architecture": arch } },
+                { "digest": "sha256:other", "platform": { "os": "linux", "architecture": other } },
+                { "digest": "sha256:native", "platform": { "os": "linux", "architecture": arch } },
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            select_platform(&list.manifests).unwrap().digest,
+            "sha256:native"
+        );
+        assert!(select_platform(&list.manifests[..2]).is_err());
+
+        let single: Manifest = serde_json::from_value(
+            serde_json::json!({ "manifests": [{ "digest": "sha256:only" }] }),
+        )
+        .unwrap();
+        assert_eq!(
+            select_platform(&single.manifests).unwrap().digest,
+            "sha256:only"
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Frame, SizeHint};
+use hyper::Response;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::warn;
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+use crate::p2p::metrics::{push_log, SharedLogs};
+
+/// Rotate `access.log` to `access.log.1` beyond this size.
+const MAX_FILE_BYTES: u64 = 64 * 1024 * 1024;
+/// Entries queued for the writer; further entries are dropped while it catches up.
+const QUEUE_DEPTH: usize = 4096;
+
+/// One completed gateway request.
+struct AccessEntry {
+    method: String,
+    path: String,
+    status: u16,
+    latency_ms: u64,
+    bytes: u64,
+    /// Set only for components known to this node or the mesh
+    component: Option<String>,
+    /// Client address, or the client and the peer for mesh requests
+    remote: String,
+}
+
+impl AccessEntry {
+    fn buffer_line(&self) -> String {
+        format!(
+            "access method={} path={} status={} latency_ms={} bytes={} remote={}",
+            self.method, self.path, self.status, self.latency_ms, self.bytes, self.remote
+        )
+    }
+
+    fn json_line(&self) -> String {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut line = serde_json::json!({
+            "ts_ms": ts,
+            "method": self.method,
+            "path": self.path,
+            "status": self.status,
+            "latency_ms": self.latency_ms,
+            "bytes": self.bytes,
+            "component": self.component,
+            "remote": self.remote,
+        })
+        .to_string();
+        line.push('\n');
+        line
+    }
+}
+
+/// Sink shared by all gateway listeners: entries go to the component's log buffer
+/// and, as JSON lines, to `<data dir>/logs/access.log`.
+#[derive(Clone)]
+pub(super) struct AccessLog {
+    tx: mpsc::Sender<AccessEntry>,
+}
+
+impl AccessLog {
+    pub(super) fn shared(logs: SharedLogs) -> Self {
+        static SINK: OnceLock<AccessLog> = OnceLock::new();
+        SINK.get_or_init(|| {
+            let (tx, rx) = mpsc::channel(QUEUE_DEPTH);
+            tokio::spawn(run_writer(logs, rx));
+            AccessLog { tx }
+        })
+        .clone()
+    }
+
+    /// Emit an entry once the response body has been sent (or the client went away).
+    pub(super) fn track(
+        &self,
+        resp: Response<HyperOutgoingBody>,
+        method: String,
+        path: String,
+        component: Option<String>,
+        remote: String,
+        started: Instant,
+    ) -> Response<HyperOutgoingBody> {
+        let record = Record {
+            tx: self.tx.clone(),
+            entry: AccessEntry {
+                method,
+                path,
+                status: resp.status().as_u16(),
+                latency_ms: 0,
+                bytes: 0,
+                component,
+                remote,
+            },
+            started,
+        };
+        resp.map(|inner| Tracked { inner, record }.boxed())
+    }
+}
+
+/// Response body that counts the bytes sent; its record reports when the body
+/// is dropped.
+struct Tracked {
+    inner: HyperOutgoingBody,
+    record: Record,
+}
+
+impl Body for Tracked {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, ErrorCode>>> {
+        let this = &mut *self;
+        let polled = Pin::new(&mut this.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &polled {
+            if let Some(data) = frame.data_ref() {
+                this.record.entry.bytes += data.len() as u64;
+            }
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Travels with the response body and reports on drop.
+struct Record {
+    tx: mpsc::Sender<AccessEntry>,
+    entry: AccessEntry,
+    started: Instant,
+}
+
+impl Drop for Record {
+    fn drop(&mut self) {
+        let mut entry = AccessEntry {
+            method: std::mem::take(&mut self.entry.method),
+            path: std::mem::take(&mut self.entry.path),
+            component: self.entry.component.take(),
+            remote: std::mem::take(&mut self.entry.remote),
+            ..self.entry
+        };
+        entry.latency_ms = self.started.elapsed().as_millis() as u64;
+        let _ = self.tx.try_send(entry);
+    }
+}
+
+async fn run_writer(logs: SharedLogs, mut rx: mpsc::Receiver<AccessEntry>) {
+    let dir = crate::p2p::state::agent_data_dir().join("logs");
+    let path = dir.join("access.log");
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        warn!(error=%e, "Access log directory unavailable; logging to component buffers only");
+    }
+    let mut file = None;
+    let mut written = tokio::fs::metadata(&path)
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+
+    while let Some(entry) = rx.recv().await {
+        if let Some(component) = &entry.component {
+            push_log(&logs, component, entry.buffer_line()).await;
+        }
+
+        if written >= MAX_FILE_BYTES {
+            file = None;
+            let _ = tokio::fs::rename(&path, dir.join("access.log.1")).await;
+            written = 0;
+        }
+        if file.is_none() {
+            file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+                .inspect_err(|e| warn!(error=%e, "Failed to open access log"))
+                .ok();
+        }
+        let line = entry.json_line();
+        if let Some(f) = file.as_mut() {
+            let res = match f.write_all(line.as_bytes()).await {
+                Ok(()) => f.flush().await,
+                Err(e) => Err(e),
+            };
+            match res {
+                Ok(()) => written += line.len() as u64,
+                Err(e) => {
+                    warn!(error=%e, "Failed to write access log");
+                    file = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn logs_streamed_bytes_once_the_body_is_done() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let log = AccessLog { tx };
+        let chunks =
+            ["first ", "second ", "third"].map(|c| Ok::<_, ErrorCode>(Frame::data(Bytes::from(c))));
+        let body = http_body_util::StreamBody::new(futures::stream::iter(chunks)).boxed();
+        let resp = log.track(
+            Response::new(body),
+            "GET".to_string(),
+            "/stream".to_string(),
+            Some("site".to_string()),
+            "127.0.0.1".to_string(),
+            Instant::now(),
+        );
+        assert!(rx.try_recv().is_err(), "logged before the body was sent");
+
+        let sent = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(sent, "first second third");
+        let entry = rx.recv().await.unwrap();
+        assert_eq!(entry.bytes, sent.len() as u64);
+        assert_eq!(entry.status, 200);
+        assert_eq!(entry.component.as_deref(), Some("site"));
+    }
+}
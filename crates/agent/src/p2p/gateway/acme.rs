@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, OrderStatus,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+use crate::p2p::state::agent_data_dir;
+
+/// Renew certificates once they are this old (Let's Encrypt issues 90-day certs).
+const RENEW_AFTER_SECS: u64 = 60 * 24 * 60 * 60;
+
+/// Pending HTTP-01 challenges: token -> key authorization.
+static CHALLENGES: OnceLock<RwLock<HashMap<String, String>>> = OnceLock::new();
+
+fn challenges() -> &'static RwLock<HashMap<String, String>> {
+    CHALLENGES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Key authorization for an HTTP-01 token, served by the plain gateway at
+/// `/.well-known/acme-challenge/<token>`.
+pub(super) fn challenge_response(token: &str) -> Option<String> {
+    challenges().read().ok()?.get(token).cloned()
+}
+
+/// ACME client settings, enabled by `REALM_ACME_DIRECTORY`.
+///
+/// For local testing against Pebble, point the directory at
+/// `https://localhost:14000/dir` and trust Pebble's CA via `SSL_CERT_FILE`.
+#[derive(Debug, Clone)]
+pub(super) struct AcmeSettings {
+    directory_url: String,
+    contact: Option<String>,
+}
+
+impl AcmeSettings {
+    pub fn from_env() -> Option<Self> {
+        let directory_url = std::env::var("REALM_ACME_DIRECTORY")
+            .ok()
+            .filter(|v| !v.trim().is_empty())?;
+        Some(Self {
+            directory_url,
+            contact: std::env::var("REALM_ACME_CONTACT")
+                .ok()
+                .filter(|v| !v.trim().is_empty()),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IssuedMeta {
+    issued_at_unix: u64,
+    directory_url: String,
+}
+
+fn acme_dir() -> PathBuf {
+    agent_data_dir().join("tls").join("acme")
+}
+
+/// Directory holding a host's certificate. Hosts come from route specs, so
+/// anything that is not a plain DNS name (e.g. `/` or `..`) is refused.
+fn host_dir(host: &str) -> anyhow::Result<PathBuf> {
+    let valid = !host.is_empty()
+        && !host.starts_with('.')
+        && !host.contains("..")
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '*'));
+    if !valid {
+        anyhow::bail!("invalid ACME host '{}'", host);
+    }
+    Ok(acme_dir().join(host.replace('*', "_wildcard")))
+}
+
+/// Write a file readable only by the agent's user (account keys, private keys).
+async fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).await?;
+    // Files written before this was enforced keep their mode on open
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .await?;
+    }
+    file.write_all(contents).await?;
+    file.flush().await
+}
+
+fn now_unix() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Previously issued certificate and key PEM for a host, if any.
+pub(super) fn load_certificate(host: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    let dir = host_dir(host).ok()?;
+    let cert = std::fs::read(dir.join("cert.pem")).ok()?;
+    let key = std::fs::read(dir.join("key.pem")).ok()?;
+    Some((cert, key))
+}
+
+fn needs_issue(settings: &AcmeSettings, dir: &Path) -> bool {
+    if !dir.join("cert.pem").exists() || !dir.join("key.pem").exists() {
+        return true;
+    }
+    match std::fs::read(dir.join("meta.json"))
+        .ok()
+        .and_then(|b| serde_json::from_slice::<IssuedMeta>(&b).ok())
+    {
+        Some(meta) => {
+            meta.directory_url != settings.directory_url
+                || now_unix().saturating_sub(meta.issued_at_unix) > RENEW_AFTER_SECS
+        }
+        None => true,
+    }
+}
+
+async fn load_or_create_account(settings: &AcmeSettings) -> anyhow::Result<Account> {
+    let path = acme_dir().join(format!(
+        "account-{}.json",
+        &common::sha256_hex(settings.directory_url.as_bytes())[..16]
+    ));
+    if let Ok(bytes) = tokio::fs::read(&path).await {
+        let creds: AccountCredentials = serde_json::from_slice(&bytes)?;
+        return Ok(Account::from_credentials(creds).await?);
+    }
+    let contact: Vec<String> = settings
+        .contact
+        .iter()
+        .map(|c| format!("mailto:{}", c.trim_start_matches("mailto:")))
+        .collect();
+    let contact_refs: Vec<&str> = contact.iter().map(|c| c.as_str()).collect();
+    let (account, creds) = Account::create(
+        &NewAccount {
+            contact: &contact_refs,
+            terms_of_service_agreed: true,
+            only_return_existing: false,
+        },
+        &settings.directory_url,
+        None,
+    )
+    .await?;
+    tokio::fs::create_dir_all(acme_dir()).await?;
+    write_private(&path, &serde_json::to_vec_pretty(&creds)?).await?;
+    Ok(account)
+}
+
+/// Issue or renew the certificate for `host` if needed. Returns true when a new
+/// certificate was written.
+pub(super) async fn ensure_certificate(
+    settings: &AcmeSettings,
+    host: &str,
+) -> anyhow::Result<bool> {
+    if host.starts_with("*.") {
+        anyhow::bail!("wildcard hosts need DNS-01, which is not supported");
+    }
+    let dir = host_dir(host)?;
+    if !needs_issue(settings, &dir) {
+        return Ok(false);
+    }
+    info!(host=%host, directory=%settings.directory_url, "Requesting ACME certificate");
+
+    let account = load_or_create_account(settings).await?;
+    let identifiers = [Identifier::Dns(host.to_string())];
+    let mut order = account
+        .new_order(&NewOrder {
+            identifiers: &identifiers,
+        })
+        .await?;
+
+    let mut tokens = Vec::new();
+    for authz in order.authorizations().await? {
+        if matches!(authz.status, AuthorizationStatus::Valid) {
+            continue;
+        }
+        let challenge = authz
+            .challenges
+            .iter()
+            .find(|c| c.r#type == ChallengeType::Http01)
+            .ok_or_else(|| anyhow::anyhow!("no http-01 challenge offered"))?;
+        let key_auth = order.key_authorization(challenge);
+        if let Ok(mut map) = challenges().write() {
+            map.insert(challenge.token.clone(), key_auth.as_str().to_string());
+        }
+        tokens.push(challenge.token.clone());
+        order.set_challenge_ready(&challenge.url).await?;
+    }
+
+    let result = finish_order(&mut order, host).await;
+    if let Ok(mut map) = challenges().write() {
+        for t in &tokens {
+            map.remove(t);
+        }
+    }
+    let (cert_pem, key_pem) = result?;
+
+    tokio::fs::create_dir_all(&dir).await?;
+    write_private(&dir.join("key.pem"), key_pem.as_bytes()).await?;
+    tokio::fs::write(dir.join("cert.pem"), cert_pem).await?;
+    let meta = IssuedMeta {
+        issued_at_unix: now_unix(),
+        directory_url: settings.directory_url.clone(),
+    };
+    tokio::fs::write(dir.join("meta.json"), serde_json::to_vec_pretty(&meta)?).await?;
+    info!(host=%host, "ACME certificate issued");
+    Ok(true)
+}
+
+async fn finish_order(
+    order: &mut instant_acme::Order,
+    host: &str,
+) -> anyhow::Result<(String, String)> {
+    let mut delay = Duration::from_millis(250);
+    for _ in 0..10 {
+        tokio::time::sleep(delay).await;
+        let state = order.refresh().await?;
+        match state.status {
+            OrderStatus::Ready => break,
+            OrderStatus::Invalid => anyhow::bail!("ACME order became invalid"),
+            _ => delay = (delay * 2).min(Duration::from_secs(10)),
+        }
+    }
+    if !matches!(order.state().status, OrderStatus::Ready) {
+        anyhow::bail!("ACME order not ready: {:?}", order.state().status);
+    }
+
+    let key_pair = rcgen::KeyPair::generate()?;
+    let params = rcgen::CertificateParams::new(vec![host.to_string()])?;
+    let csr = params.serialize_request(&key_pair)?;
+    order.finalize(csr.der()).await?;
+
+    let mut delay = Duration::from_millis(250);
+    for _ in 0..10 {
+        if let Some(chain) = order.certificate().await? {
+            return Ok((chain, key_pair.serialize_pem()));
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(Duration::from_secs(10));
+    }
+    anyhow::bail!("ACME certificate not available after finalize")
+}
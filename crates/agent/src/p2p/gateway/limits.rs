@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use common::HttpLimits;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use hyper::body::{Body, Incoming};
+use hyper::{Request, Response, StatusCode};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+/// Request body after size enforcement. It streams, and ends with
+/// `HttpRequestBodySize` once it passes the limit.
+pub(super) type RequestBody = BoxBody<Bytes, ErrorCode>;
+
+/// Body limit for components that do not set `max_body_bytes`.
+const DEFAULT_MAX_BODY_BYTES: u64 = 16 * 1024 * 1024;
+/// Bucket count above which idle buckets are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+/// Buckets untouched for this long are full again and can be forgotten.
+const BUCKET_IDLE: Duration = Duration::from_secs(60);
+
+/// Node-wide fallback body limit, from `REALM_GATEWAY_MAX_BODY_BYTES`.
+pub(super) fn default_max_body_bytes() -> u64 {
+    static VALUE: OnceLock<u64> = OnceLock::new();
+    *VALUE.get_or_init(|| {
+        std::env::var("REALM_GATEWAY_MAX_BODY_BYTES")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_MAX_BODY_BYTES)
+    })
+}
+
+/// Why the gateway refused a request.
+pub(super) enum Rejection {
+    RateLimited(Duration),
+    ClientRateLimited(Duration),
+    Busy,
+    BodyTooLarge,
+}
+
+impl Rejection {
+    /// Label for `gateway_limited_total`; `None` when no limit was hit.
+    pub(super) fn reason(&self) -> Option<&'static str> {
+        match self {
+            Rejection::RateLimited(_) => Some("rate"),
+            Rejection::ClientRateLimited(_) => Some("client_rate"),
+            Rejection::Busy => Some("concurrency"),
+            Rejection::BodyTooLarge => Some("body_size"),
+        }
+    }
+
+    pub(super) fn into_response(self) -> Response<HyperOutgoingBody> {
+        let (status, message, retry_after) = match self {
+            Rejection::RateLimited(wait) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Rate limit exceeded",
+                Some(wait),
+            ),
+            Rejection::ClientRateLimited(wait) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Client rate limit exceeded",
+                Some(wait),
+            ),
+            Rejection::Busy => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Too many concurrent requests",
+                None,
+            ),
+            Rejection::BodyTooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Request body too large",
+                None,
+            ),
+        };
+        let mut resp = super::text_response(status, message);
+        if let Some(wait) = retry_after {
+            let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            resp.headers_mut()
+                .insert(hyper::header::RETRY_AFTER, secs.max(1).into());
+        }
+        resp
+    }
+}
+
+/// Token bucket holding up to one second of requests.
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn take(&mut self, rps: u32) -> Result<(), Duration> {
+        let rate = f64::from(rps);
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * rate).min(rate);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum BucketKey {
+    Component(String),
+    Client(String, IpAddr),
+}
+
+/// Process-wide admission state, shared by every gateway listener on this node.
+#[derive(Default)]
+pub(super) struct Limiter {
+    buckets: Mutex<HashMap<BucketKey, Bucket>>,
+    /// Concurrency semaphores keyed by component, with the size they were built for
+    slots: Mutex<HashMap<String, (u32, Arc<Semaphore>)>>,
+}
+
+pub(super) fn limiter() -> &'static Limiter {
+    static LIMITER: OnceLock<Limiter> = OnceLock::new();
+    LIMITER.get_or_init(Limiter::default)
+}
+
+impl Limiter {
+    fn take(&self, key: BucketKey, rps: u32) -> Result<(), Duration> {
+        let Ok(mut buckets) = self.buckets.lock() else {
+            return Ok(());
+        };
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|_, b| b.last.elapsed() < BUCKET_IDLE);
+        }
+        buckets
+            .entry(key)
+            .or_insert_with(|| Bucket {
+                tokens: f64::from(rps),
+                last: Instant::now(),
+            })
+            .take(rps)
+    }
+
+    /// Per-client-IP limit, applied by the gateway that accepted the connection.
+    fn admit_client(
+        &self,
+        component: &str,
+        limits: &HttpLimits,
+        client: IpAddr,
+    ) -> Result<(), Rejection> {
+        match limits.client_rate_limit_rps.filter(|r| *r > 0) {
+            Some(rps) => self
+                .take(BucketKey::Client(component.to_string(), client), rps)
+                .map_err(Rejection::ClientRateLimited),
+            None => Ok(()),
+        }
+    }
+
+    /// Component-wide rate limit and concurrency cap, applied where the component
+    /// runs. The returned permit must be held until the response is complete.
+    pub(super) fn admit_component(
+        &self,
+        component: &str,
+        limits: &HttpLimits,
+    ) -> Result<Option<OwnedSemaphorePermit>, Rejection> {
+        if let Some(rps) = limits.rate_limit_rps.filter(|r| *r > 0) {
+            self.take(BucketKey::Component(component.to_string()), rps)
+                .map_err(Rejection::RateLimited)?;
+        }
+        let Some(max) = limits.max_concurrent_requests.filter(|m| *m > 0) else {
+            return Ok(None);
+        };
+        let semaphore = {
+            let Ok(mut slots) = self.slots.lock() else {
+                return Ok(None);
+            };
+            let entry = slots
+                .entry(component.to_string())
+                .or_insert_with(|| (max, Arc::new(Semaphore::new(max as usize))));
+            // A changed cap takes effect for new requests; in-flight ones keep
+            // their permits on the old semaphore.
+            if entry.0 != max {
+                *entry = (max, Arc::new(Semaphore::new(max as usize)));
+            }
+            entry.1.clone()
+        };
+        semaphore
+            .try_acquire_owned()
+            .map(Some)
+            .map_err(|_| Rejection::Busy)
+    }
+}
+
+/// Checks made by the gateway that accepted the connection: the per-client rate
+/// limit and the body size limit.
+pub(super) fn admit_ingress(
+    component: &str,
+    limits: &HttpLimits,
+    req: Request<Incoming>,
+    remote_addr: SocketAddr,
+) -> Result<Request<RequestBody>, Rejection> {
+    limiter().admit_client(component, limits, remote_addr.ip())?;
+    let max_bytes = limits
+        .max_body_bytes
+        .filter(|b| *b > 0)
+        .unwrap_or_else(default_max_body_bytes);
+    limit_body(req, max_bytes)
+}
+
+/// Enforce `max_bytes` on the request body. A declared Content-Length over the
+/// limit is refused up front; otherwise the body streams and is cut off with
+/// `HttpRequestBodySize` as soon as it passes the limit.
+pub(super) fn limit_body(
+    req: Request<Incoming>,
+    max_bytes: u64,
+) -> Result<Request<RequestBody>, Rejection> {
+    let declared = req
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .or_else(|| req.body().size_hint().exact());
+    if declared.is_some_and(|len| len > max_bytes) {
+        return Err(Rejection::BodyTooLarge);
+    }
+    Ok(req.map(|body| {
+        Limited::new(body, max_bytes as usize)
+            .map_err(move |e| {
+                if e.is::<LengthLimitError>() {
+                    return ErrorCode::HttpRequestBodySize(Some(max_bytes));
+                }
+                match e.downcast::<hyper::Error>() {
+                    Ok(e) => wasmtime_wasi_http::hyper_request_error(*e),
+                    Err(e) => ErrorCode::InternalError(Some(e.to_string())),
+                }
+            })
+            .boxed()
+    }))
+}
+
+/// Keep `guard` alive until the response body has been fully sent or dropped.
+pub(super) fn hold_until_complete<T>(
+    resp: Response<HyperOutgoingBody>,
+    guard: T,
+) -> Response<HyperOutgoingBody>
+where
+    T: Send + Sync + 'static,
+{
+    resp.map(|body| {
+        body.map_frame(move |frame| {
+            let _held = &guard;
+            frame
+        })
+        .boxed()
+    })
+}
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use common::{JobSlotUsage, Protocol, ServiceAdvert, Status};
+use futures::StreamExt;
+use http_body_util::BodyExt;
+use hyper::header::HeaderValue;
+use hyper::service::service_fn;
+use hyper::upgrade::OnUpgrade;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use libp2p::{PeerId, StreamProtocol};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tracing::{debug, info, warn};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+use super::limits::RequestBody;
+use super::Gateway;
+use crate::p2p::metrics::Metrics;
+use crate::supervisor::Supervisor;
+
+/// Stream protocol carrying one HTTP/1.1 connection between gateways.
+pub const MESH_HTTP_PROTOCOL: StreamProtocol = StreamProtocol::new("/realm/http/1");
+
+/// Header naming the target component on forwarded requests.
+pub(super) const COMPONENT_HEADER: &str = "x-realm-component";
+/// Header telling the serving peer whether the request entered on a public
+/// listener ("public") or a loopback one ("local").
+pub(super) const INGRESS_HEADER: &str = "x-realm-ingress";
+
+/// Peers whose last status is older than this are not routed to.
+const PEER_STALE_AFTER: Duration = Duration::from_secs(20);
+/// How long a peer is skipped after a failed forward.
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+/// Upper bound on peers tried for one request.
+const MAX_ATTEMPTS: usize = 3;
+
+struct PeerServices {
+    seen: Instant,
+    /// Serialized adverts, used to detect changes between heartbeats
+    fingerprint: String,
+    services: Vec<ServiceAdvert>,
+    job_slots: Vec<JobSlotUsage>,
+}
+
+/// Which peers serve which HTTP components, built from status heartbeats.
+pub struct ServiceRegistry {
+    local_peer: PeerId,
+    peers: RwLock<HashMap<PeerId, PeerServices>>,
+    /// Peers in failure cooldown, with the time they failed
+    failed: RwLock<HashMap<PeerId, Instant>>,
+    next: AtomicUsize,
+    changes: tokio::sync::watch::Sender<u64>,
+}
+
+impl ServiceRegistry {
+    pub fn new(local_peer: PeerId) -> Self {
+        Self {
+            local_peer,
+            peers: RwLock::new(HashMap::new()),
+            failed: RwLock::new(HashMap::new()),
+            next: AtomicUsize::new(0),
+            changes: tokio::sync::watch::channel(0).0,
+        }
+    }
+
+    /// Record the status heartbeat published by `peer` (the signed gossip
+    /// source). A heartbeat describing another node is ignored, and peers of
+    /// another realm (a different trusted owner) are not recorded.
+    pub fn observe(&self, peer: PeerId, status: &Status) {
+        if status.node_id.parse::<PeerId>().ok() != Some(peer) || peer == self.local_peer {
+            return;
+        }
+        if status.trusted_owner_pub_bs58 != crate::p2p::state::load_trusted_owner() {
+            let removed = self
+                .peers
+                .write()
+                .map(|mut peers| peers.remove(&peer).is_some())
+                .unwrap_or(false);
+            if removed {
+                self.changes.send_modify(|g| *g = g.wrapping_add(1));
+            }
+            return;
+        }
+        let fingerprint = serde_json::to_string(&status.services).unwrap_or_default();
+        let changed = {
+            let Ok(mut peers) = self.peers.write() else {
+                return;
+            };
+            let changed = peers
+                .get(&peer)
+                .map(|p| p.fingerprint != fingerprint)
+                .unwrap_or(!status.services.is_empty());
+            peers.insert(
+                peer,
+                PeerServices {
+                    seen: Instant::now(),
+                    fingerprint,
+                    services: status.services.clone(),
+                    job_slots: status.job_slots.clone(),
+                },
+            );
+            changed
+        };
+        if changed {
+            self.changes.send_modify(|g| *g = g.wrapping_add(1));
+        }
+    }
+
+    /// Whether `peer` is a member of this realm: it sent a heartbeat naming
+    /// the same trusted owner and has not been pruned since. The owner key in a
+    /// heartbeat is claimed, not proven, so membership only gates traffic to
+    /// and from peers; their adverts never take precedence over local routes.
+    pub fn is_member(&self, peer: &PeerId) -> bool {
+        self.peers
+            .read()
+            .map(|peers| peers.contains_key(peer))
+            .unwrap_or(false)
+    }
+
+    /// Drop peers that stopped sending heartbeats.
+    pub fn prune(&self) {
+        let removed = match self.peers.write() {
+            Ok(mut peers) => {
+                let before = peers.len();
+                peers.retain(|_, p| p.seen.elapsed() < PEER_STALE_AFTER * 3);
+                before != peers.len()
+            }
+            Err(_) => false,
+        };
+        if let Ok(mut failed) = self.failed.write() {
+            failed.retain(|_, at| at.elapsed() < FAILURE_COOLDOWN);
+        }
+        if removed {
+            self.changes.send_modify(|g| *g = g.wrapping_add(1));
+        }
+    }
+
+    pub fn subscribe_changes(&self) -> tokio::sync::watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    /// Whether `node_id` sent a heartbeat within `window`.
+    pub fn seen_within(&self, node_id: &str, window: Duration) -> bool {
+        let Ok(peer) = node_id.parse::<PeerId>() else {
+            return false;
+        };
+        self.peers
+            .read()
+            .ok()
+            .and_then(|peers| peers.get(&peer).map(|p| p.seen.elapsed() < window))
+            .unwrap_or(false)
+    }
+
+    /// Whether a live peer advertises a free job slot for `runtime`.
+    pub fn idle_peer_for(&self, runtime: &str) -> bool {
+        let Ok(peers) = self.peers.read() else {
+            return false;
+        };
+        peers
+            .values()
+            .filter(|p| p.seen.elapsed() < PEER_STALE_AFTER)
+            .flat_map(|p| p.job_slots.iter())
+            .any(|slot| slot.runtime == runtime && slot.has_free())
+    }
+
+    /// Services advertised by live peers (deduplicated by name).
+    pub fn adverts(&self) -> Vec<ServiceAdvert> {
+        let Ok(peers) = self.peers.read() else {
+            return Vec::new();
+        };
+        let mut by_name: HashMap<String, ServiceAdvert> = HashMap::new();
+        for p in peers
+            .values()
+            .filter(|p| p.seen.elapsed() < PEER_STALE_AFTER)
+        {
+            for svc in &p.services {
+                by_name
+                    .entry(svc.name.clone())
+                    .or_insert_with(|| svc.clone());
+            }
+        }
+        let mut out: Vec<ServiceAdvert> = by_name.into_values().collect();
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        out
+    }
+
+    /// Advert for HTTP component `name` from any live peer.
+    pub fn http_advert(&self, name: &str) -> Option<ServiceAdvert> {
+        self.adverts()
+            .into_iter()
+            .find(|a| a.http && a.name == name)
+    }
+
+    /// Live peers serving `name` over HTTP, rotated round-robin.
+    pub fn providers(&self, name: &str) -> Vec<PeerId> {
+        self.pick(|s| s.http && s.name == name)
+    }
+
+    /// Live peers exposing `port`/`protocol` of component `name`, rotated round-robin.
+    pub fn port_providers(&self, name: &str, port: u16, protocol: Protocol) -> Vec<PeerId> {
+        self.pick(|s| {
+            s.name == name
+                && s.ports
+                    .iter()
+                    .any(|p| p.port == port && p.protocol == protocol)
+        })
+    }
+
+    /// Peers with a matching advert. Peers in failure cooldown are moved to the
+    /// back rather than dropped so they are still tried last.
+    fn pick(&self, matches: impl Fn(&ServiceAdvert) -> bool) -> Vec<PeerId> {
+        let mut healthy = Vec::new();
+        let mut cooling = Vec::new();
+        {
+            let Ok(peers) = self.peers.read() else {
+                return Vec::new();
+            };
+            let failed = self.failed.read().ok();
+            let mut candidates: Vec<PeerId> = peers
+                .iter()
+                .filter(|(_, p)| p.seen.elapsed() < PEER_STALE_AFTER)
+                .filter(|(_, p)| p.services.iter().any(&matches))
+                .map(|(id, _)| *id)
+                .collect();
+            candidates.sort();
+            for id in candidates {
+                let in_cooldown = failed
+                    .as_ref()
+                    .and_then(|f| f.get(&id))
+                    .map(|at| at.elapsed() < FAILURE_COOLDOWN)
+                    .unwrap_or(false);
+                if in_cooldown {
+                    cooling.push(id);
+                } else {
+                    healthy.push(id);
+                }
+            }
+        }
+        if !healthy.is_empty() {
+            let start = self.next.fetch_add(1, Ordering::Relaxed) % healthy.len();
+            healthy.rotate_left(start);
+        }
+        healthy.extend(cooling);
+        healthy
+    }
+
+    pub fn mark_failed(&self, peer: PeerId) {
+        if let Ok(mut failed) = self.failed.write() {
+            failed.insert(peer, Instant::now());
+        }
+    }
+
+    pub fn mark_ok(&self, peer: &PeerId) {
+        if let Ok(mut failed) = self.failed.write() {
+            failed.remove(peer);
+        }
+    }
+}
+
+/// Everything the gateway needs to reach components on other peers.
+#[derive(Clone)]
+pub struct MeshHandle {
+    pub registry: Arc<ServiceRegistry>,
+    pub control: libp2p_stream::Control,
+}
+
+/// Forward a request for `component` to a peer that serves it. Peers are tried in
+/// round-robin order until a connection is established; once the request has been
+/// sent, errors are returned rather than retried since the body is consumed.
+pub(super) async fn forward(
+    mesh: &MeshHandle,
+    component: &str,
+    mut req: Request<RequestBody>,
+    remote_addr: SocketAddr,
+    scheme: &'static str,
+    public: bool,
+) -> anyhow::Result<Option<Response<HyperOutgoingBody>>> {
+    let providers = mesh.registry.providers(component);
+    if providers.is_empty() {
+        return Ok(None);
+    }
+
+    let headers = req.headers_mut();
+    headers.insert(COMPONENT_HEADER, HeaderValue::from_str(component)?);
+    if let Ok(v) = HeaderValue::from_str(&remote_addr.ip().to_string()) {
+        headers.append("x-forwarded-for", v);
+    }
+    headers.insert("x-forwarded-proto", HeaderValue::from_static(scheme));
+    let ingress = if public { "public" } else { "local" };
+    headers.insert(INGRESS_HEADER, HeaderValue::from_static(ingress));
+    // The client side of an upgrade is joined to the peer's once it answers 101
+    let client_upgrade = super::websocket::is_upgrade(&req).then(|| hyper::upgrade::on(&mut req));
+
+    let mut last_err = None;
+    for peer in providers.into_iter().take(MAX_ATTEMPTS) {
+        let mut control = mesh.control.clone();
+        let stream = match control.open_stream(peer, MESH_HTTP_PROTOCOL).await {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(peer=%peer, component=%component, error=%e, "Mesh stream open failed");
+                mesh.registry.mark_failed(peer);
+                last_err = Some(anyhow::anyhow!(e.to_string()));
+                continue;
+            }
+        };
+        let (mut sender, conn) =
+            match hyper::client::conn::http1::handshake(TokioIo::new(stream.compat())).await {
+                Ok(pair) => pair,
+                Err(e) => {
+                    warn!(peer=%peer, component=%component, error=%e, "Mesh HTTP handshake failed");
+                    mesh.registry.mark_failed(peer);
+                    last_err = Some(e.into());
+                    continue;
+                }
+            };
+        tokio::spawn(async move {
+            if let Err(e) = conn.with_upgrades().await {
+                debug!(peer=%peer, error=%e, "Mesh HTTP connection closed");
+            }
+        });
+
+        debug!(peer=%peer, component=%component, "Forwarding request over mesh");
+        return match sender.send_request(req).await {
+            Ok(mut resp) => {
+                mesh.registry.mark_ok(&peer);
+                if let (Some(client), StatusCode::SWITCHING_PROTOCOLS) =
+                    (client_upgrade, resp.status())
+                {
+                    tokio::spawn(splice_upgrade(client, hyper::upgrade::on(&mut resp), peer));
+                }
+                Ok(Some(resp.map(|body| {
+                    body.map_err(wasmtime_wasi_http::hyper_response_error)
+                        .boxed()
+                })))
+            }
+            Err(e) => {
+                mesh.registry.mark_failed(peer);
+                Err(e.into())
+            }
+        };
+    }
+    Err(last_err.unwrap_or_else(|| anyhow::anyhow!("no reachable peer for '{}'", component)))
+}
+
+/// Copy bytes between an upgraded client connection and the peer's upgraded stream.
+async fn splice_upgrade(client: OnUpgrade, upstream: OnUpgrade, peer: PeerId) {
+    let (client, upstream) = match tokio::try_join!(client, upstream) {
+        Ok(pair) => pair,
+        Err(e) => {
+            warn!(peer=%peer, error=%e, "Mesh upgrade failed");
+            return;
+        }
+    };
+    let mut client = TokioIo::new(client);
+    let mut upstream = TokioIo::new(upstream);
+    if let Err(e) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
+        debug!(peer=%peer, error=%e, "Mesh upgraded connection closed");
+    }
+}
+
+/// Accept mesh HTTP streams from realm peers and serve them from local
+/// components only.
+pub async fn serve_mesh_streams(
+    supervisor: Arc<Supervisor>,
+    metrics: Option<Arc<Metrics>>,
+    registry: Arc<ServiceRegistry>,
+    mut control: libp2p_stream::Control,
+) {
+    let mut incoming = match control.accept(MESH_HTTP_PROTOCOL) {
+        Ok(incoming) => incoming,
+        Err(e) => {
+            warn!(error=%e, "Mesh HTTP protocol already registered");
+            return;
+        }
+    };
+    info!(protocol=%MESH_HTTP_PROTOCOL, "Serving mesh HTTP streams");
+    let gateway = Gateway::new(supervisor, metrics, None, "http", false);
+
+    while let Some((peer, stream)) = incoming.next().await {
+        if !registry.is_member(&peer) {
+            warn!(peer=%peer, "Rejected mesh HTTP stream from a peer outside this realm");
+            continue;
+        }
+        let gateway = gateway.clone();
+        tokio::spawn(async move {
+            let service =
+                service_fn(move |req| super::handle_mesh_request(req, gateway.clone(), peer));
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream.compat()), service)
+                .with_upgrades()
+                .await
+            {
+                debug!(peer=%peer, error=%e, "Mesh HTTP stream error");
+            }
+        });
+    }
+}
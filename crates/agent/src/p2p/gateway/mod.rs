@@ -0,0 +1,590 @@
+mod access_log;
+mod acme;
+mod limits;
+mod mesh;
+mod routes;
+mod tls;
+mod websocket;
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode, Uri};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tracing::{error, info, warn};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+use crate::p2p::metrics::Metrics;
+use crate::runner::{invoke_http_component_hyper, RequestDeadline};
+use crate::supervisor::{DesiredComponent, Supervisor};
+use access_log::AccessLog;
+use common::Visibility;
+use limits::{Rejection, RequestBody};
+pub use mesh::{serve_mesh_streams, MeshHandle, ServiceRegistry};
+use routes::RouteTable;
+use tls::TlsManager;
+
+/// Shared state for one gateway listener.
+#[derive(Clone)]
+struct Gateway {
+    supervisor: Arc<Supervisor>,
+    metrics: Option<Arc<Metrics>>,
+    /// Remote peers serving components this node does not run
+    mesh: Option<MeshHandle>,
+    routes: Arc<RwLock<Arc<RouteTable>>>,
+    /// Scheme presented to components ("http" or "https")
+    scheme: &'static str,
+    /// Bound beyond loopback; serves only public components
+    public: bool,
+    access_log: AccessLog,
+}
+
+impl Gateway {
+    fn new(
+        supervisor: Arc<Supervisor>,
+        metrics: Option<Arc<Metrics>>,
+        mesh: Option<MeshHandle>,
+        scheme: &'static str,
+        public: bool,
+    ) -> Self {
+        let access_log = AccessLog::shared(supervisor.logs());
+        let gateway = Self {
+            supervisor,
+            metrics,
+            mesh,
+            scheme,
+            public,
+            routes: Arc::new(RwLock::new(Arc::new(RouteTable::default()))),
+            access_log,
+        };
+        gateway.spawn_route_reloader();
+        gateway
+    }
+
+    /// Rebuild the routing table whenever the supervisor's desired set or the
+    /// services advertised by peers change.
+    fn spawn_route_reloader(&self) {
+        let supervisor = self.supervisor.clone();
+        let registry = self.mesh.as_ref().map(|m| m.registry.clone());
+        let routes = self.routes.clone();
+        let mut changes = supervisor.subscribe_changes();
+        let mut remote_changes = registry.as_ref().map(|r| r.subscribe_changes());
+        tokio::spawn(async move {
+            loop {
+                let remote = registry.as_ref().map(|r| r.adverts()).unwrap_or_default();
+                let table =
+                    RouteTable::from_sources(&supervisor.get_desired_snapshot().await, &remote);
+                info!(routes = table.len(), "Gateway routing table reloaded");
+                if let Ok(mut guard) = routes.write() {
+                    *guard = Arc::new(table);
+                }
+                let remote_changed = async {
+                    match remote_changes.as_mut() {
+                        Some(rx) => rx.changed().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    res = changes.changed() => {
+                        if res.is_err() {
+                            break;
+                        }
+                    }
+                    res = remote_changed => {
+                        if res.is_err() {
+                            remote_changes = None;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    fn route_table(&self) -> Arc<RouteTable> {
+        self.routes.read().map(|t| t.clone()).unwrap_or_default()
+    }
+}
+
+/// Whether a listener on `bind_addr` is reachable from other hosts.
+fn is_public_bind(bind_addr: &str) -> bool {
+    bind_addr
+        .parse::<SocketAddr>()
+        .map(|addr| !addr.ip().is_loopback())
+        .unwrap_or(true)
+}
+
+/// Whether a listener may serve a component: public listeners only reach
+/// components published with public visibility.
+fn visible(public: bool, visibility: Option<&Visibility>) -> bool {
+    !public || matches!(visibility, Some(Visibility::Public))
+}
+
+async fn bind_listener(bind_addr: &str) -> Option<TcpListener> {
+    let addr: SocketAddr = match bind_addr.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!(address=%bind_addr, error=%e, "Invalid gateway bind address");
+            return None;
+        }
+    };
+
+    match TcpListener::bind(addr).await {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            error!(address=%bind_addr, error=%e, "Failed to bind HTTP gateway");
+            None
+        }
+    }
+}
+
+pub async fn serve_gateway(
+    supervisor: Arc<Supervisor>,
+    metrics: Option<Arc<Metrics>>,
+    mesh: Option<MeshHandle>,
+    bind_addr: &str,
+) {
+    let Some(listener) = bind_listener(bind_addr).await else {
+        return;
+    };
+
+    info!(address=%bind_addr, "HTTP gateway listening");
+    let gateway = Gateway::new(supervisor, metrics, mesh, "http", is_public_bind(bind_addr));
+
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!(error=%e, "Gateway accept failed");
+                continue;
+            }
+        };
+
+        tokio::spawn(serve_connection(stream, gateway.clone(), remote_addr));
+    }
+}
+
+/// Whether this node has TLS routes to serve, from its own components or
+/// from peers it fronts; the same hosts the HTTPS listener loads certificates for.
+pub async fn wants_tls(supervisor: &Supervisor, registry: &ServiceRegistry) -> bool {
+    tls::has_tls_hosts(supervisor, Some(registry)).await
+}
+
+/// TLS-terminating gateway. Certificates are selected by SNI from the `tls`
+/// settings on component routes and hot-reloaded when specs or files change.
+pub async fn serve_gateway_tls(
+    supervisor: Arc<Supervisor>,
+    metrics: Option<Arc<Metrics>>,
+    mesh: Option<MeshHandle>,
+    bind_addr: &str,
+) {
+    let manager = TlsManager::new(
+        supervisor.clone(),
+        mesh.as_ref().map(|m| m.registry.clone()),
+    );
+    let config = match manager.server_config() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            error!(error=%e, "Failed to build TLS configuration");
+            return;
+        }
+    };
+    manager.spawn();
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+    let Some(listener) = bind_listener(bind_addr).await else {
+        return;
+    };
+
+    info!(address=%bind_addr, "HTTPS gateway listening");
+    let gateway = Gateway::new(
+        supervisor,
+        metrics,
+        mesh,
+        "https",
+        is_public_bind(bind_addr),
+    );
+
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!(error=%e, "Gateway accept failed");
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let gateway = gateway.clone();
+        tokio::spawn(async move {
+            match acceptor.accept(stream).await {
+                Ok(tls_stream) => serve_connection(tls_stream, gateway, remote_addr).await,
+                Err(e) => warn!(error=%e, remote=%remote_addr, "TLS handshake failed"),
+            }
+        });
+    }
+}
+
+async fn serve_connection<S>(stream: S, gateway: Gateway, remote_addr: SocketAddr)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |req| handle_request(req, gateway.clone(), remote_addr));
+
+    // Negotiates HTTP/1.1 (with keep-alive) or HTTP/2 per connection
+    let io = TokioIo::new(stream);
+    if let Err(e) = auto::Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(io, service)
+        .await
+    {
+        warn!(error=%e, remote=%remote_addr, "HTTP connection error");
+    }
+}
+
+async fn handle_request(
+    req: Request<hyper::body::Incoming>,
+    gateway: Gateway,
+    remote_addr: SocketAddr,
+) -> Result<Response<HyperOutgoingBody>, Infallible> {
+    let start_time = std::time::Instant::now();
+
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+    let host = request_host(&req);
+
+    // HTTP-01 challenges for certificates this node is requesting
+    if let Some(token) = path.strip_prefix("/.well-known/acme-challenge/") {
+        if let Some(key_auth) = acme::challenge_response(token) {
+            return Ok(text_response(StatusCode::OK, key_auth));
+        }
+    }
+
+    // Declared routes first, then the legacy /component_name/rest layout
+    let (component_name, component_path) =
+        match gateway.route_table().resolve(host.as_deref(), &path) {
+            Some(m) => (m.component, m.path),
+            None => {
+                let mut segments = path.trim_start_matches('/').splitn(2, '/');
+                let name = segments.next().unwrap_or("").to_string();
+                let rest = format!("/{}", segments.next().unwrap_or(""));
+                (name, rest)
+            }
+        };
+
+    let local = if component_name.is_empty() {
+        None
+    } else {
+        gateway.supervisor.get_component(&component_name).await
+    };
+    // Limits of a remote component come from its advert; its host enforces the rest
+    let (http_limits, visibility) = match &local {
+        Some(desired) => (
+            Some(desired.spec.http_limits.clone().unwrap_or_default()),
+            desired.spec.visibility.clone(),
+        ),
+        None => gateway
+            .mesh
+            .as_ref()
+            .and_then(|m| m.registry.http_advert(&component_name))
+            .map(|a| (Some(a.http_limits.unwrap_or_default()), a.visibility))
+            .unwrap_or_default(),
+    };
+    // Components this listener may not serve look the same as unknown ones
+    let http_limits = http_limits.filter(|_| visible(gateway.public, visibility.as_ref()));
+
+    let response = match &http_limits {
+        _ if component_name.is_empty() => {
+            text_response(StatusCode::NOT_FOUND, "Component name required")
+        }
+        None => text_response(
+            StatusCode::NOT_FOUND,
+            format!("Component '{}' not found", component_name),
+        ),
+        Some(http_limits) => {
+            match limits::admit_ingress(&component_name, http_limits, req, remote_addr) {
+                Ok(req) => {
+                    let req = rewrite_uri(req, gateway.scheme, &component_path);
+                    dispatch(&gateway, &component_name, local.as_ref(), req, remote_addr).await
+                }
+                Err(rejection) => reject(&gateway, &component_name, rejection),
+            }
+        }
+    };
+
+    // Only components this node or the mesh knows get metrics and log buffer
+    // entries, so arbitrary paths cannot create unbounded label sets.
+    let known = http_limits.is_some().then_some(component_name);
+    record_metrics(&gateway, start_time, &response, known.as_deref());
+    Ok(gateway.access_log.track(
+        response,
+        method,
+        path,
+        known,
+        remote_addr.to_string(),
+        start_time,
+    ))
+}
+
+/// Invoke a local component, or forward to a peer that advertises it.
+async fn dispatch(
+    gateway: &Gateway,
+    component_name: &str,
+    local: Option<&DesiredComponent>,
+    req: Request<RequestBody>,
+    remote_addr: SocketAddr,
+) -> Response<HyperOutgoingBody> {
+    if let Some(desired) = local {
+        return serve_local(gateway, component_name, desired, req).await;
+    }
+    let forwarded = match &gateway.mesh {
+        Some(mesh) => {
+            mesh::forward(
+                mesh,
+                component_name,
+                req,
+                remote_addr,
+                gateway.scheme,
+                gateway.public,
+            )
+            .await
+        }
+        None => Ok(None),
+    };
+    match forwarded {
+        Ok(Some(resp)) => resp,
+        Ok(None) => text_response(
+            StatusCode::NOT_FOUND,
+            format!("Component '{}' not found", component_name),
+        ),
+        Err(e) => {
+            warn!(component=%component_name, error=%e, "Mesh forwarding failed");
+            text_response(StatusCode::BAD_GATEWAY, "No reachable replica")
+        }
+    }
+}
+
+/// Serve a request forwarded by another peer's gateway. Only local components are
+/// considered so requests never bounce around the mesh. Requests that entered on
+/// a public listener only reach public components, as on that listener.
+async fn handle_mesh_request(
+    mut req: Request<hyper::body::Incoming>,
+    gateway: Gateway,
+    peer: libp2p::PeerId,
+) -> Result<Response<HyperOutgoingBody>, Infallible> {
+    let start_time = std::time::Instant::now();
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+    let headers = req.headers_mut();
+    let component_name = headers
+        .remove(mesh::COMPONENT_HEADER)
+        .and_then(|v| v.to_str().ok().map(|s| s.to_string()))
+        .unwrap_or_default();
+    let public = headers
+        .remove(mesh::INGRESS_HEADER)
+        .is_none_or(|v| v.as_bytes() != b"local");
+    // The client as seen by the forwarding gateway, then the peer itself
+    let remote = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .last()
+        .and_then(|v| v.to_str().ok())
+        .map(|client| format!("{client} via {peer}"))
+        .unwrap_or_else(|| peer.to_string());
+
+    let local = gateway
+        .supervisor
+        .get_component(&component_name)
+        .await
+        .filter(|d| visible(public, d.spec.visibility.as_ref()));
+    let known = local.is_some().then(|| component_name.clone());
+    let response = match local {
+        Some(desired) => {
+            let max_body = desired
+                .spec
+                .http_limits
+                .as_ref()
+                .and_then(|l| l.max_body_bytes)
+                .filter(|b| *b > 0)
+                .unwrap_or_else(limits::default_max_body_bytes);
+            match limits::limit_body(req, max_body) {
+                Ok(req) => serve_local(&gateway, &component_name, &desired, req).await,
+                Err(rejection) => reject(&gateway, &component_name, rejection),
+            }
+        }
+        None => text_response(
+            StatusCode::NOT_FOUND,
+            format!("Component '{}' not served by this peer", component_name),
+        ),
+    };
+
+    record_metrics(&gateway, start_time, &response, known.as_deref());
+    Ok(gateway
+        .access_log
+        .track(response, method, path, known, remote, start_time))
+}
+
+/// Apply the component-wide rate limit and concurrency cap, then invoke. The
+/// concurrency permit is released when the response body completes, or when the
+/// connection closes for WebSocket upgrades.
+async fn serve_local(
+    gateway: &Gateway,
+    component_name: &str,
+    desired: &DesiredComponent,
+    req: Request<RequestBody>,
+) -> Response<HyperOutgoingBody> {
+    let http_limits = desired.spec.http_limits.clone().unwrap_or_default();
+    match limits::limiter().admit_component(component_name, &http_limits) {
+        Ok(permit) if websocket::is_upgrade(&req) => {
+            websocket::serve(gateway, component_name, desired, req, permit).await
+        }
+        Ok(Some(permit)) => limits::hold_until_complete(
+            invoke_local(
+                gateway,
+                component_name,
+                desired,
+                req,
+                RequestDeadline::Invocation,
+            )
+            .await,
+            permit,
+        ),
+        Ok(None) => {
+            invoke_local(
+                gateway,
+                component_name,
+                desired,
+                req,
+                RequestDeadline::Invocation,
+            )
+            .await
+        }
+        Err(rejection) => reject(gateway, component_name, rejection),
+    }
+}
+
+fn reject(
+    gateway: &Gateway,
+    component_name: &str,
+    rejection: Rejection,
+) -> Response<HyperOutgoingBody> {
+    if let (Some(m), Some(reason)) = (&gateway.metrics, rejection.reason()) {
+        m.observe_gateway_limited(component_name, reason);
+    }
+    rejection.into_response()
+}
+
+async fn invoke_local(
+    gateway: &Gateway,
+    component_name: &str,
+    desired: &DesiredComponent,
+    req: Request<RequestBody>,
+    scope: RequestDeadline,
+) -> Response<HyperOutgoingBody> {
+    let Some(cache) = gateway.supervisor.http_cache() else {
+        return text_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "HTTP component runtime unavailable",
+        );
+    };
+    match invoke_http_component_hyper(&cache, desired, req, scope).await {
+        Ok(resp) => resp,
+        Err(e) if e.is::<tokio::time::error::Elapsed>() => {
+            warn!(component=%component_name, "HTTP component request timed out");
+            text_response(StatusCode::GATEWAY_TIMEOUT, "Component timed out")
+        }
+        Err(e) => {
+            warn!(component=%component_name, error=%e, "HTTP component invocation failed");
+            text_response(StatusCode::INTERNAL_SERVER_ERROR, "Component error")
+        }
+    }
+}
+
+/// Update metrics (latency is time to response head; bodies may keep streaming)
+fn record_metrics(
+    gateway: &Gateway,
+    start_time: std::time::Instant,
+    response: &Response<HyperOutgoingBody>,
+    component_name: Option<&str>,
+) {
+    if let Some(m) = &gateway.metrics {
+        use std::sync::atomic::Ordering;
+        let latency = start_time.elapsed();
+        m.gateway_requests_total.fetch_add(1, Ordering::Relaxed);
+        m.gateway_last_latency_ms
+            .store(latency.as_millis() as u64, Ordering::Relaxed);
+        if !response.status().is_success() {
+            m.gateway_errors_total.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(name) = component_name {
+            m.observe_gateway_request(name, response.status().as_u16(), latency);
+        }
+    }
+}
+
+/// Host the client asked for: URI authority (HTTP/2) or the Host header.
+fn request_host<B>(req: &Request<B>) -> Option<String> {
+    req.uri()
+        .authority()
+        .map(|a| a.host().to_string())
+        .or_else(|| {
+            req.headers()
+                .get(hyper::header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .map(|h| h.to_string())
+        })
+}
+
+/// Rewrite the request URI to the component-relative path, keeping the query string
+/// and carrying the client's Host as the authority seen by the guest.
+fn rewrite_uri<B>(req: Request<B>, scheme: &str, component_path: &str) -> Request<B> {
+    let (mut parts, body) = req.into_parts();
+    let authority = parts
+        .uri
+        .authority()
+        .map(|a| a.to_string())
+        .or_else(|| {
+            parts
+                .headers
+                .get(hyper::header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .map(|h| h.to_string())
+        })
+        .unwrap_or_else(|| "component".to_string());
+    let mut path_and_query = format!("/{}", component_path.trim_start_matches('/'));
+    if let Some(q) = parts.uri.query() {
+        path_and_query.push('?');
+        path_and_query.push_str(q);
+    }
+    parts.uri = Uri::builder()
+        .scheme(scheme)
+        .authority(authority.as_str())
+        .path_and_query(path_and_query.as_str())
+        .build()
+        .or_else(|_| {
+            Uri::builder()
+                .scheme(scheme)
+                .authority("component")
+                .path_and_query(path_and_query.as_str())
+                .build()
+        })
+        .unwrap_or_else(|_| Uri::from_static("http://component/"));
+    Request::from_parts(parts, body)
+}
+
+fn text_response<T: Into<Bytes>>(status: StatusCode, body: T) -> Response<HyperOutgoingBody> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/plain; charset=utf-8")
+        .body(
+            Full::new(body.into())
+                .map_err(|never: Infallible| match never {})
+                .boxed(),
+        )
+        .unwrap()
+}
@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use common::{RouteSpec, ServiceAdvert};
+
+use crate::supervisor::DesiredComponent;
+
+/// One flattened route pointing at a component.
+#[derive(Debug, Clone)]
+struct RouteEntry {
+    component: String,
+    /// Lowercased host patterns; empty matches any host
+    hosts: Vec<String>,
+    path_prefix: String,
+    strip_prefix: bool,
+    priority: i32,
+    /// Advertised by a peer rather than declared by a local component
+    remote: bool,
+}
+
+/// Result of resolving a request against the table.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct RouteMatch {
+    pub component: String,
+    /// Path (without query) to hand to the component
+    pub path: String,
+}
+
+/// Gateway routing table built from the supervisor's desired components and the
+/// services advertised by other peers.
+///
+/// Local routes are tried before remote ones, then in order of priority, then
+/// host-specific before any-host, then longest prefix. Requests that match no
+/// route fall back to the legacy `/component_name/rest` layout.
+///
+/// Peers are not authenticated beyond their claimed realm, so an advert never
+/// overrides a local route: remote routes are only tried when no local route
+/// matches and the path does not name a local component.
+#[derive(Debug, Default)]
+pub(super) struct RouteTable {
+    entries: Vec<RouteEntry>,
+    local: BTreeSet<String>,
+}
+
+impl RouteTable {
+    /// Local components take precedence over remote adverts with the same name.
+    pub fn from_sources(
+        desired: &BTreeMap<String, DesiredComponent>,
+        remote: &[ServiceAdvert],
+    ) -> Self {
+        let local = desired
+            .values()
+            .map(|c| (c.name.as_str(), c.spec.routes.as_slice(), false));
+        let remote = remote
+            .iter()
+            .filter(|a| a.http && !desired.contains_key(&a.name))
+            .map(|a| (a.name.as_str(), a.routes.as_slice(), true));
+        Self::from_routes(local.chain(remote))
+    }
+
+    fn from_routes<'a>(components: impl Iterator<Item = (&'a str, &'a [RouteSpec], bool)>) -> Self {
+        let mut local = BTreeSet::new();
+        let mut entries: Vec<RouteEntry> = Vec::new();
+        for (name, routes, remote) in components {
+            if !remote {
+                local.insert(name.to_string());
+            }
+            entries.extend(routes.iter().map(|r| RouteEntry {
+                component: name.to_string(),
+                hosts: r.hosts.iter().map(|h| h.to_ascii_lowercase()).collect(),
+                path_prefix: normalize_prefix(&r.path_prefix),
+                strip_prefix: r.strip_prefix,
+                priority: r.priority,
+                remote,
+            }));
+        }
+        entries.sort_by(|a, b| {
+            a.remote
+                .cmp(&b.remote)
+                .then_with(|| b.priority.cmp(&a.priority))
+                .then_with(|| a.hosts.is_empty().cmp(&b.hosts.is_empty()))
+                .then_with(|| b.path_prefix.len().cmp(&a.path_prefix.len()))
+                .then_with(|| a.component.cmp(&b.component))
+        });
+        Self { entries, local }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Resolve a request by Host header and path.
+    pub fn resolve(&self, host: Option<&str>, path: &str) -> Option<RouteMatch> {
+        let host = host.map(strip_port).map(|h| h.to_ascii_lowercase());
+        let first_segment = path.trim_start_matches('/').split('/').next().unwrap_or("");
+        let names_local = self.local.contains(first_segment);
+        for entry in &self.entries {
+            if entry.remote && names_local {
+                break;
+            }
+            if !entry.hosts.is_empty() {
+                let Some(h) = host.as_deref() else { continue };
+                if !entry.hosts.iter().any(|pat| host_matches(pat, h)) {
+                    continue;
+                }
+            }
+            if !prefix_matches(&entry.path_prefix, path) {
+                continue;
+            }
+            let path = if entry.strip_prefix && entry.path_prefix != "/" {
+                let rest = &path[entry.path_prefix.len()..];
+                if rest.is_empty() {
+                    "/".to_string()
+                } else {
+                    rest.to_string()
+                }
+            } else {
+                path.to_string()
+            };
+            return Some(RouteMatch {
+                component: entry.component.clone(),
+                path,
+            });
+        }
+        None
+    }
+}
+
+fn normalize_prefix(prefix: &str) -> String {
+    let trimmed = prefix.trim().trim_end_matches('/');
+    if trimmed.is_empty() {
+        "/".to_string()
+    } else if trimmed.starts_with('/') {
+        trimmed.to_string()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+/// Prefixes match on path-segment boundaries: `/hook` matches `/hook` and
+/// `/hook/x`, but not `/hooks`.
+fn prefix_matches(prefix: &str, path: &str) -> bool {
+    if prefix == "/" {
+        return true;
+    }
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host
+            .strip_suffix(suffix)
+            .map(|head| head.ends_with('.') && head.len() > 1)
+            .unwrap_or(false),
+        None => pattern == host,
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    // Leave bracketed IPv6 literals alone apart from a trailing :port
+    if let Some(end) = host.rfind(']') {
+        return &host[..=end];
+    }
+    host.split(':').next().unwrap_or(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(hosts: &[&str], prefix: &str, strip: bool, priority: i32) -> RouteSpec {
+        RouteSpec {
+            hosts: hosts.iter().map(|h| h.to_string()).collect(),
+            path_prefix: prefix.to_string(),
+            strip_prefix: strip,
+            priority,
+            tls: None,
+        }
+    }
+
+    fn table(routes: &[(&str, Vec<RouteSpec>)]) -> RouteTable {
+        RouteTable::from_routes(routes.iter().map(|(n, r)| (*n, r.as_slice(), false)))
+    }
+
+    #[test]
+    fn host_routes_win_over_catch_all() {
+        let t = table(&[
+            ("site", vec![route(&[], "/", false, 0)]),
+            (
+                "ci-controller",
+                vec![route(&["ci.example.internal"], "/hook", false, 0)],
+            ),
+        ]);
+        let m = t
+            .resolve(Some("ci.example.internal:8080"), "/hook")
+            .unwrap();
+        assert_eq!(m.component, "ci-controller");
+        assert_eq!(m.path, "/hook");
+        assert_eq!(
+            t.resolve(Some("www.example.com"), "/hook")
+                .unwrap()
+                .component,
+            "site"
+        );
+        assert_eq!(t.resolve(None, "/").unwrap().component, "site");
+    }
+
+    #[test]
+    fn prefixes_match_on_segments_and_strip() {
+        let t = table(&[("api", vec![route(&[], "/api/", true, 0)])]);
+        assert_eq!(t.resolve(None, "/api/users").unwrap().path, "/users");
+        assert_eq!(t.resolve(None, "/api").unwrap().path, "/");
+        assert!(t.resolve(None, "/apis").is_none());
+    }
+
+    #[test]
+    fn priority_and_wildcard_hosts() {
+        let t = table(&[
+            ("a", vec![route(&["*.example.com"], "/", false, 0)]),
+            ("b", vec![route(&[], "/", false, 10)]),
+        ]);
+        assert_eq!(
+            t.resolve(Some("x.example.com"), "/").unwrap().component,
+            "b"
+        );
+        let t = table(&[("a", vec![route(&["*.example.com"], "/", false, 0)])]);
+        assert!(t.resolve(Some("example.com"), "/").is_none());
+        assert_eq!(
+            t.resolve(Some("X.Example.com"), "/").unwrap().component,
+            "a"
+        );
+    }
+
+    #[test]
+    fn remote_routes_never_override_local_ones() {
+        let local = [
+            ("site", vec![route(&["www.example.com"], "/", false, 0)]),
+            ("api", vec![]),
+        ];
+        let remote = [("intruder", vec![route(&[], "/", false, 1000)])];
+        let t = RouteTable::from_routes(
+            local
+                .iter()
+                .map(|(n, r)| (*n, r.as_slice(), false))
+                .chain(remote.iter().map(|(n, r)| (*n, r.as_slice(), true))),
+        );
+        assert_eq!(
+            t.resolve(Some("www.example.com"), "/login")
+                .unwrap()
+                .component,
+            "site"
+        );
+        // The legacy layout for a local component is not shadowed either
+        assert!(t.resolve(Some("other.example.com"), "/api/users").is_none());
+        assert_eq!(
+            t.resolve(Some("other.example.com"), "/").unwrap().component,
+            "intruder"
+        );
+    }
+}
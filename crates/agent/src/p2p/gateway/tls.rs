@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tokio_rustls::rustls::{
+    self,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use tracing::{debug, info, warn};
+
+use super::acme::{self, AcmeSettings};
+use super::mesh::ServiceRegistry;
+use crate::secrets::resolve_secret_ref;
+use crate::supervisor::Supervisor;
+
+/// How often certificate sources are re-read to pick up rotated files.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+/// How often ACME certificates are checked for renewal.
+const ACME_CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// SNI certificate resolver keyed by route host pattern (`a.example.com` or `*.example.com`).
+#[derive(Debug, Default)]
+pub(super) struct CertResolver {
+    certs: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let name = hello.server_name()?.to_ascii_lowercase();
+        let certs = self.certs.read().ok()?;
+        if let Some(ck) = certs.get(&name) {
+            return Some(ck.clone());
+        }
+        let (_, parent) = name.split_once('.')?;
+        certs.get(&format!("*.{}", parent)).cloned()
+    }
+}
+
+/// Where a host's certificate comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CertSource {
+    Refs { cert: String, key: String },
+    Acme,
+}
+
+/// Certificate source per host, from the TLS settings on local component
+/// routes and on routes advertised by peers. Peers may only name `cas:`
+/// material or ACME; a `file:` ref from a peer would read this node's files.
+/// Hosts declared by a local route never take a peer's settings.
+async fn desired_sources(
+    supervisor: &Supervisor,
+    registry: Option<&ServiceRegistry>,
+) -> HashMap<String, CertSource> {
+    let mut out = HashMap::new();
+    let mut routes: Vec<(common::RouteSpec, bool)> = registry
+        .map(|r| {
+            r.adverts()
+                .into_iter()
+                .flat_map(|a| a.routes)
+                .map(|route| (route, true))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut local_hosts = std::collections::HashSet::new();
+    for comp in supervisor.get_desired_snapshot().await.values() {
+        for route in &comp.spec.routes {
+            local_hosts.extend(route.hosts.iter().map(|h| h.to_ascii_lowercase()));
+            routes.push((route.clone(), false));
+        }
+    }
+    for (route, remote) in &routes {
+        let Some(tls) = &route.tls else { continue };
+        let source = if tls.acme {
+            CertSource::Acme
+        } else if let (Some(cert), Some(key)) = (&tls.cert, &tls.key) {
+            if *remote && !(is_cas_ref(cert) && is_cas_ref(key)) {
+                debug!(hosts=?route.hosts, "Ignoring non-cas certificate refs advertised by a peer");
+                continue;
+            }
+            CertSource::Refs {
+                cert: cert.clone(),
+                key: key.clone(),
+            }
+        } else {
+            continue;
+        };
+        for host in route.hosts.iter().map(|h| h.to_ascii_lowercase()) {
+            if *remote && local_hosts.contains(&host) {
+                continue;
+            }
+            out.insert(host, source.clone());
+        }
+    }
+    out
+}
+
+fn is_cas_ref(reference: &str) -> bool {
+    reference.trim().starts_with("cas:")
+}
+
+/// Whether any host needs a certificate, i.e. the HTTPS listener has work.
+pub(super) async fn has_tls_hosts(
+    supervisor: &Supervisor,
+    registry: Option<&ServiceRegistry>,
+) -> bool {
+    !desired_sources(supervisor, registry).await.is_empty()
+}
+
+/// Keeps the resolver in sync with the TLS settings declared on component routes.
+pub(super) struct TlsManager {
+    supervisor: Arc<Supervisor>,
+    /// Routes advertised by peers, for edge nodes fronting remote components
+    registry: Option<Arc<ServiceRegistry>>,
+    resolver: Arc<CertResolver>,
+    acme: Option<AcmeSettings>,
+    /// host -> fingerprint of the PEM material currently loaded
+    loaded: tokio::sync::Mutex<HashMap<String, String>>,
+}
+
+impl TlsManager {
+    pub fn new(supervisor: Arc<Supervisor>, registry: Option<Arc<ServiceRegistry>>) -> Arc<Self> {
+        Arc::new(Self {
+            supervisor,
+            registry,
+            resolver: Arc::new(CertResolver::default()),
+            acme: AcmeSettings::from_env(),
+            loaded: tokio::sync::Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn server_config(&self) -> anyhow::Result<rustls::ServerConfig> {
+        let mut cfg = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(self.resolver.clone());
+        cfg.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(cfg)
+    }
+
+    /// Reload certificates on desired-state changes and periodically (file rotation),
+    /// and drive ACME issuance/renewal when configured.
+    pub fn spawn(self: &Arc<Self>) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut changes = this.supervisor.subscribe_changes();
+            let mut tick = tokio::time::interval(RELOAD_INTERVAL);
+            tick.tick().await;
+            loop {
+                this.reload().await;
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    res = changes.changed() => {
+                        if res.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        if let Some(settings) = self.acme.clone() {
+            let this = self.clone();
+            tokio::spawn(async move {
+                let mut tick = tokio::time::interval(ACME_CHECK_INTERVAL);
+                let mut changes = this.supervisor.subscribe_changes();
+                tick.tick().await;
+                loop {
+                    let hosts: Vec<String> = this
+                        .desired_sources()
+                        .await
+                        .into_iter()
+                        .filter(|(_, src)| *src == CertSource::Acme)
+                        .map(|(host, _)| host)
+                        .collect();
+                    let mut issued = false;
+                    for host in hosts {
+                        match acme::ensure_certificate(&settings, &host).await {
+                            Ok(true) => issued = true,
+                            Ok(false) => {}
+                            Err(e) => {
+                                warn!(host=%host, error=%e, "ACME certificate request failed")
+                            }
+                        }
+                    }
+                    if issued {
+                        this.reload().await;
+                    }
+                    tokio::select! {
+                        _ = tick.tick() => {}
+                        res = changes.changed() => {
+                            if res.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    async fn desired_sources(&self) -> HashMap<String, CertSource> {
+        desired_sources(&self.supervisor, self.registry.as_deref()).await
+    }
+
+    async fn reload(&self) {
+        let sources = self.desired_sources().await;
+        let mut loaded = self.loaded.lock().await;
+        let mut next: HashMap<String, Arc<CertifiedKey>> = HashMap::new();
+        let current = self
+            .resolver
+            .certs
+            .read()
+            .map(|c| c.clone())
+            .unwrap_or_default();
+
+        for (host, source) in sources {
+            let pem = match &source {
+                CertSource::Refs { cert, key } => {
+                    match (resolve_secret_ref(cert), resolve_secret_ref(key)) {
+                        (Ok(c), Ok(k)) => Some((c, k)),
+                        (Err(e), _) | (_, Err(e)) => {
+                            warn!(host=%host, error=%e, "TLS certificate source unavailable");
+                            None
+                        }
+                    }
+                }
+                CertSource::Acme => acme::load_certificate(&host),
+            };
+            let Some((cert_pem, key_pem)) = pem else {
+                continue;
+            };
+            let fingerprint = common::sha256_hex(&[cert_pem.as_slice(), &key_pem].concat());
+            if loaded.get(&host) == Some(&fingerprint) {
+                if let Some(existing) = current.get(&host) {
+                    next.insert(host, existing.clone());
+                    continue;
+                }
+            }
+            match certified_key(&cert_pem, &key_pem) {
+                Ok(ck) => {
+                    info!(host=%host, "Loaded TLS certificate");
+                    loaded.insert(host.clone(), fingerprint);
+                    next.insert(host, Arc::new(ck));
+                }
+                Err(e) => warn!(host=%host, error=%e, "Invalid TLS certificate or key"),
+            }
+        }
+
+        loaded.retain(|host, _| next.contains_key(host));
+        if let Ok(mut certs) = self.resolver.certs.write() {
+            *certs = next;
+        }
+    }
+}
+
+fn certified_key(cert_pem: &[u8], key_pem: &[u8]) -> anyhow::Result<CertifiedKey> {
+    let chain: Vec<CertificateDer<'static>> =
+        rustls_pemfile::certs(&mut &cert_pem[..]).collect::<Result<_, _>>()?;
+    if chain.is_empty() {
+        anyhow::bail!("no certificates in PEM");
+    }
+    let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut &key_pem[..])?
+        .ok_or_else(|| anyhow::anyhow!("no private key in PEM"))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)?;
+    Ok(CertifiedKey::new(chain, signing_key))
+}
@@ -0,0 +1,269 @@
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::{Body, Frame};
+use hyper::header::{self, HeaderValue};
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, warn};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+use super::limits::RequestBody;
+use super::Gateway;
+use crate::runner::RequestDeadline;
+use crate::supervisor::DesiredComponent;
+
+/// Response header a component sets to choose how messages are framed on its bodies.
+const FRAMING_HEADER: &str = "realm-websocket-framing";
+/// Largest message accepted from a component before the connection is closed.
+const MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+/// Client messages buffered while the component is not reading.
+const INBOUND_QUEUE: usize = 64;
+
+/// Whether `req` asks for a WebSocket upgrade (RFC 6455 over HTTP/1.1).
+pub(super) fn is_upgrade<B>(req: &Request<B>) -> bool {
+    let has_token = |name: header::HeaderName, token: &str| {
+        req.headers().get_all(name).iter().any(|v| {
+            v.to_str()
+                .map(|s| s.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+                .unwrap_or(false)
+        })
+    };
+    req.method() == hyper::Method::GET
+        && has_token(header::CONNECTION, "upgrade")
+        && has_token(header::UPGRADE, "websocket")
+        && req.headers().contains_key(header::SEC_WEBSOCKET_KEY)
+}
+
+/// How WebSocket messages map onto the component's request and response bodies.
+#[derive(Clone, Copy)]
+enum Framing {
+    /// One message per line; client messages get a trailing `\n`, component
+    /// output is split on `\n` into text messages.
+    Lines,
+    /// Each message is a 1-byte kind (1 = text, 2 = binary), a 4-byte big-endian
+    /// length, then the payload, in both directions.
+    LengthPrefixed,
+}
+
+impl Framing {
+    fn from_response<B>(resp: &Response<B>) -> Self {
+        match resp
+            .headers()
+            .get(FRAMING_HEADER)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(v) if v.eq_ignore_ascii_case("length-prefixed") => Framing::LengthPrefixed,
+            _ => Framing::Lines,
+        }
+    }
+
+    fn encode(self, msg: Message) -> Option<Bytes> {
+        let (kind, payload): (u8, Vec<u8>) = match msg {
+            Message::Text(t) => (1, t.into_bytes()),
+            Message::Binary(b) => (2, b),
+            _ => return None,
+        };
+        let mut out = BytesMut::with_capacity(payload.len() + 5);
+        match self {
+            Framing::Lines => {
+                out.put_slice(&payload);
+                out.put_u8(b'\n');
+            }
+            Framing::LengthPrefixed => {
+                out.put_u8(kind);
+                out.put_u32(payload.len() as u32);
+                out.put_slice(&payload);
+            }
+        }
+        Some(out.freeze())
+    }
+
+    /// Pop complete messages from `buf`, leaving any partial message in place.
+    fn decode(self, buf: &mut BytesMut) -> Vec<Message> {
+        let mut out = Vec::new();
+        match self {
+            Framing::Lines => {
+                while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                    let raw = buf.split_to(pos + 1);
+                    let line = &raw[..pos];
+                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+                    out.push(Message::Text(String::from_utf8_lossy(line).into_owned()));
+                }
+            }
+            Framing::LengthPrefixed => {
+                while buf.len() >= 5 {
+                    let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+                    if buf.len() < 5 + len {
+                        break;
+                    }
+                    let kind = buf.get_u8();
+                    buf.advance(4);
+                    let payload = buf.split_to(len);
+                    out.push(match kind {
+                        1 => Message::Text(String::from_utf8_lossy(&payload).into_owned()),
+                        _ => Message::Binary(payload.to_vec()),
+                    });
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Request body fed with client messages for the lifetime of the connection.
+struct ChannelBody(mpsc::Receiver<Bytes>);
+
+impl Body for ChannelBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, ErrorCode>>> {
+        self.0.poll_recv(cx).map(|m| m.map(|b| Ok(Frame::data(b))))
+    }
+}
+
+/// Hand a WebSocket upgrade to a local component. The component sees the upgrade
+/// request with a request body that streams client messages, and accepts by
+/// answering 2xx; its response body is then streamed back as messages. Any other
+/// status is returned to the client unchanged. `guard` (e.g. a concurrency permit)
+/// is held until the connection closes.
+pub(super) async fn serve<G>(
+    gateway: &Gateway,
+    component_name: &str,
+    desired: &DesiredComponent,
+    mut req: Request<RequestBody>,
+    guard: G,
+) -> Response<HyperOutgoingBody>
+where
+    G: Send + 'static,
+{
+    let accept_key = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_KEY)
+        .map(|k| derive_accept_key(k.as_bytes()));
+    let on_upgrade = hyper::upgrade::on(&mut req);
+
+    let (tx, rx) = mpsc::channel(INBOUND_QUEUE);
+    let (parts, _) = req.into_parts();
+    let component_req = Request::from_parts(parts, ChannelBody(rx).boxed());
+    let resp = super::invoke_local(
+        gateway,
+        component_name,
+        desired,
+        component_req,
+        RequestDeadline::ResponseHead,
+    )
+    .await;
+    if !resp.status().is_success() && resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return resp;
+    }
+
+    let framing = Framing::from_response(&resp);
+    let protocol = resp.headers().get(header::SEC_WEBSOCKET_PROTOCOL).cloned();
+    let outbound = resp.into_body();
+    let name = component_name.to_string();
+    tokio::spawn(async move {
+        let _guard = guard;
+        match on_upgrade.await {
+            Ok(upgraded) => {
+                let ws =
+                    WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None)
+                        .await;
+                bridge(ws, tx, outbound, framing, &name).await;
+            }
+            Err(e) => warn!(component=%name, error=%e, "WebSocket upgrade failed"),
+        }
+    });
+
+    let mut builder = Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::CONNECTION, "upgrade")
+        .header(header::UPGRADE, "websocket");
+    if let Some(key) = accept_key.and_then(|k| HeaderValue::from_str(&k).ok()) {
+        builder = builder.header(header::SEC_WEBSOCKET_ACCEPT, key);
+    }
+    if let Some(protocol) = protocol {
+        builder = builder.header(header::SEC_WEBSOCKET_PROTOCOL, protocol);
+    }
+    builder
+        .body(
+            Empty::new()
+                .map_err(|never: Infallible| match never {})
+                .boxed(),
+        )
+        .unwrap()
+}
+
+/// Pump messages between the client and the component until either side ends.
+async fn bridge<S>(
+    ws: WebSocketStream<S>,
+    inbound: mpsc::Sender<Bytes>,
+    mut outbound: HyperOutgoingBody,
+    framing: Framing,
+    component_name: &str,
+) where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut sink, mut stream) = ws.split();
+    let mut inbound = Some(inbound);
+    let mut pending = BytesMut::new();
+
+    loop {
+        tokio::select! {
+            msg = stream.next(), if inbound.is_some() => match msg {
+                Some(Ok(Message::Close(_))) | None => {
+                    // Ending the request body tells the component the client left
+                    inbound = None;
+                }
+                Some(Ok(msg)) => {
+                    if let (Some(tx), Some(bytes)) = (&inbound, framing.encode(msg)) {
+                        if tx.send(bytes).await.is_err() {
+                            inbound = None;
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    debug!(component=%component_name, error=%e, "WebSocket read failed");
+                    return;
+                }
+            },
+            frame = outbound.frame() => match frame {
+                Some(Ok(frame)) => {
+                    let Ok(data) = frame.into_data() else {
+                        continue;
+                    };
+                    pending.extend_from_slice(&data);
+                    for msg in framing.decode(&mut pending) {
+                        if sink.send(msg).await.is_err() {
+                            return;
+                        }
+                    }
+                    if pending.len() > MAX_MESSAGE_BYTES {
+                        warn!(component=%component_name, "WebSocket message from component too large");
+                        break;
+                    }
+                }
+                Some(Err(e)) => {
+                    debug!(component=%component_name, error=?e, "Component WebSocket body failed");
+                    break;
+                }
+                None => break,
+            },
+        }
+    }
+    let _ = sink.send(Message::Close(None)).await;
+}
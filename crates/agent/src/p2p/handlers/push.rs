@@ -141,6 +141,18 @@ pub async fn handle_push_package(
             mounts: clone_mounts(&pkg.unsigned.mounts),
             ports: clone_ports(&pkg.unsigned.ports),
             visibility: pkg.unsigned.visibility.clone(),
+            request_timeout_ms: pkg.unsigned.request_timeout_ms,
+            routes: pkg.unsigned.routes.clone(),
+            http_limits: pkg.unsigned.http_limits.clone(),
+            egress: pkg.unsigned.egress.clone(),
+            args: pkg.unsigned.args.clone(),
+            env: pkg.unsigned.env.clone(),
+            secret_env: pkg.unsigned.secret_env.clone(),
+            stdin: pkg.unsigned.stdin.clone(),
+            capabilities: pkg.unsigned.capabilities.clone(),
+            kv: pkg.unsigned.kv.clone(),
+            subscriptions: pkg.unsigned.subscriptions.clone(),
+            schedule: pkg.unsigned.schedule.clone(),
             target_peer_ids: pkg.unsigned.target_peer_ids.clone(),
             target_tags: pkg.unsigned.target_tags.clone(),
             start: pkg.unsigned.start,
@@ -1,22 +1,294 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use crate::job_manager::JobManager;
 use crate::p2p::metrics;
 use crate::p2p::metrics::push_log;
-use common::Command;
+use crate::p2p::storage;
+use common::{Command, RestartPolicy};
 use uuid::Uuid;
 
-pub async fn execute_oneshot_job_with_broadcast(
-    job_mgr: std::sync::Arc<JobManager>,
+use super::gateway::ServiceRegistry;
+use super::jobs_queue::{JobSlots, SlotPermit, BUSY_CLAIM_DELAY};
+use super::jobs_service::{self, Stop};
+
+/// Everything accepting and starting a job needs from the agent loop.
+#[derive(Clone)]
+pub struct JobDispatch {
+    pub job_mgr: Arc<JobManager>,
+    pub logs: metrics::SharedLogs,
+    pub roles: Vec<String>,
+    pub node_id: String,
+    pub content_index: Arc<tokio::sync::Mutex<HashMap<String, HashSet<String>>>>,
+    pub storage_tx: tokio::sync::mpsc::UnboundedSender<storage::StorageRequest>,
+    pub broadcast_tx: tokio::sync::mpsc::UnboundedSender<Command>,
+    pub tx: tokio::sync::mpsc::UnboundedSender<Result<String, String>>,
+    pub slots: Arc<JobSlots>,
+    /// Peers' advertised job slots
+    pub registry: Arc<ServiceRegistry>,
+}
+
+/// Record a submitted job and, when this node is eligible and nobody has
+/// claimed it yet, claim and run it.
+pub async fn accept_job(
+    d: JobDispatch,
+    origin_node_id: String,
     job_id: String,
     job: common::JobSpec,
-    logs: metrics::SharedLogs,
-    tx: tokio::sync::mpsc::UnboundedSender<Result<String, String>>,
-    storage: Option<crate::p2p::storage::P2PStorage>,
-    broadcast_tx: tokio::sync::mpsc::UnboundedSender<Command>,
-    node_id: String,
 ) {
-    let job_runtime = job.runtime.clone();
+    let job_mgr = d.job_mgr.clone();
+    let node_id = d.node_id.clone();
+    // Insert job locally with provided id
+    let _ = job_mgr
+        .submit_job(
+            job.clone(),
+            Some(origin_node_id.clone()),
+            Some(job_id.clone()),
+        )
+        .await;
+    // Check if this node is eligible to execute the job
+    let mut eligible = true;
+    if let Some(t) = &job.targeting {
+        if let Some(p) = &t.platform {
+            let host = format!("{}/{}", std::env::consts::OS, std::env::consts::ARCH);
+            if &host != p {
+                eligible = false;
+            }
+        }
+        if eligible && !t.tags.is_empty() {
+            eligible = t.tags.iter().any(|tag| d.roles.contains(tag));
+        }
+        if eligible && !t.node_ids.is_empty() {
+            eligible = t.node_ids.iter().any(|id| id == &node_id);
+        }
+    }
+
+    if !eligible {
+        let _ = push_log(
+            &d.logs,
+            "system",
+            format!("Job {} not eligible for this node, ignoring", job.name),
+        )
+        .await;
+        return;
+    }
+
+    // This node is eligible - check if job is already accepted by another node
+    if let Some(existing) = job_mgr.get_job(&job_id).await {
+        if existing.assigned_node.is_some() {
+            let _ = push_log(
+                &d.logs,
+                "system",
+                format!("Job {} already accepted by another node", job.name),
+            )
+            .await;
+            return;
+        }
+    }
+
+    // Without a free slot here, give an idle peer the chance to claim it first
+    let runtime = job.runtime.kind();
+    if !matches!(job.job_type, common::JobType::Recurring)
+        && !d.slots.has_free(runtime)
+        && d.registry.idle_peer_for(runtime)
+    {
+        tokio::time::sleep(BUSY_CLAIM_DELAY).await;
+        let claimed = job_mgr
+            .get_job(&job_id)
+            .await
+            .is_some_and(|j| j.assigned_node.is_some());
+        if claimed {
+            let _ = push_log(
+                &d.logs,
+                "system",
+                format!("Job {} left to a node with a free {runtime} slot", job.name),
+            )
+            .await;
+            return;
+        }
+    }
+
+    // Accept the job and broadcast acceptance
+    // Mark this node as the assigned executor
+    let _ = job_mgr.assign_job(&job_id, &node_id).await;
+
+    // Broadcast job acceptance to prevent other nodes from taking it
+    let acceptance_msg = Command::JobAccepted {
+        job_id: job_id.clone(),
+        assigned_node: node_id.clone(),
+        message_id: Uuid::new_v4().to_string(),
+    };
+    let _ = d.broadcast_tx.send(acceptance_msg);
+
+    let _ = push_log(
+        &d.logs,
+        "system",
+        format!("Job accepted: {} ({})", job.name, job_id),
+    )
+    .await;
+
+    // Locality preference: if job has digest and others have it, delay start here
+    let mut locality_delay_ms: u64 = 0;
+    let job_digest: Option<String> = match &job.runtime {
+        common::JobRuntime::Wasm { sha256_hex, .. } => sha256_hex.clone(),
+        common::JobRuntime::Native { sha256_hex, .. } => sha256_hex.clone(),
+        common::JobRuntime::Qemu { sha256_hex, .. } => sha256_hex.clone(),
+        common::JobRuntime::Oci { image, .. } => image.strip_prefix("cas:").map(str::to_string),
+        common::JobRuntime::QemuSystem { image, .. } => {
+            image.strip_prefix("cas:").map(str::to_string)
+        }
+    };
+    if let Some(dg) = &job_digest {
+        let store = crate::storage::ContentStore::open();
+        let has_local = store.has(dg);
+        if !has_local {
+            let peers_with = {
+                let map = d.content_index.lock().await;
+                map.get(dg).map(|s| s.len()).unwrap_or(0)
+            };
+            if peers_with > 0 {
+                let h = common::sha256_hex(node_id.as_bytes());
+                let nib = u64::from_str_radix(&h[..4], 16).unwrap_or(0);
+                locality_delay_ms = 500 + (nib % 1500);
+            }
+        }
+    }
+
+    // Handle different job types
+    match &job.job_type {
+        common::JobType::OneShot => {
+            let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
+            let run_job_id = job_id.clone();
+            // Spawn in a separate task to avoid blocking the main event loop
+            let handle = tokio::spawn(execute_oneshot_job_with_broadcast(
+                d,
+                run_job_id,
+                job,
+                cancel_rx,
+                Duration::from_millis(locality_delay_ms),
+            ));
+            job_mgr
+                .register_running_job(job_id, handle, cancel_tx)
+                .await;
+        }
+        common::JobType::Recurring => {
+            // The claiming node schedules the runs; each run is a one-shot job
+            mark_started(&d, &job_id).await;
+            let _ = job_mgr
+                .add_job_log(
+                    &job_id,
+                    "info".to_string(),
+                    "Scheduling runs from this node".to_string(),
+                )
+                .await;
+        }
+        common::JobType::Service => {
+            start_service_job(d, job_id, job, Duration::from_millis(locality_delay_ms)).await;
+        }
+    }
+}
+
+/// Mark a job started on this node and announce it.
+async fn mark_started(d: &JobDispatch, job_id: &str) {
+    let _ = d.job_mgr.start_job(job_id).await;
+    let _ = d
+        .job_mgr
+        .add_job_log(
+            job_id,
+            "info".to_string(),
+            "Job execution started on this node".to_string(),
+        )
+        .await;
+
+    // Broadcast job started status
+    let start_msg = Command::JobStarted {
+        job_id: job_id.to_string(),
+        assigned_node: d.node_id.clone(),
+        message_id: Uuid::new_v4().to_string(),
+    };
+    let _ = d.broadcast_tx.send(start_msg);
+
+    // Observability: log start
+    let _ = push_log(&d.logs, "system", format!("job started: {}", job_id)).await;
+}
+
+/// Wait for a slot of the job's runtime, queueing the job while none is
+/// free, then mark it started. `None` when the job is cancelled while queued.
+async fn take_slot(
+    d: &JobDispatch,
+    job_id: &str,
+    job: &common::JobSpec,
+    cancel_rx: &mut tokio::sync::oneshot::Receiver<()>,
+) -> Option<SlotPermit> {
+    let runtime = job.runtime.kind();
+    if !d.slots.has_free(runtime) {
+        let ahead = d.slots.queued(runtime).len();
+        let _ = d
+            .job_mgr
+            .queue_job(
+                job_id,
+                format!("Queued: all {runtime} slots are busy, {ahead} job(s) already waiting"),
+            )
+            .await;
+        let _ = push_log(&d.logs, "system", format!("job queued: {}", job_id)).await;
+    }
+    let permit = tokio::select! {
+        permit = d.slots.acquire(job_id, runtime, job.priority, job.preemptible) => permit,
+        _ = &mut *cancel_rx => return None,
+    };
+    mark_started(d, job_id).await;
+    Some(permit)
+}
+
+/// Spawn the supervisor task of a service job and register it for
+/// cancellation. A job already supervised here is left alone.
+pub async fn start_service_job(
+    d: JobDispatch,
+    job_id: String,
+    job: common::JobSpec,
+    delay: Duration,
+) {
+    let job_mgr = d.job_mgr.clone();
+    if job_mgr.is_running_here(&job_id).await {
+        let _ = job_mgr
+            .add_job_log(
+                &job_id,
+                "info".to_string(),
+                "Service already running on this node".to_string(),
+            )
+            .await;
+        return;
+    }
+    // Create cancellation channel for service jobs
+    let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
+
+    let service_job_id = job_id.clone();
+    let handle = tokio::spawn(execute_service_job(
+        d,
+        service_job_id,
+        job,
+        cancel_rx,
+        delay,
+    ));
+
+    // Register the running job for cancellation support
+    job_mgr
+        .register_running_job(job_id, handle, cancel_tx)
+        .await;
+}
+
+/// Run a job's runtime once. `cancel_rx` stops the run early.
+async fn run_runtime(
+    job_mgr: &JobManager,
+    job_id: &str,
+    job: &common::JobSpec,
+    logs: &metrics::SharedLogs,
+    cancel_rx: Option<&mut tokio::sync::oneshot::Receiver<()>>,
+    storage: Option<crate::p2p::storage::P2PStorage>,
+) -> Result<String, String> {
     let label = format!("job:{}", job.name);
-    match job_runtime {
+    match job.runtime.clone() {
         common::JobRuntime::Wasm {
             source,
             sha256_hex,
@@ -24,87 +296,48 @@ pub async fn execute_oneshot_job_with_broadcast(
             fuel,
             epoch_ms,
             mounts,
+            egress,
+            args,
+            env,
+            secret_env,
+            stdin,
         } => {
-            let result = super::jobs_wasm::execute_wasm_job(
-                &job_mgr,
-                &job_id,
-                &job,
+            super::jobs_wasm::execute_wasm_job(
+                job_mgr,
+                job_id,
+                job,
                 &source,
                 sha256_hex,
                 memory_mb,
                 fuel,
                 epoch_ms,
                 mounts,
-                &logs,
-                None,
-                storage.clone(),
+                egress,
+                crate::runner::GuestEnv {
+                    args,
+                    env,
+                    secret_env,
+                    stdin,
+                },
+                logs,
+                cancel_rx,
+                storage,
             )
-            .await;
-            match result {
-                Ok(success_msg) => {
-                    let _ = job_mgr.complete_job(&job_id, 0).await;
-                    let _ = broadcast_tx.send(Command::JobCompleted {
-                        job_id: job_id.clone(),
-                        assigned_node: node_id.clone(),
-                        exit_code: 0,
-                        message_id: Uuid::new_v4().to_string(),
-                    });
-                    let _ = tx.send(Ok(success_msg));
-                }
-                Err(error_msg) => {
-                    let _ = job_mgr.fail_job(&job_id, error_msg.clone()).await;
-                    let _ = broadcast_tx.send(Command::JobFailed {
-                        job_id: job_id.clone(),
-                        assigned_node: node_id.clone(),
-                        error: error_msg.clone(),
-                        message_id: Uuid::new_v4().to_string(),
-                    });
-                    let _ = tx.send(Err(error_msg));
-                }
-            }
+            .await
         }
         common::JobRuntime::Native {
             binary,
             sha256_hex,
             args,
             env,
+            sandbox,
         } => {
-            push_log(&logs, &label, "dispatch: native".to_string()).await;
-            let result = super::jobs_native::execute_native_job(
-                &job_mgr,
-                &job_id,
-                &job,
-                &binary,
-                sha256_hex,
-                args,
-                env,
-                &logs,
-                None,
-                storage.clone(),
+            push_log(logs, &label, "dispatch: native".to_string()).await;
+            super::jobs_native::execute_native_job(
+                job_mgr, job_id, job, &binary, sha256_hex, args, env, sandbox, logs, cancel_rx,
+                storage,
             )
-            .await;
-            match result {
-                Ok(success_msg) => {
-                    let _ = job_mgr.complete_job(&job_id, 0).await;
-                    let _ = broadcast_tx.send(Command::JobCompleted {
-                        job_id: job_id.clone(),
-                        assigned_node: node_id.clone(),
-                        exit_code: 0,
-                        message_id: Uuid::new_v4().to_string(),
-                    });
-                    let _ = tx.send(Ok(success_msg));
-                }
-                Err(error_msg) => {
-                    let _ = job_mgr.fail_job(&job_id, error_msg.clone()).await;
-                    let _ = broadcast_tx.send(Command::JobFailed {
-                        job_id: job_id.clone(),
-                        assigned_node: node_id.clone(),
-                        error: error_msg.clone(),
-                        message_id: Uuid::new_v4().to_string(),
-                    });
-                    let _ = tx.send(Err(error_msg));
-                }
-            }
+            .await
         }
         common::JobRuntime::Qemu {
             binary,
@@ -113,125 +346,243 @@ pub async fn execute_oneshot_job_with_broadcast(
             env,
             target_platform,
             qemu_binary,
+            sysroot,
         } => {
-            push_log(&logs, &label, "dispatch: qemu".to_string()).await;
-            let result = super::jobs_qemu::execute_qemu_job(
-                &job_mgr,
-                &job_id,
-                &job,
+            push_log(logs, &label, "dispatch: qemu".to_string()).await;
+            super::jobs_qemu::execute_qemu_job(
+                job_mgr,
+                job_id,
+                job,
                 &binary,
                 sha256_hex,
                 args,
                 env,
                 target_platform,
                 qemu_binary,
-                &logs,
-                None,
-                storage.clone(),
+                sysroot,
+                logs,
+                cancel_rx,
+                storage,
             )
-            .await;
-            match result {
-                Ok(success_msg) => {
-                    let _ = job_mgr.complete_job(&job_id, 0).await;
-                    let _ = broadcast_tx.send(Command::JobCompleted {
-                        job_id: job_id.clone(),
-                        assigned_node: node_id.clone(),
-                        exit_code: 0,
-                        message_id: Uuid::new_v4().to_string(),
-                    });
-                    let _ = tx.send(Ok(success_msg));
-                }
-                Err(error_msg) => {
-                    let _ = job_mgr.fail_job(&job_id, error_msg.clone()).await;
-                    let _ = broadcast_tx.send(Command::JobFailed {
-                        job_id: job_id.clone(),
-                        assigned_node: node_id.clone(),
-                        error: error_msg.clone(),
-                        message_id: Uuid::new_v4().to_string(),
-                    });
-                    let _ = tx.send(Err(error_msg));
+            .await
+        }
+        common::JobRuntime::Oci {
+            image,
+            entrypoint,
+            args,
+            env,
+            mounts,
+            network,
+        } => {
+            push_log(logs, &label, "dispatch: oci".to_string()).await;
+            super::jobs_oci::execute_oci_job(
+                job_mgr, job_id, job, &image, entrypoint, args, env, mounts, network, logs,
+                cancel_rx, storage,
+            )
+            .await
+        }
+        common::JobRuntime::QemuSystem {
+            image,
+            arch,
+            args,
+            env,
+            ports,
+            network,
+            qemu_binary,
+            qemu_args,
+        } => {
+            push_log(logs, &label, "dispatch: qemu-system".to_string()).await;
+            super::jobs_qemu_system::execute_qemu_system_job(
+                job_mgr,
+                job_id,
+                job,
+                &image,
+                arch,
+                args,
+                env,
+                ports,
+                network,
+                qemu_binary,
+                qemu_args,
+                logs,
+                cancel_rx,
+                storage,
+            )
+            .await
+        }
+    }
+}
+
+/// Run a one-shot job once a slot is free. A preempted run gives up its
+/// slot and starts over from the queue.
+pub async fn execute_oneshot_job_with_broadcast(
+    d: JobDispatch,
+    job_id: String,
+    job: common::JobSpec,
+    mut cancel_rx: tokio::sync::oneshot::Receiver<()>,
+    start_delay: Duration,
+) {
+    let storage = Some(storage::P2PStorage::new(d.storage_tx.clone()));
+    let mut first = true;
+    let result = loop {
+        let Some(mut permit) = take_slot(&d, &job_id, &job, &mut cancel_rx).await else {
+            return;
+        };
+        if std::mem::take(&mut first) && !start_delay.is_zero() {
+            tokio::time::sleep(start_delay).await;
+        }
+        let (stop_tx, mut stop_rx) = tokio::sync::oneshot::channel();
+        let run = run_runtime(
+            &d.job_mgr,
+            &job_id,
+            &job,
+            &d.logs,
+            Some(&mut stop_rx),
+            storage.clone(),
+        );
+        tokio::pin!(run);
+        let ended = tokio::select! {
+            res = &mut run => Ok(res),
+            _ = &mut cancel_rx => Err(Stop::Cancelled),
+            reason = permit.preempted() => Err(Stop::Preempted(reason)),
+        };
+        match ended {
+            Ok(res) => break res,
+            Err(stop) => {
+                let _ = stop_tx.send(());
+                let _ = (&mut run).await;
+                match stop {
+                    Stop::Preempted(reason) => {
+                        let _ = d.job_mgr.record_attempt(&job_id, reason).await;
+                        drop(permit);
+                    }
+                    // cancel_job already recorded the outcome
+                    _ => return,
                 }
             }
         }
+    };
+    let job_mgr = &d.job_mgr;
+    job_mgr.unregister_running_job(&job_id).await;
+    // A cancelled run already has its final status
+    if job_mgr
+        .get_job(&job_id)
+        .await
+        .is_some_and(|j| j.status == common::JobStatus::Cancelled)
+    {
+        return;
+    }
+    match result {
+        Ok(success_msg) => {
+            let _ = job_mgr.complete_job(&job_id, 0).await;
+            let _ = d.broadcast_tx.send(Command::JobCompleted {
+                job_id: job_id.clone(),
+                assigned_node: d.node_id.clone(),
+                exit_code: 0,
+                message_id: Uuid::new_v4().to_string(),
+            });
+            let _ = d.tx.send(Ok(success_msg));
+        }
+        Err(error_msg) => {
+            let _ = job_mgr.fail_job(&job_id, error_msg.clone()).await;
+            let _ = d.broadcast_tx.send(Command::JobFailed {
+                job_id: job_id.clone(),
+                assigned_node: d.node_id.clone(),
+                error: error_msg.clone(),
+                message_id: Uuid::new_v4().to_string(),
+            });
+            let _ = d.tx.send(Err(error_msg));
+        }
     }
 }
 
+/// Keep a service job running until it is cancelled, taken over by another
+/// node, or ends under its restart policy. Each run gets its own stop
+/// channel so a failed health check restarts the run without ending the job.
+/// The slot is held for as long as the service is supervised, unless a
+/// higher-priority job preempts it.
 pub async fn execute_service_job(
-    job_mgr: std::sync::Arc<JobManager>,
+    d: JobDispatch,
     job_id: String,
     job: common::JobSpec,
-    logs: metrics::SharedLogs,
-    tx: tokio::sync::mpsc::UnboundedSender<Result<String, String>>,
     mut cancel_rx: tokio::sync::oneshot::Receiver<()>,
-    storage: Option<crate::p2p::storage::P2PStorage>,
+    start_delay: Duration,
 ) {
-    let job_runtime = job.runtime.clone();
+    let job_mgr = d.job_mgr.clone();
+    let logs = d.logs.clone();
+    let tx = d.tx.clone();
+    let storage = Some(storage::P2PStorage::new(d.storage_tx.clone()));
+    let Some(mut permit) = take_slot(&d, &job_id, &job, &mut cancel_rx).await else {
+        return;
+    };
+    if !start_delay.is_zero() {
+        tokio::time::sleep(start_delay).await;
+    }
+    let service = job.service.clone().unwrap_or_default();
     let label = format!("job:{}", job.name);
-    match job_runtime {
-        common::JobRuntime::Wasm {
-            source,
-            sha256_hex,
-            memory_mb,
-            fuel,
-            epoch_ms,
-            mounts,
-        } => {
-            let result = super::jobs_wasm::execute_wasm_job(
-                &job_mgr,
-                &job_id,
-                &job,
-                &source,
-                sha256_hex,
-                memory_mb,
-                fuel,
-                epoch_ms,
-                mounts,
-                &logs,
-                Some(&mut cancel_rx),
-                storage.clone(),
-            )
-            .await;
-            match result {
-                Ok(success_msg) => {
-                    let _ = job_mgr
-                        .add_job_log(
-                            &job_id,
-                            "info".to_string(),
-                            "Service job completed normally".to_string(),
-                        )
-                        .await;
-                    let _ = job_mgr.complete_job(&job_id, 0).await;
-                    let _ = tx.send(Ok(success_msg));
-                }
-                Err(error_msg) => {
-                    let _ = job_mgr.fail_job(&job_id, error_msg.clone()).await;
-                    let _ = tx.send(Err(error_msg));
+    // Consecutive failed runs
+    let mut failures: u32 = 0;
+    loop {
+        let started = Instant::now();
+        let (stop_tx, mut stop_rx) = tokio::sync::oneshot::channel();
+        let run = run_runtime(
+            &job_mgr,
+            &job_id,
+            &job,
+            &logs,
+            Some(&mut stop_rx),
+            storage.clone(),
+        );
+        tokio::pin!(run);
+        let ended = tokio::select! {
+            res = &mut run => Ok(res),
+            _ = &mut cancel_rx => Err(Stop::Cancelled),
+            reason = jobs_service::unhealthy(service.health.as_ref()) => Err(Stop::Unhealthy(reason)),
+            node = jobs_service::displaced(&job_mgr, &job_id) => Err(Stop::Displaced(node)),
+            reason = permit.preempted() => Err(Stop::Preempted(reason)),
+        };
+        let result = match ended {
+            Ok(res) => res,
+            Err(stop) => {
+                let _ = stop_tx.send(());
+                let _ = (&mut run).await;
+                match stop {
+                    // cancel_job already recorded the outcome
+                    Stop::Cancelled => return,
+                    Stop::Displaced(node) => {
+                        let _ = job_mgr
+                            .add_job_log(
+                                &job_id,
+                                "warn".to_string(),
+                                format!("Service taken over by node {node}; stopped the copy here"),
+                            )
+                            .await;
+                        break;
+                    }
+                    Stop::Unhealthy(reason) => Err(reason),
+                    // Requeue without counting a restart
+                    Stop::Preempted(reason) => {
+                        let _ = job_mgr.record_attempt(&job_id, reason).await;
+                        drop(permit);
+                        permit = match take_slot(&d, &job_id, &job, &mut cancel_rx).await {
+                            Some(permit) => permit,
+                            None => return,
+                        };
+                        continue;
+                    }
                 }
             }
-            job_mgr.unregister_running_job(&job_id).await;
-        }
-        common::JobRuntime::Native {
-            binary,
-            sha256_hex,
-            args,
-            env,
-        } => {
-            push_log(&logs, &label, "dispatch: native".to_string()).await;
-            let result = super::jobs_native::execute_native_job(
-                &job_mgr,
-                &job_id,
-                &job,
-                &binary,
-                sha256_hex,
-                args,
-                env,
-                &logs,
-                Some(&mut cancel_rx),
-                storage.clone(),
-            )
-            .await;
+        };
+        let _ = tx.send(result.clone());
+
+        let restart = match service.restart {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => result.is_err(),
+            RestartPolicy::Never => false,
+        };
+        if !restart {
             match result {
-                Ok(success_msg) => {
+                Ok(_) => {
                     let _ = job_mgr
                         .add_job_log(
                             &job_id,
@@ -240,57 +591,49 @@ pub async fn execute_service_job(
                         )
                         .await;
                     let _ = job_mgr.complete_job(&job_id, 0).await;
-                    let _ = tx.send(Ok(success_msg));
                 }
                 Err(error_msg) => {
-                    let _ = job_mgr.fail_job(&job_id, error_msg.clone()).await;
-                    let _ = tx.send(Err(error_msg));
+                    let _ = job_mgr.fail_job(&job_id, error_msg).await;
                 }
             }
-            job_mgr.unregister_running_job(&job_id).await;
+            break;
         }
-        common::JobRuntime::Qemu {
-            binary,
-            sha256_hex,
-            args,
-            env,
-            target_platform,
-            qemu_binary,
-        } => {
-            push_log(&logs, &label, "dispatch: qemu".to_string()).await;
-            let result = super::jobs_qemu::execute_qemu_job(
-                &job_mgr,
+
+        if started.elapsed() >= jobs_service::STABLE_AFTER {
+            failures = 0;
+        }
+        let reason = match &result {
+            Ok(_) => "exited".to_string(),
+            Err(e) => {
+                failures += 1;
+                e.clone()
+            }
+        };
+        if let Some(max) = service.max_restarts {
+            if failures > max {
+                let _ = job_mgr
+                    .fail_job(&job_id, format!("gave up after {max} restarts: {reason}"))
+                    .await;
+                break;
+            }
+        }
+        let delay = jobs_service::backoff(&service, failures);
+        push_log(
+            &logs,
+            &label,
+            format!("service restarting in {}s: {reason}", delay.as_secs()),
+        )
+        .await;
+        let _ = job_mgr
+            .record_restart(
                 &job_id,
-                &job,
-                &binary,
-                sha256_hex,
-                args,
-                env,
-                target_platform,
-                qemu_binary,
-                &logs,
-                Some(&mut cancel_rx),
-                storage.clone(),
+                format!("{reason}; next run in {}s", delay.as_secs()),
             )
             .await;
-            match result {
-                Ok(success_msg) => {
-                    let _ = job_mgr
-                        .add_job_log(
-                            &job_id,
-                            "info".to_string(),
-                            "Service job completed normally".to_string(),
-                        )
-                        .await;
-                    let _ = job_mgr.complete_job(&job_id, 0).await;
-                    let _ = tx.send(Ok(success_msg));
-                }
-                Err(error_msg) => {
-                    let _ = job_mgr.fail_job(&job_id, error_msg.clone()).await;
-                    let _ = tx.send(Err(error_msg));
-                }
-            }
-            job_mgr.unregister_running_job(&job_id).await;
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = &mut cancel_rx => return,
         }
     }
+    job_mgr.unregister_running_job(&job_id).await;
 }
@@ -1,8 +1,11 @@
 use crate::job_manager::JobManager;
+use crate::p2p::jobs_process::{self, Outcome, RunAs};
+use crate::p2p::jobs_workspace::JobWorkspace;
 use crate::p2p::metrics;
 use crate::policy::{load_policy, ExecutionPolicy};
 use crate::storage::ContentStore;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_native_job(
     job_mgr: &JobManager,
     job_id: &str,
@@ -11,6 +14,7 @@ pub async fn execute_native_job(
     sha256_hex: Option<String>,
     args: Vec<String>,
     env: std::collections::BTreeMap<String, String>,
+    sandbox: Option<common::NativeSandbox>,
     logs: &metrics::SharedLogs,
     cancel_rx: Option<&mut tokio::sync::oneshot::Receiver<()>>,
     storage: Option<crate::p2p::storage::P2PStorage>,
@@ -27,6 +31,22 @@ pub async fn execute_native_job(
         );
         return Err(msg);
     }
+    // The policy can require the sandbox; jobs without a profile get the default one
+    let sandbox_profile = match sandbox {
+        Some(profile) => Some(profile),
+        None if policy.require_native_sandbox => Some(common::NativeSandbox::default()),
+        None => None,
+    };
+    let run_as = match &job.execution.user {
+        Some(_) if sandbox_profile.is_some() => {
+            return Err(format!(
+                "job failed: {}: execution.user cannot be combined with the native sandbox, which runs as the agent's user",
+                job.name
+            ));
+        }
+        Some(user) => Some(RunAs::resolve(user).map_err(|e| format!("job failed: {e}"))?),
+        None => None,
+    };
 
     let label = format!("job:{}", job.name);
 
@@ -149,85 +169,112 @@ pub async fn execute_native_job(
         )
         .await;
 
-    let mut cmd = Command::new(&binary_path);
-    cmd.args(&args);
-    // set environment
-    for (k, v) in env.into_iter() {
+    let workspace = if run_as.is_some() {
+        JobWorkspace::create_shared(job_id).await?
+    } else {
+        JobWorkspace::create(job_id).await?
+    };
+    // Staged binaries live in the agent's private data dir; give the job's
+    // user a copy it can reach
+    let binary_path = match &run_as {
+        Some(_) if !std::path::Path::new(binary).is_absolute() => {
+            copy_executable(&binary_path, &workspace.scratch_dir().await?).await?
+        }
+        _ => binary_path,
+    };
+    let sandbox = match &sandbox_profile {
+        Some(profile) => {
+            let sandbox = crate::sandbox::Sandbox::prepare(
+                job_id,
+                profile,
+                std::path::Path::new(&binary_path),
+                workspace.root(),
+                job.execution
+                    .working_dir
+                    .as_deref()
+                    .map(std::path::Path::new),
+                job.resources.as_ref(),
+            )
+            .map_err(|e| format!("job failed: sandbox: {e}"))?;
+            push_log(logs, &label, format!("sandbox: {}", sandbox.describe())).await;
+            let _ = job_mgr
+                .add_job_log(
+                    job_id,
+                    "info".to_string(),
+                    format!("Running in sandbox: {}", sandbox.describe()),
+                )
+                .await;
+            Some(sandbox)
+        }
+        None => None,
+    };
+
+    let mut cmd = match &sandbox {
+        Some(sandbox) => sandbox.command(&binary_path, &args)?,
+        None => {
+            let mut cmd = Command::new(&binary_path);
+            cmd.args(&args);
+            cmd
+        }
+    };
+    jobs_process::configure(&mut cmd, job.resources.as_ref(), run_as.as_ref());
+    // set environment; the job's own env can override the workspace paths
+    for (k, v) in workspace.env().into_iter().chain(env) {
         cmd.env(k, v);
     }
-    // working dir if provided
-    if let Some(dir) = &job.execution.working_dir {
-        cmd.current_dir(dir);
+    // working dir if provided, else the workspace; the sandbox helper
+    // changes into it itself
+    if sandbox.is_none() {
+        match &job.execution.working_dir {
+            Some(dir) => cmd.current_dir(dir),
+            None => cmd.current_dir(workspace.root()),
+        };
     }
 
     // Pre-stage attachments if requested
-    for item in job.execution.pre_stage.iter() {
-        // Expect source in form cas:<sha256>
-        if let Some(hex) = item.source.strip_prefix("cas:") {
-            let store = ContentStore::open();
-            let bytes = if let Some(path) = store.get_path(hex) {
-                tokio::fs::read(path)
-                    .await
-                    .map_err(|e| format!("prestage cas read failed: {e}"))?
-            } else if let Some(sto) = &storage {
-                if let Some(bytes) = sto
-                    .get(hex.to_string(), std::time::Duration::from_secs(5))
-                    .await
-                {
-                    bytes
-                } else {
-                    return Err(format!("prestage: digest not available via P2P: {hex}"));
-                }
-            } else {
-                return Err("prestage: digest not local and no P2P storage available".to_string());
-            };
-            if let Some(parent) = std::path::Path::new(&item.dest).parent() {
-                let _ = tokio::fs::create_dir_all(parent).await;
-            }
-            tokio::fs::write(&item.dest, &bytes)
-                .await
-                .map_err(|e| format!("prestage write failed: {e}"))?;
-        }
+    workspace
+        .prestage(&job.execution.pre_stage, storage.as_ref())
+        .await?;
+    if let Some(run_as) = &run_as {
+        run_as
+            .chown_tree(workspace.root())
+            .map_err(|e| format!("job failed: {e}"))?;
     }
-    // spawn child and handle cancellation
+    // spawn child and handle cancellation and timeout; either stops the
+    // whole process tree
+    let timeout = job
+        .execution
+        .timeout_minutes
+        .map(|m| std::time::Duration::from_secs(m * 60));
     let mut child = cmd.spawn().map_err(|e| e.to_string())?;
-    let wait_fut = child.wait();
-    let status = if let Some(cancel_rx) = cancel_rx {
-        tokio::select! {
-            res = wait_fut => res.map_err(|e| e.to_string())?,
-            _ = cancel_rx => {
-                let _ = child.start_kill();
-                return Err("Job cancelled".to_string());
+    let status = match jobs_process::wait(&mut child, cancel_rx, timeout).await? {
+        Outcome::Exited(status) => status,
+        stopped => {
+            if let Some(sandbox) = &sandbox {
+                sandbox.kill();
             }
+            return Err(match stopped {
+                Outcome::TimedOut => format!(
+                    "job error: {}: timed out after {} minutes",
+                    job.name,
+                    job.execution.timeout_minutes.unwrap_or_default()
+                ),
+                _ => "Job cancelled".to_string(),
+            });
         }
-    } else {
-        wait_fut.await.map_err(|e| e.to_string())?
     };
 
     if status.success() {
         // Capture artifacts if requested
-        if let Some(exec) = &job.execution.artifacts {
-            for art in exec.iter() {
-                let stored = art.path.clone();
-                let meta = tokio::fs::metadata(&stored).await.ok();
-                let size = meta
-                    .as_ref()
-                    .and_then(|m| if m.is_file() { Some(m.len()) } else { None });
-                let artifact = common::JobArtifact {
-                    name: art.name.clone().unwrap_or_else(|| stored.clone()),
-                    stored_path: stored,
-                    size_bytes: size,
-                    sha256_hex: None,
-                };
-                let _ = job_mgr.add_job_artifact(job_id, artifact).await;
-            }
-            if let Some(job_instance) = job_mgr.get_job(job_id).await {
-                let _ = job_mgr
-                    .stage_artifacts(job_id, &job_instance.artifacts)
-                    .await;
-            }
-        }
+        workspace
+            .capture_artifacts(job_mgr, job_id, job.execution.artifacts.as_ref())
+            .await;
         Ok(format!("job ok: {}", job.name))
+    } else if sandbox.is_some() && status.code() == Some(crate::sandbox::SETUP_FAILED_EXIT) {
+        Err(format!(
+            "job error: {}: sandbox setup failed (details on the agent's stderr)",
+            job.name
+        ))
     } else {
         Err(format!(
             "job error: {}: native exit code {:?}",
@@ -236,3 +283,20 @@ pub async fn execute_native_job(
         ))
     }
 }
+
+/// Copy a staged executable into `dir`, readable and executable by anyone.
+pub(super) async fn copy_executable(path: &str, dir: &std::path::Path) -> Result<String, String> {
+    let name = std::path::Path::new(path)
+        .file_name()
+        .ok_or_else(|| format!("job failed: bad binary path {path}"))?;
+    let dest = dir.join(name);
+    tokio::fs::copy(path, &dest)
+        .await
+        .map_err(|e| format!("job failed: copy binary: {e}"))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = tokio::fs::set_permissions(&dest, std::fs::Permissions::from_mode(0o755)).await;
+    }
+    Ok(dest.display().to_string())
+}
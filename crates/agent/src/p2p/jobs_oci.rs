@@ -0,0 +1,179 @@
+use crate::job_manager::JobManager;
+use crate::p2p::jobs_process::{self, Outcome};
+use crate::p2p::jobs_workspace::{JobWorkspace, GUEST_ROOT};
+use crate::p2p::metrics;
+use crate::policy::{load_policy, ExecutionPolicy};
+
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_oci_job(
+    job_mgr: &JobManager,
+    job_id: &str,
+    job: &common::JobSpec,
+    image: &str,
+    entrypoint: Option<Vec<String>>,
+    args: Option<Vec<String>>,
+    env: std::collections::BTreeMap<String, String>,
+    mounts: Option<Vec<common::MountSpec>>,
+    network: bool,
+    logs: &metrics::SharedLogs,
+    cancel_rx: Option<&mut tokio::sync::oneshot::Receiver<()>>,
+    storage: Option<crate::p2p::storage::P2PStorage>,
+) -> Result<String, String> {
+    use crate::p2p::metrics::push_log;
+
+    let policy: ExecutionPolicy = load_policy();
+    if !policy.allow_containers {
+        return Err(format!(
+            "job failed: container jobs are disabled by policy for job {}. Enable with `realm policy-set --containers true` or REALM_ALLOW_CONTAINERS=1.",
+            job.name
+        ));
+    }
+
+    let label = format!("job:{}", job.name);
+    push_log(logs, &label, format!("pulling image {image}")).await;
+    let _ = job_mgr
+        .add_job_log(
+            job_id,
+            "info".to_string(),
+            format!("Pulling image {}", image),
+        )
+        .await;
+    let pulled = crate::oci::pull(image, storage.as_ref())
+        .await
+        .map_err(|e| format!("job failed: image {image}: {e}"))?;
+    push_log(
+        logs,
+        &label,
+        format!(
+            "image sha256:{} ({} layers)",
+            &pulled.digest,
+            pulled.layers.len()
+        ),
+    )
+    .await;
+    let unpacked = pulled.clone();
+    let rootfs = tokio::task::spawn_blocking(move || crate::oci::unpack(&unpacked))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("job failed: unpack {image}: {e}"))?;
+
+    let config = pulled.config;
+    if let Some(user) = config.user.as_deref() {
+        if !matches!(user, "" | "0" | "root" | "0:0" | "root:root") {
+            push_log(
+                logs,
+                &label,
+                format!(
+                    "image user '{user}' ignored; containers run as root of their user namespace"
+                ),
+            )
+            .await;
+        }
+    }
+    let mut argv: Vec<String> = entrypoint.or(config.entrypoint).unwrap_or_default();
+    argv.extend(args.or(config.cmd).unwrap_or_default());
+    if argv.is_empty() {
+        return Err(format!(
+            "job failed: image {image} has no entrypoint or command; set args"
+        ));
+    }
+
+    let workspace = JobWorkspace::create(job_id)
+        .await?
+        .with_guest_root(GUEST_ROOT);
+    workspace
+        .prestage(&job.execution.pre_stage, storage.as_ref())
+        .await?;
+    let mut mounts = mounts.unwrap_or_default();
+    mounts.push(common::MountSpec {
+        host: workspace.root().display().to_string(),
+        guest: GUEST_ROOT.to_string(),
+        ro: false,
+    });
+    let workdir = job
+        .execution
+        .working_dir
+        .clone()
+        .or(config.working_dir.filter(|d| !d.is_empty()))
+        .unwrap_or_else(|| GUEST_ROOT.to_string());
+
+    let sandbox = crate::sandbox::Sandbox::prepare_container(
+        job_id,
+        &rootfs,
+        mounts,
+        workdir.into(),
+        network,
+        job.resources.as_ref(),
+    )
+    .map_err(|e| format!("job failed: sandbox: {e}"))?;
+    push_log(
+        logs,
+        &label,
+        format!(
+            "starting container: {} ({})",
+            argv.join(" "),
+            sandbox.describe()
+        ),
+    )
+    .await;
+    let _ = job_mgr
+        .add_job_log(
+            job_id,
+            "info".to_string(),
+            format!("Running container: {}", sandbox.describe()),
+        )
+        .await;
+
+    let mut cmd = sandbox.command(&argv[0], &argv[1..])?;
+    cmd.env("HOME", "/root");
+    // Image env first; the workspace paths and the job's env take precedence
+    for kv in config.env.unwrap_or_default() {
+        if let Some((k, v)) = kv.split_once('=') {
+            cmd.env(k, v);
+        }
+    }
+    for (k, v) in workspace.env().into_iter().chain(env) {
+        cmd.env(k, v);
+    }
+
+    // Own session, so cancellation and the timeout stop the helper's whole
+    // tree; the cgroup catches anything that escaped it
+    jobs_process::configure(&mut cmd, None, None);
+    let timeout = job
+        .execution
+        .timeout_minutes
+        .map(|m| std::time::Duration::from_secs(m * 60));
+    let mut child = cmd.spawn().map_err(|e| e.to_string())?;
+    let status = match jobs_process::wait(&mut child, cancel_rx, timeout).await? {
+        Outcome::Exited(status) => status,
+        stopped => {
+            sandbox.kill();
+            return Err(match stopped {
+                Outcome::TimedOut => format!(
+                    "job error: {}: timed out after {} minutes",
+                    job.name,
+                    job.execution.timeout_minutes.unwrap_or_default()
+                ),
+                _ => "Job cancelled".to_string(),
+            });
+        }
+    };
+
+    if status.success() {
+        workspace
+            .capture_artifacts(job_mgr, job_id, job.execution.artifacts.as_ref())
+            .await;
+        Ok(format!("job ok: {}", job.name))
+    } else if status.code() == Some(crate::sandbox::SETUP_FAILED_EXIT) {
+        Err(format!(
+            "job error: {}: container setup failed (details on the agent's stderr)",
+            job.name
+        ))
+    } else {
+        Err(format!(
+            "job error: {}: container exit code {:?}",
+            job.name,
+            status.code()
+        ))
+    }
+}
@@ -0,0 +1,252 @@
+//! Process control for native, qemu-user and container jobs. Each job starts in its own
+//! session, so cancellation, timeouts and completion can signal the whole
+//! process tree (build tools forking compilers, shells running pipelines)
+//! instead of only the direct child. rlimits and the `user` privilege drop
+//! are applied between fork and exec.
+
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use common::JobResources;
+use tokio::process::{Child, Command};
+
+/// Time between SIGTERM and SIGKILL when a job tree is stopped.
+const KILL_GRACE: Duration = Duration::from_secs(10);
+
+/// How a job process ended.
+pub enum Outcome {
+    Exited(ExitStatus),
+    Cancelled,
+    TimedOut,
+}
+
+/// Account a job runs as after dropping privileges.
+pub struct RunAs {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: Option<String>,
+}
+
+impl RunAs {
+    /// Resolve `name`, `uid` or `uid:gid`. Switching users needs the agent
+    /// to run as root.
+    #[cfg(unix)]
+    pub fn resolve(user: &str) -> Result<Self, String> {
+        let (who, group) = match user.split_once(':') {
+            Some((u, g)) => (u, Some(g)),
+            None => (user, None),
+        };
+        let entry = match who.parse::<u32>() {
+            Ok(uid) => Some(passwd_by_uid(uid).unwrap_or(RunAs {
+                name: uid.to_string(),
+                uid,
+                gid: uid,
+                home: None,
+            })),
+            Err(_) => passwd_by_name(who),
+        };
+        let mut run_as = entry.ok_or_else(|| format!("unknown user '{who}'"))?;
+        if let Some(group) = group {
+            run_as.gid = group
+                .parse::<u32>()
+                .map_err(|_| format!("invalid gid '{group}' (expected a number)"))?;
+        }
+        // SAFETY: geteuid has no preconditions
+        let euid = unsafe { libc::geteuid() };
+        if euid != 0 && euid != run_as.uid {
+            return Err(format!(
+                "running jobs as '{user}' needs the agent to run as root"
+            ));
+        }
+        Ok(run_as)
+    }
+
+    #[cfg(not(unix))]
+    pub fn resolve(_user: &str) -> Result<Self, String> {
+        Err(format!(
+            "running jobs as another user is not supported on {}",
+            std::env::consts::OS
+        ))
+    }
+
+    /// Hand `path` and everything below it to this user, so a job dropped
+    /// from root can use its workspace.
+    pub fn chown_tree(&self, path: &std::path::Path) -> Result<(), String> {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::lchown(path, Some(self.uid), Some(self.gid))
+                .map_err(|e| format!("chown {}: {e}", path.display()))?;
+            if path.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+                let entries =
+                    std::fs::read_dir(path).map_err(|e| format!("{}: {e}", path.display()))?;
+                for entry in entries.flatten() {
+                    self.chown_tree(&entry.path())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Start the command in a new session, with the job's rlimits and, when
+/// given, as `run_as`.
+pub fn configure(cmd: &mut Command, resources: Option<&JobResources>, run_as: Option<&RunAs>) {
+    cmd.kill_on_drop(true);
+    #[cfg(unix)]
+    {
+        const MB: u64 = 1024 * 1024;
+        let limits = resources.map(|r| {
+            [
+                (libc::RLIMIT_CPU, r.cpu_seconds),
+                (libc::RLIMIT_AS, r.address_space_mb.map(|mb| mb * MB)),
+                (libc::RLIMIT_NOFILE, r.open_files),
+                (libc::RLIMIT_FSIZE, r.file_size_mb.map(|mb| mb * MB)),
+            ]
+        });
+        // SAFETY: the closure runs between fork and exec and only makes
+        // async-signal-safe calls (setsid, setrlimit)
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::setsid() < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                for (resource, value) in limits.iter().flatten() {
+                    if let Some(value) = value {
+                        let limit = libc::rlimit {
+                            rlim_cur: *value as libc::rlim_t,
+                            rlim_max: *value as libc::rlim_t,
+                        };
+                        if libc::setrlimit(*resource, &limit) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                }
+                Ok(())
+            });
+        }
+        if let Some(run_as) = run_as {
+            // std drops supplementary groups before switching from root
+            cmd.uid(run_as.uid).gid(run_as.gid);
+            cmd.env("USER", &run_as.name).env("LOGNAME", &run_as.name);
+            if let Some(home) = &run_as.home {
+                cmd.env("HOME", home);
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (resources, run_as);
+}
+
+/// Wait for the job, stopping its whole tree when `cancel_rx` fires or
+/// `timeout` passes. Processes it leaves behind after exiting are killed too.
+pub async fn wait(
+    child: &mut Child,
+    cancel_rx: Option<&mut tokio::sync::oneshot::Receiver<()>>,
+    timeout: Option<Duration>,
+) -> Result<Outcome, String> {
+    let pid = child.id();
+    let cancelled = async {
+        match cancel_rx {
+            Some(rx) => {
+                let _ = rx.await;
+            }
+            None => std::future::pending::<()>().await,
+        }
+    };
+    let deadline = async {
+        match timeout {
+            Some(t) => tokio::time::sleep(t).await,
+            None => std::future::pending::<()>().await,
+        }
+    };
+    let outcome = tokio::select! {
+        res = child.wait() => Outcome::Exited(res.map_err(|e| e.to_string())?),
+        _ = cancelled => Outcome::Cancelled,
+        _ = deadline => Outcome::TimedOut,
+    };
+    match outcome {
+        Outcome::Exited(_) => signal_group(pid, Signal::Kill),
+        _ => stop_tree(child, pid).await,
+    }
+    Ok(outcome)
+}
+
+/// SIGTERM the job's process group, then SIGKILL it after [`KILL_GRACE`].
+async fn stop_tree(child: &mut Child, pid: Option<u32>) {
+    signal_group(pid, Signal::Term);
+    if tokio::time::timeout(KILL_GRACE, child.wait())
+        .await
+        .is_err()
+    {
+        let _ = child.start_kill();
+    }
+    signal_group(pid, Signal::Kill);
+    let _ = child.wait().await;
+}
+
+enum Signal {
+    Term,
+    Kill,
+}
+
+fn signal_group(pid: Option<u32>, signal: Signal) {
+    #[cfg(unix)]
+    if let Some(pid) = pid.and_then(|p| libc::pid_t::try_from(p).ok()) {
+        let sig = match signal {
+            Signal::Term => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+        };
+        // SAFETY: plain syscall; the child leads its own process group
+        // (setsid in `configure`), so -pid addresses only the job's tree
+        unsafe {
+            libc::kill(-pid, sig);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (pid, signal);
+}
+
+#[cfg(unix)]
+fn passwd_by_name(name: &str) -> Option<RunAs> {
+    let name = std::ffi::CString::new(name).ok()?;
+    lookup_passwd(|pwd, buf, result| unsafe {
+        libc::getpwnam_r(name.as_ptr(), pwd, buf.as_mut_ptr(), buf.len(), result)
+    })
+}
+
+#[cfg(unix)]
+fn passwd_by_uid(uid: u32) -> Option<RunAs> {
+    lookup_passwd(|pwd, buf, result| unsafe {
+        libc::getpwuid_r(uid, pwd, buf.as_mut_ptr(), buf.len(), result)
+    })
+}
+
+#[cfg(unix)]
+fn lookup_passwd(
+    call: impl Fn(*mut libc::passwd, &mut [libc::c_char], *mut *mut libc::passwd) -> libc::c_int,
+) -> Option<RunAs> {
+    use std::ffi::CStr;
+
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    // SAFETY: passwd is plain data; it is only read after the call filled it
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    if call(&mut pwd, &mut buf, &mut result) != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: on success the string fields point into `buf`, which is alive
+    let (name, home) = unsafe {
+        (
+            CStr::from_ptr(pwd.pw_name).to_string_lossy().into_owned(),
+            (!pwd.pw_dir.is_null())
+                .then(|| CStr::from_ptr(pwd.pw_dir).to_string_lossy().into_owned()),
+        )
+    };
+    Some(RunAs {
+        name,
+        uid: pwd.pw_uid,
+        gid: pwd.pw_gid,
+        home,
+    })
+}
@@ -1,8 +1,15 @@
+use std::path::{Path, PathBuf};
+
 use crate::job_manager::JobManager;
+use crate::p2p::jobs_process::{self, Outcome, RunAs};
+use crate::p2p::jobs_workspace::JobWorkspace;
 use crate::p2p::metrics;
-use crate::policy::{load_policy, policy_enable_help, qemu_install_help, ExecutionPolicy};
+use crate::policy::{
+    binfmt_qemu_interpreter, load_policy, policy_enable_help, qemu_install_help, ExecutionPolicy,
+};
 use crate::storage::ContentStore;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_qemu_job(
     job_mgr: &JobManager,
     job_id: &str,
@@ -13,6 +20,7 @@ pub async fn execute_qemu_job(
     env: std::collections::BTreeMap<String, String>,
     target_platform: Option<String>,
     qemu_binary: Option<String>,
+    sysroot: Option<String>,
     logs: &metrics::SharedLogs,
     cancel_rx: Option<&mut tokio::sync::oneshot::Receiver<()>>,
     storage: Option<crate::p2p::storage::P2PStorage>,
@@ -29,6 +37,11 @@ pub async fn execute_qemu_job(
         ));
     }
 
+    let run_as = match &job.execution.user {
+        Some(user) => Some(RunAs::resolve(user).map_err(|e| format!("job failed: {e}"))?),
+        None => None,
+    };
+
     let label = format!("job:{}", job.name);
     push_log(logs, &label, format!("staging qemu binary from {binary}")).await;
     let _ = job_mgr
@@ -97,32 +110,18 @@ pub async fn execute_qemu_job(
     }
 
     // Pre-stage attachments if requested (same as native)
-    for item in job.execution.pre_stage.iter() {
-        if let Some(hex) = item.source.strip_prefix("cas:") {
-            let store = ContentStore::open();
-            let bytes = if let Some(path) = store.get_path(hex) {
-                tokio::fs::read(path)
-                    .await
-                    .map_err(|e| format!("prestage cas read failed: {e}"))?
-            } else if let Some(sto) = &storage {
-                if let Some(bytes) = sto
-                    .get(hex.to_string(), std::time::Duration::from_secs(5))
-                    .await
-                {
-                    bytes
-                } else {
-                    return Err(format!("prestage: digest not available via P2P: {hex}"));
-                }
-            } else {
-                return Err("prestage: digest not local and no P2P storage available".to_string());
-            };
-            if let Some(parent) = std::path::Path::new(&item.dest).parent() {
-                let _ = tokio::fs::create_dir_all(parent).await;
-            }
-            tokio::fs::write(&item.dest, &bytes)
-                .await
-                .map_err(|e| format!("prestage write failed: {e}"))?;
-        }
+    let workspace = if run_as.is_some() {
+        JobWorkspace::create_shared(job_id).await?
+    } else {
+        JobWorkspace::create(job_id).await?
+    };
+    workspace
+        .prestage(&job.execution.pre_stage, storage.as_ref())
+        .await?;
+    if let Some(run_as) = &run_as {
+        run_as
+            .chown_tree(workspace.root())
+            .map_err(|e| format!("job failed: {e}"))?;
     }
 
     // Store in CAS
@@ -143,6 +142,17 @@ pub async fn execute_qemu_job(
             let _ = tokio::fs::set_permissions(&file_path, p).await;
         }
     }
+    // The CAS is in the agent's private data dir; give the job's user a copy
+    let file_path = match &run_as {
+        Some(_) => PathBuf::from(
+            super::jobs_native::copy_executable(
+                &file_path.display().to_string(),
+                &workspace.scratch_dir().await?,
+            )
+            .await?,
+        ),
+        None => file_path,
+    };
 
     // Resolve qemu-user binary
     let qemu_path = if let Some(path) = qemu_binary {
@@ -160,6 +170,15 @@ pub async fn execute_qemu_job(
         }
     };
 
+    let sysroot = match sysroot {
+        Some(spec) => {
+            let path = resolve_sysroot(&spec, storage.as_ref()).await?;
+            push_log(logs, &label, format!("sysroot: {}", path.display())).await;
+            Some(path)
+        }
+        None => None,
+    };
+
     push_log(
         logs,
         &label,
@@ -180,50 +199,43 @@ pub async fn execute_qemu_job(
         .await;
 
     let mut cmd = Command::new(qemu_path);
-    // Best-effort: pass through a sane default for binfmt; execute binary as first arg
+    // The sysroot lets dynamically linked binaries find their loader and libraries
+    if let Some(root) = &sysroot {
+        cmd.arg("-L").arg(root);
+    }
     cmd.arg(file_path.as_os_str());
     cmd.args(&args);
-    for (k, v) in env.into_iter() {
+    jobs_process::configure(&mut cmd, job.resources.as_ref(), run_as.as_ref());
+    for (k, v) in workspace.env().into_iter().chain(env) {
         cmd.env(k, v);
     }
-    if let Some(dir) = &job.execution.working_dir {
-        cmd.current_dir(dir);
-    }
+    match &job.execution.working_dir {
+        Some(dir) => cmd.current_dir(dir),
+        None => cmd.current_dir(workspace.root()),
+    };
 
+    let timeout = job
+        .execution
+        .timeout_minutes
+        .map(|m| std::time::Duration::from_secs(m * 60));
     let mut child = cmd.spawn().map_err(|e| e.to_string())?;
-    let wait_fut = child.wait();
-    let status = if let Some(cancel_rx) = cancel_rx {
-        tokio::select! {
-            res = wait_fut => res.map_err(|e| e.to_string())?,
-            _ = cancel_rx => { let _ = child.start_kill(); return Err("Job cancelled".to_string()) }
+    let status = match jobs_process::wait(&mut child, cancel_rx, timeout).await? {
+        Outcome::Exited(status) => status,
+        Outcome::Cancelled => return Err("Job cancelled".to_string()),
+        Outcome::TimedOut => {
+            return Err(format!(
+                "job error: {}: timed out after {} minutes",
+                job.name,
+                job.execution.timeout_minutes.unwrap_or_default()
+            ))
         }
-    } else {
-        wait_fut.await.map_err(|e| e.to_string())?
     };
 
     if status.success() {
         // Capture artifacts if requested
-        if let Some(exec) = &job.execution.artifacts {
-            for art in exec.iter() {
-                let stored = art.path.clone();
-                let meta = tokio::fs::metadata(&stored).await.ok();
-                let size = meta
-                    .as_ref()
-                    .and_then(|m| if m.is_file() { Some(m.len()) } else { None });
-                let artifact = common::JobArtifact {
-                    name: art.name.clone().unwrap_or_else(|| stored.clone()),
-                    stored_path: stored,
-                    size_bytes: size,
-                    sha256_hex: None,
-                };
-                let _ = job_mgr.add_job_artifact(job_id, artifact).await;
-            }
-            if let Some(job_instance) = job_mgr.get_job(job_id).await {
-                let _ = job_mgr
-                    .stage_artifacts(job_id, &job_instance.artifacts)
-                    .await;
-            }
-        }
+        workspace
+            .capture_artifacts(job_mgr, job_id, job.execution.artifacts.as_ref())
+            .await;
         Ok(format!("job ok: {}", job.name))
     } else {
         Err(format!(
@@ -240,19 +252,137 @@ fn resolve_qemu_binary(target_platform: &Option<String>) -> Option<String> {
         .as_deref()
         .and_then(|p| p.split('/').nth(1))
         .unwrap_or(arch_hint());
-    let qemu_name = match arch {
-        "x86_64" | "amd64" => "qemu-x86_64",
-        "aarch64" | "arm64" => "qemu-aarch64",
-        "arm" | "armv7" => "qemu-arm",
-        "riscv64" => "qemu-riscv64",
+    let qemu_arch = match arch {
+        "x86_64" | "amd64" => "x86_64",
+        "aarch64" | "arm64" => "aarch64",
+        "arm" | "armv7" => "arm",
+        "riscv64" => "riscv64",
         _ => return None,
     };
-    which::which(qemu_name)
-        .ok()
-        .map(|p| p.display().to_string())
+    let on_path = [
+        format!("qemu-{qemu_arch}"),
+        format!("qemu-{qemu_arch}-static"),
+    ]
+    .into_iter()
+    .find_map(|name| which::which(name).ok())
+    .map(|p| p.display().to_string());
+    // Fall back to the emulator registered with binfmt_misc
+    on_path.or_else(|| binfmt_qemu_interpreter(qemu_arch))
 }
 
 fn arch_hint() -> &'static str {
     // default to a common arch if not provided; this will likely fail but guides user
     "x86_64"
 }
+
+/// Directory to pass as `-L`. CAS tarballs are unpacked once and cached
+/// under `qemu/sysroots/<sha256>` in the agent data dir.
+async fn resolve_sysroot(
+    spec: &str,
+    storage: Option<&crate::p2p::storage::P2PStorage>,
+) -> Result<PathBuf, String> {
+    let Some(hex) = spec.strip_prefix("cas:") else {
+        let dir = PathBuf::from(spec);
+        if !dir.is_absolute() || !dir.is_dir() {
+            return Err(format!(
+                "job failed: sysroot {spec} is neither cas:<sha256> nor an existing absolute directory"
+            ));
+        }
+        return Ok(dir);
+    };
+    let root = sysroots_dir().join(hex);
+    if root.is_dir() {
+        return Ok(root);
+    }
+    let store = ContentStore::open();
+    let bytes = if let Some(path) = store.get_path(hex) {
+        tokio::fs::read(path)
+            .await
+            .map_err(|e| format!("sysroot cas read failed: {e}"))?
+    } else if let Some(sto) = storage {
+        let bytes = sto
+            .get(hex.to_string(), std::time::Duration::from_secs(30))
+            .await
+            .ok_or_else(|| format!("job failed: sysroot not available via P2P: {hex}"))?;
+        if common::sha256_hex(&bytes) != hex {
+            return Err("job failed: sysroot digest mismatch".to_string());
+        }
+        let _ = store.put_bytes(&bytes);
+        bytes
+    } else {
+        return Err("job failed: sysroot not local and no P2P storage available".to_string());
+    };
+    let hex = hex.to_string();
+    tokio::task::spawn_blocking(move || unpack_sysroot(&hex, &bytes))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("job failed: sysroot: {e}"))
+}
+
+fn sysroots_dir() -> PathBuf {
+    crate::p2p::state::agent_data_dir()
+        .join("qemu")
+        .join("sysroots")
+}
+
+fn unpack_sysroot(hex: &str, bytes: &[u8]) -> Result<PathBuf, String> {
+    let root = sysroots_dir().join(hex);
+    let tmp = sysroots_dir().join(format!(".tmp-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&tmp).map_err(|e| format!("{}: {e}", tmp.display()))?;
+    let unpacked =
+        crate::oci::unpack_tarball(&tmp, bytes).map(|()| relativize_symlinks(&tmp, &tmp));
+    if let Err(e) = unpacked {
+        let _ = std::fs::remove_dir_all(&tmp);
+        return Err(e);
+    }
+    match std::fs::rename(&tmp, &root) {
+        Ok(()) => Ok(root),
+        // Another job unpacked the same sysroot meanwhile
+        Err(_) if root.is_dir() => {
+            let _ = std::fs::remove_dir_all(&tmp);
+            Ok(root)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&tmp);
+            Err(format!("{}: {e}", root.display()))
+        }
+    }
+}
+
+/// Rootfs tarballs use absolute symlinks (`lib/ld-linux-aarch64.so.1 ->
+/// /lib/aarch64-linux-gnu/...`), which the host kernel would resolve outside
+/// the sysroot. Point them inside it instead.
+#[cfg(unix)]
+fn relativize_symlinks(root: &Path, dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(meta) = path.symlink_metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            relativize_symlinks(root, &path);
+        } else if meta.file_type().is_symlink() {
+            let Ok(target) = std::fs::read_link(&path) else {
+                continue;
+            };
+            let Ok(inner) = target.strip_prefix("/") else {
+                continue;
+            };
+            let depth = path
+                .parent()
+                .and_then(|p| p.strip_prefix(root).ok())
+                .map(|p| p.components().count())
+                .unwrap_or(0);
+            let mut relative: PathBuf = (0..depth).map(|_| "..").collect();
+            relative.push(inner);
+            let _ = std::fs::remove_file(&path);
+            let _ = std::os::unix::fs::symlink(relative, &path);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn relativize_symlinks(_root: &Path, _dir: &Path) {}
@@ -0,0 +1,653 @@
+//! Full-system QEMU VMs for `qemu-system` jobs. Each run boots a
+//! copy-on-write overlay of a CAS disk image with a cloud-init NoCloud seed
+//! that mounts the job workspace (shared over virtio-9p) at `/workspace` and
+//! runs the job's command. The serial console is copied into the job log;
+//! cancellation and `timeout_minutes` ask the guest to power off over QMP
+//! before killing QEMU.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use base64::Engine;
+use common::{Protocol, ServicePort};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::process::{Child, Command};
+
+use crate::job_manager::JobManager;
+use crate::p2p::jobs_workspace::{JobWorkspace, GUEST_ROOT};
+use crate::p2p::metrics;
+use crate::policy::{load_policy, policy_enable_help, ExecutionPolicy};
+use crate::storage::ContentStore;
+
+/// 9p mount tag the workspace is shared under.
+const SHARE_TAG: &str = "realm";
+/// Written into the workspace by the guest with the exit status of `args`.
+const EXIT_FILE: &str = ".realm-exit";
+/// How long the guest gets to power off after an ACPI shutdown request.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+const DEFAULT_MEMORY_MB: u64 = 1024;
+
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_qemu_system_job(
+    job_mgr: &JobManager,
+    job_id: &str,
+    job: &common::JobSpec,
+    image: &str,
+    arch: Option<String>,
+    args: Vec<String>,
+    env: BTreeMap<String, String>,
+    ports: Vec<ServicePort>,
+    network: bool,
+    qemu_binary: Option<String>,
+    qemu_args: Vec<String>,
+    logs: &metrics::SharedLogs,
+    mut cancel_rx: Option<&mut tokio::sync::oneshot::Receiver<()>>,
+    storage: Option<crate::p2p::storage::P2PStorage>,
+) -> Result<String, String> {
+    use crate::p2p::metrics::push_log;
+
+    let policy: ExecutionPolicy = load_policy();
+    if !policy.allow_emulation {
+        return Err(format!(
+            "job failed: QEMU emulation is disabled by policy for job {}. {}",
+            job.name,
+            policy_enable_help()
+        ));
+    }
+
+    let label = format!("job:{}", job.name);
+    let arch = normalize_arch(arch.as_deref().unwrap_or(std::env::consts::ARCH));
+    let qemu = match qemu_binary {
+        Some(path) => path,
+        None => which::which(format!("qemu-system-{arch}"))
+            .map(|p| p.display().to_string())
+            .map_err(|_| {
+                format!(
+                    "job failed: qemu-system-{arch} not found. {}",
+                    qemu_system_install_help()
+                )
+            })?,
+    };
+    let qemu_img = which::which("qemu-img").map_err(|_| {
+        format!(
+            "job failed: qemu-img not found. {}",
+            qemu_system_install_help()
+        )
+    })?;
+
+    push_log(logs, &label, format!("staging disk image {image}")).await;
+    let _ = job_mgr
+        .add_job_log(
+            job_id,
+            "info".to_string(),
+            format!("Staging disk image {}", image),
+        )
+        .await;
+    let base = fetch_image(image, storage.as_ref()).await?;
+
+    let workspace = JobWorkspace::create(job_id)
+        .await?
+        .with_guest_root(GUEST_ROOT);
+    workspace
+        .prestage(&job.execution.pre_stage, storage.as_ref())
+        .await?;
+    let scratch = workspace.scratch_dir().await?;
+    let overlay = create_overlay(&qemu_img, &base, &scratch).await?;
+
+    let workdir = job
+        .execution
+        .working_dir
+        .clone()
+        .unwrap_or_else(|| GUEST_ROOT.to_string());
+    let guest_env: BTreeMap<String, String> = workspace.env().into_iter().chain(env).collect();
+    let seed_dir = scratch.join("seed");
+    write_seed(&seed_dir, job_id, &job.name, &args, &guest_env, &workdir).await?;
+    let seed_drive = build_seed(&seed_dir, &scratch.join("seed.iso")).await?;
+
+    let mut netdev = "user,id=net0".to_string();
+    if !network {
+        netdev.push_str(",restrict=on");
+    }
+    for port in &ports {
+        let host_port = match port.host_port {
+            Some(p) => p,
+            None => free_port(port.protocol)?,
+        };
+        let proto = match port.protocol {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        };
+        netdev.push_str(&format!(
+            ",hostfwd={proto}:127.0.0.1:{host_port}-:{}",
+            port.port
+        ));
+        push_log(
+            logs,
+            &label,
+            format!(
+                "forwarding 127.0.0.1:{host_port}/{proto} -> guest port {}",
+                port.port
+            ),
+        )
+        .await;
+        let _ = job_mgr
+            .add_job_log(
+                job_id,
+                "info".to_string(),
+                format!(
+                    "Guest port {}/{} available on 127.0.0.1:{}",
+                    port.port, proto, host_port
+                ),
+            )
+            .await;
+    }
+
+    let (cpus, memory_mb) = match &job.resources {
+        Some(r) => (
+            r.cpus.map(|c| c.ceil().max(1.0) as u32).unwrap_or(1),
+            r.memory_mb.unwrap_or(DEFAULT_MEMORY_MB),
+        ),
+        None => (1, DEFAULT_MEMORY_MB),
+    };
+    let qmp_socket = scratch.join("qmp.sock");
+
+    let mut cmd = Command::new(&qemu);
+    cmd.args(["-nodefaults", "-display", "none", "-serial", "stdio"])
+        .args(accel_args(&arch))
+        .args(["-smp", &cpus.to_string(), "-m", &memory_mb.to_string()])
+        .arg("-drive")
+        .arg(format!(
+            "file={},if=virtio,format=qcow2",
+            escape_opt(&overlay.display().to_string())
+        ))
+        .arg("-drive")
+        .arg(seed_drive)
+        .arg("-virtfs")
+        .arg(format!(
+            "local,path={},mount_tag={SHARE_TAG},security_model=none,id=realm",
+            escape_opt(&workspace.root().display().to_string())
+        ))
+        .args(["-netdev", &netdev, "-device", "virtio-net-pci,netdev=net0"]);
+    if cfg!(unix) {
+        cmd.arg("-qmp").arg(format!(
+            "unix:{},server=on,wait=off",
+            escape_opt(&qmp_socket.display().to_string())
+        ));
+    }
+    cmd.args(&qemu_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    push_log(
+        logs,
+        &label,
+        format!("booting {arch} VM: {cpus} cpus, {memory_mb}MB, qemu {qemu}"),
+    )
+    .await;
+    let _ = job_mgr
+        .add_job_log(
+            job_id,
+            "info".to_string(),
+            format!("Booting {} VM ({} cpus, {}MB)", arch, cpus, memory_mb),
+        )
+        .await;
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("job failed: {qemu}: {e}"))?;
+    let (line_tx, mut console) = tokio::sync::mpsc::unbounded_channel();
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(forward_lines(stdout, line_tx));
+    }
+    let stderr_task = child.stderr.take().map(|mut stderr| {
+        tokio::spawn(async move {
+            let mut buf = Vec::new();
+            let _ = stderr.read_to_end(&mut buf).await;
+            String::from_utf8_lossy(&buf).trim().to_string()
+        })
+    });
+
+    let cancelled = async {
+        match cancel_rx.as_mut() {
+            Some(rx) => {
+                let _ = (&mut **rx).await;
+            }
+            None => std::future::pending::<()>().await,
+        }
+    };
+    tokio::pin!(cancelled);
+    let timeout = job.execution.timeout_minutes;
+    let deadline = async {
+        match timeout {
+            Some(m) => tokio::time::sleep(Duration::from_secs(m * 60)).await,
+            None => std::future::pending::<()>().await,
+        }
+    };
+    tokio::pin!(deadline);
+    let mut console_open = true;
+    let status = loop {
+        tokio::select! {
+            line = console.recv(), if console_open => match line {
+                Some(line) => log_console(job_mgr, job_id, logs, &label, line).await,
+                None => console_open = false,
+            },
+            res = child.wait() => break res.map_err(|e| e.to_string())?,
+            _ = &mut cancelled => {
+                power_off(&mut child, &qmp_socket, logs, &label).await;
+                return Err("Job cancelled".to_string());
+            }
+            _ = &mut deadline => {
+                power_off(&mut child, &qmp_socket, logs, &label).await;
+                return Err(format!(
+                    "job error: {}: timed out after {} minutes",
+                    job.name,
+                    timeout.unwrap_or_default()
+                ));
+            }
+        }
+    };
+    while let Some(line) = console.recv().await {
+        log_console(job_mgr, job_id, logs, &label, line).await;
+    }
+
+    if !status.success() {
+        let stderr = match stderr_task {
+            Some(task) => task.await.unwrap_or_default(),
+            None => String::new(),
+        };
+        return Err(format!(
+            "job error: {}: qemu exit code {:?}{}{}",
+            job.name,
+            status.code(),
+            if stderr.is_empty() { "" } else { ": " },
+            stderr
+        ));
+    }
+    if !args.is_empty() {
+        let exit_file = workspace.root().join(EXIT_FILE);
+        let code = tokio::fs::read_to_string(&exit_file)
+            .await
+            .ok()
+            .and_then(|s| s.trim().parse::<i32>().ok());
+        let _ = tokio::fs::remove_file(&exit_file).await;
+        match code {
+            Some(0) => {}
+            Some(code) => return Err(format!("job error: {}: guest exit code {}", job.name, code)),
+            None => {
+                return Err(format!(
+                    "job error: {}: VM powered off before the command reported an exit status",
+                    job.name
+                ))
+            }
+        }
+    }
+    workspace
+        .capture_artifacts(job_mgr, job_id, job.execution.artifacts.as_ref())
+        .await;
+    Ok(format!("job ok: {}", job.name))
+}
+
+pub fn qemu_system_install_help() -> String {
+    match std::env::consts::OS {
+        "linux" => "Install QEMU system emulators and tools (e.g., `sudo apt install qemu-system qemu-utils genisoimage` or `sudo dnf install qemu-kvm qemu-img genisoimage`).".to_string(),
+        "macos" => "Install QEMU with Homebrew: `brew install qemu` (provides qemu-system-* and qemu-img).".to_string(),
+        _ => "Install QEMU system emulators and qemu-img (package names vary by OS).".to_string(),
+    }
+}
+
+/// Path of the base image in the local CAS, fetched from peers if needed.
+async fn fetch_image(
+    image: &str,
+    storage: Option<&crate::p2p::storage::P2PStorage>,
+) -> Result<PathBuf, String> {
+    let hex = image.strip_prefix("cas:").ok_or_else(|| {
+        format!("job failed: disk image must be a cas:<sha256> reference, got {image}")
+    })?;
+    let store = ContentStore::open();
+    if let Some(path) = store.get_path(hex) {
+        return Ok(path);
+    }
+    let sto = storage.ok_or_else(|| {
+        "job failed: disk image not local and no P2P storage available".to_string()
+    })?;
+    let bytes = sto
+        .get(hex.to_string(), Duration::from_secs(60))
+        .await
+        .ok_or_else(|| format!("job failed: disk image not available via P2P: {hex}"))?;
+    if common::sha256_hex(&bytes) != hex {
+        return Err("job failed: disk image digest mismatch".to_string());
+    }
+    let digest = store
+        .put_bytes(&bytes)
+        .map_err(|e| format!("cas put failed: {e}"))?;
+    store
+        .get_path(&digest)
+        .ok_or_else(|| "cas path missing".to_string())
+}
+
+/// qcow2 overlay backed by `base`; the VM writes here and never to the CAS.
+async fn create_overlay(qemu_img: &Path, base: &Path, dir: &Path) -> Result<PathBuf, String> {
+    let info = Command::new(qemu_img)
+        .args(["info", "--output=json"])
+        .arg(base)
+        .output()
+        .await
+        .map_err(|e| format!("qemu-img: {e}"))?;
+    if !info.status.success() {
+        return Err(format!(
+            "job failed: qemu-img info: {}",
+            String::from_utf8_lossy(&info.stderr).trim()
+        ));
+    }
+    let format = serde_json::from_slice::<serde_json::Value>(&info.stdout)
+        .ok()
+        .and_then(|v| v.get("format")?.as_str().map(str::to_string))
+        .ok_or_else(|| "job failed: qemu-img could not detect the image format".to_string())?;
+
+    let overlay = dir.join("disk.qcow2");
+    let out = Command::new(qemu_img)
+        .args(["create", "-f", "qcow2", "-F", &format, "-b"])
+        .arg(base)
+        .arg(&overlay)
+        .output()
+        .await
+        .map_err(|e| format!("qemu-img: {e}"))?;
+    if !out.status.success() {
+        return Err(format!(
+            "job failed: qemu-img create: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(overlay)
+}
+
+/// Write the NoCloud `meta-data` and `user-data` into `dir`. The guest mounts
+/// the workspace, loads the job environment and, when there is a command,
+/// runs it, records its exit status in the workspace and powers off.
+async fn write_seed(
+    dir: &Path,
+    job_id: &str,
+    job_name: &str,
+    args: &[String],
+    env: &BTreeMap<String, String>,
+    workdir: &str,
+) -> Result<(), String> {
+    let mut env_file = String::new();
+    for (k, v) in env {
+        let valid = !k.is_empty()
+            && !k.starts_with(|c: char| c.is_ascii_digit())
+            && k.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!(
+                "job failed: invalid environment variable name {k:?}"
+            ));
+        }
+        env_file.push_str(&format!("{k}={}\n", sh_quote(v)));
+    }
+
+    let mut script = format!(
+        "#!/bin/sh\n\
+         # Generated by realm for job {job_name}\n\
+         exec >/dev/console 2>&1\n\
+         mkdir -p {root}\n\
+         mount -t 9p -o trans=virtio,version=9p2000.L,msize=262144 {SHARE_TAG} {root} || {{ echo 'realm: cannot mount the job workspace'; poweroff; exit 1; }}\n\
+         set -a; . /etc/realm/job.env; set +a\n",
+        root = GUEST_ROOT,
+    );
+    if !args.is_empty() {
+        let command: Vec<String> = args.iter().map(|a| sh_quote(a)).collect();
+        script.push_str(&format!(
+            "mkdir -p {dir} && cd {dir} && {command}\n\
+             echo $? > {root}/{EXIT_FILE}\n\
+             sync\n\
+             poweroff\n",
+            dir = sh_quote(workdir),
+            command = command.join(" "),
+            root = GUEST_ROOT,
+        ));
+    }
+
+    let b64 = |s: &str| base64::engine::general_purpose::STANDARD.encode(s);
+    let user_data = format!(
+        "#cloud-config\n\
+         write_files:\n  \
+           - path: /etc/realm/job.env\n    permissions: '0600'\n    encoding: b64\n    content: {}\n  \
+           - path: /etc/realm/run.sh\n    permissions: '0755'\n    encoding: b64\n    content: {}\n\
+         runcmd:\n  \
+           - [/etc/realm/run.sh]\n",
+        b64(&env_file),
+        b64(&script)
+    );
+    let hostname: String = job_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(63)
+        .collect::<String>()
+        .trim_matches('-')
+        .to_string();
+    let meta_data = format!("instance-id: {job_id}\nlocal-hostname: {hostname}\n");
+
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("seed dir: {e}"))?;
+    for (name, content) in [("user-data", user_data), ("meta-data", meta_data)] {
+        tokio::fs::write(dir.join(name), content)
+            .await
+            .map_err(|e| format!("seed {name}: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Pack the seed into an ISO labelled `cidata` with whichever tool is
+/// installed, falling back to QEMU's virtual FAT drive. Returns the `-drive`
+/// option for it.
+async fn build_seed(dir: &Path, iso: &Path) -> Result<String, String> {
+    let files = [dir.join("user-data"), dir.join("meta-data")];
+    let mkisofs_args = |prefix: &[&str]| -> Vec<OsString> {
+        prefix
+            .iter()
+            .chain(&["-volid", "cidata", "-joliet", "-rock", "-output"])
+            .map(OsString::from)
+            .chain(std::iter::once(iso.as_os_str().to_owned()))
+            .chain(files.iter().map(|f| f.as_os_str().to_owned()))
+            .collect()
+    };
+    let candidates: [(&str, Vec<OsString>); 4] = [
+        (
+            "cloud-localds",
+            std::iter::once(iso.as_os_str().to_owned())
+                .chain(files.iter().map(|f| f.as_os_str().to_owned()))
+                .collect(),
+        ),
+        ("genisoimage", mkisofs_args(&[])),
+        ("mkisofs", mkisofs_args(&[])),
+        ("xorriso", mkisofs_args(&["-as", "mkisofs"])),
+    ];
+    for (tool, args) in candidates {
+        let Ok(path) = which::which(tool) else {
+            continue;
+        };
+        let ok = Command::new(path)
+            .args(&args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .map(|s| s.success())
+            .unwrap_or(false);
+        if ok {
+            return Ok(format!(
+                "file={},if=virtio,format=raw,readonly=on",
+                escape_opt(&iso.display().to_string())
+            ));
+        }
+    }
+    Ok(format!(
+        "driver=vvfat,dir={},label=cidata,if=virtio,readonly=on",
+        escape_opt(&dir.display().to_string())
+    ))
+}
+
+async fn forward_lines<R: AsyncRead + Unpin>(
+    reader: R,
+    tx: tokio::sync::mpsc::UnboundedSender<String>,
+) {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&buf)
+                    .trim_end_matches(['\r', '\n'])
+                    .to_string();
+                if !line.is_empty() && tx.send(line).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn log_console(
+    job_mgr: &JobManager,
+    job_id: &str,
+    logs: &metrics::SharedLogs,
+    label: &str,
+    line: String,
+) {
+    metrics::push_log(logs, label, format!("console: {line}")).await;
+    let _ = job_mgr
+        .add_job_log(job_id, "info".to_string(), format!("console: {line}"))
+        .await;
+}
+
+/// Ask the guest for an ACPI power-off through the QMP socket.
+#[cfg(unix)]
+/// Ask the guest to power off, killing QEMU when QMP is unreachable or the
+/// guest is still up after [`SHUTDOWN_GRACE`].
+async fn power_off(child: &mut Child, qmp_socket: &Path, logs: &metrics::SharedLogs, label: &str) {
+    use crate::p2p::metrics::push_log;
+
+    push_log(logs, label, "requesting guest shutdown".to_string()).await;
+    if let Err(e) = qmp_powerdown(qmp_socket).await {
+        push_log(logs, label, format!("qmp: {e}; killing VM")).await;
+        let _ = child.start_kill();
+    } else if tokio::time::timeout(SHUTDOWN_GRACE, child.wait())
+        .await
+        .is_err()
+    {
+        push_log(
+            logs,
+            label,
+            "guest did not power off; killing VM".to_string(),
+        )
+        .await;
+        let _ = child.start_kill();
+    }
+    let _ = child.wait().await;
+}
+
+async fn qmp_powerdown(socket: &Path) -> Result<(), String> {
+    use tokio::io::AsyncWriteExt;
+
+    async fn reply(
+        lines: &mut tokio::io::Lines<BufReader<tokio::net::unix::OwnedReadHalf>>,
+    ) -> Result<(), String> {
+        // Skip asynchronous events until the command's reply arrives
+        while let Some(line) = lines.next_line().await.map_err(|e| e.to_string())? {
+            let msg: serde_json::Value = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+            if msg.get("return").is_some() {
+                return Ok(());
+            }
+            if let Some(err) = msg.get("error") {
+                return Err(err.to_string());
+            }
+        }
+        Err("connection closed".to_string())
+    }
+
+    let exchange = async {
+        let stream = tokio::net::UnixStream::connect(socket)
+            .await
+            .map_err(|e| e.to_string())?;
+        let (rd, mut wr) = stream.into_split();
+        let mut lines = BufReader::new(rd).lines();
+        // Greeting
+        lines.next_line().await.map_err(|e| e.to_string())?;
+        for command in ["qmp_capabilities", "system_powerdown"] {
+            wr.write_all(format!("{{\"execute\":\"{command}\"}}\n").as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+            reply(&mut lines).await?;
+        }
+        Ok::<(), String>(())
+    };
+    tokio::time::timeout(Duration::from_secs(5), exchange)
+        .await
+        .map_err(|_| "timed out".to_string())?
+}
+
+#[cfg(not(unix))]
+async fn qmp_powerdown(_socket: &Path) -> Result<(), String> {
+    Err("QMP control needs Unix sockets".to_string())
+}
+
+fn normalize_arch(arch: &str) -> String {
+    match arch {
+        "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        other => other,
+    }
+    .to_string()
+}
+
+/// Hardware acceleration when the guest matches the host, TCG otherwise,
+/// plus the usual machine type for the architecture.
+fn accel_args(arch: &str) -> Vec<String> {
+    let native = arch == std::env::consts::ARCH;
+    let kvm = native
+        && cfg!(target_os = "linux")
+        && std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/kvm")
+            .is_ok();
+    let hvf = native && cfg!(target_os = "macos");
+    let mut args: Vec<String> = match (kvm, hvf) {
+        (true, _) => vec!["-accel".into(), "kvm".into(), "-cpu".into(), "host".into()],
+        (_, true) => vec!["-accel".into(), "hvf".into(), "-cpu".into(), "host".into()],
+        _ => vec!["-accel".into(), "tcg".into(), "-cpu".into(), "max".into()],
+    };
+    match arch {
+        "x86_64" => args.extend(["-machine".into(), "q35".into()]),
+        "aarch64" | "arm" | "riscv64" => args.extend(["-machine".into(), "virt".into()]),
+        _ => {}
+    }
+    args
+}
+
+/// A loopback port that is free right now, for ports without a `host_port`.
+fn free_port(protocol: Protocol) -> Result<u16, String> {
+    let port = match protocol {
+        Protocol::Tcp => std::net::TcpListener::bind(("127.0.0.1", 0)).and_then(|l| l.local_addr()),
+        Protocol::Udp => std::net::UdpSocket::bind(("127.0.0.1", 0)).and_then(|s| s.local_addr()),
+    };
+    port.map(|a| a.port())
+        .map_err(|e| format!("job failed: allocate host port: {e}"))
+}
+
+/// Commas separate QEMU option values; literal ones are doubled.
+fn escape_opt(value: &str) -> String {
+    value.replace(',', ",,")
+}
+
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
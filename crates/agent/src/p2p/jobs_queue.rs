@@ -0,0 +1,366 @@
+//! Per-node job slots. Each runtime gets the number of slots set in the
+//! node policy (`job_slots`); a job claimed while all slots of its runtime
+//! are busy waits in a local queue, highest priority first and then in the
+//! order the jobs arrived. The agent refreshes the limits from the policy
+//! with every status heartbeat, so changing them does not need a restart.
+//!
+//! When the first job in line has a higher priority than a running
+//! `preemptible` job of the same runtime, that job is told to stop and
+//! give up its slot; its task requeues it. One job is evicted at a time.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::JobSlotUsage;
+use tokio::sync::{oneshot, Notify};
+
+use crate::policy::JobSlotLimits;
+
+/// How long a node without a free slot waits before claiming a job that a
+/// peer advertises a free slot for, so the idle peer gets it first.
+pub const BUSY_CLAIM_DELAY: Duration = Duration::from_secs(2);
+
+struct Waiter {
+    seq: u64,
+    job_id: String,
+    runtime: &'static str,
+    priority: i32,
+}
+
+/// A job holding a slot.
+struct Holder {
+    seq: u64,
+    runtime: &'static str,
+    priority: i32,
+    /// Set for preemptible jobs until they are asked to stop
+    preempt: Option<oneshot::Sender<String>>,
+    evicting: bool,
+}
+
+#[derive(Default)]
+struct SlotState {
+    limits: JobSlotLimits,
+    busy: HashMap<&'static str, u32>,
+    waiting: Vec<Waiter>,
+    holders: Vec<Holder>,
+    next_seq: u64,
+}
+
+pub struct JobSlots {
+    state: Mutex<SlotState>,
+    freed: Notify,
+}
+
+/// A taken slot; dropping it frees the slot for the next queued job.
+pub struct SlotPermit {
+    slots: Arc<JobSlots>,
+    runtime: &'static str,
+    seq: u64,
+    preempted: Option<oneshot::Receiver<String>>,
+}
+
+impl SlotPermit {
+    /// Resolves with the reason once a higher-priority job needs this slot;
+    /// never resolves for jobs that are not preemptible.
+    pub async fn preempted(&mut self) -> String {
+        match self.preempted.as_mut() {
+            Some(rx) => match rx.await {
+                Ok(reason) => reason,
+                Err(_) => std::future::pending().await,
+            },
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.slots.state.lock() {
+            if let Some(busy) = state.busy.get_mut(self.runtime) {
+                *busy = busy.saturating_sub(1);
+            }
+            state.holders.retain(|h| h.seq != self.seq);
+        }
+        self.slots.freed.notify_waiters();
+    }
+}
+
+/// Takes a waiter out of the queue when its wait ends without a slot.
+struct LeaveQueue<'a> {
+    slots: &'a JobSlots,
+    seq: u64,
+}
+
+impl Drop for LeaveQueue<'_> {
+    fn drop(&mut self) {
+        let left = match self.slots.state.lock() {
+            Ok(mut state) => {
+                let before = state.waiting.len();
+                state.waiting.retain(|w| w.seq != self.seq);
+                before != state.waiting.len()
+            }
+            Err(_) => false,
+        };
+        // The next waiter may have been blocked behind this one
+        if left {
+            self.slots.freed.notify_waiters();
+        }
+    }
+}
+
+impl JobSlots {
+    pub fn new(limits: JobSlotLimits) -> Self {
+        Self {
+            state: Mutex::new(SlotState {
+                limits,
+                ..Default::default()
+            }),
+            freed: Notify::new(),
+        }
+    }
+
+    /// Replace the slot limits; queued jobs start if the new ones leave room.
+    pub fn set_limits(&self, limits: JobSlotLimits) {
+        if let Ok(mut state) = self.state.lock() {
+            state.limits = limits;
+        }
+        self.freed.notify_waiters();
+    }
+
+    /// Whether a job of `runtime` would start right away.
+    pub fn has_free(&self, runtime: &'static str) -> bool {
+        self.state
+            .lock()
+            .map(|state| {
+                state.busy.get(runtime).copied().unwrap_or(0) < state.limits.limit(runtime)
+                    && !state.waiting.iter().any(|w| w.runtime == runtime)
+            })
+            .unwrap_or(true)
+    }
+
+    /// Take a slot for the job, waiting in the queue while none is free.
+    /// Dropping the future leaves the queue.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        job_id: &str,
+        runtime: &'static str,
+        priority: i32,
+        preemptible: bool,
+    ) -> SlotPermit {
+        let seq = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                seq,
+                job_id: job_id.to_string(),
+                runtime,
+                priority,
+            });
+            seq
+        };
+        let _leave = LeaveQueue { slots: self, seq };
+        loop {
+            // Register before checking so a slot freed in between is not missed
+            let freed = self.freed.notified();
+            tokio::pin!(freed);
+            freed.as_mut().enable();
+            if self.try_take(seq, runtime) {
+                let (preempt, preempted) = if preemptible {
+                    let (tx, rx) = oneshot::channel();
+                    (Some(tx), Some(rx))
+                } else {
+                    (None, None)
+                };
+                if let Ok(mut state) = self.state.lock() {
+                    state.holders.push(Holder {
+                        seq,
+                        runtime,
+                        priority,
+                        preempt,
+                        evicting: false,
+                    });
+                }
+                return SlotPermit {
+                    slots: self.clone(),
+                    runtime,
+                    seq,
+                    preempted,
+                };
+            }
+            freed.await;
+        }
+    }
+
+    /// Give the waiter `seq` a slot when one is free and it is first in line.
+    /// Without a free slot, ask a lower-priority preemptible job to make room.
+    fn try_take(&self, seq: u64, runtime: &'static str) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let limit = state.limits.limit(runtime);
+        let first = state
+            .waiting
+            .iter()
+            .filter(|w| w.runtime == runtime)
+            .min_by_key(|w| (std::cmp::Reverse(w.priority), w.seq))
+            .map(|w| (w.seq, w.priority, w.job_id.clone()));
+        let Some((_, priority, job_id)) = first.filter(|f| f.0 == seq) else {
+            return false;
+        };
+        let busy = state.busy.get(runtime).copied().unwrap_or(0);
+        if busy >= limit {
+            let evicting = state
+                .holders
+                .iter()
+                .any(|h| h.runtime == runtime && h.evicting);
+            let victim = state
+                .holders
+                .iter_mut()
+                .filter(|h| h.runtime == runtime && h.preempt.is_some() && h.priority < priority)
+                // Lowest priority first, then the most recently started
+                .min_by_key(|h| (h.priority, std::cmp::Reverse(h.seq)));
+            if let (false, Some(victim)) = (evicting, victim) {
+                if let Some(tx) = victim.preempt.take() {
+                    victim.evicting = true;
+                    let _ = tx.send(format!("preempted by {job_id} (priority {priority})"));
+                }
+            }
+            return false;
+        }
+        state.waiting.retain(|w| w.seq != seq);
+        *state.busy.entry(runtime).or_insert(0) += 1;
+        let next_in_line = state.waiting.iter().any(|w| w.runtime == runtime);
+        drop(state);
+        // The next job in line may now need to evict a preemptible job
+        if next_in_line {
+            self.freed.notify_waiters();
+        }
+        true
+    }
+
+    /// Queued job ids of a runtime, in the order they will start.
+    pub fn queued(&self, runtime: &str) -> Vec<String> {
+        let Ok(state) = self.state.lock() else {
+            return Vec::new();
+        };
+        let mut waiting: Vec<&Waiter> = state
+            .waiting
+            .iter()
+            .filter(|w| w.runtime == runtime)
+            .collect();
+        waiting.sort_by_key(|w| (std::cmp::Reverse(w.priority), w.seq));
+        waiting.into_iter().map(|w| w.job_id.clone()).collect()
+    }
+
+    /// Slot use per runtime, for the status heartbeat.
+    pub fn usage(&self) -> Vec<JobSlotUsage> {
+        let Ok(state) = self.state.lock() else {
+            return Vec::new();
+        };
+        JobSlotLimits::RUNTIMES
+            .iter()
+            .map(|runtime| JobSlotUsage {
+                runtime: runtime.to_string(),
+                total: state.limits.limit(runtime),
+                busy: state.busy.get(runtime).copied().unwrap_or(0),
+                queued: state
+                    .waiting
+                    .iter()
+                    .filter(|w| w.runtime == *runtime)
+                    .count() as u32,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Started = tokio::sync::mpsc::UnboundedSender<(&'static str, SlotPermit)>;
+
+    fn slots(wasm: u32) -> Arc<JobSlots> {
+        Arc::new(JobSlots::new(JobSlotLimits {
+            wasm: Some(wasm),
+            ..Default::default()
+        }))
+    }
+
+    /// Queue `id` behind the held slots; its permit is sent on `started`
+    /// once it gets a slot.
+    async fn enqueue(slots: &Arc<JobSlots>, id: &'static str, priority: i32, started: &Started) {
+        let before = slots.queued("wasm").len();
+        let (s, started) = (slots.clone(), started.clone());
+        tokio::spawn(async move {
+            let permit = s.acquire(id, "wasm", priority, false).await;
+            let _ = started.send((id, permit));
+        });
+        while slots.queued("wasm").len() == before {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn queue_starts_higher_priority_first_then_in_order() {
+        let slots = slots(1);
+        let held = slots.acquire("held", "wasm", 0, false).await;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        enqueue(&slots, "low", 0, &tx).await;
+        enqueue(&slots, "high-1", 5, &tx).await;
+        enqueue(&slots, "high-2", 5, &tx).await;
+        assert_eq!(slots.queued("wasm"), ["high-1", "high-2", "low"]);
+        assert!(!slots.has_free("wasm"));
+
+        drop(held);
+        let mut order = Vec::new();
+        for _ in 0..3 {
+            // Each job frees its slot before the next one is awaited
+            let (id, _permit) = rx.recv().await.unwrap();
+            order.push(id);
+        }
+        assert_eq!(order, ["high-1", "high-2", "low"]);
+        assert!(slots.has_free("wasm"));
+    }
+
+    #[tokio::test]
+    async fn preempts_one_job_at_a_time() {
+        let slots = slots(2);
+        let mut lowest = slots.acquire("lowest", "wasm", 0, true).await;
+        let mut low = slots.acquire("low", "wasm", 1, true).await;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        enqueue(&slots, "urgent-1", 10, &tx).await;
+        enqueue(&slots, "urgent-2", 10, &tx).await;
+
+        let wait = Duration::from_millis(100);
+        let reason = tokio::time::timeout(wait, lowest.preempted()).await;
+        assert!(reason.unwrap().contains("urgent-1"));
+        assert!(tokio::time::timeout(wait, low.preempted()).await.is_err());
+
+        // Once the evicted job gives up its slot, the next job in line evicts another
+        drop(lowest);
+        let (id, _urgent_1) = rx.recv().await.unwrap();
+        assert_eq!(id, "urgent-1");
+        let reason = tokio::time::timeout(wait, low.preempted()).await;
+        assert!(reason.unwrap().contains("urgent-2"));
+        drop(low);
+        let (id, _urgent_2) = rx.recv().await.unwrap();
+        assert_eq!(id, "urgent-2");
+    }
+
+    #[tokio::test]
+    async fn dropped_acquire_leaves_the_queue() {
+        let slots = slots(1);
+        let held = slots.acquire("held", "wasm", 0, false).await;
+        let mut first = Box::pin(slots.acquire("first", "wasm", 5, false));
+        assert!(futures::poll!(first.as_mut()).is_pending());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        enqueue(&slots, "second", 0, &tx).await;
+        assert_eq!(slots.queued("wasm"), ["first", "second"]);
+
+        drop(first);
+        assert_eq!(slots.queued("wasm"), ["second"]);
+        drop(held);
+        let (id, _permit) = rx.recv().await.unwrap();
+        assert_eq!(id, "second");
+    }
+}
@@ -0,0 +1,169 @@
+//! Supervision helpers for `service` jobs: health probes, restart backoff,
+//! and recovery of services whose node went away or whose agent restarted.
+//! Recurring jobs are recovered the same way, so their schedule moves to a
+//! live node.
+
+use std::time::Duration;
+
+use common::{Command, HealthCheck, JobInstance, JobService, JobStatus};
+use tokio::net::TcpStream;
+
+use super::jobs::{accept_job, start_service_job, JobDispatch};
+use crate::job_manager::JobManager;
+use crate::p2p::metrics::push_log;
+
+/// A run that lasted this long resets the consecutive failure count.
+pub const STABLE_AFTER: Duration = Duration::from_secs(60);
+/// Longest wait between restarts.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// How often a running service checks it is still assigned to this node.
+const OWNER_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// A node silent for this long loses its service and recurring jobs to
+/// other nodes.
+pub const RELOCATE_AFTER: Duration = Duration::from_secs(60);
+/// Upper bound on the per-node delay before claiming a stranded job.
+const CLAIM_JITTER_MS: u64 = 5000;
+
+/// Why a run was stopped from outside.
+pub enum Stop {
+    Cancelled,
+    Unhealthy(String),
+    /// The job was assigned to another node
+    Displaced(String),
+    /// A higher-priority job took the slot
+    Preempted(String),
+}
+
+/// Delay before the next run after `failures` consecutive failures.
+pub fn backoff(service: &JobService, failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    let secs = service
+        .backoff_secs
+        .max(1)
+        .saturating_mul(1u64 << doublings);
+    Duration::from_secs(secs).min(MAX_BACKOFF)
+}
+
+/// Resolves once the health check has failed `failure_threshold` times in a
+/// row; never resolves without a check.
+pub async fn unhealthy(check: Option<&HealthCheck>) -> String {
+    let Some(check) = check else {
+        return std::future::pending().await;
+    };
+    tokio::time::sleep(Duration::from_secs(check.grace_secs)).await;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(check.timeout_secs.max(1)))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .ok();
+    let mut failed = 0u32;
+    loop {
+        match probe(check, client.as_ref()).await {
+            Ok(()) => failed = 0,
+            Err(e) => {
+                failed += 1;
+                if failed >= check.failure_threshold.max(1) {
+                    return format!("health check failed {failed} times in a row: {e}");
+                }
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(check.interval_secs.max(1))).await;
+    }
+}
+
+async fn probe(check: &HealthCheck, client: Option<&reqwest::Client>) -> Result<(), String> {
+    let timeout = Duration::from_secs(check.timeout_secs.max(1));
+    match (&check.path, client) {
+        (Some(path), Some(client)) => {
+            let url = format!(
+                "http://127.0.0.1:{}/{}",
+                check.port,
+                path.trim_start_matches('/')
+            );
+            let status = client
+                .get(&url)
+                .send()
+                .await
+                .map_err(|e| format!("GET {url}: {e}"))?
+                .status();
+            if status.is_success() || status.is_redirection() {
+                Ok(())
+            } else {
+                Err(format!("GET {url}: {status}"))
+            }
+        }
+        _ => match tokio::time::timeout(timeout, TcpStream::connect(("127.0.0.1", check.port)))
+            .await
+        {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(format!("connect 127.0.0.1:{}: {e}", check.port)),
+            Err(_) => Err(format!("connect 127.0.0.1:{}: timed out", check.port)),
+        },
+    }
+}
+
+/// Resolves with the new node once the job is assigned to another node, so
+/// a node that was cut off does not keep a second copy running.
+pub async fn displaced(job_mgr: &JobManager, job_id: &str) -> String {
+    loop {
+        tokio::time::sleep(OWNER_CHECK_INTERVAL).await;
+        if let Some(node) = job_mgr.get_job(job_id).await.and_then(|j| j.assigned_node) {
+            if node != job_mgr.node_id() {
+                return node;
+            }
+        }
+    }
+}
+
+/// Restart a stranded job: resume a service here when it was ours, or
+/// resubmit the job to the mesh and compete for it when its node went silent.
+pub async fn recover(d: JobDispatch, job: JobInstance) {
+    let Some(node) = job.assigned_node.clone() else {
+        return;
+    };
+    if node == d.node_id {
+        let _ = d
+            .job_mgr
+            .add_job_log(
+                &job.id,
+                "warn".to_string(),
+                "Agent restarted; resuming service".to_string(),
+            )
+            .await;
+        start_service_job(d, job.id, job.spec, Duration::ZERO).await;
+        return;
+    }
+
+    // Every surviving node notices; stagger the claims and skip the job if
+    // someone else took it over meanwhile
+    let h = common::sha256_hex(d.node_id.as_bytes());
+    let jitter = u64::from_str_radix(&h[..4], 16).unwrap_or(0) % CLAIM_JITTER_MS;
+    tokio::time::sleep(Duration::from_millis(jitter)).await;
+    let still_stranded = d.job_mgr.get_job(&job.id).await.is_some_and(|j| {
+        j.status == JobStatus::Running && j.assigned_node.as_deref() == Some(node.as_str())
+    });
+    if !still_stranded {
+        return;
+    }
+
+    push_log(
+        &d.logs,
+        "system",
+        format!("Node {node} stopped reporting; relocating job {}", job.id),
+    )
+    .await;
+    let _ = d.broadcast_tx.send(Command::SubmitJob {
+        origin_node_id: job.origin_node_id.clone(),
+        job_id: job.id.clone(),
+        spec: job.spec.clone(),
+    });
+    let job_mgr = d.job_mgr.clone();
+    accept_job(d, job.origin_node_id, job.id.clone(), job.spec).await;
+    let _ = job_mgr
+        .add_job_log(
+            &job.id,
+            "warn".to_string(),
+            format!("Relocated: node {node} stopped reporting"),
+        )
+        .await;
+}
@@ -1,4 +1,6 @@
+use crate::host::HostState;
 use crate::job_manager::JobManager;
+use crate::p2p::jobs_workspace::{JobWorkspace, GUEST_ROOT};
 use crate::p2p::{handlers, metrics};
 use crate::storage::ContentStore;
 
@@ -12,6 +14,8 @@ pub async fn execute_wasm_job(
     fuel: u64,
     epoch_ms: u64,
     mounts: Option<Vec<common::MountSpec>>,
+    egress: Option<common::EgressPolicy>,
+    mut guest: crate::runner::GuestEnv,
     logs: &metrics::SharedLogs,
     cancel_rx: Option<&mut tokio::sync::oneshot::Receiver<()>>,
     storage: Option<crate::p2p::storage::P2PStorage>,
@@ -91,31 +95,39 @@ pub async fn execute_wasm_job(
     }
 
     // Pre-stage attachments if requested (write blobs to host before execution)
-    for item in job.execution.pre_stage.iter() {
-        if let Some(hex) = item.source.strip_prefix("cas:") {
-            let store = ContentStore::open();
-            let bytes = if let Some(path) = store.get_path(hex) {
-                tokio::fs::read(path)
-                    .await
-                    .map_err(|e| format!("prestage cas read failed: {e}"))?
-            } else if let Some(sto) = &storage {
-                if let Some(bytes) = sto
-                    .get(hex.to_string(), std::time::Duration::from_secs(5))
-                    .await
-                {
-                    bytes
-                } else {
-                    return Err(format!("prestage: digest not available via P2P: {hex}"));
-                }
-            } else {
-                return Err("prestage: digest not local and no P2P storage available".to_string());
+    let workspace = JobWorkspace::create(job_id)
+        .await?
+        .with_guest_root(GUEST_ROOT);
+    workspace
+        .prestage(&job.execution.pre_stage, storage.as_ref())
+        .await?;
+    // The guest sees its workspace at a fixed path
+    let mut mounts = mounts.unwrap_or_default();
+    mounts.push(common::MountSpec {
+        host: workspace.root().display().to_string(),
+        guest: GUEST_ROOT.to_string(),
+        ro: false,
+    });
+    let mounts = Some(mounts);
+    guest.env = workspace.env().into_iter().chain(guest.env).collect();
+
+    // Make a cas: stdin blob local so the runner can read it
+    if let Some(hex) = guest.stdin.as_deref().and_then(|s| s.strip_prefix("cas:")) {
+        let store = ContentStore::open();
+        if store.get_path(hex).is_none() {
+            let Some(sto) = &storage else {
+                return Err("stdin: digest not local and no P2P storage available".to_string());
             };
-            if let Some(parent) = std::path::Path::new(&item.dest).parent() {
-                let _ = tokio::fs::create_dir_all(parent).await;
-            }
-            tokio::fs::write(&item.dest, &bytes)
+            let bytes = sto
+                .get(hex.to_string(), std::time::Duration::from_secs(5))
                 .await
-                .map_err(|e| format!("prestage write failed: {e}"))?;
+                .ok_or_else(|| format!("stdin: digest not available via P2P: {hex}"))?;
+            if common::sha256_hex(&bytes) != hex {
+                return Err("stdin: digest mismatch".to_string());
+            }
+            store
+                .put_bytes(&bytes)
+                .map_err(|e| format!("stdin: cas put failed: {e}"))?;
         }
     }
 
@@ -152,7 +164,7 @@ pub async fn execute_wasm_job(
         let file_path_str = file_path.display().to_string();
         tokio::select! {
             res = crate::runner::run_wasm_module_with_limits(
-                &file_path_str, &label, logs.clone(), memory_mb, fuel, epoch_ms, None, mounts,
+                &file_path_str, &label, logs.clone(), memory_mb, fuel, epoch_ms, None, mounts, None, egress, guest, HostState::new(&label, &[], logs.clone()),
             ) => res,
             _ = cancel_rx => {
                 let _ = job_mgr.add_job_log(job_id, "warn".to_string(), "Service job cancelled during execution".to_string()).await;
@@ -170,6 +182,10 @@ pub async fn execute_wasm_job(
             epoch_ms,
             None,
             mounts,
+            None,
+            egress,
+            guest,
+            HostState::new(&label, &[], logs.clone()),
         )
         .await
     };
@@ -177,27 +193,9 @@ pub async fn execute_wasm_job(
     match result {
         Ok(_) => {
             let success_msg = format!("job ok: {}", job.name);
-            if let Some(exec) = &job.execution.artifacts {
-                for art in exec.iter() {
-                    let stored = art.path.clone();
-                    let meta = tokio::fs::metadata(&stored).await.ok();
-                    let size =
-                        meta.as_ref()
-                            .and_then(|m| if m.is_file() { Some(m.len()) } else { None });
-                    let artifact = common::JobArtifact {
-                        name: art.name.clone().unwrap_or_else(|| stored.clone()),
-                        stored_path: stored,
-                        size_bytes: size,
-                        sha256_hex: None,
-                    };
-                    let _ = job_mgr.add_job_artifact(job_id, artifact).await;
-                }
-                if let Some(job_instance) = job_mgr.get_job(job_id).await {
-                    let _ = job_mgr
-                        .stage_artifacts(job_id, &job_instance.artifacts)
-                        .await;
-                }
-            }
+            workspace
+                .capture_artifacts(job_mgr, job_id, job.execution.artifacts.as_ref())
+                .await;
             Ok(success_msg)
         }
         Err(e) => Err(format!("job error: {}: {}", job.name, e)),
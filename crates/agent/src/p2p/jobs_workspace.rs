@@ -0,0 +1,226 @@
+//! Per-run job workspaces. Each execution gets a fresh directory under the
+//! agent data dir with `assets/` for attachments and `artifacts/` for
+//! outputs. Relative pre-stage destinations and artifact paths resolve
+//! against it, and it is removed once artifacts have been captured.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::job_manager::JobManager;
+use crate::storage::ContentStore;
+
+/// Guest path the workspace is mounted at for wasm and container jobs.
+pub const GUEST_ROOT: &str = "/workspace";
+
+pub struct JobWorkspace {
+    root: PathBuf,
+    /// Where the job sees `root`; differs from it for wasm and container jobs
+    guest_root: PathBuf,
+}
+
+impl JobWorkspace {
+    pub async fn create(job_id: &str) -> Result<Self, String> {
+        Self::create_in(&workspaces_dir(), job_id).await
+    }
+
+    /// Create the workspace where other local users can reach it, for jobs
+    /// that drop privileges (the agent data dir is usually private). The
+    /// caller hands it to the job's user once it is populated.
+    pub async fn create_shared(job_id: &str) -> Result<Self, String> {
+        let base = shared_workspaces_dir();
+        tokio::fs::create_dir_all(&base)
+            .await
+            .map_err(|e| format!("job workspace {}: {e}", base.display()))?;
+        // Traversable, but nobody else can list the runs
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = tokio::fs::set_permissions(&base, std::fs::Permissions::from_mode(0o711)).await;
+        }
+        Self::create_in(&base, job_id).await
+    }
+
+    async fn create_in(base: &Path, job_id: &str) -> Result<Self, String> {
+        let run = uuid::Uuid::new_v4().simple().to_string();
+        let root = base.join(format!("{}-{}", sanitize(job_id), &run[..8]));
+        for dir in [root.join("assets"), root.join("artifacts")] {
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .map_err(|e| format!("job workspace {}: {e}", dir.display()))?;
+        }
+        Ok(Self {
+            guest_root: root.clone(),
+            root,
+        })
+    }
+
+    /// Expose the workspace to the job at `guest_root` instead of its host path.
+    pub fn with_guest_root(mut self, guest_root: impl Into<PathBuf>) -> Self {
+        self.guest_root = guest_root.into();
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Host-only directory next to the workspace for files the job itself
+    /// must not see (VM disks, control sockets). Removed with the workspace.
+    pub async fn scratch_dir(&self) -> Result<PathBuf, String> {
+        let dir = self.root.with_extension("scratch");
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| format!("job scratch dir {}: {e}", dir.display()))?;
+        Ok(dir)
+    }
+
+    /// `REALM_WORKSPACE`, `REALM_ASSETS` and `REALM_ARTIFACTS` as the job sees them.
+    pub fn env(&self) -> BTreeMap<String, String> {
+        let path = |p: PathBuf| p.display().to_string();
+        BTreeMap::from([
+            ("REALM_WORKSPACE".to_string(), path(self.guest_root.clone())),
+            (
+                "REALM_ASSETS".to_string(),
+                path(self.guest_root.join("assets")),
+            ),
+            (
+                "REALM_ARTIFACTS".to_string(),
+                path(self.guest_root.join("artifacts")),
+            ),
+        ])
+    }
+
+    /// Host path for a pre-stage destination or artifact path. Relative
+    /// paths (and guest paths inside the workspace) land in the workspace;
+    /// other absolute paths are used as they are.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let p = Path::new(path);
+        let rel = if let Ok(inner) = p.strip_prefix(&self.guest_root) {
+            inner
+        } else if p.is_absolute() {
+            return Ok(p.to_path_buf());
+        } else {
+            p
+        };
+        if rel.components().any(|c| matches!(c, Component::ParentDir)) {
+            return Err(format!("path {path} leaves the job workspace"));
+        }
+        Ok(self.root.join(rel))
+    }
+
+    /// Write `cas:` pre-stage blobs to their destinations.
+    pub async fn prestage(
+        &self,
+        items: &[common::PreStageSpec],
+        storage: Option<&crate::p2p::storage::P2PStorage>,
+    ) -> Result<(), String> {
+        for item in items {
+            let Some(hex) = item.source.strip_prefix("cas:") else {
+                continue;
+            };
+            let store = ContentStore::open();
+            let bytes = if let Some(path) = store.get_path(hex) {
+                tokio::fs::read(path)
+                    .await
+                    .map_err(|e| format!("prestage cas read failed: {e}"))?
+            } else if let Some(sto) = storage {
+                sto.get(hex.to_string(), std::time::Duration::from_secs(5))
+                    .await
+                    .ok_or_else(|| format!("prestage: digest not available via P2P: {hex}"))?
+            } else {
+                return Err("prestage: digest not local and no P2P storage available".to_string());
+            };
+            let dest = self.resolve(&item.dest)?;
+            if let Some(parent) = dest.parent() {
+                let _ = tokio::fs::create_dir_all(parent).await;
+            }
+            tokio::fs::write(&dest, &bytes)
+                .await
+                .map_err(|e| format!("prestage write failed: {e}"))?;
+        }
+        Ok(())
+    }
+
+    /// Record the job's declared artifacts and copy them out of the
+    /// workspace into the job's artifact store.
+    pub async fn capture_artifacts(
+        &self,
+        job_mgr: &JobManager,
+        job_id: &str,
+        specs: Option<&Vec<common::ArtifactSpec>>,
+    ) {
+        let Some(specs) = specs else {
+            return;
+        };
+        for art in specs {
+            let stored = match self.resolve(&art.path) {
+                Ok(p) => p,
+                Err(e) => {
+                    let _ = job_mgr
+                        .add_job_log(job_id, "warn".to_string(), format!("artifact: {e}"))
+                        .await;
+                    continue;
+                }
+            };
+            let size = tokio::fs::metadata(&stored).await.ok().and_then(|m| {
+                if m.is_file() {
+                    Some(m.len())
+                } else {
+                    None
+                }
+            });
+            let name = art.name.clone().unwrap_or_else(|| {
+                stored
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| art.path.clone())
+            });
+            let artifact = common::JobArtifact {
+                name,
+                stored_path: stored.display().to_string(),
+                size_bytes: size,
+                sha256_hex: None,
+            };
+            let _ = job_mgr.add_job_artifact(job_id, artifact).await;
+        }
+        if let Some(job_instance) = job_mgr.get_job(job_id).await {
+            let _ = job_mgr
+                .stage_artifacts(job_id, &job_instance.artifacts)
+                .await;
+        }
+    }
+}
+
+impl Drop for JobWorkspace {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+        let _ = std::fs::remove_dir_all(self.root.with_extension("scratch"));
+    }
+}
+
+fn workspaces_dir() -> PathBuf {
+    crate::p2p::state::agent_data_dir().join("workspaces")
+}
+
+fn shared_workspaces_dir() -> PathBuf {
+    std::env::temp_dir().join("realm-workspaces")
+}
+
+/// Remove workspaces left behind by runs that never finished (agent crash or
+/// kill). Called once at startup, before any job runs.
+pub fn remove_stale() {
+    let _ = std::fs::remove_dir_all(workspaces_dir());
+    let _ = std::fs::remove_dir_all(shared_workspaces_dir());
+}
+
+fn sanitize(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
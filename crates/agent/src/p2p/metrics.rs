@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::sync::{
     atomic::{AtomicU64, Ordering},
     Arc,
@@ -53,6 +54,25 @@ pub struct Metrics {
     pub gateway_requests_total: AtomicU64,
     pub gateway_errors_total: AtomicU64,
     pub gateway_last_latency_ms: AtomicU64,
+    /// Per-component gateway request counts and latency histograms
+    gateway_components: std::sync::Mutex<BTreeMap<String, GatewayComponentStats>>,
+    /// Outbound connections refused by egress policies, per component
+    egress_denied: std::sync::Mutex<BTreeMap<String, u64>>,
+}
+
+/// Upper bounds (seconds) of the gateway latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct GatewayComponentStats {
+    by_code: BTreeMap<u16, u64>,
+    /// Non-cumulative counts per bucket; the last slot is +Inf
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum_seconds: f64,
+    count: u64,
+    limited: BTreeMap<&'static str, u64>,
 }
 
 impl Metrics {
@@ -79,6 +99,8 @@ impl Metrics {
             gateway_requests_total: AtomicU64::new(0),
             gateway_errors_total: AtomicU64::new(0),
             gateway_last_latency_ms: AtomicU64::new(0),
+            gateway_components: std::sync::Mutex::new(BTreeMap::new()),
+            egress_denied: std::sync::Mutex::new(BTreeMap::new()),
         }
     }
 
@@ -121,6 +143,88 @@ impl Metrics {
         self.msgs_per_sec.store(rate, Ordering::Relaxed);
     }
 
+    /// Record one gateway request served for `component` (latency to response head).
+    pub fn observe_gateway_request(&self, component: &str, status: u16, latency: Duration) {
+        let Ok(mut stats) = self.gateway_components.lock() else {
+            return;
+        };
+        let entry = stats.entry(component.to_string()).or_default();
+        *entry.by_code.entry(status).or_default() += 1;
+        let secs = latency.as_secs_f64();
+        let slot = LATENCY_BUCKETS
+            .iter()
+            .position(|le| secs <= *le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        entry.buckets[slot] += 1;
+        entry.sum_seconds += secs;
+        entry.count += 1;
+    }
+
+    /// Record a request the gateway refused for `component` (rate, concurrency or size limit).
+    pub fn observe_gateway_limited(&self, component: &str, reason: &'static str) {
+        if let Ok(mut stats) = self.gateway_components.lock() {
+            *stats
+                .entry(component.to_string())
+                .or_default()
+                .limited
+                .entry(reason)
+                .or_default() += 1;
+        }
+    }
+
+    pub fn observe_egress_denied(&self, component: &str) {
+        if let Ok(mut denied) = self.egress_denied.lock() {
+            *denied.entry(component.to_string()).or_default() += 1;
+        }
+    }
+
+    fn render_gateway_components(&self, out: &mut String) {
+        let Ok(stats) = self.gateway_components.lock() else {
+            return;
+        };
+        out.push_str("# TYPE gateway_component_requests_total counter\n");
+        for (name, s) in stats.iter() {
+            for (code, n) in &s.by_code {
+                out.push_str(&format!(
+                    "gateway_component_requests_total{{component=\"{}\",code=\"{}\"}} {}\n",
+                    name, code, n
+                ));
+            }
+        }
+        out.push_str("# TYPE gateway_request_duration_seconds histogram\n");
+        for (name, s) in stats.iter().filter(|(_, s)| s.count > 0) {
+            let mut cumulative = 0;
+            for (le, n) in LATENCY_BUCKETS.iter().zip(s.buckets.iter()) {
+                cumulative += n;
+                out.push_str(&format!(
+                    "gateway_request_duration_seconds_bucket{{component=\"{}\",le=\"{}\"}} {}\n",
+                    name, le, cumulative
+                ));
+            }
+            out.push_str(&format!(
+                "gateway_request_duration_seconds_bucket{{component=\"{}\",le=\"+Inf\"}} {}\n",
+                name, s.count
+            ));
+            out.push_str(&format!(
+                "gateway_request_duration_seconds_sum{{component=\"{}\"}} {}\n",
+                name, s.sum_seconds
+            ));
+            out.push_str(&format!(
+                "gateway_request_duration_seconds_count{{component=\"{}\"}} {}\n",
+                name, s.count
+            ));
+        }
+        out.push_str("# TYPE gateway_limited_total counter\n");
+        for (name, s) in stats.iter() {
+            for (reason, n) in &s.limited {
+                out.push_str(&format!(
+                    "gateway_limited_total{{component=\"{}\",reason=\"{}\"}} {}\n",
+                    name, reason, n
+                ));
+            }
+        }
+    }
+
     pub fn render_prometheus(&self) -> String {
         let mut out = String::new();
         out.push_str("# TYPE agent_status_published_total counter\n");
@@ -235,6 +339,16 @@ impl Metrics {
             "gateway_last_latency_ms {}\n",
             self.gateway_last_latency_ms.load(Ordering::Relaxed)
         ));
+        self.render_gateway_components(&mut out);
+        if let Ok(denied) = self.egress_denied.lock() {
+            out.push_str("# TYPE egress_denied_total counter\n");
+            for (name, n) in denied.iter() {
+                out.push_str(&format!(
+                    "egress_denied_total{{component=\"{}\"}} {}\n",
+                    name, n
+                ));
+            }
+        }
         out
     }
 }
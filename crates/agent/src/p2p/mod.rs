@@ -9,11 +9,12 @@ use std::sync::{atomic::Ordering, Arc};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex as AsyncMutex;
 
-use uuid::Uuid;
 // Maximum number of pending broadcasts to track (evict oldest beyond this)
 const MAX_PENDING_BROADCASTS: usize = 50000;
 // Pending broadcast entry TTL before automatic prune
 const PENDING_ENTRY_TTL: std::time::Duration = std::time::Duration::from_secs(30 * 60);
+// Stop resending a component event well before receivers forget its id
+const PENDING_EVENT_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
 
 use anyhow::anyhow;
 use base64::Engine;
@@ -40,15 +41,22 @@ mod gateway;
 mod handlers;
 mod jobs;
 mod jobs_native;
+mod jobs_oci;
+mod jobs_process;
 mod jobs_qemu;
+mod jobs_qemu_system;
+mod jobs_queue;
+mod jobs_service;
 mod jobs_wasm;
+mod jobs_workspace;
 pub mod metrics;
+mod ports;
 pub mod state; // Make state module public
 pub mod storage;
 
 use handlers::{handle_apply_manifest, handle_upgrade};
 pub use handlers::{handle_push_package, PushAcceptanceError};
-use jobs::{execute_oneshot_job_with_broadcast, execute_service_job};
+pub(crate) use jobs::{accept_job, JobDispatch};
 
 struct PendingJob {
     cmd: Command,
@@ -123,6 +131,7 @@ fn job_status_tuple(cmd: &Command) -> Option<(String, String, String, String)> {
         _ => None,
     }
 }
+
 use crate::supervisor::Supervisor;
 use metrics::{push_log, serve_metrics, Metrics, SharedLogs};
 
@@ -132,6 +141,33 @@ struct NodeBehaviour {
     kademlia: kad::Behaviour<kad::store::MemoryStore>,
     mdns: mdns::tokio::Behaviour,
     identify: identify::Behaviour,
+    stream: libp2p_stream::Behaviour,
+}
+
+/// Services this node offers the mesh: wasm components and supervised
+/// service jobs with declared ports.
+async fn local_adverts(
+    supervisor: &Supervisor,
+    job_manager: &crate::job_manager::JobManager,
+) -> Vec<common::ServiceAdvert> {
+    let mut adverts = supervisor.service_adverts().await;
+    for job in job_manager.local_services().await {
+        let Some(service) = job.spec.service else {
+            continue;
+        };
+        if service.ports.is_empty() {
+            continue;
+        }
+        adverts.push(common::ServiceAdvert {
+            name: job.spec.name,
+            http: false,
+            routes: Vec::new(),
+            visibility: service.visibility,
+            ports: service.ports,
+            http_limits: None,
+        });
+    }
+    adverts
 }
 
 fn load_or_create_node_key() -> identity::Keypair {
@@ -211,6 +247,8 @@ pub async fn run_agent(
     // UI/ephemeral nodes should not reconcile desired state or schedule workloads
     if !ephemeral {
         supervisor.clone().spawn_reconcile();
+        supervisor.clone().spawn_scheduler();
+        jobs_workspace::remove_stale();
     }
 
     // Initialize job manager and restore job state
@@ -222,6 +260,9 @@ pub async fn run_agent(
     if let Err(e) = job_manager.load_from_disk().await {
         warn!(error=%e, "Failed to restore job state from disk, starting fresh");
     }
+    let job_slots = Arc::new(jobs_queue::JobSlots::new(
+        crate::policy::load_policy().job_slots,
+    ));
 
     let gossip_config = gossipsub::ConfigBuilder::default()
         .max_transmit_size(10 * 1024 * 1024) // allow up to 10 MiB messages
@@ -237,6 +278,10 @@ pub async fn run_agent(
     let topic_status = gossipsub::IdentTopic::new(REALM_STATUS_TOPIC);
     gossipsub.subscribe(&topic_cmd)?;
     gossipsub.subscribe(&topic_status)?;
+    // Component events stay within the realm: the topic follows the trusted owner
+    let mut topic_events =
+        gossipsub::IdentTopic::new(common::events_topic(state::load_trusted_owner().as_deref()));
+    gossipsub.subscribe(&topic_events)?;
 
     let store = kad::store::MemoryStore::new(local_peer_id);
     let kademlia = kad::Behaviour::new(local_peer_id, store);
@@ -254,11 +299,19 @@ pub async fn run_agent(
     id_cfg.agent_version = format!("realm-agent v{}{}", boot.agent_version, roles_str);
     let identify = identify::Behaviour::new(id_cfg);
 
+    // Raw streams for gateway-to-gateway HTTP forwarding
+    let stream = libp2p_stream::Behaviour::new();
+    let mesh = gateway::MeshHandle {
+        registry: Arc::new(gateway::ServiceRegistry::new(local_peer_id)),
+        control: stream.new_control(),
+    };
+
     let behaviour = NodeBehaviour {
         gossipsub,
         kademlia,
         mdns,
         identify,
+        stream,
     };
 
     let mut swarm = SwarmBuilder::with_existing_identity(id_keys.clone())
@@ -367,6 +420,10 @@ pub async fn run_agent(
                 epoch_ms,
                 Some(metrics0.clone()),
                 None,
+                None,
+                None,
+                Default::default(),
+                crate::host::HostState::new("adhoc", &[], logs0.clone()),
             )
             .await
             .map(|_| format!("run ok: {path}"))
@@ -400,45 +457,92 @@ pub async fn run_agent(
             "127.0.0.1:9920",
         ));
 
+        // Serve local HTTP components to peers' gateways
+        tokio::spawn(gateway::serve_mesh_streams(
+            supervisor.clone(),
+            Some(metrics.clone()),
+            mesh.registry.clone(),
+            mesh.control.clone(),
+        ));
+
+        // Expose declared service ports on the host and tunnel them across the mesh
+        tokio::spawn(ports::serve_port_tunnels(
+            supervisor.clone(),
+            job_manager.clone(),
+            mesh.registry.clone(),
+            mesh.control.clone(),
+        ));
+        ports::PortManager::new(
+            supervisor.clone(),
+            job_manager.clone(),
+            mesh.clone(),
+            roles.iter().any(|r| r == "edge"),
+        )
+        .spawn();
+
         // Spawn gateway manager: always serve loopback; add public bind if visibility requires it
         {
             let sup_for_local = supervisor.clone();
             let m = metrics.clone();
+            let mesh_for_local = mesh.clone();
             tokio::spawn(async move {
-                gateway::serve_gateway(sup_for_local, Some(m), "127.0.0.1:8080").await;
+                gateway::serve_gateway(
+                    sup_for_local,
+                    Some(m),
+                    Some(mesh_for_local),
+                    "127.0.0.1:8080",
+                )
+                .await;
             });
         }
         {
             let sup_for_public = supervisor.clone();
             let metrics_for_public = metrics.clone();
+            let mesh_for_public = mesh.clone();
             let roles_for_public = roles.clone();
             tokio::spawn(async move {
                 let mut public_spawned = false;
+                let mut tls_spawned = false;
                 let mut intv = tokio::time::interval(Duration::from_secs(2));
                 loop {
                     intv.tick().await;
-                    if !public_spawned {
-                        let desired = sup_for_public.get_desired_snapshot().await;
-                        let mut any_public = false;
-                        for (_name, comp) in desired.iter() {
-                            if let Some(vis) = &comp.spec.visibility {
-                                if matches!(vis, common::Visibility::Public) {
-                                    any_public = true;
-                                    break;
-                                }
-                            }
-                        }
-                        // gate public binding on 'edge' role present on this peer
-                        let is_edge = roles_for_public.iter().any(|r| r == "edge");
-                        if any_public && is_edge {
-                            // Best effort: start public gateway; if bind fails, log and continue loop
-                            let sup2 = sup_for_public.clone();
-                            let m2 = metrics_for_public.clone();
-                            tokio::spawn(async move {
-                                gateway::serve_gateway(sup2, Some(m2), "0.0.0.0:8080").await;
-                            });
-                            public_spawned = true;
-                        }
+                    if public_spawned && tls_spawned {
+                        continue;
+                    }
+                    let desired = sup_for_public.get_desired_snapshot().await;
+                    // Public components hosted here or on peers this edge fronts
+                    let remote = mesh_for_public.registry.adverts();
+                    let any_public = desired
+                        .values()
+                        .map(|c| &c.spec.visibility)
+                        .chain(remote.iter().map(|a| &a.visibility))
+                        .any(|vis| matches!(vis, Some(common::Visibility::Public)));
+                    // gate public binding on 'edge' role present on this peer
+                    let is_edge = roles_for_public.iter().any(|r| r == "edge");
+                    if !public_spawned && any_public && is_edge {
+                        // Best effort: start public gateway; if bind fails, log and continue loop
+                        let sup2 = sup_for_public.clone();
+                        let m2 = metrics_for_public.clone();
+                        let mesh2 = mesh_for_public.clone();
+                        tokio::spawn(async move {
+                            gateway::serve_gateway(sup2, Some(m2), Some(mesh2), "0.0.0.0:8080")
+                                .await;
+                        });
+                        public_spawned = true;
+                    }
+                    if !tls_spawned
+                        && is_edge
+                        && gateway::wants_tls(&sup_for_public, &mesh_for_public.registry).await
+                    {
+                        let bind = std::env::var("REALM_GATEWAY_TLS_BIND")
+                            .unwrap_or_else(|_| "0.0.0.0:8443".to_string());
+                        let sup2 = sup_for_public.clone();
+                        let m2 = metrics_for_public.clone();
+                        let mesh2 = mesh_for_public.clone();
+                        tokio::spawn(async move {
+                            gateway::serve_gateway_tls(sup2, Some(m2), Some(mesh2), &bind).await;
+                        });
+                        tls_spawned = true;
                     }
                 }
             });
@@ -446,6 +550,8 @@ pub async fn run_agent(
     }
 
     let mut pending_job_broadcasts: HashMap<(String, String, String), PendingJob> = HashMap::new();
+    // Component events awaiting acks, by message id
+    let mut pending_events: HashMap<String, PendingJob> = HashMap::new();
     // TTL-based deduplication cache: message_id -> (timestamp, job_content_for_debugging)
     let seen_cache = Arc::new(AsyncMutex::new(HashMap::<
         String,
@@ -464,6 +570,24 @@ pub async fn run_agent(
     // Minimal P2P storage request/response plumbing
     let (storage_req_tx, mut storage_req_rx) =
         tokio::sync::mpsc::unbounded_channel::<storage::StorageRequest>();
+    // Let components reach peers through realm:host (events, CAS fetches)
+    crate::host::attach_mesh(
+        local_peer_id.to_string(),
+        job_broadcast_tx.clone(),
+        storage::P2PStorage::new(storage_req_tx.clone()),
+        JobDispatch {
+            job_mgr: job_manager.clone(),
+            logs: logs.clone(),
+            roles: roles.clone(),
+            node_id: local_peer_id.to_string(),
+            content_index: content_index.clone(),
+            storage_tx: storage_req_tx.clone(),
+            broadcast_tx: job_broadcast_tx.clone(),
+            tx: tx.clone(),
+            slots: job_slots.clone(),
+            registry: mesh.registry.clone(),
+        },
+    );
     // For reassembling incoming chunked blobs
     let chunk_bufs: std::sync::Arc<
         tokio::sync::Mutex<std::collections::HashMap<String, (u32, Vec<Vec<u8>>)>>,
@@ -474,6 +598,8 @@ pub async fn run_agent(
         >,
     > = std::sync::Arc::new(tokio::sync::Mutex::new(std::collections::HashMap::new()));
     let mut schedule_tick = tokio::time::interval(Duration::from_secs(60));
+    let mut service_watch_tick = tokio::time::interval(Duration::from_secs(30));
+    let agent_started = Instant::now();
     let mut job_sync_interval = 5u64;
     let mut job_sync_tick = tokio::time::interval(Duration::from_secs(job_sync_interval));
     let mut last_job_sync = job_manager.last_update();
@@ -488,6 +614,27 @@ pub async fn run_agent(
         tokio::select! {
             // Handle job status broadcasts
             Some(job_broadcast) = job_broadcast_rx.recv() => {
+                if let Command::ComponentEvent { message_id, .. } = &job_broadcast {
+                    // Gossip never loops back, so deliver locally too; resend to realm peers until they ack
+                    let events_hash = topic_events.hash();
+                    let peers: HashSet<PeerId> = swarm.behaviour().gossipsub.all_peers()
+                        .filter(|(_, topics)| topics.contains(&&events_hash))
+                        .map(|(p, _)| *p)
+                        .collect();
+                    if !peers.is_empty() {
+                        pending_events.insert(message_id.clone(), PendingJob { cmd: job_broadcast.clone(), peers, created: Instant::now(), last_sent: Instant::now(), retry_count: 0 });
+                    }
+                    if let Some(event) = crate::events::BusEvent::from_command(&job_broadcast) {
+                        let sup = supervisor.clone();
+                        tokio::spawn(async move { crate::events::receive(&sup, event).await; });
+                    }
+                    let _ = swarm.behaviour_mut().gossipsub.publish(topic_events.clone(), serialize_message(&job_broadcast));
+                    continue;
+                }
+                if matches!(job_broadcast, Command::EventAck { .. }) {
+                    let _ = swarm.behaviour_mut().gossipsub.publish(topic_events.clone(), serialize_message(&job_broadcast));
+                    continue;
+                }
                 if let Some((job_id, status, message_id)) = job_status_key(&job_broadcast) {
                     let peers: HashSet<PeerId> = swarm.connected_peers().cloned().collect();
                     pending_job_broadcasts.insert(
@@ -543,6 +690,8 @@ pub async fn run_agent(
                     drift: metrics.components_desired.load(Ordering::Relaxed) as i64 - metrics.components_running.load(Ordering::Relaxed) as i64,
                     trusted_owner_pub_bs58: load_trusted_owner(),
                     links: link_count as u64,
+                    services: local_adverts(&supervisor, &job_manager).await,
+                    job_slots: job_slots.usage(),
                 };
                 // Mirror into shared status sink for UI
                 if let Some(sink) = &status_sink { let mut m = sink.lock().await; m.insert(status.node_id.clone(), status.clone()); }
@@ -554,6 +703,8 @@ pub async fn run_agent(
                 }
             }
             _ = interval.tick() => {
+                // Pick up slot limit changes from the policy file
+                job_slots.set_limits(crate::policy::load_policy().job_slots);
                 let (cpu_percent, mem_percent) = {
                     let mut s = sys.lock().await;
                     s.refresh_all();
@@ -586,6 +737,8 @@ pub async fn run_agent(
                     drift: metrics.components_desired.load(Ordering::Relaxed) as i64 - metrics.components_running.load(Ordering::Relaxed) as i64,
                     trusted_owner_pub_bs58: load_trusted_owner(),
                     links: link_count as u64,
+                    services: local_adverts(&supervisor, &job_manager).await,
+                    job_slots: job_slots.usage(),
                 };
                 // Mirror into shared status sink for UI
                 if let Some(sink) = &status_sink { let mut m = sink.lock().await; m.insert(status.node_id.clone(), status.clone()); }
@@ -596,6 +749,28 @@ pub async fn run_agent(
                     metrics.status_published_total.fetch_add(1, Ordering::Relaxed);
                     last_publish_count = last_publish_count.saturating_add(1);
                 }
+                // Forget mesh services from peers that went quiet
+                mesh.registry.prune();
+                // Move to the realm's events topic once an owner is trusted
+                let wanted_events_topic = common::events_topic(state::load_trusted_owner().as_deref());
+                if topic_events.to_string() != wanted_events_topic {
+                    let _ = swarm.behaviour_mut().gossipsub.unsubscribe(&topic_events);
+                    topic_events = gossipsub::IdentTopic::new(wanted_events_topic);
+                    let _ = swarm.behaviour_mut().gossipsub.subscribe(&topic_events);
+                }
+                // Resend unacked component events; peers that left no longer count
+                pending_events.retain(|_, p| p.created.elapsed() < PENDING_EVENT_TTL);
+                let connected: HashSet<PeerId> = swarm.connected_peers().cloned().collect();
+                for pending in pending_events.values_mut() {
+                    pending.peers.retain(|p| connected.contains(p));
+                    let backoff_secs = 1u64 << pending.retry_count.min(5);
+                    if !pending.peers.is_empty() && pending.last_sent.elapsed() >= Duration::from_secs(backoff_secs) {
+                        let _ = swarm.behaviour_mut().gossipsub.publish(topic_events.clone(), serialize_message(&pending.cmd));
+                        pending.last_sent = Instant::now();
+                        pending.retry_count = pending.retry_count.saturating_add(1);
+                    }
+                }
+                pending_events.retain(|_, p| !p.peers.is_empty());
                 // Prune stale pending broadcasts beyond TTL
                 {
                     let now = Instant::now();
@@ -725,18 +900,58 @@ pub async fn run_agent(
                 }
                 job_sync_tick = tokio::time::interval(Duration::from_secs(job_sync_interval));
             }
+            _ = service_watch_tick.tick(), if !ephemeral => {
+                // Resume our own service jobs after a restart and take over ones whose node
+                // went silent; peers are only judged once there was time to hear from them
+                let registry = mesh.registry.clone();
+                let warmed_up = agent_started.elapsed() >= jobs_service::RELOCATE_AFTER;
+                let stranded = job_manager
+                    .stranded_jobs(|node| !warmed_up || registry.seen_within(node, jobs_service::RELOCATE_AFTER))
+                    .await;
+                for job in stranded {
+                    let dispatch = JobDispatch {
+                        job_mgr: job_manager.clone(),
+                        logs: logs.clone(),
+                        roles: roles.clone(),
+                        node_id: local_peer_id.to_string(),
+                        content_index: content_index.clone(),
+                        storage_tx: storage_req_tx.clone(),
+                        broadcast_tx: job_broadcast_tx.clone(),
+                        tx: tx.clone(),
+                        slots: job_slots.clone(),
+                        registry: mesh.registry.clone(),
+                    };
+                    tokio::spawn(jobs_service::recover(dispatch, job));
+                }
+            }
             _ = schedule_tick.tick() => {
-                // Evaluate recurring job schedules
-                if let Ok(due_specs) = job_manager.evaluate_schedules().await {
-                    for spec in due_specs {
-                        if let Ok(job_id) = job_manager.submit_job(spec.clone(), None, None).await {
-                            let msg = Command::SubmitJob {
-                                origin_node_id: local_peer_id.to_string(),
-                                job_id,
-                                spec,
-                            };
+                // Submit the runs of the recurring jobs this node schedules
+                if let Ok(runs) = job_manager.evaluate_schedules().await {
+                    for run in runs {
+                        for job_id in run.replaces {
+                            let _ = job_manager.cancel_job(&job_id).await;
+                            let msg = Command::CancelJob { job_id };
                             let _ = swarm.behaviour_mut().gossipsub.publish(topic_cmd.clone(), serialize_message(&msg));
                         }
+                        let msg = Command::SubmitJob {
+                            origin_node_id: local_peer_id.to_string(),
+                            job_id: run.child_id.clone(),
+                            spec: run.spec.clone(),
+                        };
+                        let _ = swarm.behaviour_mut().gossipsub.publish(topic_cmd.clone(), serialize_message(&msg));
+                        let dispatch = JobDispatch {
+                            job_mgr: job_manager.clone(),
+                            logs: logs.clone(),
+                            roles: roles.clone(),
+                            node_id: local_peer_id.to_string(),
+                            content_index: content_index.clone(),
+                            storage_tx: storage_req_tx.clone(),
+                            broadcast_tx: job_broadcast_tx.clone(),
+                            tx: tx.clone(),
+                            slots: job_slots.clone(),
+                            registry: mesh.registry.clone(),
+                        };
+                        tokio::spawn(accept_job(dispatch, local_peer_id.to_string(), run.child_id, run.spec));
                     }
                 }
             }
@@ -853,6 +1068,12 @@ pub async fn run_agent(
                             }
                             // First, try to parse peer Status updates and mirror them into the sink for UI
                             if let Ok(st) = common::deserialize_message::<common::Status>(&message.data) {
+                                // Status is unsigned; only take it from the node it describes.
+                                // message.source is the signed origin, which relays cannot change.
+                                let Some(source) = message.source.filter(|src| src.to_string() == st.node_id) else {
+                                    continue;
+                                };
+                                mesh.registry.observe(source, &st);
                                 if let Some(sink) = &status_sink { let mut m = sink.lock().await; m.insert(st.node_id.clone(), st); }
                                 continue;
                             }
@@ -974,6 +1195,8 @@ pub async fn run_agent(
                                             drift: metrics.components_desired.load(Ordering::Relaxed) as i64 - metrics.components_running.load(Ordering::Relaxed) as i64,
                                             trusted_owner_pub_bs58: load_trusted_owner(),
                                             links: link_count as u64,
+                                            services: local_adverts(&supervisor, &job_manager).await,
+                                            job_slots: job_slots.usage(),
                                         };
                                         if let Err(_e) = swarm.behaviour_mut().gossipsub.publish(topic_status.clone(), serialize_message(&status)) {
                                             metrics.status_publish_errors_total.fetch_add(1, Ordering::Relaxed);
@@ -987,7 +1210,7 @@ pub async fn run_agent(
                                         let m_run = metrics.clone();
                                         tokio::spawn(async move {
                                             push_log(&logs1, "adhoc", format!("starting run {wasm_path}")).await;
-                                            let res = run_wasm_module_with_limits(&wasm_path, "adhoc", logs1.clone(), memory_max_mb, fuel, epoch_ms, Some(m_run.clone()), None).await
+                                            let res = run_wasm_module_with_limits(&wasm_path, "adhoc", logs1.clone(), memory_max_mb, fuel, epoch_ms, Some(m_run.clone()), None, None, None, Default::default(), crate::host::HostState::new("adhoc", &[], logs1.clone())).await
                                                 .map(|_| format!("run ok: {wasm_path}"))
                                                 .map_err(|e| format!("run error: {e}"));
                                             match &res {
@@ -1067,6 +1290,8 @@ pub async fn run_agent(
                                             drift: metrics.components_desired.load(Ordering::Relaxed) as i64 - metrics.components_running.load(Ordering::Relaxed) as i64,
                                             trusted_owner_pub_bs58: load_trusted_owner(),
                                             links: link_count as u64,
+                                            services: local_adverts(&supervisor, &job_manager).await,
+                                            job_slots: job_slots.usage(),
                                         };
                                         if let Err(_e) = swarm.behaviour_mut().gossipsub.publish(topic_status.clone(), serialize_message(&status)) {
                                             metrics.status_publish_errors_total.fetch_add(1, Ordering::Relaxed);
@@ -1134,158 +1359,19 @@ pub async fn run_agent(
                                         }
                                     }
                                     Command::SubmitJob { origin_node_id, job_id, spec: job } => {
-                                        let logsj = logs.clone();
-                                        let rolesj = roles.clone();
-                                        let job_mgr = job_manager.clone();
-                                        let node_id = local_peer_id.to_string();
-                                        let content_index2 = content_index.clone();
-                                        let storage_tx = storage_req_tx.clone();
-                                        let job_broadcast_tx_clone = job_broadcast_tx.clone();
-                                        let tx_clone = tx.clone();
-                                        tokio::spawn(async move {
-                                            // Insert job locally with provided id
-                                            let _ = job_mgr.submit_job(job.clone(), Some(origin_node_id.clone()), Some(job_id.clone())).await;
-                                            // Check if this node is eligible to execute the job
-                                            let mut eligible = true;
-                                            if let Some(t) = &job.targeting {
-                                                if let Some(p) = &t.platform {
-                                                    let host = format!("{}/{}", std::env::consts::OS, std::env::consts::ARCH);
-                                                    if &host != p { eligible = false; }
-                                                }
-                                                if eligible && !t.tags.is_empty() {
-                                                    eligible = t.tags.iter().any(|tag| rolesj.contains(tag));
-                                                }
-                                                if eligible && !t.node_ids.is_empty() {
-                                                    eligible = t.node_ids.iter().any(|id| id == &node_id);
-                                                }
-                                            }
-
-                                            if !eligible {
-                                                let _ = push_log(&logsj, "system", format!("Job {} not eligible for this node, ignoring", job.name)).await;
-                                                return;
-                                            }
-
-                                            // This node is eligible - check if job is already accepted by another node
-                                            if let Some(existing) = job_mgr.get_job(&job_id).await {
-                                                if existing.assigned_node.is_some() {
-                                                    let _ = push_log(&logsj, "system", format!("Job {} already accepted by another node", job.name)).await;
-                                                    return;
-                                                }
-                                            }
-
-                                            // Accept the job and broadcast acceptance
-                                            // Mark this node as the assigned executor
-                                            let _ = job_mgr.assign_job(&job_id, &node_id).await;
-
-                                            // Broadcast job acceptance to prevent other nodes from taking it
-                                            let acceptance_msg = Command::JobAccepted {
-                                                job_id: job_id.clone(),
-                                                assigned_node: node_id.clone(),
-                                                message_id: Uuid::new_v4().to_string(),
-                                            };
-                                            let _ = job_broadcast_tx_clone.send(acceptance_msg);
-
-                                            let _ = push_log(&logsj, "system", format!("Job accepted: {} ({})", job.name, job_id)).await;
-
-                                            // This node is executing the job
-                                            let selected = true;
-
-                                            // Locality preference: if job has digest and others have it, delay start here
-                                            let mut locality_delay_ms: u64 = 0;
-                                            let job_digest: Option<String> = match &job.runtime {
-                                                common::JobRuntime::Wasm { sha256_hex, .. } => sha256_hex.clone(),
-                                                common::JobRuntime::Native { sha256_hex, .. } => sha256_hex.clone(),
-                                                common::JobRuntime::Qemu { sha256_hex, .. } => sha256_hex.clone(),
-                                            };
-                                            if let Some(d) = &job_digest {
-                                                let store = crate::storage::ContentStore::open();
-                                                let has_local = store.has(d);
-                                                if !has_local {
-                                                    let peers_with = {
-                                                        let map = content_index2.lock().await;
-                                                        map.get(d).map(|s| s.len()).unwrap_or(0)
-                                                    };
-                                                    if peers_with > 0 {
-                                                        let h = common::sha256_hex(node_id.as_bytes());
-                                                        let nib = u64::from_str_radix(&h[..4], 16).unwrap_or(0);
-                                                        locality_delay_ms = 500 + (nib % 1500);
-                                                    }
-                                                }
-                                            }
-                                            if selected {
-                                                // Mark job as started
-                                                let _ = job_mgr.start_job(&job_id).await;
-                                                let _ = job_mgr.add_job_log(&job_id, "info".to_string(), "Job execution started on this node".to_string()).await;
-
-                                                // Broadcast job started status
-                                                let start_msg = Command::JobStarted {
-                                                    job_id: job_id.clone(),
-                                                    assigned_node: node_id.clone(),
-                                                    message_id: Uuid::new_v4().to_string(),
-                                                };
-                                                let _ = job_broadcast_tx_clone.send(start_msg);
-
-                                                // Observability: log start
-                                                let _ = push_log(&logsj, "system", format!("job started: {}", job_id)).await;
-
-                                                // Handle different job types
-                                                match &job.job_type {
-                                                    common::JobType::OneShot => {
-                                                        // Spawn one-shot jobs in separate tasks to avoid blocking the main event loop
-                                                        let oneshot_job_mgr = job_mgr.clone();
-                                                        let oneshot_job_id = job_id.clone();
-                                                        let oneshot_job = job.clone();
-                                                        let oneshot_logs = logsj.clone();
-                                                        let oneshot_tx = tx_clone.clone();
-                                                        let oneshot_storage_tx = storage_tx.clone();
-                                                        let oneshot_broadcast_tx = job_broadcast_tx_clone.clone();
-                                                        let oneshot_node_id = node_id.clone();
-
-                                                        tokio::spawn(async move {
-                                                            if locality_delay_ms > 0 { tokio::time::sleep(Duration::from_millis(locality_delay_ms)).await; }
-                                                            execute_oneshot_job_with_broadcast(oneshot_job_mgr, oneshot_job_id, oneshot_job, oneshot_logs, oneshot_tx, Some(storage::P2PStorage::new(oneshot_storage_tx)), oneshot_broadcast_tx, oneshot_node_id).await;
-                                                        });
-                                                    },
-                                                    common::JobType::Recurring => {
-                                                        // Spawn recurring jobs in separate tasks to avoid blocking the main event loop
-                                                        let recurring_job_mgr = job_mgr.clone();
-                                                        let recurring_job_id = job_id.clone();
-                                                        let recurring_job = job.clone();
-                                                        let recurring_logs = logsj.clone();
-                                                        let recurring_tx = tx_clone.clone();
-                                                        let recurring_storage_tx = storage_tx.clone();
-                                                        let recurring_broadcast_tx = job_broadcast_tx_clone.clone();
-                                                        let recurring_node_id = node_id.clone();
-
-                                                        tokio::spawn(async move {
-                                                            if locality_delay_ms > 0 { tokio::time::sleep(Duration::from_millis(locality_delay_ms)).await; }
-                                                            execute_oneshot_job_with_broadcast(recurring_job_mgr, recurring_job_id, recurring_job, recurring_logs, recurring_tx, Some(storage::P2PStorage::new(recurring_storage_tx)), recurring_broadcast_tx, recurring_node_id).await;
-                                                        });
-                                                    },
-                                                    common::JobType::Service => {
-                                                        // Create cancellation channel for service jobs
-                                                        let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
-
-                                                        let service_job_mgr = job_mgr.clone();
-                                                        let service_job_id = job_id.clone();
-                                                        let service_job = job.clone();
-                                                        let service_logs = logsj.clone();
-                                                        let service_tx = tx_clone.clone();
-
-                                                        let handle = tokio::spawn(async move {
-                                                            if locality_delay_ms > 0 { tokio::time::sleep(Duration::from_millis(locality_delay_ms)).await; }
-                                                            execute_service_job(service_job_mgr, service_job_id, service_job, service_logs, service_tx, cancel_rx, Some(storage::P2PStorage::new(storage_tx.clone()))).await;
-                                                        });
-
-                                                        // Register the running job for cancellation support
-                                                        job_mgr.register_running_job(job_id.clone(), handle, cancel_tx).await;
-                                                    }
-                                                }
-                                            } else {
-                                                // Job not selected for this node, but still track it
-                                                let _ = job_mgr.add_job_log(&job_id, "info".to_string(), "Job not selected for execution on this node".to_string()).await;
-                                            }
-                                        });
+                                        let dispatch = JobDispatch {
+                                            job_mgr: job_manager.clone(),
+                                            logs: logs.clone(),
+                                            roles: roles.clone(),
+                                            node_id: local_peer_id.to_string(),
+                                            content_index: content_index.clone(),
+                                            storage_tx: storage_req_tx.clone(),
+                                            broadcast_tx: job_broadcast_tx.clone(),
+                                            tx: tx.clone(),
+                                            slots: job_slots.clone(),
+                                            registry: mesh.registry.clone(),
+                                        };
+                                        tokio::spawn(accept_job(dispatch, origin_node_id, job_id, job));
                                     }
                                     Command::JobSyncRequest { .. } => {
                                         let mut jobs = job_manager.list_jobs(None, usize::MAX).await;
@@ -1380,6 +1466,51 @@ pub async fn run_agent(
                                             }
                                         }
                                     }
+                                    event_cmd @ Command::ComponentEvent { .. } => {
+                                        // Only events from this realm's topic; the ack goes out once delivered here
+                                        if message.topic == topic_events.hash() {
+                                            if let Some(event) = crate::events::BusEvent::from_command(&event_cmd) {
+                                                let sup = supervisor.clone();
+                                                let acks = job_broadcast_tx.clone();
+                                                let from = local_peer_id.to_string();
+                                                tokio::spawn(async move {
+                                                    let message_id = event.id.clone();
+                                                    if crate::events::receive(&sup, event).await {
+                                                        let _ = acks.send(Command::EventAck { message_id, from });
+                                                    }
+                                                });
+                                            }
+                                        }
+                                    }
+                                    Command::EventAck { message_id, from } => {
+                                        if let Ok(pid) = PeerId::from_str(&from) {
+                                            if let Some(p) = pending_events.get_mut(&message_id) {
+                                                p.peers.remove(&pid);
+                                                if p.peers.is_empty() {
+                                                    pending_events.remove(&message_id);
+                                                }
+                                            }
+                                        }
+                                    }
+                                    Command::KvReplicate { store, key, entry } => {
+                                        // Only writes signed by realm members, for their own entries;
+                                        // a member cannot replay or forge another node's writes
+                                        if !message.source.is_some_and(|src| {
+                                            mesh.registry.is_member(&src) && entry.origin == src.to_string()
+                                        }) {
+                                            continue;
+                                        }
+                                        // Only nodes hosting a component on this replicated store keep a copy
+                                        let hosted = supervisor.get_desired_snapshot().await.values().any(|d| {
+                                            d.spec.kv.as_ref().is_some_and(|kv| kv.replicate)
+                                                && crate::kv::store_name(&d.name, d.spec.kv.as_ref()) == store
+                                        });
+                                        if hosted {
+                                            if let Err(e) = crate::kv::open(&store).await.apply_remote(key, entry).await {
+                                                warn!(%store, error=?e, "kv replication failed");
+                                            }
+                                        }
+                                    }
                                 }
                             }
                         }
@@ -0,0 +1,629 @@
+//! Host exposure of declared service ports.
+//!
+//! Service components and service jobs listen on their declared guest ports on
+//! loopback. For each port the agent allocates a host port (persisted in
+//! `ports.json` so it is stable across restarts) and runs a proxy on it: bound to
+//! loopback, or to all interfaces for `Visibility::Public`. Ports of services
+//! hosted on other peers are exposed the same way and carried over the
+//! `/realm/port/1` mesh tunnel.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::{Protocol, ServicePort, Visibility};
+use futures::StreamExt;
+use libp2p::{PeerId, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tracing::{debug, info, warn};
+
+use super::gateway::{MeshHandle, ServiceRegistry};
+use super::state::agent_data_dir;
+use crate::job_manager::JobManager;
+use crate::supervisor::Supervisor;
+
+/// Stream protocol carrying one TCP connection or UDP session between peers.
+pub const PORT_TUNNEL_PROTOCOL: StreamProtocol = StreamProtocol::new("/realm/port/1");
+
+/// Host ports handed out when a service port does not request one.
+const DEFAULT_PORT_RANGE: (u16, u16) = (20000, 29999);
+/// UDP client sessions are dropped after this long without traffic.
+const UDP_SESSION_IDLE: Duration = Duration::from_secs(60);
+/// How often exposures are re-checked (replicas starting, service jobs moving,
+/// peers appearing).
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(15);
+/// Upper bound on peers tried for one tunnel.
+const MAX_ATTEMPTS: usize = 3;
+
+/// First line of a tunnel stream, naming the target port.
+#[derive(Debug, Serialize, Deserialize)]
+struct TunnelHeader {
+    component: String,
+    port: u16,
+    protocol: Protocol,
+}
+
+/// Persisted host-port allocations, keyed by `component/port/proto`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PortTable {
+    allocations: BTreeMap<String, u16>,
+}
+
+impl PortTable {
+    fn path() -> std::path::PathBuf {
+        agent_data_dir().join("ports.json")
+    }
+
+    fn load() -> Self {
+        std::fs::read(Self::path())
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        let _ = std::fs::create_dir_all(agent_data_dir());
+        if let Ok(bytes) = serde_json::to_vec_pretty(self) {
+            let _ = std::fs::write(Self::path(), bytes);
+        }
+    }
+
+    fn key(component: &str, port: u16, protocol: Protocol) -> String {
+        format!("{component}/{port}/{}", proto_str(protocol))
+    }
+
+    fn owner_of(&self, host_port: u16, protocol: Protocol) -> Option<&str> {
+        let suffix = format!("/{}", proto_str(protocol));
+        self.allocations
+            .iter()
+            .find(|(k, v)| **v == host_port && k.ends_with(&suffix))
+            .map(|(k, _)| k.as_str())
+    }
+
+    /// Allocate a host port for `key`. An explicit request must not collide with
+    /// another allocation or a guest port; otherwise the previous allocation is
+    /// reused, or the first free port in the range is taken.
+    fn allocate(
+        &mut self,
+        key: &str,
+        protocol: Protocol,
+        requested: Option<u16>,
+        reserved: &HashSet<(Protocol, u16)>,
+    ) -> Result<u16, String> {
+        if let Some(port) = requested {
+            if reserved.contains(&(protocol, port)) {
+                return Err(format!("host port {port} is a guest port on this node"));
+            }
+            if let Some(owner) = self.owner_of(port, protocol) {
+                if owner != key {
+                    return Err(format!("host port {port} already allocated to {owner}"));
+                }
+            }
+            self.allocations.insert(key.to_string(), port);
+            return Ok(port);
+        }
+        if let Some(port) = self.allocations.get(key) {
+            if !reserved.contains(&(protocol, *port)) {
+                return Ok(*port);
+            }
+        }
+        let (lo, hi) = port_range();
+        for port in lo..=hi {
+            if reserved.contains(&(protocol, port)) || self.owner_of(port, protocol).is_some() {
+                continue;
+            }
+            if !port_is_free(port, protocol) {
+                continue;
+            }
+            self.allocations.insert(key.to_string(), port);
+            return Ok(port);
+        }
+        Err(format!("no free host port in {lo}-{hi}"))
+    }
+}
+
+fn proto_str(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Tcp => "tcp",
+        Protocol::Udp => "udp",
+    }
+}
+
+/// `REALM_PORT_RANGE=lo-hi` overrides the auto-allocation range.
+fn port_range() -> (u16, u16) {
+    std::env::var("REALM_PORT_RANGE")
+        .ok()
+        .and_then(|v| {
+            let (lo, hi) = v.split_once('-')?;
+            let lo = lo.trim().parse::<u16>().ok()?;
+            let hi = hi.trim().parse::<u16>().ok()?;
+            (lo <= hi).then_some((lo, hi))
+        })
+        .unwrap_or(DEFAULT_PORT_RANGE)
+}
+
+fn port_is_free(port: u16, protocol: Protocol) -> bool {
+    match protocol {
+        Protocol::Tcp => std::net::TcpListener::bind(("0.0.0.0", port)).is_ok(),
+        Protocol::Udp => std::net::UdpSocket::bind(("0.0.0.0", port)).is_ok(),
+    }
+}
+
+/// Where a proxied port leads.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Target {
+    /// Guest listening on loopback on this node
+    Local,
+    /// Component on another peer, reached through the tunnel
+    Remote,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Exposure {
+    component: String,
+    guest_port: u16,
+    protocol: Protocol,
+    bind: SocketAddr,
+    target: Target,
+}
+
+/// Keeps host listeners in line with the declared ports of local and remote services.
+pub struct PortManager {
+    supervisor: Arc<Supervisor>,
+    jobs: Arc<JobManager>,
+    mesh: MeshHandle,
+    is_edge: bool,
+    table: tokio::sync::Mutex<PortTable>,
+    active: tokio::sync::Mutex<HashMap<Exposure, JoinHandle<()>>>,
+}
+
+impl PortManager {
+    pub fn new(
+        supervisor: Arc<Supervisor>,
+        jobs: Arc<JobManager>,
+        mesh: MeshHandle,
+        is_edge: bool,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            supervisor,
+            jobs,
+            mesh,
+            is_edge,
+            table: tokio::sync::Mutex::new(PortTable::load()),
+            active: tokio::sync::Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn spawn(self: &Arc<Self>) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut changes = this.supervisor.subscribe_changes();
+            let mut remote_changes = this.mesh.registry.subscribe_changes();
+            let mut tick = tokio::time::interval(RECONCILE_INTERVAL);
+            loop {
+                this.reconcile().await;
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    res = changes.changed() => {
+                        if res.is_err() {
+                            break;
+                        }
+                    }
+                    _ = remote_changes.changed() => {}
+                }
+            }
+        });
+    }
+
+    async fn reconcile(&self) {
+        let desired = self.supervisor.get_desired_snapshot().await;
+        let services = local_job_ports(&self.jobs).await;
+        let reserved: HashSet<(Protocol, u16)> = desired
+            .values()
+            .flat_map(|c| c.spec.ports.iter().flatten())
+            .chain(services.iter().map(|(_, p, _)| p))
+            .map(|p| (p.protocol, p.port))
+            .collect();
+
+        // (component, port, visibility, target)
+        let mut declared: Vec<(String, ServicePort, Option<Visibility>, Target)> = Vec::new();
+        for c in desired.values() {
+            for p in c.spec.ports.iter().flatten() {
+                declared.push((
+                    c.name.clone(),
+                    p.clone(),
+                    c.spec.visibility.clone(),
+                    Target::Local,
+                ));
+            }
+        }
+        for (name, p, visibility) in &services {
+            declared.push((name.clone(), p.clone(), visibility.clone(), Target::Local));
+        }
+        for advert in self.mesh.registry.adverts() {
+            if desired.contains_key(&advert.name)
+                || services.iter().any(|(name, _, _)| name == &advert.name)
+            {
+                continue;
+            }
+            for p in &advert.ports {
+                declared.push((
+                    advert.name.clone(),
+                    p.clone(),
+                    advert.visibility.clone(),
+                    Target::Remote,
+                ));
+            }
+        }
+
+        let mut wanted: HashSet<Exposure> = HashSet::new();
+        let mut keys: HashSet<String> = HashSet::new();
+        {
+            let mut table = self.table.lock().await;
+            for (component, port, visibility, target) in declared {
+                let key = PortTable::key(&component, port.port, port.protocol);
+                let mut host_port = table.allocate(&key, port.protocol, port.host_port, &reserved);
+                // A remote service's requested port may be taken here; pick another
+                if host_port.is_err() && target == Target::Remote {
+                    host_port = table.allocate(&key, port.protocol, None, &reserved);
+                }
+                let host_port = match host_port {
+                    Ok(p) => p,
+                    Err(e) => {
+                        warn!(component=%component, port=port.port, error=%e, "Service port not exposed");
+                        continue;
+                    }
+                };
+                // Only edge nodes expose ports beyond loopback
+                let public = matches!(visibility, Some(Visibility::Public)) && self.is_edge;
+                let ip = if public { "0.0.0.0" } else { "127.0.0.1" };
+                let Ok(bind) = format!("{ip}:{host_port}").parse::<SocketAddr>() else {
+                    continue;
+                };
+                keys.insert(key);
+                wanted.insert(Exposure {
+                    component,
+                    guest_port: port.port,
+                    protocol: port.protocol,
+                    bind,
+                    target,
+                });
+            }
+            table.allocations.retain(|k, _| keys.contains(k));
+            table.save();
+        }
+
+        let mut active = self.active.lock().await;
+        active.retain(|exp, handle| {
+            let keep = wanted.contains(exp) && !handle.is_finished();
+            if !keep {
+                handle.abort();
+                info!(component=%exp.component, port=exp.guest_port, bind=%exp.bind, "Service port proxy stopped");
+            }
+            keep
+        });
+        for exp in wanted {
+            if active.contains_key(&exp) {
+                continue;
+            }
+            info!(component=%exp.component, port=exp.guest_port, protocol=?exp.protocol, bind=%exp.bind, target=?exp.target, "Exposing service port");
+            let handle = match exp.protocol {
+                Protocol::Tcp => tokio::spawn(run_tcp_proxy(exp.clone(), self.mesh.clone())),
+                Protocol::Udp => tokio::spawn(run_udp_proxy(exp.clone(), self.mesh.clone())),
+            };
+            active.insert(exp, handle);
+        }
+    }
+}
+
+async fn run_tcp_proxy(exp: Exposure, mesh: MeshHandle) {
+    let listener = match TcpListener::bind(exp.bind).await {
+        Ok(l) => l,
+        Err(e) => {
+            warn!(component=%exp.component, bind=%exp.bind, error=%e, "Failed to bind service port");
+            return;
+        }
+    };
+    loop {
+        let (mut client, remote) = match listener.accept().await {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(error=%e, "Service port accept failed");
+                continue;
+            }
+        };
+        let exp = exp.clone();
+        let mesh = mesh.clone();
+        tokio::spawn(async move {
+            let res = match exp.target {
+                Target::Local => match TcpStream::connect(("127.0.0.1", exp.guest_port)).await {
+                    Ok(mut upstream) => tokio::io::copy_bidirectional(&mut client, &mut upstream)
+                        .await
+                        .map(|_| ()),
+                    Err(e) => Err(e),
+                },
+                Target::Remote => match open_tunnel(&mesh, &exp).await {
+                    Ok(mut tunnel) => tokio::io::copy_bidirectional(&mut client, &mut tunnel)
+                        .await
+                        .map(|_| ()),
+                    Err(e) => Err(std::io::Error::other(e.to_string())),
+                },
+            };
+            if let Err(e) = res {
+                debug!(component=%exp.component, remote=%remote, error=%e, "Service port connection ended");
+            }
+        });
+    }
+}
+
+/// Open a tunnel stream to a peer exposing the port, trying peers in turn.
+async fn open_tunnel(
+    mesh: &MeshHandle,
+    exp: &Exposure,
+) -> anyhow::Result<tokio_util::compat::Compat<libp2p::Stream>> {
+    let header = serde_json::to_string(&TunnelHeader {
+        component: exp.component.clone(),
+        port: exp.guest_port,
+        protocol: exp.protocol,
+    })?;
+    let mut last_err = None;
+    for peer in mesh
+        .registry
+        .port_providers(&exp.component, exp.guest_port, exp.protocol)
+        .into_iter()
+        .take(MAX_ATTEMPTS)
+    {
+        match try_open(mesh, peer, &header).await {
+            Ok(stream) => {
+                mesh.registry.mark_ok(&peer);
+                return Ok(stream);
+            }
+            Err(e) => {
+                warn!(peer=%peer, component=%exp.component, error=%e, "Port tunnel open failed");
+                mesh.registry.mark_failed(peer);
+                last_err = Some(e);
+            }
+        }
+    }
+    Err(last_err.unwrap_or_else(|| anyhow::anyhow!("no peer exposes {}", exp.component)))
+}
+
+async fn try_open(
+    mesh: &MeshHandle,
+    peer: PeerId,
+    header: &str,
+) -> anyhow::Result<tokio_util::compat::Compat<libp2p::Stream>> {
+    let mut control = mesh.control.clone();
+    let stream = control
+        .open_stream(peer, PORT_TUNNEL_PROTOCOL)
+        .await
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let mut stream = stream.compat();
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+    stream.flush().await?;
+    Ok(stream)
+}
+
+/// UDP: one upstream per client address, idle sessions expire.
+async fn run_udp_proxy(exp: Exposure, mesh: MeshHandle) {
+    let socket = match UdpSocket::bind(exp.bind).await {
+        Ok(s) => Arc::new(s),
+        Err(e) => {
+            warn!(component=%exp.component, bind=%exp.bind, error=%e, "Failed to bind service port");
+            return;
+        }
+    };
+    let sessions: Arc<tokio::sync::Mutex<HashMap<SocketAddr, tokio::sync::mpsc::Sender<Vec<u8>>>>> =
+        Arc::new(tokio::sync::Mutex::new(HashMap::new()));
+    let mut buf = vec![0u8; 65535];
+    loop {
+        let (n, client) = match socket.recv_from(&mut buf).await {
+            Ok(r) => r,
+            Err(e) => {
+                debug!(error=%e, "Service port recv failed");
+                continue;
+            }
+        };
+        let datagram = buf[..n].to_vec();
+        let mut map = sessions.lock().await;
+        if let Some(tx) = map.get(&client) {
+            // A full session queue drops the datagram, as the network would
+            match tx.try_send(datagram.clone()) {
+                Ok(()) | Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => continue,
+                Err(_) => {
+                    map.remove(&client);
+                }
+            }
+        }
+        let (tx, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(64);
+        let _ = tx.try_send(datagram);
+        map.insert(client, tx);
+        drop(map);
+
+        let socket = socket.clone();
+        let sessions = sessions.clone();
+        let exp = exp.clone();
+        let mesh = mesh.clone();
+        tokio::spawn(async move {
+            let res = match exp.target {
+                Target::Local => udp_session_local(&socket, client, rx, exp.guest_port).await,
+                Target::Remote => match open_tunnel(&mesh, &exp).await {
+                    Ok(tunnel) => udp_session_tunnel(&socket, client, rx, tunnel).await,
+                    Err(e) => Err(std::io::Error::other(e.to_string())),
+                },
+            };
+            if let Err(e) = res {
+                debug!(component=%exp.component, client=%client, error=%e, "UDP session ended");
+            }
+            sessions.lock().await.remove(&client);
+        });
+    }
+}
+
+async fn udp_session_local(
+    socket: &UdpSocket,
+    client: SocketAddr,
+    mut rx: tokio::sync::mpsc::Receiver<Vec<u8>>,
+    guest_port: u16,
+) -> std::io::Result<()> {
+    let upstream = UdpSocket::bind("127.0.0.1:0").await?;
+    upstream.connect(("127.0.0.1", guest_port)).await?;
+    let mut buf = vec![0u8; 65535];
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(d) => { upstream.send(&d).await?; }
+                None => return Ok(()),
+            },
+            res = upstream.recv(&mut buf) => {
+                let n = res?;
+                socket.send_to(&buf[..n], client).await?;
+            }
+            _ = tokio::time::sleep(UDP_SESSION_IDLE) => return Ok(()),
+        }
+    }
+}
+
+async fn udp_session_tunnel<S>(
+    socket: &UdpSocket,
+    client: SocketAddr,
+    mut rx: tokio::sync::mpsc::Receiver<Vec<u8>>,
+    tunnel: S,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut rd, mut wr) = tokio::io::split(tunnel);
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(d) => write_frame(&mut wr, &d).await?,
+                None => return Ok(()),
+            },
+            frame = read_frame(&mut rd) => {
+                socket.send_to(&frame?, client).await?;
+            }
+            _ = tokio::time::sleep(UDP_SESSION_IDLE) => return Ok(()),
+        }
+    }
+}
+
+/// Datagrams cross the tunnel as a big-endian u16 length followed by the payload.
+async fn write_frame<W: AsyncWrite + Unpin>(wr: &mut W, data: &[u8]) -> std::io::Result<()> {
+    let len = u16::try_from(data.len()).map_err(|_| std::io::Error::other("datagram too large"))?;
+    wr.write_all(&len.to_be_bytes()).await?;
+    wr.write_all(data).await?;
+    wr.flush().await
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(rd: &mut R) -> std::io::Result<Vec<u8>> {
+    let len = rd.read_u16().await? as usize;
+    let mut data = vec![0u8; len];
+    rd.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+/// Ports of the service jobs supervised on this node, as
+/// (job name, port, visibility).
+async fn local_job_ports(jobs: &JobManager) -> Vec<(String, ServicePort, Option<Visibility>)> {
+    jobs.local_services()
+        .await
+        .into_iter()
+        .filter_map(|j| j.spec.service.map(|s| (j.spec.name, s)))
+        .flat_map(|(name, s)| {
+            s.ports
+                .into_iter()
+                .map(move |p| (name.clone(), p, s.visibility.clone()))
+        })
+        .collect()
+}
+
+/// Accept port tunnels from realm peers for services hosted here.
+pub async fn serve_port_tunnels(
+    supervisor: Arc<Supervisor>,
+    jobs: Arc<JobManager>,
+    registry: Arc<ServiceRegistry>,
+    mut control: libp2p_stream::Control,
+) {
+    let mut incoming = match control.accept(PORT_TUNNEL_PROTOCOL) {
+        Ok(incoming) => incoming,
+        Err(e) => {
+            warn!(error=%e, "Port tunnel protocol already registered");
+            return;
+        }
+    };
+    while let Some((peer, stream)) = incoming.next().await {
+        if !registry.is_member(&peer) {
+            warn!(peer=%peer, "Rejected port tunnel from a peer outside this realm");
+            continue;
+        }
+        let supervisor = supervisor.clone();
+        let jobs = jobs.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_tunnel(supervisor, jobs, stream.compat()).await {
+                debug!(peer=%peer, error=%e, "Port tunnel ended");
+            }
+        });
+    }
+}
+
+async fn handle_tunnel<S>(
+    supervisor: Arc<Supervisor>,
+    jobs: Arc<JobManager>,
+    stream: S,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    (&mut stream).take(1024).read_line(&mut line).await?;
+    let header: TunnelHeader = serde_json::from_str(line.trim())?;
+
+    // Only ports the local component or service job actually declares are reachable
+    let matches = |p: &ServicePort| p.port == header.port && p.protocol == header.protocol;
+    let declared = supervisor
+        .get_component(&header.component)
+        .await
+        .and_then(|c| c.spec.ports)
+        .unwrap_or_default()
+        .iter()
+        .any(matches)
+        || local_job_ports(&jobs)
+            .await
+            .iter()
+            .any(|(name, p, _)| name == &header.component && matches(p));
+    if !declared {
+        anyhow::bail!("{}:{} not served here", header.component, header.port);
+    }
+
+    match header.protocol {
+        Protocol::Tcp => {
+            let mut upstream = TcpStream::connect(("127.0.0.1", header.port)).await?;
+            tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+        }
+        Protocol::Udp => {
+            let upstream = UdpSocket::bind("127.0.0.1:0").await?;
+            upstream.connect(("127.0.0.1", header.port)).await?;
+            let (mut rd, mut wr) = tokio::io::split(stream);
+            let mut buf = vec![0u8; 65535];
+            loop {
+                tokio::select! {
+                    frame = read_frame(&mut rd) => { upstream.send(&frame?).await?; }
+                    res = upstream.recv(&mut buf) => {
+                        let n = res?;
+                        write_frame(&mut wr, &buf[..n]).await?;
+                    }
+                    _ = tokio::time::sleep(UDP_SESSION_IDLE) => break,
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -6,6 +6,81 @@ pub struct ExecutionPolicy {
     pub allow_native_execution: bool,
     #[serde(default)]
     pub allow_emulation: bool,
+    /// Run every native job in the Linux sandbox, with a default profile
+    /// when the job has none
+    #[serde(default)]
+    pub require_native_sandbox: bool,
+    /// Allow OCI container jobs (always sandboxed, Linux only)
+    #[serde(default)]
+    pub allow_containers: bool,
+    /// Jobs of each runtime this node runs at once; more wait in a queue
+    #[serde(default)]
+    pub job_slots: JobSlotLimits,
+}
+
+/// Job slots per runtime. Unset runtimes get a default from the CPU count.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobSlotLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wasm: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qemu: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oci: Option<u32>,
+    #[serde(
+        default,
+        rename = "qemu-system",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub qemu_system: Option<u32>,
+}
+
+impl JobSlotLimits {
+    pub const RUNTIMES: [&'static str; 5] = ["wasm", "native", "qemu", "oci", "qemu-system"];
+
+    /// Slots for a runtime kind (see `JobRuntime::kind`); at least one.
+    pub fn limit(&self, runtime: &str) -> u32 {
+        let cpus = std::thread::available_parallelism()
+            .map(|n| n.get() as u32)
+            .unwrap_or(1);
+        let (set, default) = match runtime {
+            "wasm" => (self.wasm, cpus * 2),
+            "native" => (self.native, cpus / 2),
+            "qemu" => (self.qemu, cpus / 2),
+            "oci" => (self.oci, cpus / 2),
+            "qemu-system" => (self.qemu_system, 1),
+            _ => (None, 1),
+        };
+        set.unwrap_or(default).max(1)
+    }
+
+    /// Set a runtime's slots from `runtime=count`.
+    pub fn set(&mut self, entry: &str) -> Result<(), String> {
+        let (runtime, count) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("invalid slot setting '{entry}' (expected runtime=count)"))?;
+        let count = count
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("invalid slot count in '{entry}'"))?;
+        let slot = match runtime.trim() {
+            "wasm" => &mut self.wasm,
+            "native" => &mut self.native,
+            "qemu" => &mut self.qemu,
+            "oci" => &mut self.oci,
+            "qemu-system" => &mut self.qemu_system,
+            other => {
+                return Err(format!(
+                    "unknown runtime '{other}' (expected one of {})",
+                    Self::RUNTIMES.join(", ")
+                ))
+            }
+        };
+        *slot = Some(count);
+        Ok(())
+    }
 }
 
 impl Default for ExecutionPolicy {
@@ -13,6 +88,9 @@ impl Default for ExecutionPolicy {
         Self {
             allow_native_execution: false,
             allow_emulation: false,
+            require_native_sandbox: false,
+            allow_containers: false,
+            job_slots: JobSlotLimits::default(),
         }
     }
 }
@@ -21,6 +99,8 @@ pub fn load_policy() -> ExecutionPolicy {
     // Env overrides take precedence for simple toggles
     let env_native = std::env::var("REALM_ALLOW_NATIVE_EXECUTION").ok();
     let env_emul = std::env::var("REALM_ALLOW_EMULATION").ok();
+    let env_sandbox = std::env::var("REALM_REQUIRE_NATIVE_SANDBOX").ok();
+    let env_containers = std::env::var("REALM_ALLOW_CONTAINERS").ok();
 
     let mut policy = read_policy_file().unwrap_or_default();
     if let Some(v) = env_native.as_deref() {
@@ -29,6 +109,12 @@ pub fn load_policy() -> ExecutionPolicy {
     if let Some(v) = env_emul.as_deref() {
         policy.allow_emulation = v == "1" || v.eq_ignore_ascii_case("true");
     }
+    if let Some(v) = env_sandbox.as_deref() {
+        policy.require_native_sandbox = v == "1" || v.eq_ignore_ascii_case("true");
+    }
+    if let Some(v) = env_containers.as_deref() {
+        policy.allow_containers = v == "1" || v.eq_ignore_ascii_case("true");
+    }
     policy
 }
 
@@ -78,5 +164,99 @@ pub fn find_any_qemu_user() -> Option<String> {
             return Some(path.display().to_string());
         }
     }
-    None
+    // Static emulators registered with binfmt_misc are often not on PATH
+    qemu_binfmt_status()
+        .handlers
+        .into_iter()
+        .find(|h| h.enabled && std::path::Path::new(&h.interpreter).exists())
+        .map(|h| h.interpreter)
+}
+
+/// Where binfmt_misc is normally mounted on Linux.
+const BINFMT_MISC_DIR: &str = "/proc/sys/fs/binfmt_misc";
+
+/// A binfmt_misc registration whose interpreter is a qemu-user emulator.
+#[derive(Debug, Clone, Serialize)]
+pub struct BinfmtHandler {
+    /// Registration name, e.g. "qemu-aarch64"
+    pub name: String,
+    pub enabled: bool,
+    pub interpreter: String,
+    /// Registration flags; "F" means the kernel holds the interpreter open,
+    /// so it works inside containers and chroots
+    pub flags: String,
+}
+
+/// State of binfmt_misc on this node, for `/api/qemu/status`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BinfmtStatus {
+    /// binfmt_misc is mounted
+    pub available: bool,
+    /// Handlers are globally enabled (`status` reads "enabled")
+    pub enabled: bool,
+    pub handlers: Vec<BinfmtHandler>,
+}
+
+/// Read the qemu handlers registered with binfmt_misc. Empty when it is not
+/// mounted, which includes every non-Linux host.
+pub fn qemu_binfmt_status() -> BinfmtStatus {
+    let dir = std::path::Path::new(BINFMT_MISC_DIR);
+    let Ok(status) = std::fs::read_to_string(dir.join("status")) else {
+        return BinfmtStatus::default();
+    };
+    let mut handlers = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == "status" || name == "register" {
+                continue;
+            }
+            let Ok(text) = std::fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            let mut handler = BinfmtHandler {
+                name,
+                enabled: false,
+                interpreter: String::new(),
+                flags: String::new(),
+            };
+            for line in text.lines() {
+                if line == "enabled" {
+                    handler.enabled = true;
+                } else if let Some(path) = line.strip_prefix("interpreter ") {
+                    handler.interpreter = path.trim().to_string();
+                } else if let Some(flags) = line.strip_prefix("flags:") {
+                    handler.flags = flags.trim().to_string();
+                }
+            }
+            let is_qemu = std::path::Path::new(&handler.interpreter)
+                .file_name()
+                .is_some_and(|f| f.to_string_lossy().starts_with("qemu-"));
+            if is_qemu {
+                handlers.push(handler);
+            }
+        }
+    }
+    handlers.sort_by(|a, b| a.name.cmp(&b.name));
+    BinfmtStatus {
+        available: true,
+        enabled: status.trim() == "enabled",
+        handlers,
+    }
+}
+
+/// Interpreter registered with binfmt_misc for a qemu target like "aarch64",
+/// when the handler is enabled and the interpreter still exists.
+pub fn binfmt_qemu_interpreter(qemu_arch: &str) -> Option<String> {
+    let status = qemu_binfmt_status();
+    if !status.enabled {
+        return None;
+    }
+    let name = format!("qemu-{qemu_arch}");
+    status
+        .handlers
+        .into_iter()
+        .find(|h| h.enabled && h.name == name)
+        .map(|h| h.interpreter)
+        .filter(|path| std::path::Path::new(path).exists())
 }
@@ -1,23 +1,104 @@
 use tokio::io::{duplex, AsyncBufReadExt, BufReader};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use wasmtime::{
     component::{Component, Linker as CLinker, ResourceTable, Val},
     Config, Engine, ResourceLimiter, Store,
 };
 
+use crate::egress::EgressGuard;
+use crate::events::BusEvent;
+use crate::host::HostState;
+use crate::http_cache::HttpComponentCache;
 use crate::p2p::metrics::{push_log, Metrics, SharedLogs};
+use crate::supervisor::DesiredComponent;
 use bytes::Bytes;
-use common::MountSpec;
-use core::convert::Infallible;
-use http_body_util::{BodyExt, Full};
+use common::{ComponentSpec, EgressPolicy, MountSpec, Protocol, ServicePort};
 use hyper::body::Body;
-use wasmtime_wasi::pipe::AsyncWriteStream;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use wasmtime_wasi::pipe::{AsyncWriteStream, MemoryInputPipe};
 use wasmtime_wasi::AsyncStdoutStream;
 use wasmtime_wasi::{DirPerms, FilePerms};
-use wasmtime_wasi_http::bindings::http::types::Scheme;
-use wasmtime_wasi_http::bindings::ProxyPre;
-use wasmtime_wasi_http::body::HyperOutgoingBody;
-use wasmtime_wasi_http::{WasiHttpCtx, WasiHttpView};
+use wasmtime_wasi_http::bindings::http::types::{ErrorCode, Scheme};
+use wasmtime_wasi_http::body::{HostIncomingBody, HyperOutgoingBody};
+use wasmtime_wasi_http::types::{
+    default_send_request, HostFutureIncomingResponse, HostIncomingRequest, OutgoingRequestConfig,
+};
+use wasmtime_wasi_http::{HttpResult, WasiHttpCtx, WasiHttpView};
+
+/// Socket permissions for a component. The guest may listen on its declared
+/// ports on loopback, where the agent's port proxies forward host and mesh traffic,
+/// and UDP replies back to loopback peers (the proxies) are allowed. Outbound
+/// connections are allowed only by an egress policy; anything else is denied.
+fn configure_sockets(
+    builder: &mut wasmtime_wasi::WasiCtxBuilder,
+    ports: Vec<ServicePort>,
+    egress: Option<Arc<EgressGuard>>,
+) {
+    use wasmtime_wasi::SocketAddrUse;
+    let tcp: Vec<u16> = ports
+        .iter()
+        .filter(|p| matches!(p.protocol, Protocol::Tcp))
+        .map(|p| p.port)
+        .collect();
+    let udp: Vec<u16> = ports
+        .iter()
+        .filter(|p| matches!(p.protocol, Protocol::Udp))
+        .map(|p| p.port)
+        .collect();
+    let has_ports = !ports.is_empty();
+    let outbound = egress.as_ref().is_some_and(|g| g.has_socket_rules());
+    builder.allow_ip_name_lookup(outbound);
+    builder.socket_addr_check(move |addr, use_| {
+        let loopback = addr.ip().is_loopback();
+        let egress_allows =
+            |proto: Protocol| egress.as_ref().is_some_and(|g| g.allow_socket(proto, addr));
+        let allowed = match use_ {
+            SocketAddrUse::TcpBind => loopback && tcp.contains(&addr.port()),
+            // Outbound UDP needs an ephemeral local bind first
+            SocketAddrUse::UdpBind => {
+                (loopback && udp.contains(&addr.port())) || (outbound && addr.port() == 0)
+            }
+            SocketAddrUse::UdpConnect | SocketAddrUse::UdpOutgoingDatagram => {
+                (has_ports && loopback) || egress_allows(Protocol::Udp)
+            }
+            SocketAddrUse::TcpConnect => egress_allows(Protocol::Tcp),
+        };
+        Box::pin(async move { allowed })
+    });
+}
+
+/// Outgoing wasi-http request hook. With a guard, only allowlisted destinations are
+/// reached; without one, outgoing HTTP is unrestricted.
+fn send_with_policy(
+    egress: Option<&EgressGuard>,
+    request: hyper::Request<HyperOutgoingBody>,
+    config: OutgoingRequestConfig,
+) -> HttpResult<HostFutureIncomingResponse> {
+    let allowed = match egress {
+        Some(guard) => {
+            let host = request.uri().host().unwrap_or_default();
+            let port = request
+                .uri()
+                .port_u16()
+                .unwrap_or(if config.use_tls { 443 } else { 80 });
+            guard.allow_http(config.use_tls, host, port)
+        }
+        None => true,
+    };
+    if !allowed {
+        return Err(ErrorCode::HttpRequestDenied.into());
+    }
+    Ok(default_send_request(request, config))
+}
+
+/// Default deadline for a single HTTP component request or event delivery.
+const DEFAULT_HTTP_REQUEST_TIMEOUT_MS: u64 = 30_000;
+/// Period of the shared epoch ticker driving HTTP component yields.
+pub(crate) const HTTP_EPOCH_TICK_MS: u64 = 10;
+/// Longest pause between request body chunks, as in `new_incoming_request`.
+const INCOMING_BODY_BETWEEN_BYTES_TIMEOUT: std::time::Duration =
+    std::time::Duration::from_secs(600);
 
 struct MemoryLimiter {
     max_bytes: usize,
@@ -43,11 +124,99 @@ impl ResourceLimiter for MemoryLimiter {
     }
 }
 
+/// Preopen each mount into the WASI context. Returns one log line per mount,
+/// `Err` when the host directory could not be opened.
+fn preopen_mounts(
+    builder: &mut wasmtime_wasi::WasiCtxBuilder,
+    mounts: &[MountSpec],
+) -> Vec<Result<String, String>> {
+    let mut out = Vec::with_capacity(mounts.len());
+    for m in mounts {
+        let (dperms, fperms) = if m.ro {
+            (DirPerms::READ, FilePerms::READ)
+        } else {
+            (
+                DirPerms::READ | DirPerms::MUTATE,
+                FilePerms::READ | FilePerms::WRITE,
+            )
+        };
+        match builder.preopened_dir(&m.host, m.guest.as_str(), dperms, fperms) {
+            Ok(_) if m.ro => out.push(Ok(format!("mounted {} -> {} (ro)", m.host, m.guest))),
+            Ok(_) => out.push(Ok(format!("mounted {} -> {}", m.host, m.guest))),
+            Err(e) => out.push(Err(format!(
+                "mount failed {} -> {}: {}",
+                m.host, m.guest, e
+            ))),
+        }
+    }
+    out
+}
+
+/// What a guest sees of its process: arguments after the program name,
+/// environment variables, env vars read from `file:`/`cas:` secret references,
+/// and an optional `cas:` stdin blob (command components and jobs only).
+#[derive(Clone, Debug, Default)]
+pub struct GuestEnv {
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub secret_env: BTreeMap<String, String>,
+    pub stdin: Option<String>,
+}
+
+impl GuestEnv {
+    pub fn from_spec(spec: &ComponentSpec) -> Self {
+        Self {
+            args: spec.args.clone(),
+            env: spec.env.clone(),
+            secret_env: spec.secret_env.clone(),
+            stdin: spec.stdin.clone(),
+        }
+    }
+
+    /// Set argv (with `program` as argv[0]) and the environment on `builder`.
+    /// Secrets are read on every call so rotated files take effect; a single
+    /// trailing newline is dropped from their values.
+    fn apply(
+        &self,
+        builder: &mut wasmtime_wasi::WasiCtxBuilder,
+        program: &str,
+    ) -> Result<(), String> {
+        builder.arg(program);
+        builder.args(&self.args);
+        for (key, value) in &self.env {
+            builder.env(key, value);
+        }
+        for (key, reference) in &self.secret_env {
+            let bytes = crate::secrets::resolve_secret_ref(reference)
+                .map_err(|e| format!("secret env {key}: {e}"))?;
+            let value = String::from_utf8(bytes)
+                .map_err(|_| format!("secret env {key}: value is not UTF-8"))?;
+            let value = value
+                .strip_suffix('\n')
+                .map(|v| v.strip_suffix('\r').unwrap_or(v))
+                .unwrap_or(&value);
+            builder.env(key, value);
+        }
+        Ok(())
+    }
+
+    /// Contents for the guest's stdin, if a reference is set.
+    fn read_stdin(&self) -> Result<Option<Vec<u8>>, String> {
+        self.stdin
+            .as_deref()
+            .map(crate::secrets::resolve_stdin_ref)
+            .transpose()
+            .map_err(|e| format!("stdin: {e}"))
+    }
+}
+
 struct StoreData {
     table: ResourceTable,
     wasi: wasmtime_wasi::WasiCtx,
     http: WasiHttpCtx,
     limiter: MemoryLimiter,
+    egress: Option<Arc<EgressGuard>>,
+    host: HostState,
 }
 
 impl wasmtime_wasi::WasiView for StoreData {
@@ -66,8 +235,17 @@ impl WasiHttpView for StoreData {
     fn table(&mut self) -> &mut ResourceTable {
         &mut self.table
     }
+    // Command components and jobs keep unrestricted outgoing HTTP unless a policy is set
+    fn send_request(
+        &mut self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        send_with_policy(self.egress.as_deref(), request, config)
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run_wasm_module_with_limits(
     wasm_path: &str,
     component_name: &str,
@@ -77,6 +255,10 @@ pub async fn run_wasm_module_with_limits(
     epoch_ms: u64,
     metrics: Option<std::sync::Arc<Metrics>>,
     mounts: Option<Vec<MountSpec>>,
+    ports: Option<Vec<ServicePort>>,
+    egress: Option<EgressPolicy>,
+    guest: GuestEnv,
+    host: HostState,
 ) -> anyhow::Result<()> {
     let wasm = tokio::fs::read(wasm_path).await?;
 
@@ -97,47 +279,33 @@ pub async fn run_wasm_module_with_limits(
     builder.stderr(AsyncStdoutStream::new(AsyncWriteStream::new(
         1024, stderr_w,
     )));
+    guest
+        .apply(&mut builder, component_name)
+        .map_err(|e| anyhow::anyhow!(e))?;
+    if let Some(bytes) = guest.read_stdin().map_err(|e| anyhow::anyhow!(e))? {
+        builder.stdin(MemoryInputPipe::new(bytes));
+    }
 
     // Preopen directories as requested in spec (best-effort; logs on failure)
     if let Some(mounts) = mounts {
-        for m in mounts.into_iter() {
-            let (dperms, fperms) = if m.ro {
-                (DirPerms::READ, FilePerms::READ)
-            } else {
-                (
-                    DirPerms::READ | DirPerms::MUTATE,
-                    FilePerms::READ | FilePerms::WRITE,
-                )
-            };
-            match builder.preopened_dir(&m.host, m.guest.as_str(), dperms, fperms) {
-                Ok(_) => {
-                    if m.ro {
-                        push_log(
-                            &logs,
-                            component_name,
-                            format!("mounted {} -> {} (ro)", m.host, m.guest),
-                        )
-                        .await;
-                    } else {
-                        push_log(
-                            &logs,
-                            component_name,
-                            format!("mounted {} -> {}", m.host, m.guest),
-                        )
-                        .await;
-                    }
-                }
-                Err(e) => {
-                    push_log(
-                        &logs,
-                        component_name,
-                        format!("mount failed {} -> {}: {}", m.host, m.guest, e),
-                    )
-                    .await;
-                }
-            }
+        for line in preopen_mounts(&mut builder, &mounts) {
+            push_log(&logs, component_name, line.unwrap_or_else(|e| e)).await;
         }
     }
+    let egress = match egress {
+        Some(policy) => Some(Arc::new(
+            EgressGuard::new(component_name, policy, logs.clone(), metrics.clone())
+                .resolve_hosts()
+                .await,
+        )),
+        None => None,
+    };
+    // Declared service ports and egress rules enable WASI sockets, limited to loopback
+    // binds on those ports and allowlisted destinations
+    let ports = ports.unwrap_or_default();
+    if !ports.is_empty() || egress.as_ref().is_some_and(|g| g.has_socket_rules()) {
+        configure_sockets(&mut builder, ports, egress.clone());
+    }
     let wasi = builder.build();
 
     // readers for stdout/stderr pushing into ring buffers
@@ -192,6 +360,8 @@ pub async fn run_wasm_module_with_limits(
             wasi,
             http: WasiHttpCtx::new(),
             limiter,
+            egress,
+            host,
         },
     );
 
@@ -215,6 +385,8 @@ pub async fn run_wasm_module_with_limits(
     let component = Component::from_binary(&engine, &wasm)?;
     let mut linker = CLinker::<StoreData>::new(&engine);
     wasmtime_wasi::add_to_linker_async(&mut linker)?;
+    wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker)?;
+    crate::host::add_to_linker(&mut linker, |data: &mut StoreData| &mut data.host)?;
     let instance = linker.instantiate_async(&mut store, &component).await?;
     info!(path = %wasm_path, "Component instantiated with limits");
 
@@ -255,100 +427,216 @@ pub async fn run_wasm_module_with_limits(
     Ok(())
 }
 
-/// One-shot HTTP handler invocation placeholder.
-/// For now, this returns 501 Not Implemented until WASI HTTP is wired.
-pub async fn invoke_http_component_once(
-    wasm_path: &str,
-    component_name: &str,
-    method: &str,
-    path: &str,
-    headers: Vec<(String, String)>,
-    body: Vec<u8>,
-) -> anyhow::Result<(u16, Vec<(String, String)>, Vec<u8>)> {
-    // Create a hyper Request with a body type compatible with wasi-http new_incoming_request
-    let body_full = Full::new(Bytes::from(body)).map_err(|_e: Infallible| match _e {});
-    let uri = if path.is_empty() {
-        "http://component/".to_string()
-    } else {
-        format!("http://component/{}", path.trim_start_matches('/'))
-    };
-    let mut req = hyper::Request::builder()
-        .method(method)
-        .uri(uri)
-        .body(body_full)?;
-    {
-        let h = req.headers_mut();
-        for (k, v) in headers {
-            if let Ok(name) = hyper::header::HeaderName::from_bytes(k.as_bytes()) {
-                if let Ok(val) = hyper::header::HeaderValue::from_str(&v) {
-                    h.append(name, val);
-                }
-            }
-        }
-    }
-    let resp = invoke_http_component_hyper(wasm_path, component_name, req).await?;
-    let (parts, body) = resp.into_parts();
-    let collected = BodyExt::collect(body).await?;
-    let bytes = collected.to_bytes();
-    let mut out_headers = Vec::new();
-    for (k, v) in parts.headers.iter() {
-        out_headers.push((k.to_string(), v.to_str().unwrap_or("").to_string()));
+/// What the request timeout of an HTTP component invocation bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestDeadline {
+    /// The whole invocation, including streaming both bodies
+    Invocation,
+    /// Only the time to the response head; used for WebSocket connections,
+    /// which stay open for as long as both sides want
+    ResponseHead,
+}
+
+pub(crate) fn request_timeout(desired: &DesiredComponent) -> std::time::Duration {
+    std::time::Duration::from_millis(
+        desired
+            .spec
+            .request_timeout_ms
+            .unwrap_or(DEFAULT_HTTP_REQUEST_TIMEOUT_MS),
+    )
+}
+
+/// Run a guest call, stopping it at `deadline` when one is set. Dropping the
+/// future drops the store, which also works for a guest that spins: stores
+/// yield on every epoch tick.
+async fn run_until<F>(deadline: Option<tokio::time::Instant>, call: F) -> anyhow::Result<()>
+where
+    F: std::future::Future<Output = anyhow::Result<()>>,
+{
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, call).await?,
+        None => call.await,
     }
-    Ok((parts.status.as_u16(), out_headers, bytes.to_vec()))
 }
 
+/// Invoke an HTTP component for one request.
+///
+/// Resolves as soon as the guest sets the response head; the handler keeps running
+/// in a background task that owns the store, so bodies (and trailers) stream in
+/// both directions. The request timeout bounds the time to the response head and,
+/// with [`RequestDeadline::Invocation`], the handler's whole run: a guest still
+/// running at the deadline is stopped and its response body ends early.
 pub async fn invoke_http_component_hyper<B>(
-    wasm_path: &str,
-    component_name: &str,
+    cache: &HttpComponentCache,
+    desired: &DesiredComponent,
     req: hyper::Request<B>,
+    scope: RequestDeadline,
 ) -> anyhow::Result<hyper::Response<HyperOutgoingBody>>
 where
-    B: Body<Data = Bytes, Error = hyper::Error> + Send + Sync + 'static,
+    B: Body<Data = Bytes, Error = ErrorCode> + Send + Sync + 'static,
 {
-    // Configure Wasmtime engine for components
-    let mut cfg = Config::new();
-    cfg.wasm_component_model(true).async_support(true);
-    let engine = Engine::new(&cfg)?;
-    let component = Component::from_file(&engine, wasm_path)?;
-    let mut linker = CLinker::<HttpStore>::new(&engine);
-    linker.allow_shadowing(true);
-    wasmtime_wasi::add_to_linker_async(&mut linker)?;
-    wasmtime_wasi_http::add_to_linker_async(&mut linker)?;
-    let pre = ProxyPre::new(linker.instantiate_pre(&component)?)?;
+    let cached = cache
+        .get_or_load(&desired.spec.sha256_hex, &desired.path)
+        .await?;
+    let deadline = tokio::time::Instant::now() + request_timeout(desired);
+
+    let mut store = wasmtime::Store::new(
+        &cached.engine,
+        HttpStore::new(
+            desired,
+            cache.egress_guard(desired).await,
+            cache.host_state(desired),
+        )?,
+    );
+    store.limiter(|data| &mut data.limiter);
+    // Fuel metering is always on for the shared engine; 0 means unlimited.
+    let fuel = desired.spec.fuel.filter(|f| *f > 0).unwrap_or(u64::MAX);
+    store.set_fuel(fuel)?;
+    // Yield back to the runtime every `epoch_ms` so a busy handler cannot starve
+    // the gateway and the request deadline below can stop it.
+    let epoch_ms = desired.spec.epoch_ms.unwrap_or(100).max(HTTP_EPOCH_TICK_MS);
+    store.epoch_deadline_async_yield_and_update(epoch_ms / HTTP_EPOCH_TICK_MS);
 
-    let mut store = wasmtime::Store::new(&engine, HttpStore::new(component_name));
     let (sender, receiver) = tokio::sync::oneshot::channel();
-    let incoming = store.data_mut().new_incoming_request(Scheme::Http, req)?;
+    // Built by hand rather than with `new_incoming_request`, which only takes
+    // hyper bodies; the gateway's body reports its own errors (e.g. the size limit).
+    let (parts, body) = req.into_parts();
+    let body = HostIncomingBody::new(
+        http_body_util::BodyExt::boxed(body),
+        INCOMING_BODY_BETWEEN_BYTES_TIMEOUT,
+    );
+    let incoming = HostIncomingRequest::new(store.data_mut(), parts, Scheme::Http, Some(body))?;
+    let incoming = store.data_mut().table().push(incoming)?;
     let out = store.data_mut().new_response_outparam(sender)?;
 
-    let instance = pre.instantiate_async(&mut store).await?;
-    instance
-        .wasi_http_incoming_handler()
-        .call_handle(&mut store, incoming, out)
-        .await?;
-    match receiver.await {
-        Ok(Ok(resp)) => Ok(resp),
-        Ok(Err(e)) => Err(e.into()),
-        Err(e) => Err(anyhow::anyhow!(e)),
+    let component_name = desired.name.clone();
+    let task = tokio::spawn(async move {
+        let handle = async {
+            let pre = cached.pre.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("component does not export wasi:http/incoming-handler")
+            })?;
+            let instance = pre.instantiate_async(&mut store).await?;
+            instance
+                .wasi_http_incoming_handler()
+                .call_handle(&mut store, incoming, out)
+                .await
+        };
+        let deadline = (scope == RequestDeadline::Invocation).then_some(deadline);
+        run_until(deadline, handle)
+            .await
+            .inspect_err(|e| warn!(component=%component_name, error=%e, "HTTP handler stopped"))
+    });
+
+    match tokio::time::timeout_at(deadline, receiver).await {
+        Ok(Ok(Ok(resp))) => Ok(resp),
+        Ok(Ok(Err(e))) => Err(e.into()),
+        // The guest returned (or trapped) without ever setting a response
+        Ok(Err(_)) => match task.await {
+            Ok(Err(e)) => Err(e),
+            Ok(Ok(())) => Err(anyhow::anyhow!(
+                "component returned without setting a response"
+            )),
+            Err(e) => Err(e.into()),
+        },
+        Err(elapsed) => {
+            task.abort();
+            Err(elapsed.into())
+        }
     }
 }
 
-struct HttpStore {
+/// Run a component's `realm:host/event-handler` export for one event, within
+/// the component's request timeout. Returns false when the component does not
+/// export it.
+pub async fn invoke_event_handler(
+    cache: &HttpComponentCache,
+    desired: &DesiredComponent,
+    event: &BusEvent,
+) -> anyhow::Result<bool> {
+    let cached = cache
+        .get_or_load(&desired.spec.sha256_hex, &desired.path)
+        .await?;
+    let Some(pre) = cached.events_pre.as_ref() else {
+        return Ok(false);
+    };
+    let mut store = wasmtime::Store::new(
+        &cached.engine,
+        HttpStore::new(
+            desired,
+            cache.egress_guard(desired).await,
+            cache.host_state(desired),
+        )?,
+    );
+    store.limiter(|data| &mut data.limiter);
+    let fuel = desired.spec.fuel.filter(|f| *f > 0).unwrap_or(u64::MAX);
+    store.set_fuel(fuel)?;
+    let epoch_ms = desired.spec.epoch_ms.unwrap_or(100).max(HTTP_EPOCH_TICK_MS);
+    store.epoch_deadline_async_yield_and_update(epoch_ms / HTTP_EPOCH_TICK_MS);
+
+    let timeout = request_timeout(desired);
+    let call = async {
+        crate::host::call_event_handler(pre, &mut store, event)
+            .await?
+            .map_err(|e| anyhow::anyhow!(e))
+    };
+    run_until(Some(tokio::time::Instant::now() + timeout), call)
+        .await
+        .map_err(|e| match e.is::<tokio::time::error::Elapsed>() {
+            true => anyhow::anyhow!("timed out after {timeout:?}"),
+            false => e,
+        })?;
+    Ok(true)
+}
+
+pub(crate) struct HttpStore {
     table: ResourceTable,
     wasi: wasmtime_wasi::WasiCtx,
     http: WasiHttpCtx,
+    limiter: MemoryLimiter,
+    egress: Option<Arc<EgressGuard>>,
+    host: HostState,
     _name: String,
 }
 
 impl HttpStore {
-    fn new(name: &str) -> Self {
-        let wasi = wasmtime_wasi::WasiCtxBuilder::new().build();
-        Self {
+    fn new(
+        desired: &DesiredComponent,
+        egress: Option<Arc<EgressGuard>>,
+        host: HostState,
+    ) -> anyhow::Result<Self> {
+        let mut builder = wasmtime_wasi::WasiCtxBuilder::new();
+        GuestEnv::from_spec(&desired.spec)
+            .apply(&mut builder, &desired.name)
+            .map_err(|e| anyhow::anyhow!(e))?;
+        if let Some(mounts) = &desired.spec.mounts {
+            for line in preopen_mounts(&mut builder, mounts) {
+                if let Err(e) = line {
+                    warn!(component=%desired.name, "{}", e);
+                }
+            }
+        }
+        // Socket rules open outbound sockets to allowlisted destinations; HTTP
+        // components declare no listening ports of their own
+        if egress.as_ref().is_some_and(|g| g.has_socket_rules()) {
+            configure_sockets(&mut builder, Vec::new(), egress.clone());
+        }
+        let memory_max_mb = desired.spec.memory_max_mb.unwrap_or(64);
+        Ok(Self {
             table: ResourceTable::new(),
-            wasi,
+            wasi: builder.build(),
             http: WasiHttpCtx::new(),
-            _name: name.to_string(),
-        }
+            limiter: MemoryLimiter {
+                max_bytes: (memory_max_mb * 1024 * 1024) as usize,
+            },
+            egress,
+            host,
+            _name: desired.name.clone(),
+        })
+    }
+
+    /// `realm:host` state, for linking the host imports.
+    pub(crate) fn host(&mut self) -> &mut HostState {
+        &mut self.host
     }
 }
 
@@ -368,4 +656,46 @@ impl WasiHttpView for HttpStore {
     fn table(&mut self) -> &mut ResourceTable {
         &mut self.table
     }
+    // HTTP components keep unrestricted outgoing HTTP unless a policy is set
+    fn send_request(
+        &mut self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        send_with_policy(self.egress.as_deref(), request, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn deadline_stops_guest_that_stalls_after_headers() {
+        let (head_tx, head_rx) = tokio::sync::oneshot::channel();
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(50);
+        let task = tokio::spawn(run_until(Some(deadline), async move {
+            let _ = head_tx.send(());
+            std::future::pending::<()>().await;
+            Ok(())
+        }));
+        head_rx.await.expect("head sent before the deadline");
+        let res = tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("guest stopped at the deadline")
+            .unwrap();
+        assert!(res.unwrap_err().is::<tokio::time::error::Elapsed>());
+    }
+
+    #[tokio::test]
+    async fn response_head_scope_leaves_stream_running() {
+        let task = tokio::spawn(run_until(None, async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(())
+        }));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!task.is_finished());
+        assert!(task.await.unwrap().is_ok());
+    }
 }
@@ -0,0 +1,149 @@
+//! cgroup v2 groups for sandboxed runs. They live under `jobs/` in the
+//! agent's own group, which must be delegated to the agent's user (systemd
+//! `Delegate=yes`), or under `REALM_CGROUP_ROOT` when set.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use common::JobResources;
+
+const CGROUP_MOUNT: &str = "/sys/fs/cgroup";
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+const CPU_PERIOD_US: u64 = 100_000;
+
+pub struct JobCgroup {
+    path: PathBuf,
+}
+
+impl JobCgroup {
+    /// Create the group for one run and write its limits.
+    pub fn create(job_id: &str, limits: Option<&JobResources>) -> Result<Self, String> {
+        let root = jobs_root().clone()?;
+        let path = root.join(format!(
+            "{}-{}",
+            super::sanitize(job_id),
+            uuid::Uuid::new_v4().simple()
+        ));
+        std::fs::create_dir(&path).map_err(|e| format!("create {}: {e}", path.display()))?;
+        let group = Self { path };
+        if let Some(limits) = limits {
+            group.apply(limits)?;
+        }
+        Ok(group)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn apply(&self, limits: &JobResources) -> Result<(), String> {
+        if let Some(cpus) = limits.cpus {
+            if cpus.is_nan() || cpus <= 0.0 {
+                return Err(format!("cpus must be positive, got {cpus}"));
+            }
+            let quota = ((cpus * CPU_PERIOD_US as f64) as u64).max(1_000);
+            self.write("cpu.max", &format!("{quota} {CPU_PERIOD_US}"))?;
+        }
+        if let Some(mb) = limits.memory_mb {
+            self.write("memory.max", &(mb * 1024 * 1024).to_string())?;
+            // Keep the limit from spilling into swap; absent without swap accounting
+            let _ = self.write("memory.swap.max", "0");
+        }
+        if let Some(pids) = limits.pids {
+            self.write("pids.max", &pids.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// SIGKILL every process in the group.
+    pub fn kill(&self) {
+        if self.write("cgroup.kill", "1").is_ok() {
+            return;
+        }
+        // cgroup.kill needs Linux 5.14
+        if let Ok(procs) = std::fs::read_to_string(self.path.join("cgroup.procs")) {
+            for pid in procs.lines().filter_map(|l| l.trim().parse::<i32>().ok()) {
+                unsafe {
+                    libc::kill(pid, libc::SIGKILL);
+                }
+            }
+        }
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<(), String> {
+        std::fs::write(self.path.join(file), value).map_err(|e| format!("{file}: {e}"))
+    }
+}
+
+impl Drop for JobCgroup {
+    fn drop(&mut self) {
+        // The group can only be removed once its killed processes are gone
+        let path = self.path.clone();
+        std::thread::spawn(move || {
+            for _ in 0..50 {
+                if std::fs::remove_dir(&path).is_ok() || !path.exists() {
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+        });
+    }
+}
+
+/// Parent of all job groups, set up once per agent process.
+fn jobs_root() -> &'static Result<PathBuf, String> {
+    static ROOT: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        let base = match std::env::var("REALM_CGROUP_ROOT") {
+            Ok(root) => PathBuf::from(root),
+            Err(_) => own_cgroup()?,
+        };
+        if !base.join("cgroup.controllers").exists() {
+            return Err(format!("{} is not a cgroup v2 directory", base.display()));
+        }
+        if enable_controllers(&base).is_err() {
+            // A group with member processes cannot hand controllers to its
+            // children, so the agent moves into a leaf of its own first
+            let leaf = base.join("agent");
+            let _ = std::fs::create_dir(&leaf);
+            std::fs::write(leaf.join("cgroup.procs"), std::process::id().to_string())
+                .map_err(|e| format!("move agent into {}: {e}", leaf.display()))?;
+            enable_controllers(&base).map_err(|e| {
+                format!(
+                    "enable controllers in {}: {e}; set REALM_CGROUP_ROOT to a delegated group",
+                    base.display()
+                )
+            })?;
+        }
+        let root = base.join("jobs");
+        if !root.exists() {
+            std::fs::create_dir(&root).map_err(|e| format!("create {}: {e}", root.display()))?;
+        }
+        enable_controllers(&root).map_err(|e| format!("{}: {e}", root.display()))?;
+        Ok(root)
+    })
+}
+
+fn own_cgroup() -> Result<PathBuf, String> {
+    let own = std::fs::read_to_string("/proc/self/cgroup")
+        .map_err(|e| format!("read /proc/self/cgroup: {e}"))?;
+    let rel = own
+        .lines()
+        .find_map(|l| l.strip_prefix("0::"))
+        .ok_or_else(|| "cgroup v2 is not mounted".to_string())?;
+    Ok(Path::new(CGROUP_MOUNT).join(rel.trim().trim_start_matches('/')))
+}
+
+/// Delegate the cpu, memory and pids controllers (those available) to children.
+fn enable_controllers(group: &Path) -> std::io::Result<()> {
+    let available = std::fs::read_to_string(group.join("cgroup.controllers"))?;
+    let wanted: Vec<String> = CONTROLLERS
+        .iter()
+        .filter(|c| available.split_whitespace().any(|a| a == **c))
+        .map(|c| format!("+{c}"))
+        .collect();
+    if wanted.is_empty() {
+        return Ok(());
+    }
+    std::fs::write(group.join("cgroup.subtree_control"), wanted.join(" "))
+}
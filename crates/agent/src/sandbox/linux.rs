@@ -0,0 +1,523 @@
+//! The sandbox helper, `realm __sandbox-exec <spec> <binary> [args...]`. It
+//! runs before the agent starts any threads: it unshares namespaces, forks an
+//! init that builds the root and drops privileges, and that init forks and
+//! execs the job, reaping orphans until the job exits.
+
+use std::collections::BTreeMap;
+use std::ffi::{CString, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+
+use landlock::{
+    path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus,
+    ABI,
+};
+use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, SeccompRule};
+
+use super::{HelperSpec, SETUP_FAILED_EXIT};
+
+/// Device nodes bound into the sandbox's `/dev`.
+const DEVICES: &[&str] = &["null", "zero", "full", "random", "urandom", "tty"];
+const OLD_ROOT: &str = ".old-root";
+
+/// Syscalls a job has no business making; they fail with EPERM.
+const DENIED_SYSCALLS: &[i64] = &[
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_fsopen,
+    libc::SYS_fsmount,
+    libc::SYS_move_mount,
+    libc::SYS_open_tree,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_syslog,
+    libc::SYS_acct,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_name_to_handle_at,
+    libc::SYS_settimeofday,
+    libc::SYS_clock_settime,
+    libc::SYS_clock_adjtime,
+    libc::SYS_adjtimex,
+    libc::SYS_quotactl,
+];
+
+pub fn helper_main() -> ! {
+    let mut argv = std::env::args_os().skip(2);
+    let spec: HelperSpec = match argv
+        .next()
+        .and_then(|s| serde_json::from_slice(s.as_bytes()).ok())
+    {
+        Some(spec) => spec,
+        None => fail("invalid sandbox spec"),
+    };
+    let Some(binary) = argv.next() else {
+        fail("missing program")
+    };
+    let args: Vec<OsString> = argv.collect();
+    if let Err(e) = enter(&spec) {
+        fail(&e);
+    }
+    // The first child is PID 1 of the new pid namespace
+    match unsafe { libc::fork() } {
+        -1 => fail(&format!("fork: {}", std::io::Error::last_os_error())),
+        0 => init(&spec, &binary, &args),
+        init_pid => std::process::exit(wait_for(init_pid)),
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("sandbox: {msg}");
+    std::process::exit(SETUP_FAILED_EXIT)
+}
+
+/// Join the cgroup and unshare namespaces.
+fn enter(spec: &HelperSpec) -> Result<(), String> {
+    if let Some(cgroup) = &spec.cgroup {
+        std::fs::write(cgroup.join("cgroup.procs"), "0")
+            .map_err(|e| format!("join cgroup {}: {e}", cgroup.display()))?;
+    }
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let mut flags = libc::CLONE_NEWUSER
+        | libc::CLONE_NEWNS
+        | libc::CLONE_NEWPID
+        | libc::CLONE_NEWIPC
+        | libc::CLONE_NEWUTS;
+    if !spec.network {
+        flags |= libc::CLONE_NEWNET;
+    }
+    check(unsafe { libc::unshare(flags) }, "unshare")?;
+    // Native jobs keep their uid, containers see root; capabilities in the
+    // new namespaces last until exec
+    let (inner_uid, inner_gid) = if spec.map_root { (0, 0) } else { (uid, gid) };
+    std::fs::write("/proc/self/setgroups", "deny").map_err(|e| format!("setgroups: {e}"))?;
+    std::fs::write("/proc/self/uid_map", format!("{inner_uid} {uid} 1"))
+        .map_err(|e| format!("uid_map: {e}"))?;
+    std::fs::write("/proc/self/gid_map", format!("{inner_gid} {gid} 1"))
+        .map_err(|e| format!("gid_map: {e}"))
+}
+
+/// PID 1 of the sandbox.
+fn init(spec: &HelperSpec, binary: &OsString, args: &[OsString]) -> ! {
+    // Die with the helper, taking the whole pid namespace along
+    unsafe {
+        libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+    }
+    if let Err(e) = build_root(spec).and_then(|writable_root| restrict(spec, writable_root)) {
+        fail(&e);
+    }
+    match unsafe { libc::fork() } {
+        -1 => fail(&format!("fork: {}", std::io::Error::last_os_error())),
+        0 => {
+            let err = std::process::Command::new(binary).args(args).exec();
+            eprintln!("sandbox: exec {}: {err}", Path::new(binary).display());
+            std::process::exit(127)
+        }
+        job => std::process::exit(wait_for(job)),
+    }
+}
+
+/// Wait for `pid`, reaping any other children meanwhile; returns its exit
+/// code, or 128 + signal.
+fn wait_for(pid: libc::pid_t) -> i32 {
+    loop {
+        let mut status = 0;
+        let reaped = unsafe { libc::waitpid(-1, &mut status, 0) };
+        if reaped == pid {
+            if libc::WIFEXITED(status) {
+                return libc::WEXITSTATUS(status);
+            }
+            if libc::WIFSIGNALED(status) {
+                return 128 + libc::WTERMSIG(status);
+            }
+        } else if reaped == -1
+            && std::io::Error::last_os_error().raw_os_error() != Some(libc::EINTR)
+        {
+            return SETUP_FAILED_EXIT;
+        }
+    }
+}
+
+/// Assemble the root at `spec.root` (a tmpfs, or the image for containers)
+/// and pivot into it. Returns whether the root itself stays writable.
+fn build_root(spec: &HelperSpec) -> Result<bool, String> {
+    let root = &spec.root;
+    mount(
+        None,
+        Path::new("/"),
+        None,
+        libc::MS_REC | libc::MS_PRIVATE,
+        None,
+    )?;
+    let writable_root = match &spec.rootfs {
+        Some(rootfs) => mount_image(spec, rootfs)?,
+        None => {
+            mount(
+                Some("tmpfs"),
+                root,
+                Some("tmpfs"),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                Some("mode=0755"),
+            )?;
+            false
+        }
+    };
+
+    let tmp = guest_path(root, Path::new("/tmp"));
+    mkdir(&tmp)?;
+    mount(
+        Some("tmpfs"),
+        &tmp,
+        Some("tmpfs"),
+        libc::MS_NOSUID | libc::MS_NODEV,
+        Some("mode=1777"),
+    )?;
+
+    let dev = guest_path(root, Path::new("/dev"));
+    mkdir(&dev)?;
+    for name in DEVICES {
+        let host = Path::new("/dev").join(name);
+        if host.exists() {
+            bind(&host, &dev.join(name), false)?;
+        }
+    }
+    for (name, target) in [
+        ("fd", "/proc/self/fd"),
+        ("stdin", "/proc/self/fd/0"),
+        ("stdout", "/proc/self/fd/1"),
+        ("stderr", "/proc/self/fd/2"),
+    ] {
+        let _ = std::os::unix::fs::symlink(target, dev.join(name));
+    }
+    let shm = dev.join("shm");
+    mkdir(&shm)?;
+    mount(
+        Some("tmpfs"),
+        &shm,
+        Some("tmpfs"),
+        libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+        Some("mode=1777"),
+    )?;
+
+    for path in &spec.ro_paths {
+        let target = guest_path(root, path);
+        // Merged-/usr links like /bin -> usr/bin are recreated, not bound
+        if path
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_symlink())
+        {
+            let link = std::fs::read_link(path).map_err(|e| format!("{}: {e}", path.display()))?;
+            if let Some(parent) = target.parent() {
+                mkdir(parent)?;
+            }
+            let _ = std::os::unix::fs::symlink(link, &target);
+            continue;
+        }
+        bind(path, &target, true)?;
+    }
+    for m in &spec.mounts {
+        bind(
+            Path::new(&m.host),
+            &guest_path(root, Path::new(&m.guest)),
+            m.ro,
+        )?;
+    }
+    if spec.rootfs.is_some() {
+        let run = guest_path(root, Path::new("/run"));
+        if mkdir(&run).is_ok() {
+            mount(
+                Some("tmpfs"),
+                &run,
+                Some("tmpfs"),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                Some("mode=0755"),
+            )?;
+        }
+        // Name resolution comes from the host when the container shares its network
+        if spec.network {
+            for file in ["/etc/resolv.conf", "/etc/hosts"] {
+                let _ = bind(Path::new(file), &guest_path(root, Path::new(file)), true);
+            }
+        }
+        let _ = mkdir(&guest_path(root, &spec.workdir));
+    }
+
+    let proc_dir = guest_path(root, Path::new("/proc"));
+    mkdir(&proc_dir)?;
+    mount(
+        Some("proc"),
+        &proc_dir,
+        Some("proc"),
+        libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+        None,
+    )?;
+
+    let old_root = root.join(OLD_ROOT);
+    mkdir(&old_root)?;
+    let new_c = c_path(root)?;
+    let old_c = c_path(&old_root)?;
+    check(
+        unsafe { libc::syscall(libc::SYS_pivot_root, new_c.as_ptr(), old_c.as_ptr()) as i32 },
+        "pivot_root",
+    )?;
+    std::env::set_current_dir("/").map_err(|e| format!("chdir /: {e}"))?;
+    let old_c = c_path(&Path::new("/").join(OLD_ROOT))?;
+    check(
+        unsafe { libc::umount2(old_c.as_ptr(), libc::MNT_DETACH) },
+        "detach old root",
+    )?;
+    let _ = std::fs::remove_dir(Path::new("/").join(OLD_ROOT));
+    if !writable_root {
+        // Nothing else may be created in the root itself
+        mount(
+            None,
+            Path::new("/"),
+            None,
+            libc::MS_REMOUNT | libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV,
+            None,
+        )?;
+    }
+
+    let hostname = b"sandbox";
+    unsafe {
+        libc::sethostname(hostname.as_ptr().cast(), hostname.len());
+    }
+    std::env::set_current_dir(&spec.workdir)
+        .map_err(|e| format!("chdir {}: {e}", spec.workdir.display()))?;
+    Ok(writable_root)
+}
+
+/// Mount the image at `spec.root`: an overlay with a private upper layer when
+/// the kernel allows one in a user namespace (5.11+), otherwise a tmpfs with
+/// the image's top-level entries bound read-only. Returns whether the root
+/// is writable.
+fn mount_image(spec: &HelperSpec, rootfs: &Path) -> Result<bool, String> {
+    let root = &spec.root;
+    let scratch = root.parent().unwrap_or(root);
+    let upper = scratch.join("upper");
+    let work = scratch.join("overlay-work");
+    mkdir(&upper)?;
+    mkdir(&work)?;
+    let options = format!(
+        "lowerdir={},upperdir={},workdir={},userxattr",
+        rootfs.display(),
+        upper.display(),
+        work.display()
+    );
+    if mount(
+        Some("overlay"),
+        root,
+        Some("overlay"),
+        libc::MS_NOSUID | libc::MS_NODEV,
+        Some(&options),
+    )
+    .is_ok()
+    {
+        return Ok(true);
+    }
+    mount(
+        Some("tmpfs"),
+        root,
+        Some("tmpfs"),
+        libc::MS_NOSUID | libc::MS_NODEV,
+        Some("mode=0755"),
+    )?;
+    let entries = std::fs::read_dir(rootfs).map_err(|e| format!("{}: {e}", rootfs.display()))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let target = root.join(entry.file_name());
+        // These get fresh mounts of their own
+        if ["dev", "proc", "tmp", "run"]
+            .iter()
+            .any(|d| entry.file_name() == *d)
+        {
+            mkdir(&target)?;
+            continue;
+        }
+        if entry.file_type().is_ok_and(|t| t.is_symlink()) {
+            let link = std::fs::read_link(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            let _ = std::os::unix::fs::symlink(link, &target);
+        } else {
+            bind(&path, &target, true)?;
+        }
+    }
+    Ok(false)
+}
+
+/// Apply the landlock allowlist and the seccomp filter.
+fn restrict(spec: &HelperSpec, writable_root: bool) -> Result<(), String> {
+    check(
+        unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) },
+        "no_new_privs",
+    )?;
+
+    let abi = ABI::V3;
+    let mut read_only: Vec<PathBuf> = spec.ro_paths.clone();
+    read_only.push(PathBuf::from("/proc"));
+    let mut writable = vec![
+        PathBuf::from("/tmp"),
+        PathBuf::from("/dev"),
+        spec.workdir.clone(),
+    ];
+    if spec.rootfs.is_some() {
+        // The whole image is the container's own
+        if writable_root {
+            writable.push(PathBuf::from("/"));
+        } else {
+            read_only.push(PathBuf::from("/"));
+            writable.push(PathBuf::from("/run"));
+        }
+    }
+    for m in &spec.mounts {
+        if m.ro {
+            read_only.push(PathBuf::from(&m.guest));
+        } else {
+            writable.push(PathBuf::from(&m.guest));
+        }
+    }
+    read_only.retain(|p| p.exists());
+    writable.retain(|p| p.exists());
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))
+        .and_then(|r| r.create())
+        .and_then(|r| r.add_rules(path_beneath_rules(&read_only, AccessFs::from_read(abi))))
+        .and_then(|r| r.add_rules(path_beneath_rules(&writable, AccessFs::from_all(abi))))
+        .and_then(|r| r.restrict_self())
+        .map_err(|e| format!("landlock: {e}"))?;
+    if status.ruleset == RulesetStatus::NotEnforced {
+        eprintln!("sandbox: landlock unsupported by this kernel; relying on mount isolation");
+    }
+
+    let rules: BTreeMap<i64, Vec<SeccompRule>> =
+        DENIED_SYSCALLS.iter().map(|nr| (*nr, Vec::new())).collect();
+    let arch = std::env::consts::ARCH
+        .try_into()
+        .map_err(|e| format!("seccomp: {e:?}"))?;
+    let filter = SeccompFilter::new(
+        rules,
+        SeccompAction::Allow,
+        SeccompAction::Errno(libc::EPERM as u32),
+        arch,
+    )
+    .map_err(|e| format!("seccomp: {e}"))?;
+    let program: BpfProgram = filter.try_into().map_err(|e| format!("seccomp: {e}"))?;
+    seccompiler::apply_filter(&program).map_err(|e| format!("seccomp: {e}"))
+}
+
+/// `path` (absolute, as the job sees it) under the new root.
+fn guest_path(root: &Path, path: &Path) -> PathBuf {
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+fn mkdir(path: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(path).map_err(|e| format!("mkdir {}: {e}", path.display()))
+}
+
+/// Bind `host` at `target`, creating the mount point; read-only when `ro`.
+fn bind(host: &Path, target: &Path, ro: bool) -> Result<(), String> {
+    let meta = std::fs::metadata(host).map_err(|e| format!("{}: {e}", host.display()))?;
+    if meta.is_dir() {
+        mkdir(target)?;
+    } else {
+        if let Some(parent) = target.parent() {
+            mkdir(parent)?;
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(target)
+            .map_err(|e| format!("create {}: {e}", target.display()))?;
+    }
+    mount(
+        Some(&host.to_string_lossy()),
+        target,
+        None,
+        libc::MS_BIND | libc::MS_REC,
+        None,
+    )?;
+    if ro {
+        remount_read_only(target)?;
+    }
+    Ok(())
+}
+
+/// A bind mount inside a user namespace may only be remounted with the
+/// source's locked flags (nosuid, nodev, ...) kept.
+fn remount_read_only(target: &Path) -> Result<(), String> {
+    let c_target = c_path(target)?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    check(
+        unsafe { libc::statvfs(c_target.as_ptr(), &mut st) },
+        "statvfs",
+    )?;
+    let mut flags = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY;
+    for (st_flag, ms_flag) in [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ] {
+        if st.f_flag & st_flag != 0 {
+            flags |= ms_flag;
+        }
+    }
+    mount(None, target, None, flags, None)
+}
+
+fn mount(
+    source: Option<&str>,
+    target: &Path,
+    fstype: Option<&str>,
+    flags: libc::c_ulong,
+    data: Option<&str>,
+) -> Result<(), String> {
+    let cstr = |s: Option<&str>| s.map(CString::new).transpose();
+    let source_c = cstr(source).map_err(|e| e.to_string())?;
+    let fstype_c = cstr(fstype).map_err(|e| e.to_string())?;
+    let data_c = cstr(data).map_err(|e| e.to_string())?;
+    let target_c = c_path(target)?;
+    let ptr = |c: &Option<CString>| c.as_ref().map_or(std::ptr::null(), |c| c.as_ptr());
+    let rc = unsafe {
+        libc::mount(
+            ptr(&source_c),
+            target_c.as_ptr(),
+            ptr(&fstype_c),
+            flags,
+            ptr(&data_c).cast(),
+        )
+    };
+    check(rc, &format!("mount {}", target.display()))
+}
+
+fn c_path(path: &Path) -> Result<CString, String> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())
+}
+
+fn check(rc: i32, what: &str) -> Result<(), String> {
+    if rc == -1 {
+        Err(format!("{what}: {}", std::io::Error::last_os_error()))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! Linux sandbox for native jobs. The agent re-executes itself with
+//! [`HELPER_ARG`]; that helper enters new user, mount, pid, ipc, uts and
+//! (unless the profile allows networking) network namespaces, builds a root
+//! from read-only bind mounts plus the job's writable directories, applies a
+//! landlock allowlist and a seccomp filter, and execs the job. CPU, memory and
+//! pids limits come from a cgroup v2 group the agent creates per run. OCI
+//! containers use the same helper with an image as the root instead.
+
+#![cfg_attr(not(target_os = "linux"), allow(dead_code))]
+
+#[cfg(target_os = "linux")]
+mod cgroup;
+#[cfg(target_os = "linux")]
+pub mod linux;
+
+use std::path::{Path, PathBuf};
+
+use common::{JobResources, MountSpec, NativeSandbox};
+use serde::{Deserialize, Serialize};
+
+/// First argument that turns the agent binary into the sandbox helper.
+pub const HELPER_ARG: &str = "__sandbox-exec";
+/// Exit code of the helper when the sandbox could not be set up.
+pub const SETUP_FAILED_EXIT: i32 = 125;
+
+/// Directories every sandbox sees read-only, when present on the host.
+const SYSTEM_RO_PATHS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc"];
+
+/// What the helper sets up before exec; passed to it as JSON.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HelperSpec {
+    /// Empty directory the new root is mounted on
+    pub root: PathBuf,
+    /// Host paths bound read-only at the same path
+    pub ro_paths: Vec<PathBuf>,
+    /// Host directories bound at their guest path
+    pub mounts: Vec<MountSpec>,
+    /// Working directory inside the sandbox; always writable
+    pub workdir: PathBuf,
+    pub network: bool,
+    /// cgroup the helper moves itself into before unsharing
+    pub cgroup: Option<PathBuf>,
+    /// Unpacked image the root is built from, instead of `ro_paths`
+    #[serde(default)]
+    pub rootfs: Option<PathBuf>,
+    /// Map the caller's uid and gid to root inside the user namespace
+    #[serde(default)]
+    pub map_root: bool,
+}
+
+/// One sandboxed run. Dropping it kills whatever is left in its cgroup and
+/// removes its scratch directory.
+pub struct Sandbox {
+    dir: PathBuf,
+    spec: HelperSpec,
+    #[cfg(target_os = "linux")]
+    cgroup: Option<cgroup::JobCgroup>,
+    limits: Option<String>,
+}
+
+impl Sandbox {
+    /// Prepare a sandbox for running `binary`. The job's workspace and its
+    /// `working_dir` (which defaults to the workspace) are bound read-write
+    /// at their own paths.
+    #[cfg(target_os = "linux")]
+    pub fn prepare(
+        job_id: &str,
+        profile: &NativeSandbox,
+        binary: &Path,
+        workspace: &Path,
+        working_dir: Option<&Path>,
+        resources: Option<&JobResources>,
+    ) -> Result<Self, String> {
+        let workdir = working_dir.unwrap_or(workspace).to_path_buf();
+        std::fs::create_dir_all(&workdir).map_err(|e| format!("sandbox working dir: {e}"))?;
+
+        let mut ro_paths: Vec<PathBuf> = SYSTEM_RO_PATHS.iter().map(PathBuf::from).collect();
+        ro_paths.extend(profile.ro_paths.iter().map(PathBuf::from));
+        ro_paths.push(binary.to_path_buf());
+        ro_paths.retain(|p| p.is_absolute() && p.symlink_metadata().is_ok());
+        // Paths inside another read-only path are visible already
+        let all = ro_paths.clone();
+        ro_paths.retain(|p| !all.iter().any(|other| other != p && p.starts_with(other)));
+        ro_paths.dedup();
+        let mut mounts = profile.mounts.clone();
+        check_mounts(&mounts)?;
+        for rw in [workspace, workdir.as_path()] {
+            let path = rw.display().to_string();
+            if !mounts.iter().any(|m| m.guest == path) {
+                mounts.push(MountSpec {
+                    host: path.clone(),
+                    guest: path,
+                    ro: false,
+                });
+            }
+        }
+
+        let dir = scratch_dir(job_id)?;
+        let spec = HelperSpec {
+            root: dir.join("root"),
+            ro_paths,
+            mounts,
+            workdir,
+            network: profile.network,
+            cgroup: None,
+            rootfs: None,
+            map_root: false,
+        };
+        Self::finish(job_id, dir, spec, resources)
+    }
+
+    /// Prepare a sandbox whose root is a writable overlay on the unpacked
+    /// image at `rootfs`. The job runs as root of its user namespace and
+    /// starts in `workdir`, a path inside the image.
+    #[cfg(target_os = "linux")]
+    pub fn prepare_container(
+        job_id: &str,
+        rootfs: &Path,
+        mounts: Vec<MountSpec>,
+        workdir: PathBuf,
+        network: bool,
+        resources: Option<&JobResources>,
+    ) -> Result<Self, String> {
+        check_mounts(&mounts)?;
+        let dir = scratch_dir(job_id)?;
+        let spec = HelperSpec {
+            root: dir.join("root"),
+            ro_paths: Vec::new(),
+            mounts,
+            workdir,
+            network,
+            cgroup: None,
+            rootfs: Some(rootfs.to_path_buf()),
+            map_root: true,
+        };
+        Self::finish(job_id, dir, spec, resources)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn finish(
+        job_id: &str,
+        dir: PathBuf,
+        mut spec: HelperSpec,
+        resources: Option<&JobResources>,
+    ) -> Result<Self, String> {
+        let limits = resources.filter(|r| r.has_cgroup_limits());
+        let cgroup = match cgroup::JobCgroup::create(job_id, limits) {
+            Ok(cg) => Some(cg),
+            Err(e) if limits.is_some() => {
+                let _ = std::fs::remove_dir_all(&dir);
+                return Err(format!("cannot apply resource limits: {e}"));
+            }
+            // Without limits the group is only used to kill the process tree
+            Err(_) => None,
+        };
+        spec.cgroup = cgroup.as_ref().map(|c| c.path().to_path_buf());
+        Ok(Self {
+            spec,
+            dir,
+            cgroup,
+            limits: limits.map(describe_limits),
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn prepare(
+        _job_id: &str,
+        _profile: &NativeSandbox,
+        _binary: &Path,
+        _workspace: &Path,
+        _working_dir: Option<&Path>,
+        _resources: Option<&JobResources>,
+    ) -> Result<Self, String> {
+        Err(format!(
+            "the native job sandbox needs Linux (this node runs {})",
+            std::env::consts::OS
+        ))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn prepare_container(
+        _job_id: &str,
+        _rootfs: &Path,
+        _mounts: Vec<MountSpec>,
+        _workdir: PathBuf,
+        _network: bool,
+        _resources: Option<&JobResources>,
+    ) -> Result<Self, String> {
+        Err(format!(
+            "containers need Linux (this node runs {})",
+            std::env::consts::OS
+        ))
+    }
+
+    /// Command running `binary` in the sandbox. The environment starts empty
+    /// apart from `PATH`, `HOME` (the workspace) and `TMPDIR`.
+    pub fn command(
+        &self,
+        binary: &str,
+        args: &[String],
+    ) -> Result<tokio::process::Command, String> {
+        let exe = std::env::current_exe().map_err(|e| format!("locate agent binary: {e}"))?;
+        let spec = serde_json::to_string(&self.spec).map_err(|e| e.to_string())?;
+        let mut cmd = tokio::process::Command::new(exe);
+        cmd.arg(HELPER_ARG)
+            .arg(spec)
+            .arg(binary)
+            .args(args)
+            .env_clear()
+            .env("PATH", "/usr/local/bin:/usr/bin:/bin:/usr/sbin:/sbin")
+            .env("HOME", &self.spec.workdir)
+            .env("TMPDIR", "/tmp")
+            .kill_on_drop(true);
+        Ok(cmd)
+    }
+
+    /// One line for the job log.
+    pub fn describe(&self) -> String {
+        let network = if self.spec.network {
+            "host network"
+        } else {
+            "no network"
+        };
+        let limits = match (&self.limits, self.spec.cgroup.is_some()) {
+            (Some(limits), _) => limits.clone(),
+            (None, true) => "no resource limits".to_string(),
+            (None, false) => "no cgroup".to_string(),
+        };
+        format!(
+            "workdir {}, {network}, {limits}",
+            self.spec.workdir.display()
+        )
+    }
+
+    /// Kill every process left in the sandbox.
+    pub fn kill(&self) {
+        #[cfg(target_os = "linux")]
+        if let Some(cg) = &self.cgroup {
+            cg.kill();
+        }
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        self.kill();
+        #[cfg(target_os = "linux")]
+        drop(self.cgroup.take());
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Per-run directory holding the (empty) root mount point and overlay layers.
+#[cfg(target_os = "linux")]
+fn scratch_dir(job_id: &str) -> Result<PathBuf, String> {
+    let dir = crate::p2p::state::agent_data_dir()
+        .join("sandbox")
+        .join(format!(
+            "{}-{}",
+            sanitize(job_id),
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        ));
+    std::fs::create_dir_all(dir.join("root")).map_err(|e| format!("sandbox dir: {e}"))?;
+    Ok(dir)
+}
+
+fn check_mounts(mounts: &[MountSpec]) -> Result<(), String> {
+    for m in mounts {
+        if !Path::new(&m.host).is_absolute() || !Path::new(&m.guest).is_absolute() {
+            return Err(format!(
+                "sandbox mount {} -> {} needs absolute paths",
+                m.host, m.guest
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn describe_limits(r: &JobResources) -> String {
+    let mut parts = Vec::new();
+    if let Some(cpus) = r.cpus {
+        parts.push(format!("cpus={cpus}"));
+    }
+    if let Some(mb) = r.memory_mb {
+        parts.push(format!("memory={mb}MB"));
+    }
+    if let Some(pids) = r.pids {
+        parts.push(format!("pids={pids}"));
+    }
+    parts.join(" ")
+}
+
+/// Job ids become directory names.
+fn sanitize(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,239 @@
+//! Cron triggers for components whose spec sets `schedule`. On every tick the
+//! supervisor invokes the component once: components exporting
+//! `realm:host/event-handler` receive a `realm.schedule` event, command
+//! components have their `run` export called. A tick that comes due while the
+//! previous run is still going is skipped and recorded as skipped.
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+
+/// Topic of the event handed to event handlers on a scheduled tick.
+pub const SCHEDULE_TOPIC: &str = "realm.schedule";
+/// Runs remembered per component for `/api/components`.
+const HISTORY_LEN: usize = 20;
+
+/// Parse a cron expression. Five-field expressions get a leading seconds
+/// field of `0`; six- and seven-field ones (with seconds, optional year) are
+/// used as written.
+pub fn parse_schedule(expr: &str) -> Result<Schedule, String> {
+    let expr = expr.trim();
+    let full = if expr.split_whitespace().count() == 5 {
+        format!("0 {expr}")
+    } else {
+        expr.to_string()
+    };
+    Schedule::from_str(&full).map_err(|e| format!("invalid schedule '{expr}': {e}"))
+}
+
+/// Validate a `schedule` from the CLI or API: trimmed, empty means none.
+pub fn normalize_schedule(expr: Option<&str>) -> Result<Option<String>, String> {
+    match expr.map(str::trim).filter(|e| !e.is_empty()) {
+        Some(expr) => parse_schedule(expr).map(|_| Some(expr.to_string())),
+        None => Ok(None),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Ok,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledRun {
+    /// Cron tick the run belongs to (unix ms)
+    pub scheduled_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_ms: Option<u64>,
+    pub status: RunStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Scheduler state of one component.
+#[derive(Default)]
+pub struct ScheduleState {
+    expr: String,
+    next: Option<DateTime<Utc>>,
+    running: bool,
+    runs: VecDeque<ScheduledRun>,
+}
+
+impl ScheduleState {
+    /// The tick due at `now`, if any; advances to the following tick. Ticks
+    /// from before the component was first seen, or before its expression
+    /// changed, are not run.
+    pub fn take_due(
+        &mut self,
+        expr: &str,
+        schedule: &Schedule,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        if self.expr != expr {
+            self.expr = expr.to_string();
+            self.next = schedule.after(&now).next();
+            return None;
+        }
+        let due = self.next.filter(|t| *t <= now)?;
+        self.next = schedule.after(&now).next();
+        Some(due)
+    }
+
+    /// Record the start of the run for `tick`. Returns false, recording a
+    /// skipped run instead, while the previous run is still in progress.
+    pub fn begin(&mut self, tick: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        let scheduled_ms = tick.timestamp_millis() as u64;
+        if self.running {
+            self.push(ScheduledRun {
+                scheduled_ms,
+                started_ms: None,
+                finished_ms: None,
+                status: RunStatus::Skipped,
+                error: Some("previous run still in progress".to_string()),
+            });
+            return false;
+        }
+        self.running = true;
+        self.push(ScheduledRun {
+            scheduled_ms,
+            started_ms: Some(now.timestamp_millis() as u64),
+            finished_ms: None,
+            status: RunStatus::Running,
+            error: None,
+        });
+        true
+    }
+
+    pub fn finish(&mut self, tick: DateTime<Utc>, result: Result<(), String>) {
+        self.running = false;
+        let scheduled_ms = tick.timestamp_millis() as u64;
+        if let Some(run) = self
+            .runs
+            .iter_mut()
+            .rev()
+            .find(|r| r.scheduled_ms == scheduled_ms && r.status == RunStatus::Running)
+        {
+            run.finished_ms = Some(Utc::now().timestamp_millis() as u64);
+            match result {
+                Ok(()) => run.status = RunStatus::Ok,
+                Err(e) => {
+                    run.status = RunStatus::Failed;
+                    run.error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Next tick (unix ms), once the scheduler has seen the component.
+    pub fn next_ms(&self) -> Option<u64> {
+        self.next.map(|t| t.timestamp_millis() as u64)
+    }
+
+    /// Recorded runs, newest first.
+    pub fn runs(&self) -> Vec<ScheduledRun> {
+        self.runs.iter().rev().cloned().collect()
+    }
+
+    fn push(&mut self, run: ScheduledRun) {
+        if self.runs.len() == HISTORY_LEN {
+            self.runs.pop_front();
+        }
+        self.runs.push_back(run);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, h, m, s).unwrap()
+    }
+
+    #[test]
+    fn five_field_expressions_fire_on_the_minute() {
+        let every_15 = parse_schedule(" */15 * * * * ").unwrap();
+        assert_eq!(every_15.after(&at(0, 0, 10)).next(), Some(at(0, 15, 0)));
+        let with_seconds = parse_schedule("30 * * * * *").unwrap();
+        assert_eq!(with_seconds.after(&at(0, 0, 10)).next(), Some(at(0, 0, 30)));
+        assert!(parse_schedule("* * *").is_err());
+        assert_eq!(normalize_schedule(Some("  ")).unwrap(), None);
+        assert_eq!(
+            normalize_schedule(Some(" 0 3 * * * ")).unwrap().as_deref(),
+            Some("0 3 * * *")
+        );
+    }
+
+    #[test]
+    fn changed_expression_does_not_fire_stale_tick() {
+        let hourly = parse_schedule("0 * * * *").unwrap();
+        let every_5 = parse_schedule("*/5 * * * *").unwrap();
+        let mut state = ScheduleState::default();
+
+        assert_eq!(state.take_due("0 * * * *", &hourly, at(0, 30, 0)), None);
+        assert_eq!(state.next_ms(), Some(at(1, 0, 0).timestamp_millis() as u64));
+
+        // The hourly tick has passed, but the expression changed before it ran
+        assert_eq!(state.take_due("*/5 * * * *", &every_5, at(1, 2, 0)), None);
+        assert_eq!(state.next_ms(), Some(at(1, 5, 0).timestamp_millis() as u64));
+        assert_eq!(state.take_due("*/5 * * * *", &every_5, at(1, 4, 0)), None);
+        assert_eq!(
+            state.take_due("*/5 * * * *", &every_5, at(1, 5, 1)),
+            Some(at(1, 5, 0))
+        );
+        assert_eq!(
+            state.next_ms(),
+            Some(at(1, 10, 0).timestamp_millis() as u64)
+        );
+    }
+
+    #[test]
+    fn overlapping_tick_is_recorded_as_skipped() {
+        let mut state = ScheduleState::default();
+        assert!(state.begin(at(0, 0, 0), at(0, 0, 1)));
+        assert!(!state.begin(at(0, 5, 0), at(0, 5, 0)));
+        state.finish(at(0, 0, 0), Err("boom".to_string()));
+        assert!(state.begin(at(0, 10, 0), at(0, 10, 0)));
+        state.finish(at(0, 10, 0), Ok(()));
+
+        let runs = state.runs();
+        let statuses: Vec<RunStatus> = runs.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            [RunStatus::Ok, RunStatus::Skipped, RunStatus::Failed]
+        );
+        assert_eq!(runs[1].started_ms, None);
+        assert_eq!(runs[2].error.as_deref(), Some("boom"));
+        assert!(runs[0].finished_ms.is_some());
+    }
+
+    #[test]
+    fn history_keeps_the_latest_runs() {
+        let mut state = ScheduleState::default();
+        for minute in 0..(HISTORY_LEN as u32 + 5) {
+            let tick = at(0, minute, 0);
+            assert!(state.begin(tick, tick));
+            state.finish(tick, Ok(()));
+        }
+        let runs = state.runs();
+        assert_eq!(runs.len(), HISTORY_LEN);
+        assert_eq!(
+            runs[0].scheduled_ms,
+            at(0, HISTORY_LEN as u32 + 4, 0).timestamp_millis() as u64
+        );
+        assert_eq!(
+            runs[HISTORY_LEN - 1].scheduled_ms,
+            at(0, 5, 0).timestamp_millis() as u64
+        );
+    }
+}
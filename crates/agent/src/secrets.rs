@@ -0,0 +1,56 @@
+//! Resolution of secret references used in specs (TLS keys, credentials).
+//!
+//! Supported forms:
+//!   `file:/abs/path`  read from the local filesystem
+//!   `cas:<sha256>`    read from the local content store
+//!
+//! Stdin blobs travel with pushed specs, so they only come from the content
+//! store; a `file:` stdin would let a spec read any file the agent can.
+
+use crate::storage::ContentStore;
+
+/// Read the bytes behind a secret reference.
+pub fn resolve_secret_ref(reference: &str) -> Result<Vec<u8>, String> {
+    let reference = reference.trim();
+    if let Some(path) = reference.strip_prefix("file:") {
+        return std::fs::read(path).map_err(|e| format!("read {}: {}", path, e));
+    }
+    if reference.starts_with("cas:") {
+        return read_cas(stdin_digest(reference)?);
+    }
+    Err(format!(
+        "unsupported secret reference '{}' (expected file: or cas:)",
+        reference
+    ))
+}
+
+/// Digest of a stdin reference, which must be `cas:<sha256>`.
+pub fn stdin_digest(reference: &str) -> Result<&str, String> {
+    let reference = reference.trim();
+    let digest = reference
+        .strip_prefix("cas:")
+        .ok_or_else(|| {
+            format!(
+                "unsupported stdin reference '{}' (expected cas:)",
+                reference
+            )
+        })?
+        .trim();
+    if digest.len() < 4 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid cas digest '{}'", digest));
+    }
+    Ok(digest)
+}
+
+/// Read the bytes behind a stdin reference.
+pub fn resolve_stdin_ref(reference: &str) -> Result<Vec<u8>, String> {
+    read_cas(stdin_digest(reference)?)
+}
+
+fn read_cas(digest: &str) -> Result<Vec<u8>, String> {
+    let store = ContentStore::open();
+    let path = store
+        .get_path(digest)
+        .ok_or_else(|| format!("cas:{} not present in local store", digest))?;
+    std::fs::read(&path).map_err(|e| format!("read {}: {}", path.display(), e))
+}
@@ -91,6 +91,32 @@ impl ContentStore {
         Ok(digest)
     }
 
+    /// A fresh path next to the blobs to write a large blob into before
+    /// [`put_file`](Self::put_file) moves it into place.
+    pub fn staging_path(&self) -> PathBuf {
+        self.base_dir
+            .join(format!(".tmp-{}", uuid::Uuid::new_v4().simple()))
+    }
+
+    /// Move a staged file whose sha256 is `digest` into the store.
+    pub fn put_file(&self, staged: &Path, digest: &str) -> Result<(), String> {
+        let size = std::fs::metadata(staged).map_err(|e| e.to_string())?.len();
+        let path = self.path_for_digest(digest);
+        if path.exists() {
+            let _ = std::fs::remove_file(staged);
+        } else {
+            if let Some(parent) = path.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            std::fs::rename(staged, &path).map_err(|e| e.to_string())?;
+        }
+        let mut idx = self.load_index();
+        let entry = idx.entries.entry(digest.to_string()).or_default();
+        entry.size_bytes = size;
+        entry.last_accessed_unix = Self::now_unix();
+        self.save_index(&idx)
+    }
+
     pub fn get_path(&self, digest: &str) -> Option<PathBuf> {
         let p = self.path_for_digest(digest);
         if p.exists() {
@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
@@ -8,10 +8,13 @@ use tokio::task::JoinHandle;
 
 use tracing::{info, warn};
 
+use crate::host::HostState;
+use crate::http_cache::HttpComponentCache;
 use crate::p2p::metrics::{push_log, Metrics, SharedLogs};
 use crate::p2p::state::{agent_data_dir, load_desired_manifest};
 use crate::runner::run_wasm_module_with_limits;
-use common::{sha256_hex, ComponentSpec, Manifest};
+use crate::schedule::{parse_schedule, ScheduleState, ScheduledRun, SCHEDULE_TOPIC};
+use common::{sha256_hex, ComponentSpec, Manifest, ServiceAdvert};
 
 #[derive(Clone, Debug)]
 pub struct DesiredComponent {
@@ -27,19 +30,51 @@ pub struct Supervisor {
     desired: tokio::sync::Mutex<BTreeMap<String, DesiredComponent>>,
     counts: tokio::sync::Mutex<HashMap<String, Arc<AtomicUsize>>>,
     tasks: tokio::sync::Mutex<HashMap<String, Vec<JoinHandle<()>>>>,
+    http_cache: Option<Arc<HttpComponentCache>>,
+    /// Components staged for on-demand HTTP invocation
+    http_ready: tokio::sync::Mutex<HashSet<String>>,
+    /// Cron state and run history of components with a `schedule`
+    schedules: tokio::sync::Mutex<HashMap<String, ScheduleState>>,
+    changes: tokio::sync::watch::Sender<u64>,
 }
 
 impl Supervisor {
     pub fn new(logs: SharedLogs, metrics: Arc<Metrics>) -> Self {
+        let http_cache = match HttpComponentCache::new(logs.clone(), metrics.clone()) {
+            Ok(cache) => Some(Arc::new(cache)),
+            Err(e) => {
+                warn!(error=%e, "HTTP component cache unavailable");
+                None
+            }
+        };
         Self {
             logs,
             metrics,
             desired: tokio::sync::Mutex::new(BTreeMap::new()),
             counts: tokio::sync::Mutex::new(HashMap::new()),
             tasks: tokio::sync::Mutex::new(HashMap::new()),
+            http_cache,
+            http_ready: tokio::sync::Mutex::new(HashSet::new()),
+            schedules: tokio::sync::Mutex::new(HashMap::new()),
+            changes: tokio::sync::watch::channel(0).0,
         }
     }
 
+    /// Watch for changes to the desired set; the value is a generation counter.
+    pub fn subscribe_changes(&self) -> tokio::sync::watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    /// Shared cache of compiled HTTP components used by the gateway.
+    pub fn http_cache(&self) -> Option<Arc<HttpComponentCache>> {
+        self.http_cache.clone()
+    }
+
+    /// Per-component log buffers shown in the web UI.
+    pub fn logs(&self) -> SharedLogs {
+        self.logs.clone()
+    }
+
     /// Restore desired state from disk on startup
     pub async fn restore_from_disk(
         &self,
@@ -121,6 +156,10 @@ impl Supervisor {
     pub async fn set_desired(&self, desired: BTreeMap<String, DesiredComponent>) {
         let mut d = self.desired.lock().await;
         *d = desired;
+        if let Some(cache) = &self.http_cache {
+            let keep: Vec<String> = d.values().map(|c| c.spec.sha256_hex.clone()).collect();
+            cache.retain_digests(&keep).await;
+        }
         self.metrics.set_components_desired(d.len() as u64);
         // ensure counters exist
         let mut counts = self.counts.lock().await;
@@ -129,19 +168,30 @@ impl Supervisor {
                 .entry(name.clone())
                 .or_insert_with(|| Arc::new(AtomicUsize::new(0)));
         }
+        self.changes.send_modify(|generation| *generation += 1);
     }
 
     /// Upsert a single desired component specification and trigger reconciliation on next tick.
     pub async fn upsert_component(&self, desired: DesiredComponent) {
         let mut d = self.desired.lock().await;
         let name = desired.name.clone();
-        d.insert(name.clone(), desired);
+        if let Some(prev) = d.insert(name.clone(), desired) {
+            if let Some(cache) = &self.http_cache {
+                let still_used = d
+                    .values()
+                    .any(|c| c.spec.sha256_hex == prev.spec.sha256_hex);
+                if !still_used {
+                    cache.invalidate(&prev.spec.sha256_hex).await;
+                }
+            }
+        }
         self.metrics.set_components_desired(d.len() as u64);
         // ensure counter exists
         let mut counts = self.counts.lock().await;
         counts
             .entry(name)
             .or_insert_with(|| Arc::new(AtomicUsize::new(0)));
+        self.changes.send_modify(|generation| *generation += 1);
     }
 
     pub fn spawn_reconcile(self: Arc<Self>) {
@@ -154,11 +204,182 @@ impl Supervisor {
         });
     }
 
+    /// Invoke scheduled components when their cron ticks come due.
+    pub fn spawn_scheduler(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut intv = tokio::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                intv.tick().await;
+                self.run_due_schedules().await;
+            }
+        });
+    }
+
+    /// Next tick (unix ms) and recent runs of a scheduled component, newest first.
+    pub async fn schedule_status(&self, name: &str) -> Option<(Option<u64>, Vec<ScheduledRun>)> {
+        let schedules = self.schedules.lock().await;
+        schedules.get(name).map(|s| (s.next_ms(), s.runs()))
+    }
+
+    async fn run_due_schedules(self: &Arc<Self>) {
+        let desired = self.desired.lock().await.clone();
+        let now = chrono::Utc::now();
+        let mut schedules = self.schedules.lock().await;
+        // Stopped components keep no schedule; ticks missed while stopped are not run
+        schedules.retain(|name, _| {
+            desired
+                .get(name)
+                .is_some_and(|d| d.spec.start && d.spec.schedule.is_some())
+        });
+        for (name, desired) in desired {
+            if !desired.spec.start {
+                continue;
+            }
+            let Some(expr) = desired.spec.schedule.clone() else {
+                continue;
+            };
+            // Invalid expressions are reported when the component is staged
+            let Ok(schedule) = parse_schedule(&expr) else {
+                continue;
+            };
+            let state = schedules.entry(name.clone()).or_default();
+            let Some(tick) = state.take_due(&expr, &schedule, now) else {
+                continue;
+            };
+            if !state.begin(tick, now) {
+                push_log(
+                    &self.logs,
+                    &name,
+                    format!("scheduled run for {tick} skipped: previous run still in progress"),
+                )
+                .await;
+                continue;
+            }
+            let supervisor = self.clone();
+            tokio::spawn(async move {
+                let result = supervisor.run_scheduled(&desired, tick).await;
+                let line = match &result {
+                    Ok(()) => format!("scheduled run for {tick} finished"),
+                    Err(e) => format!("scheduled run for {tick} failed: {e}"),
+                };
+                push_log(&supervisor.logs, &desired.name, line).await;
+                if let Some(state) = supervisor.schedules.lock().await.get_mut(&desired.name) {
+                    state.finish(tick, result);
+                }
+            });
+        }
+    }
+
+    /// Invoke a scheduled component once for `tick`, with its spec's limits and
+    /// mounts, for at most its request timeout.
+    async fn run_scheduled(
+        &self,
+        desired: &DesiredComponent,
+        tick: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), String> {
+        let wasm_bytes = tokio::fs::read(&desired.path)
+            .await
+            .map_err(|e| format!("read {}: {e}", desired.path.display()))?;
+        let wasm_string = String::from_utf8_lossy(&wasm_bytes);
+        if wasm_string.contains("realm:host/event-handler") {
+            let cache = self
+                .http_cache
+                .clone()
+                .ok_or_else(|| "component cache unavailable".to_string())?;
+            let event = crate::events::BusEvent {
+                id: format!("{}@{}", desired.name, tick.timestamp_millis()),
+                topic: SCHEDULE_TOPIC.to_string(),
+                source: desired.name.clone(),
+                origin_node: crate::host::node_id(),
+                payload: tick.to_rfc3339().into_bytes(),
+            };
+            return match crate::runner::invoke_event_handler(&cache, desired, &event).await {
+                Ok(true) => Ok(()),
+                Ok(false) => Err("realm:host/event-handler export not found".to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+        }
+        if wasm_string.contains("wasi:http/incoming-handler") {
+            return Err(
+                "HTTP components need a realm:host/event-handler export to run on a schedule"
+                    .to_string(),
+            );
+        }
+        // Runs share the component-level work dir, like on-demand components
+        if let Some(mounts) = &desired.spec.mounts {
+            let base_work_dir = agent_data_dir()
+                .join("work")
+                .join("components")
+                .join(&desired.name);
+            for m in mounts {
+                if std::path::Path::new(&m.host).starts_with(&base_work_dir) {
+                    let _ = std::fs::create_dir_all(&m.host);
+                }
+            }
+        }
+        let timeout = crate::runner::request_timeout(desired);
+        let path = desired.path.to_string_lossy();
+        let run = run_wasm_module_with_limits(
+            &path,
+            &desired.name,
+            self.logs.clone(),
+            desired.spec.memory_max_mb.unwrap_or(64),
+            desired.spec.fuel.unwrap_or(0),
+            desired.spec.epoch_ms.unwrap_or(100),
+            Some(self.metrics.clone()),
+            desired.spec.mounts.clone(),
+            None,
+            desired.spec.egress.clone(),
+            crate::runner::GuestEnv::from_spec(&desired.spec),
+            HostState::new(&desired.name, &desired.spec.capabilities, self.logs.clone())
+                .with_kv(desired.spec.kv.as_ref()),
+        );
+        match tokio::time::timeout(timeout, run).await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => Err(format!("timed out after {timeout:?}")),
+        }
+    }
+
     /// Return a snapshot of desired components (cheap clone for gateway).
     pub async fn get_desired_snapshot(&self) -> BTreeMap<String, DesiredComponent> {
         self.desired.lock().await.clone()
     }
 
+    /// Components this node serves to the mesh: staged HTTP components and running
+    /// services with declared ports. Advertised to peers in status heartbeats.
+    pub async fn service_adverts(&self) -> Vec<ServiceAdvert> {
+        let ready = self.http_ready.lock().await.clone();
+        let running: HashSet<String> = self
+            .counts
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, c)| c.load(Ordering::Relaxed) > 0)
+            .map(|(name, _)| name.clone())
+            .collect();
+        self.desired
+            .lock()
+            .await
+            .values()
+            .filter_map(|c| {
+                let http = ready.contains(&c.name);
+                let ports = if running.contains(&c.name) {
+                    c.spec.ports.clone().unwrap_or_default()
+                } else {
+                    Vec::new()
+                };
+                (http || !ports.is_empty()).then(|| ServiceAdvert {
+                    name: c.name.clone(),
+                    http,
+                    routes: c.spec.routes.clone(),
+                    visibility: c.spec.visibility.clone(),
+                    ports,
+                    http_limits: c.spec.http_limits.clone(),
+                })
+            })
+            .collect()
+    }
+
     /// Get one desired component by name, if present.
     pub async fn get_component(&self, name: &str) -> Option<DesiredComponent> {
         self.desired.lock().await.get(name).cloned()
@@ -196,6 +417,12 @@ impl Supervisor {
             }
             info!(component=%component_name, "Component tasks cleaned up");
         }
+        self.http_ready.lock().await.remove(component_name);
+        if let Some(cache) = &self.http_cache {
+            if let Some(c) = self.desired.lock().await.get(component_name) {
+                cache.invalidate(&c.spec.sha256_hex).await;
+            }
+        }
         // Best-effort cleanup of component-level work directory (ephemeral). Any
         // per-replica subdirectories created for running replicas will be removed
         // on their normal exit path; if we forcibly stopped tasks, clear the tree.
@@ -228,26 +455,73 @@ impl Supervisor {
         let mem = desired.spec.memory_max_mb.unwrap_or(64);
         let fuel = desired.spec.fuel.unwrap_or(0);
         let epoch = desired.spec.epoch_ms.unwrap_or(100);
+        if let Some(Err(e)) = desired.spec.schedule.as_deref().map(parse_schedule) {
+            push_log(&logs, &name, format!("schedule ignored: {e}")).await;
+        }
 
-        // Check if this is an HTTP component by inspecting the binary for HTTP handler exports
+        // Check if this is an HTTP or event-triggered component by inspecting the binary for handler exports
         if let Ok(wasm_bytes) = std::fs::read(&path) {
             // Simple string search in the binary for HTTP handler export signature
             let wasm_string = String::from_utf8_lossy(&wasm_bytes);
-            if wasm_string.contains("wasi:http/incoming-handler") {
-                info!(component=%name, "HTTP component detected - will be invoked on-demand via gateway");
-                // For HTTP components, just mark as "running" but don't actually start a persistent process
+            let exports_http = wasm_string.contains("wasi:http/incoming-handler");
+            let exports_events = wasm_string.contains("realm:host/event-handler");
+            if exports_http || exports_events {
+                if exports_http {
+                    info!(component=%name, "HTTP component detected - will be invoked on-demand via gateway");
+                }
+                if exports_events {
+                    info!(component=%name, "Event handler detected - will be invoked on matching events");
+                }
+                // HTTP components share the component-level work dir across requests
+                if let Some(mounts) = &desired.spec.mounts {
+                    let base_work_dir =
+                        agent_data_dir().join("work").join("components").join(&name);
+                    for m in mounts {
+                        if std::path::Path::new(&m.host).starts_with(&base_work_dir) {
+                            let _ = std::fs::create_dir_all(&m.host);
+                        }
+                    }
+                }
+                // Warm the compiled-component cache so the first request skips compilation
+                if let Some(cache) = self.http_cache.clone() {
+                    let digest = desired.spec.sha256_hex.clone();
+                    let wasm_path = desired.path.clone();
+                    let name_warm = name.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = cache.get_or_load(&digest, &wasm_path).await {
+                            warn!(component=%name_warm, error=%e, "on-demand component precompile failed");
+                        }
+                    });
+                }
+                if exports_http {
+                    self.http_ready.lock().await.insert(name.clone());
+                }
+                // For on-demand components, just mark as "running" but don't actually start a persistent process
                 metrics.inc_components_running();
                 count.fetch_add(1, Ordering::Relaxed);
-                push_log(
-                    &logs,
-                    &name,
-                    format!("HTTP component staged from {path}, ready for gateway invocation"),
-                )
-                .await;
+                let staged = if exports_http {
+                    format!("HTTP component staged from {path}, ready for gateway invocation")
+                } else {
+                    format!("event handler staged from {path}, ready for matching events")
+                };
+                push_log(&logs, &name, staged).await;
                 return;
             }
         }
 
+        // Scheduled command components run once per cron tick instead of as replicas
+        if let Some(expr) = &desired.spec.schedule {
+            metrics.inc_components_running();
+            count.fetch_add(1, Ordering::Relaxed);
+            push_log(
+                &logs,
+                &name,
+                format!("scheduled component staged from {path}, runs on '{expr}'"),
+            )
+            .await;
+            return;
+        }
+
         // Resolve per-replica work mount directory. Package 'work' mounts are resolved to
         // agent_data_dir()/work/components/{name}. Here we allocate a unique subdirectory
         // per replica and rewrite any matching mount host paths to that subdir. The subdir
@@ -296,6 +570,11 @@ impl Supervisor {
         count.fetch_add(1, Ordering::Relaxed);
         let name_run = name.clone();
         let mounts_for_run = effective_mounts.clone();
+        let ports_for_run = desired.spec.ports.clone();
+        let egress_for_run = desired.spec.egress.clone();
+        let guest_for_run = crate::runner::GuestEnv::from_spec(&desired.spec);
+        let host_for_run = HostState::new(&name, &desired.spec.capabilities, logs.clone())
+            .with_kv(desired.spec.kv.as_ref());
         let cleanup_work_dir = replica_work_dir.clone();
         let task_handle = tokio::spawn(async move {
             let res = run_wasm_module_with_limits(
@@ -307,6 +586,10 @@ impl Supervisor {
                 epoch,
                 Some(metrics.clone()),
                 mounts_for_run,
+                ports_for_run,
+                egress_for_run,
+                guest_for_run,
+                host_for_run,
             )
             .await;
             if let Err(e) = &res {
@@ -235,6 +235,18 @@ pub async fn api_deploy_package_multipart(
         },
         ports: None,
         visibility: None,
+        request_timeout_ms: None,
+        routes: Vec::new(),
+        http_limits: None,
+        egress: None,
+        args: Vec::new(),
+        env: Default::default(),
+        secret_env: Default::default(),
+        stdin: None,
+        capabilities: Vec::new(),
+        kv: None,
+        subscriptions: Vec::new(),
+        schedule: None,
         target_peer_ids: Vec::new(),
         target_tags: Vec::new(),
         start: true,
@@ -431,6 +443,18 @@ pub async fn install_package_from_bytes(
         },
         ports: None,
         visibility: None,
+        request_timeout_ms: None,
+        routes: Vec::new(),
+        http_limits: None,
+        egress: None,
+        args: Vec::new(),
+        env: Default::default(),
+        secret_env: Default::default(),
+        stdin: None,
+        capabilities: Vec::new(),
+        kv: None,
+        subscriptions: Vec::new(),
+        schedule: None,
         target_peer_ids: Vec::new(),
         target_tags: Vec::new(),
         start: true,
@@ -142,5 +142,7 @@ fn parse_peer_info(peer_info: &PeerInfo) -> Result<common::Status, String> {
         drift: components_desired as i64 - components_running as i64,
         trusted_owner_pub_bs58: None,
         links: 0,
+        services: Vec::new(),
+        job_slots: Vec::new(),
     })
 }
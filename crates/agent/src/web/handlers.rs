@@ -13,11 +13,15 @@ use crate::cmd;
 use crate::cmd::util::{dial_bootstrap, mdns_warmup, new_swarm};
 use crate::p2p::state::{load_state, save_state, NodeAnnotation};
 use crate::p2p::{handle_push_package, PushAcceptanceError};
-use crate::policy::{find_any_qemu_user, load_policy, save_policy, ExecutionPolicy};
+use crate::policy::{
+    find_any_qemu_user, load_policy, qemu_binfmt_status, save_policy, ExecutionPolicy,
+};
+use crate::schedule::normalize_schedule;
 use crate::storage::ContentStore;
 use base64::Engine;
 use common::{
-    sign_bytes_ed25519, Manifest, MountSpec, OwnerKeypair, Protocol, PushPackage, PushUnsigned,
+    parse_capabilities, parse_env_entries, parse_subscriptions, sign_bytes_ed25519, EgressPolicy,
+    HttpLimits, KvSpec, Manifest, MountSpec, OwnerKeypair, PushPackage, PushUnsigned, RouteSpec,
     ServicePort, Visibility,
 };
 
@@ -62,6 +66,7 @@ pub async fn api_nodes(State(state): State<WebState>) -> Json<Vec<ApiNode>> {
             cpu_percent: status.cpu_percent as u32,
             mem_percent: status.mem_percent as u32,
             alias,
+            job_slots: status.job_slots.clone(),
         });
     }
 
@@ -81,6 +86,7 @@ pub async fn api_nodes(State(state): State<WebState>) -> Json<Vec<ApiNode>> {
             cpu_percent: 0, // We don't track local CPU in this endpoint
             mem_percent: 0, // We don't track local memory in this endpoint
             alias,
+            job_slots: Vec::new(),
         });
     }
 
@@ -236,6 +242,11 @@ pub async fn api_components(State(state): State<WebState>) -> Json<Vec<ApiCompon
         } else {
             peers.keys().cloned().collect()
         };
+        let (next_run_ms, runs) = state
+            .supervisor
+            .schedule_status(name)
+            .await
+            .unwrap_or_default();
 
         components.push(ApiComponent {
             name: name.clone(),
@@ -244,6 +255,9 @@ pub async fn api_components(State(state): State<WebState>) -> Json<Vec<ApiCompon
             replicas_desired,
             memory_mb: memory_mb as u32,
             nodes,
+            schedule: desired.spec.schedule.clone(),
+            next_run_ms,
+            runs,
         });
     }
 
@@ -380,6 +394,35 @@ pub async fn api_deploy(
         Ok(v) => v,
         Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
     };
+    let request_timeout_ms = request.request_timeout_ms.filter(|t| *t > 0);
+    let http_limits = HttpLimits {
+        rate_limit_rps: request.rate_limit_rps.filter(|v| *v > 0),
+        client_rate_limit_rps: request.client_rate_limit_rps.filter(|v| *v > 0),
+        max_body_bytes: request.max_body_bytes.filter(|v| *v > 0),
+        max_concurrent_requests: request.max_concurrent_requests.filter(|v| *v > 0),
+    }
+    .non_empty();
+    let egress = match EgressPolicy::from_entries(&request.egress.clone().unwrap_or_default()) {
+        Ok(e) => e,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let route_strings = request.routes.clone().unwrap_or_default();
+    let routes = match parse_route_entries(&route_strings) {
+        Ok(r) => r,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let subscriptions = match parse_subscriptions(&request.subscriptions) {
+        Ok(s) => s,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let stdin = request.stdin.clone().filter(|s| !s.trim().is_empty());
+    if let Some(Err(err)) = stdin.as_deref().map(crate::secrets::stdin_digest) {
+        return (StatusCode::BAD_REQUEST, err).into_response();
+    }
+    let schedule = match normalize_schedule(request.schedule.as_deref()) {
+        Ok(s) => s,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
 
     // Load owner signing key (same as CLI `realm push`)
     let owner_dir = match crate::cmd::util::owner_dir() {
@@ -431,6 +474,18 @@ pub async fn api_deploy(
         mounts,
         ports,
         visibility,
+        request_timeout_ms,
+        routes,
+        http_limits,
+        egress,
+        args: request.args.clone(),
+        env: request.env.clone(),
+        secret_env: request.secret_env.clone(),
+        stdin,
+        capabilities: request.capabilities.clone(),
+        kv: request.kv.clone(),
+        subscriptions,
+        schedule,
     };
     let unsigned_bytes = serde_json::to_vec(&unsigned).expect("PushUnsigned serialization");
     let signature = match sign_bytes_ed25519(&owner.private_hex, &unsigned_bytes) {
@@ -506,35 +561,12 @@ fn parse_mount_entries(entries: &[String]) -> Result<Option<Vec<MountSpec>>, Str
 }
 
 fn parse_port_entries(entries: &[String]) -> Result<Option<Vec<ServicePort>>, String> {
-    if entries.is_empty() {
-        return Ok(None);
-    }
-    let mut out = Vec::new();
-    for entry in entries {
-        let trimmed = entry.trim();
-        if trimmed.is_empty() {
-            continue;
-        }
-        let mut parts = trimmed.split('/').map(str::trim);
-        let port_str = parts.next().unwrap_or("");
-        if port_str.is_empty() {
-            return Err(format!("invalid port '{entry}'"));
-        }
-        let proto_str = parts.next().unwrap_or("tcp");
-        let port = port_str
-            .parse::<u16>()
-            .map_err(|_| format!("invalid port number '{port_str}'"))?;
-        let protocol = if proto_str.eq_ignore_ascii_case("udp") {
-            Protocol::Udp
-        } else {
-            Protocol::Tcp
-        };
-        out.push(ServicePort {
-            name: None,
-            port,
-            protocol,
-        });
-    }
+    let out = entries
+        .iter()
+        .map(|e| e.trim())
+        .filter(|e| !e.is_empty())
+        .map(|e| e.parse::<ServicePort>())
+        .collect::<Result<Vec<_>, _>>()?;
     if out.is_empty() {
         Ok(None)
     } else {
@@ -542,6 +574,21 @@ fn parse_port_entries(entries: &[String]) -> Result<Option<Vec<ServicePort>>, St
     }
 }
 
+fn parse_route_entries(entries: &[String]) -> Result<Vec<RouteSpec>, String> {
+    entries
+        .iter()
+        .map(|e| e.trim())
+        .filter(|e| !e.is_empty())
+        .map(|e| e.parse::<RouteSpec>())
+        .collect()
+}
+
+/// Parse an optional positive number from a form field; blank or zero means unset.
+fn parse_positive<T: std::str::FromStr + PartialOrd + Default>(raw: Option<String>) -> Option<T> {
+    raw.and_then(|s| s.trim().parse().ok())
+        .filter(|v: &T| *v > T::default())
+}
+
 fn parse_visibility(raw: Option<&str>) -> Result<Option<Visibility>, String> {
     match raw.map(|s| s.trim()).filter(|s| !s.is_empty()) {
         None => Ok(None),
@@ -567,8 +614,12 @@ pub async fn api_set_policy(Json(body): Json<ExecutionPolicy>) -> impl IntoRespo
 }
 
 pub async fn api_qemu_status() -> impl IntoResponse {
-    let have_qemu = find_any_qemu_user().is_some();
-    let payload = serde_json::json!({ "qemu_installed": have_qemu });
+    let qemu_user = find_any_qemu_user();
+    let payload = serde_json::json!({
+        "qemu_installed": qemu_user.is_some(),
+        "qemu_user": qemu_user,
+        "binfmt_misc": qemu_binfmt_status(),
+    });
     (StatusCode::OK, Json(payload))
 }
 
@@ -622,16 +673,29 @@ pub async fn api_deploy_multipart(
     State(state): State<WebState>,
     mut multipart: Multipart,
 ) -> impl IntoResponse {
-    // Expected fields: name (text), file (file), replicas, memory, fuel, epoch_ms, tags, mounts, ports, visibility, start
+    // Expected fields: name (text), file (file), replicas, memory, fuel, epoch_ms, request_timeout_ms, rate_limit_rps, client_rate_limit_rps, max_body_bytes, max_concurrent_requests, tags, mounts, ports, routes, egress, args, env, secret_env, stdin, capabilities, kv_store, kv_replicate, subscriptions, schedule, visibility, start
     let mut name: Option<String> = None;
     let mut replicas: Option<u32> = None;
     let mut memory_max_mb: Option<u64> = None;
     let mut fuel: Option<u64> = None;
     let mut epoch_ms: Option<u64> = None;
+    let mut request_timeout_ms: Option<u64> = None;
+    let mut http_limits = HttpLimits::default();
     let mut tags_csv: Option<String> = None;
     let mut file_bytes: Option<Vec<u8>> = None;
     let mut mount_entries: Vec<String> = Vec::new();
     let mut port_entries: Vec<String> = Vec::new();
+    let mut route_entries: Vec<String> = Vec::new();
+    let mut egress_entries: Vec<String> = Vec::new();
+    let mut args: Vec<String> = Vec::new();
+    let mut env_entries: Vec<String> = Vec::new();
+    let mut secret_env_entries: Vec<String> = Vec::new();
+    let mut stdin: Option<String> = None;
+    let mut capability_entries: Vec<String> = Vec::new();
+    let mut kv_store: Option<String> = None;
+    let mut kv_replicate = false;
+    let mut subscription_entries: Vec<String> = Vec::new();
+    let mut schedule_raw: Option<String> = None;
     let mut visibility_raw: Option<String> = None;
     let mut start_flag: Option<bool> = None;
 
@@ -653,6 +717,26 @@ pub async fn api_deploy_multipart(
             "epoch" | "epoch_ms" => {
                 epoch_ms = field.text().await.ok().and_then(|s| s.parse().ok());
             }
+            "request_timeout_ms" => {
+                request_timeout_ms = field
+                    .text()
+                    .await
+                    .ok()
+                    .and_then(|s| s.trim().parse().ok())
+                    .filter(|t: &u64| *t > 0);
+            }
+            "rate_limit_rps" => {
+                http_limits.rate_limit_rps = parse_positive(field.text().await.ok());
+            }
+            "client_rate_limit_rps" => {
+                http_limits.client_rate_limit_rps = parse_positive(field.text().await.ok());
+            }
+            "max_body_bytes" => {
+                http_limits.max_body_bytes = parse_positive(field.text().await.ok());
+            }
+            "max_concurrent_requests" => {
+                http_limits.max_concurrent_requests = parse_positive(field.text().await.ok());
+            }
             "tags" => {
                 tags_csv = field.text().await.ok();
             }
@@ -690,6 +774,71 @@ pub async fn api_deploy_multipart(
                     );
                 }
             }
+            "routes" | "route" => {
+                if let Ok(text) = field.text().await {
+                    route_entries.extend(
+                        text.lines()
+                            .map(str::trim)
+                            .filter(|s| !s.is_empty())
+                            .map(|s| s.to_string()),
+                    );
+                }
+            }
+            "egress" => {
+                if let Ok(text) = field.text().await {
+                    egress_entries.extend(text.lines().map(|s| s.to_string()));
+                }
+            }
+            // One argument per line; blank lines are dropped
+            "args" => {
+                if let Ok(text) = field.text().await {
+                    args.extend(
+                        text.lines()
+                            .filter(|s| !s.trim().is_empty())
+                            .map(|s| s.to_string()),
+                    );
+                }
+            }
+            "env" => {
+                if let Ok(text) = field.text().await {
+                    env_entries.extend(text.lines().map(|s| s.to_string()));
+                }
+            }
+            "secret_env" => {
+                if let Ok(text) = field.text().await {
+                    secret_env_entries.extend(text.lines().map(|s| s.to_string()));
+                }
+            }
+            "stdin" => {
+                stdin = field
+                    .text()
+                    .await
+                    .ok()
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty());
+            }
+            "capabilities" | "capability" => {
+                if let Ok(text) = field.text().await {
+                    capability_entries.push(text);
+                }
+            }
+            "kv_store" => {
+                kv_store = field.text().await.ok();
+            }
+            "kv_replicate" => {
+                if let Ok(text) = field.text().await {
+                    let normalized = text.trim().to_lowercase();
+                    kv_replicate = matches!(normalized.as_str(), "1" | "true" | "yes" | "on");
+                }
+            }
+            "subscriptions" | "subscribe" => {
+                if let Ok(text) = field.text().await {
+                    subscription_entries.push(text);
+                }
+            }
+            "schedule" => {
+                schedule_raw = field.text().await.ok();
+            }
             "visibility" => {
                 visibility_raw = field.text().await.ok();
             }
@@ -734,6 +883,41 @@ pub async fn api_deploy_multipart(
         Ok(p) => p,
         Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
     };
+    let routes = match parse_route_entries(&route_entries) {
+        Ok(r) => r,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let egress = match EgressPolicy::from_entries(&egress_entries) {
+        Ok(e) => e,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let env = match parse_env_entries(&env_entries) {
+        Ok(e) => e,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let secret_env = match parse_env_entries(&secret_env_entries) {
+        Ok(e) => e,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let capabilities = match parse_capabilities(&capability_entries) {
+        Ok(c) => c,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let kv = match KvSpec::from_options(kv_store.as_deref(), kv_replicate) {
+        Ok(k) => k,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    if let Some(Err(err)) = stdin.as_deref().map(crate::secrets::stdin_digest) {
+        return (StatusCode::BAD_REQUEST, err).into_response();
+    }
+    let subscriptions = match parse_subscriptions(&subscription_entries) {
+        Ok(s) => s,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let schedule = match normalize_schedule(schedule_raw.as_deref()) {
+        Ok(s) => s,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
     let visibility = match parse_visibility(visibility_raw.as_deref()) {
         Ok(v) => v,
         Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
@@ -789,6 +973,18 @@ pub async fn api_deploy_multipart(
         mounts,
         ports,
         visibility,
+        request_timeout_ms,
+        routes,
+        http_limits: http_limits.non_empty(),
+        egress,
+        args,
+        env,
+        secret_env,
+        stdin,
+        capabilities,
+        kv,
+        subscriptions,
+        schedule,
     };
     let unsigned_bytes = serde_json::to_vec(&unsigned).expect("PushUnsigned serialization");
     let signature = match sign_bytes_ed25519(&owner.private_hex, &unsigned_bytes) {
@@ -1145,6 +1341,8 @@ fn parse_peer_info(peer_info: &PeerInfo) -> Result<common::Status, String> {
         drift: components_desired as i64 - components_running as i64,
         trusted_owner_pub_bs58: None,
         links: 0,
+        services: Vec::new(),
+        job_slots: Vec::new(),
     })
 }
 
@@ -77,10 +77,10 @@ pub async fn api_jobs_submit(
                             }),
                         );
                     }
-                    // Add pre-stage mapping to /tmp/assets/<filename>
+                    // Add pre-stage mapping to $REALM_ASSETS/<filename>
                     prestage.push(PreStageSpec {
                         source: format!("cas:{}", digest),
-                        dest: format!("/tmp/assets/{}", filename),
+                        dest: format!("assets/{}", filename),
                     });
                 }
             }
@@ -97,6 +97,11 @@ pub async fn api_jobs_submit(
             return (StatusCode::BAD_REQUEST, format!("Invalid job TOML: {}", e)).into_response()
         }
     };
+    if let Some(expr) = &job_spec.schedule {
+        if let Err(e) = crate::schedule::parse_schedule(expr) {
+            return (StatusCode::BAD_REQUEST, e).into_response();
+        }
+    }
     // Inject pre_stage entries
     job_spec.execution.pre_stage.extend(prestage.into_iter());
 
@@ -37,6 +37,7 @@ pub async fn api_nodes(State(state): State<WebState>) -> Json<Vec<ApiNode>> {
             cpu_percent: status.cpu_percent as u32,
             mem_percent: status.mem_percent as u32,
             alias: None,
+            job_slots: status.job_slots.clone(),
         });
     }
     if nodes.is_empty() {
@@ -50,6 +51,7 @@ pub async fn api_nodes(State(state): State<WebState>) -> Json<Vec<ApiNode>> {
             cpu_percent: 0,
             mem_percent: 0,
             alias: None,
+            job_slots: Vec::new(),
         });
     }
     Json(nodes)
@@ -84,6 +86,11 @@ pub async fn api_components(State(state): State<WebState>) -> Json<Vec<ApiCompon
         } else {
             peers.keys().cloned().collect()
         };
+        let (next_run_ms, runs) = state
+            .supervisor
+            .schedule_status(name)
+            .await
+            .unwrap_or_default();
         components.push(ApiComponent {
             name: name.clone(),
             running,
@@ -91,6 +98,9 @@ pub async fn api_components(State(state): State<WebState>) -> Json<Vec<ApiCompon
             replicas_desired,
             memory_mb: memory_mb as u32,
             nodes,
+            schedule: desired.spec.schedule.clone(),
+            next_run_ms,
+            runs,
         });
     }
     Json(components)
@@ -128,6 +128,9 @@ pub struct ApiNode {
     pub mem_percent: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub alias: Option<String>,
+    /// Job slot use per runtime, from the node's heartbeat
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub job_slots: Vec<common::JobSlotUsage>,
 }
 
 #[derive(Serialize)]
@@ -138,6 +141,15 @@ pub struct ApiComponent {
     pub replicas_desired: u32,
     pub memory_mb: u32,
     pub nodes: Vec<String>,
+    /// Cron expression of a scheduled component
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    /// Next scheduled run (unix ms)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_run_ms: Option<u64>,
+    /// Recent scheduled runs, newest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub runs: Vec<crate::schedule::ScheduledRun>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -178,6 +190,37 @@ pub struct DeployRequest {
     pub fuel: Option<u64>,
     pub epoch_ms: Option<u64>,
     #[serde(default)]
+    pub request_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub rate_limit_rps: Option<u32>,
+    #[serde(default)]
+    pub client_rate_limit_rps: Option<u32>,
+    #[serde(default)]
+    pub max_body_bytes: Option<u64>,
+    #[serde(default)]
+    pub max_concurrent_requests: Option<u32>,
+    #[serde(default)]
+    pub egress: Option<Vec<String>>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Env name -> `file:`/`cas:` secret reference
+    #[serde(default)]
+    pub secret_env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub stdin: Option<String>,
+    #[serde(default)]
+    pub capabilities: Vec<common::Capability>,
+    #[serde(default)]
+    pub kv: Option<common::KvSpec>,
+    /// Event topics (or `prefix*`) that trigger the component's event handler
+    #[serde(default)]
+    pub subscriptions: Vec<String>,
+    /// Cron expression to invoke the component on
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default)]
     pub target_peer_ids: Vec<String>,
     #[serde(default)]
     pub target_tags: Vec<String>,
@@ -188,6 +231,8 @@ pub struct DeployRequest {
     #[serde(default)]
     pub mounts: Option<Vec<String>>,
     #[serde(default)]
+    pub routes: Option<Vec<String>>,
+    #[serde(default)]
     pub visibility: Option<String>,
 }
 
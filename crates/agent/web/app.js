@@ -175,7 +175,16 @@ class RealmApp {
                 try {
                     const allow_native_execution = !!document.getElementById('policy-native').checked;
                     const allow_emulation = !!document.getElementById('policy-qemu').checked;
-                    const body = { allow_native_execution, allow_emulation };
+                    const require_native_sandbox = !!document.getElementById('policy-native-sandbox').checked;
+                    const allow_containers = !!document.getElementById('policy-containers').checked;
+                    // "native=2, wasm=8" -> { native: 2, wasm: 8 }
+                    const job_slots = {};
+                    (document.getElementById('policy-slots')?.value || '').split(',').map(s => s.trim()).filter(Boolean).forEach(entry => {
+                        const [runtime, count] = entry.split('=').map(s => s.trim());
+                        const n = parseInt(count, 10);
+                        if (runtime && Number.isFinite(n)) job_slots[runtime] = n;
+                    });
+                    const body = { allow_native_execution, allow_emulation, require_native_sandbox, allow_containers, job_slots };
                     await this.apiCall('/api/policy', { method: 'POST', body: JSON.stringify(body) });
                     this.showSuccess('Policy saved');
                 } catch (e) { this.showError('Failed to save policy'); }
@@ -359,7 +368,7 @@ class RealmApp {
             tbody.innerHTML = '';
 
             if (nodes.length === 0) {
-                tbody.innerHTML = '<tr><td colspan="7" class="loading">No nodes found</td></tr>';
+                tbody.innerHTML = '<tr><td colspan="8" class="loading">No nodes found</td></tr>';
                 return;
             }
 
@@ -368,6 +377,12 @@ class RealmApp {
                 const statusHtml = node.online
                     ? '<span class="inline-flex items-center rounded-full px-2 py-0.5 text-xs font-medium bg-green-900/30 text-green-400">Online</span>'
                     : '<span class="inline-flex items-center rounded-full px-2 py-0.5 text-xs font-medium bg-red-900/30 text-red-400">Offline</span>';
+                // Busy runtimes as used/total, with queued jobs
+                const slots = (node.job_slots || []).filter(s => s.busy || s.queued);
+                const slotsTitle = (node.job_slots || []).map(s => `${s.runtime}: ${s.busy}/${s.total}, ${s.queued} queued`).join('\n');
+                const slotsHtml = slots.length
+                    ? slots.map(s => `${s.runtime} ${s.busy}/${s.total}${s.queued ? ` <span class="text-yellow-400">+${s.queued}</span>` : ''}`).join(', ')
+                    : '<span class="text-gray-400">idle</span>';
                 row.innerHTML = `
                     <td class="p-4" title="${node.id}">${node.alias ? `<span class=\"font-medium\">${node.alias}</span><div class=\"text-xs text-gray-400\">${node.id.substring(0, 12)}...</div>` : `${node.id.substring(0, 12)}...`}</td>
                     <td class="p-4">${statusHtml}</td>
@@ -375,6 +390,7 @@ class RealmApp {
                     <td class="p-4">${node.components_running}/${node.components_desired}</td>
                     <td class="p-4">${node.cpu_percent}%</td>
                     <td class="p-4">${node.mem_percent}%</td>
+                    <td class="p-4" title="${slotsTitle}">${slotsHtml}</td>
                     <td class="p-4">
                         <button class="border border-graphite px-2 py-1 rounded text-sm hover:bg-graphite" onclick="app.viewNodeDetails('${node.id}')">Details</button>
                     </td>
@@ -384,7 +400,7 @@ class RealmApp {
 
         } catch (error) {
             console.error('Failed to load nodes data:', error);
-            document.getElementById('nodes-tbody').innerHTML = '<tr><td colspan="7" class="loading">Failed to load nodes</td></tr>';
+            document.getElementById('nodes-tbody').innerHTML = '<tr><td colspan="8" class="loading">Failed to load nodes</td></tr>';
         }
     }
 
@@ -409,11 +425,21 @@ class RealmApp {
                 const statusHtml = component.running
                     ? '<span class="inline-flex items-center rounded-full px-2 py-0.5 text-xs font-medium bg-green-900/30 text-green-400">Running</span>'
                     : '<span class="inline-flex items-center rounded-full px-2 py-0.5 text-xs font-medium bg-red-900/30 text-red-400">Stopped</span>';
+                // Scheduled components show their cron expression, next run and last outcome
+                let replicasHtml = `<span class="bg-azure/20 text-azure px-2 py-1 rounded text-xs">${component.replicas_running}/${component.replicas_desired}</span>`;
+                if (component.schedule) {
+                    const lastRun = (component.runs || [])[0];
+                    const scheduleTitle = [
+                        component.next_run_ms ? `next: ${new Date(component.next_run_ms).toLocaleString()}` : null,
+                        lastRun ? `last: ${lastRun.status}${lastRun.error ? ` (${lastRun.error})` : ''}` : null,
+                    ].filter(Boolean).join(', ');
+                    replicasHtml = `<span class="bg-azure/20 text-azure px-2 py-1 rounded text-xs" title="${scheduleTitle}">${component.schedule}</span>`;
+                }
                 row.className = 'border-b border-graphite hover:bg-graphite';
                 row.innerHTML = `
                     <td class="p-4">${component.name}</td>
                     <td class="p-4">${statusHtml}</td>
-                    <td class="p-4"><span class="bg-azure/20 text-azure px-2 py-1 rounded text-xs">${component.replicas_running}/${component.replicas_desired}</span></td>
+                    <td class="p-4">${replicasHtml}</td>
                     <td class="p-4">${component.memory_mb}MB</td>
                     <td class="p-4">${component.nodes.join(', ')}</td>
                     <td class="p-4">
@@ -652,7 +678,20 @@ class RealmApp {
             const qemuStatus = document.getElementById('qemu-status');
             if (nativeEl) nativeEl.checked = !!pol.allow_native_execution;
             if (qemuEl) qemuEl.checked = !!pol.allow_emulation;
-            if (qemuStatus) qemuStatus.textContent = `QEMU: ${qemu.qemu_installed ? 'installed' : 'not detected'}`;
+            const sandboxEl = document.getElementById('policy-native-sandbox');
+            if (sandboxEl) sandboxEl.checked = !!pol.require_native_sandbox;
+            const containersEl = document.getElementById('policy-containers');
+            if (containersEl) containersEl.checked = !!pol.allow_containers;
+            const slotsEl = document.getElementById('policy-slots');
+            if (slotsEl) slotsEl.value = Object.entries(pol.job_slots || {}).map(([runtime, n]) => `${runtime}=${n}`).join(', ');
+            if (qemuStatus) {
+                const binfmt = qemu.binfmt_misc || {};
+                const handlers = (binfmt.handlers || []).filter(h => h.enabled).map(h => h.name.replace(/^qemu-/, ''));
+                const binfmtText = !binfmt.available ? '' :
+                    !binfmt.enabled ? ' · binfmt_misc disabled' :
+                    handlers.length ? ` · binfmt: ${handlers.join(', ')}` : ' · no binfmt handlers';
+                qemuStatus.textContent = `QEMU: ${qemu.qemu_installed ? 'installed' : 'not detected'}${binfmtText}`;
+            }
         } catch (e) {
             // Non-fatal
         }
@@ -21,10 +21,10 @@ export async function loadJobsData(app) {
 		const resp = await apiCall(app.sessionToken, `/api/jobs${qs}`);
 		const jobs = await resp.json();
 		const total = jobs.length;
-		const counts = { pending: 0, running: 0, completed: 0 };
+		const counts = { pending: 0, queued: 0, running: 0, completed: 0 };
 		jobs.forEach(j => { const s = (j.status || '').toLowerCase(); if (s in counts) counts[s] += 1; });
 		const el = (id, v) => { const x = document.getElementById(id); if (x) x.textContent = v; };
-		el('jobs-total', total); el('jobs-pending', counts.pending || 0); el('jobs-running', counts.running || 0); el('jobs-completed', counts.completed || 0);
+		el('jobs-total', total); el('jobs-pending', counts.pending + counts.queued); el('jobs-running', counts.running || 0); el('jobs-completed', counts.completed || 0);
 		const tbody = document.getElementById('jobs-tbody'); if (!tbody) return; tbody.innerHTML = '';
 		if (!jobs.length) { tbody.innerHTML = '<tr><td colspan="8" class="p-4 text-gray-400">No jobs</td></tr>'; return; }
 		jobs.forEach(job => {
@@ -54,12 +54,19 @@ export function openJobSubmitModal(app) {
 	const examples = {
 		'one-shot': `name = "example-oneshot"
 job_type = "one-shot"
+# priority = 10  # higher starts first when the node's job slots are busy
+# preemptible = true  # may be stopped and requeued for a higher-priority job
 
 [runtime]
 type = "wasm"
 source = "file:///path/to/task.wasm"
 memory_mb = 64
 epoch_ms = 100
+# egress = { allow = ["https://api.github.com"] }  # outbound network is off unless allowlisted
+# args = ["--input", "/workspace/assets/data.csv"]
+# env = { LOG_LEVEL = "info" }
+# secret_env = { API_TOKEN = "file:/etc/realm/api-token" }
+# stdin = "cas:<sha256>"
 
 [execution]
 timeout_minutes = 30
@@ -72,7 +79,13 @@ platform = "linux/x86_64"
 tags = ["worker"]`,
 		'recurring': `name = "scheduled-backup"
 job_type = "recurring"
-schedule = "0 2 * * *"  # Daily at 2 AM
+schedule = "0 0 2 * * *"  # sec min hour day month weekday: daily at 2 AM
+
+# [recurring]
+# timezone = "Europe/Berlin"  # default UTC
+# catch_up = "skip"  # skip | run-once | run-all
+# concurrency = "forbid"  # allow | forbid | replace
+# history = 20
 
 [runtime]
 type = "wasm"
@@ -94,6 +107,14 @@ source = "file:///path/to/service.wasm"
 memory_mb = 256
 epoch_ms = 1000
 
+# [service]
+# restart = "on-failure"  # always | on-failure | never
+# ports = [{ port = 8080 }]
+#
+# [service.health]
+# port = 8080
+# path = "/healthz"
+
 [targeting]
 tags = ["server"]`,
 		'native': `name = "native-job"
@@ -104,8 +125,18 @@ type = "native"
 binary = "file:///usr/bin/echo"
 args = ["hello", "world"]
 
+# Optional Linux sandbox (namespaces, seccomp, landlock)
+# [runtime.sandbox]
+# network = false
+#
+# [resources]
+# cpus = 1
+# memory_mb = 256
+# open_files = 1024  # rlimits: cpu_seconds, address_space_mb, file_size_mb
+
 [execution]
 timeout_minutes = 5
+# user = "nobody"  # drop privileges (agent must run as root)
 
 [targeting]
 tags = ["builder"]
@@ -118,12 +149,51 @@ type = "qemu"
 binary = "file:///path/to/foreign-arch-binary"
 args = ["--help"]
 target_platform = "linux/amd64"  # or linux/arm64, etc
+# sysroot = "cas:<sha256>"  # target rootfs tarball for dynamically linked binaries
 
 [execution]
 timeout_minutes = 10
 
 [targeting]
 tags = ["builder"]
+`,
+		'oci': `name = "container-job"
+job_type = "one-shot"
+
+[runtime]
+type = "oci"
+image = "alpine:3.20"  # or localhost:5000/app:tag, cas:<sha256> of an OCI layout tar
+args = ["sh", "-c", "uname -a > $REALM_ARTIFACTS/uname.txt"]
+# env = { LOG_LEVEL = "info" }
+# network = true
+
+[resources]
+memory_mb = 256
+
+[execution]
+timeout_minutes = 10
+artifacts = [{ path = "artifacts/uname.txt" }]
+
+[targeting]
+platform = "linux/x86_64"
+`,
+		'vm': `name = "vm-service"
+job_type = "service"
+
+[runtime]
+type = "qemu-system"
+image = "cas:<sha256 of a cloud-init enabled qcow2 image>"
+# arch = "aarch64"  # defaults to the node's architecture
+args = ["python3", "-m", "http.server", "8080"]
+ports = [{ port = 8080, host_port = 18080 }]
+# network = true
+
+[resources]
+cpus = 2
+memory_mb = 1024
+
+[targeting]
+tags = ["vm-host"]
 `
 	};
 
@@ -137,6 +207,8 @@ tags = ["builder"]
 					<button type="button" class="text-xs bg-azure hover:bg-neon-blue px-3 py-1 rounded" onclick="setJobTemplate('service')">Service</button>
 					<button type="button" class="text-xs border border-graphite px-3 py-1 rounded" onclick="setJobTemplate('native')">Native</button>
 					<button type="button" class="text-xs border border-graphite px-3 py-1 rounded" onclick="setJobTemplate('qemu')">QEMU</button>
+					<button type="button" class="text-xs border border-graphite px-3 py-1 rounded" onclick="setJobTemplate('oci')">Container</button>
+					<button type="button" class="text-xs border border-graphite px-3 py-1 rounded" onclick="setJobTemplate('vm')">VM</button>
 				</div>
 			</div>
 			<div>
@@ -147,7 +219,7 @@ tags = ["builder"]
 				<label class="block text-sm text-gray-300 mb-1">Attachments (optional)</label>
 				<input id="job-assets" type="file" multiple class="w-full bg-graphite border border-graphite rounded px-3 py-2 text-sm" />
 				<div id="job-assets-preview" class="mt-2 text-xs text-gray-300 space-y-1"></div>
-				<p class="text-xs text-gray-400 mt-1">Files will be content-addressed and available as /tmp/assets/&lt;filename&gt; during job execution.</p>
+				<p class="text-xs text-gray-400 mt-1">Files will be content-addressed and available as $REALM_ASSETS/&lt;filename&gt; in the job workspace.</p>
 			</div>
 			<div class="flex items-center justify-end gap-2">
 				<button type="button" class="border border-graphite px-4 py-2 rounded" id="job-cancel">Close</button>
@@ -182,7 +254,7 @@ tags = ["builder"]
 						const digest = await sha256Hex(new Uint8Array(ab));
 						const div = document.createElement('div');
 						div.className = 'font-mono';
-						div.textContent = `${f.name} → /tmp/assets/${f.name}  sha256:${digest.slice(0, 16)}…`;
+						div.textContent = `${f.name} → $REALM_ASSETS/${f.name}  sha256:${digest.slice(0, 16)}…`;
 						preview.appendChild(div);
 					}
 				}
@@ -227,8 +299,16 @@ export async function viewJob(app, jobId) {
 				<div><strong>Name:</strong> ${job.spec?.name || '-'}</div>
 				<div><strong>Status:</strong> ${job.status}</div>
 				<div><strong>Type:</strong> ${job.spec?.job_type || '-'}</div>
+				${job.spec?.priority ? `<div><strong>Priority:</strong> ${job.spec.priority}</div>` : ''}
+				${job.status === 'queued' && job.queued_at ? `<div><strong>Queued since:</strong> ${new Date(job.queued_at * 1000).toLocaleString()}</div>` : ''}
 				<div><strong>Node:</strong> ${job.assigned_node || '-'}</div>
-				${job.spec?.schedule ? `<div><strong>Schedule:</strong> ${job.spec.schedule}</div>` : ''}
+				${job.restarts ? `<div><strong>Restarts:</strong> ${job.restarts}</div>` : ''}
+				${job.spec?.preemptible ? `<div><strong>Preemptible:</strong> yes</div>` : ''}
+				${job.attempts?.length ? `<div><strong>Attempts:</strong><ol class='list-decimal ml-6'>${job.attempts.map(a => `<li>${a.node} · ${a.started_at ? new Date(a.started_at * 1000).toLocaleString() : '-'} – ${new Date(a.ended_at * 1000).toLocaleString()}: ${a.reason}</li>`).join('')}</ol></div>` : ''}
+				${job.spec?.schedule ? `<div><strong>Schedule:</strong> ${job.spec.schedule}${job.spec.recurring?.timezone ? ` (${job.spec.recurring.timezone})` : ''}</div>` : ''}
+				${job.schedule_next_at ? `<div><strong>Next run:</strong> ${new Date(job.schedule_next_at * 1000).toLocaleString()}</div>` : ''}
+				${job.parent_id ? `<div><strong>Parent:</strong> <a href="#" class="text-neon-blue" onclick="app.viewJob('${job.parent_id}'); return false;">${job.parent_id}</a></div>` : ''}
+				${job.children?.length ? `<div><strong>Runs:</strong> ${job.children.map(id => `<a href="#" class="text-neon-blue" onclick="app.viewJob('${id}'); return false;">${id}</a>`).join(', ')}</div>` : ''}
 				${artifactsHtml}
 				<div><strong>Logs:</strong><div class='mt-2 max-h-64 overflow-y-auto bg-graphite p-2 rounded'>${logsHtml || 'No logs'}</div></div>
 			</div>`);
@@ -4,6 +4,17 @@ use std::fmt;
 
 pub const REALM_CMD_TOPIC: &str = "realm/cmd/v1";
 pub const REALM_STATUS_TOPIC: &str = "realm/status/v1";
+/// Component events are gossiped per realm on `<prefix>/<owner key>`.
+pub const REALM_EVENTS_TOPIC_PREFIX: &str = "realm/events/v1";
+
+/// Events topic for the realm owned by `owner_pub_bs58`; nodes without a
+/// trusted owner yet share an `unclaimed` topic.
+pub fn events_topic(owner_pub_bs58: Option<&str>) -> String {
+    format!(
+        "{REALM_EVENTS_TOPIC_PREFIX}/{}",
+        owner_pub_bs58.unwrap_or("unclaimed")
+    )
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Command {
@@ -122,6 +133,25 @@ pub enum Command {
         target_peer_ids: Vec<String>,
         roles: Vec<String>,
     },
+    /// Event published by a component through `realm:host/events`
+    ComponentEvent {
+        topic: String,
+        source_component: String,
+        origin_node_id: String,
+        payload_b64: String,
+        message_id: String,
+    },
+    /// Receipt of a `ComponentEvent`, sent once it was delivered on the node
+    EventAck {
+        message_id: String,
+        from: String,
+    },
+    /// Write to a replicated `realm:host/kv` store
+    KvReplicate {
+        store: String,
+        key: String,
+        entry: KvEntry,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -139,6 +169,48 @@ pub struct Status {
     pub trusted_owner_pub_bs58: Option<String>,
     #[serde(default)]
     pub links: u64,
+    /// HTTP components this node can serve for the mesh gateway
+    #[serde(default)]
+    pub services: Vec<ServiceAdvert>,
+    /// Job slots per runtime, so busy nodes can leave jobs to idle ones
+    #[serde(default)]
+    pub job_slots: Vec<JobSlotUsage>,
+}
+
+/// Use of one runtime's job slots on a node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSlotUsage {
+    /// Runtime kind, e.g. "native" or "wasm"
+    pub runtime: String,
+    pub total: u32,
+    pub busy: u32,
+    /// Jobs claimed by the node and waiting for one of these slots
+    pub queued: u32,
+}
+
+impl JobSlotUsage {
+    pub fn has_free(&self) -> bool {
+        self.busy < self.total && self.queued == 0
+    }
+}
+
+/// A component a peer serves to the mesh: HTTP via the gateway and/or raw ports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAdvert {
+    pub name: String,
+    /// Served through the HTTP gateway
+    #[serde(default)]
+    pub http: bool,
+    #[serde(default)]
+    pub routes: Vec<RouteSpec>,
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+    /// Service ports reachable through the mesh port tunnel, with their host ports
+    #[serde(default)]
+    pub ports: Vec<ServicePort>,
+    /// Limits the ingress gateway applies per client before forwarding
+    #[serde(default)]
+    pub http_limits: Option<HttpLimits>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -228,6 +300,31 @@ pub struct PushUnsigned {
     pub mounts: Option<Vec<MountSpec>>,  // preopened directories for ad-hoc push
     pub ports: Option<Vec<ServicePort>>, // declared guest ports
     pub visibility: Option<Visibility>,  // gateway binding policy
+    // Omitted when unset so signatures from older clients still verify
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<RouteSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_limits: Option<HttpLimits>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub egress: Option<EgressPolicy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub secret_env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<Capability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kv: Option<KvSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subscriptions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -253,6 +350,29 @@ pub struct ComponentSpec {
     pub mounts: Option<Vec<MountSpec>>,  // preopened directories
     pub ports: Option<Vec<ServicePort>>, // declared guest ports (Service)
     pub visibility: Option<Visibility>,  // gateway binding policy
+    pub request_timeout_ms: Option<u64>, // per-request deadline for HTTP components
+    #[serde(default)]
+    pub routes: Vec<RouteSpec>, // gateway routes (hosts / path prefixes)
+    #[serde(default)]
+    pub http_limits: Option<HttpLimits>, // gateway admission limits
+    #[serde(default)]
+    pub egress: Option<EgressPolicy>, // outbound network allowlist
+    #[serde(default)]
+    pub args: Vec<String>, // guest argv after the program name
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub secret_env: BTreeMap<String, String>, // env name -> file:/cas: reference
+    #[serde(default)]
+    pub stdin: Option<String>, // cas: reference fed to stdin
+    #[serde(default)]
+    pub capabilities: Vec<Capability>, // realm:host interfaces the component may call
+    #[serde(default)]
+    pub kv: Option<KvSpec>, // realm:host/kv store settings
+    #[serde(default)]
+    pub subscriptions: Vec<String>, // event topics that trigger the component's event handler
+    #[serde(default)]
+    pub schedule: Option<String>, // cron expression the component is invoked on
     #[serde(default)]
     pub target_peer_ids: Vec<String>,
     #[serde(default)]
@@ -352,9 +472,143 @@ pub struct ServicePort {
     pub port: u16,
     #[serde(default = "default_protocol")]
     pub protocol: Protocol,
+    /// Port exposed on the node; allocated by the agent when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_port: Option<u16>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Host interfaces from the `realm:host` WIT package a component may call.
+/// Logging is always allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    /// Submit jobs and read their status
+    Jobs,
+    /// Read and write the content store
+    Cas,
+    /// Use the component's key-value store
+    Kv,
+    /// Publish events to the realm
+    Events,
+}
+
+impl std::str::FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "jobs" => Ok(Capability::Jobs),
+            "cas" => Ok(Capability::Cas),
+            "kv" => Ok(Capability::Kv),
+            "events" => Ok(Capability::Events),
+            other => Err(format!(
+                "unknown capability '{other}' (expected jobs, cas, kv or events)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Capability::Jobs => "jobs",
+            Capability::Cas => "cas",
+            Capability::Kv => "kv",
+            Capability::Events => "events",
+        })
+    }
+}
+
+/// Parse capability names (CLI flags, comma/newline-separated form input),
+/// dropping blanks and duplicates.
+pub fn parse_capabilities<S: AsRef<str>>(entries: &[S]) -> Result<Vec<Capability>, String> {
+    let mut out = Vec::new();
+    for name in entries
+        .iter()
+        .flat_map(|e| e.as_ref().split([',', '\n']))
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+    {
+        let cap = name.parse::<Capability>()?;
+        if !out.contains(&cap) {
+            out.push(cap);
+        }
+    }
+    Ok(out)
+}
+
+/// Parse event topic patterns (CLI flags, comma/newline-separated form input):
+/// an exact topic, or a prefix ending in `*`.
+pub fn parse_subscriptions<S: AsRef<str>>(entries: &[S]) -> Result<Vec<String>, String> {
+    let mut out: Vec<String> = Vec::new();
+    for pattern in entries
+        .iter()
+        .flat_map(|e| e.as_ref().split([',', '\n']))
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+    {
+        if pattern.len() > 256 || pattern.trim_end_matches('*').contains('*') {
+            return Err(format!(
+                "invalid subscription '{pattern}' (up to 256 bytes; '*' only at the end)"
+            ));
+        }
+        if !out.iter().any(|p| p == pattern) {
+            out.push(pattern.to_string());
+        }
+    }
+    Ok(out)
+}
+
+/// Which key-value store a component's `realm:host/kv` calls use.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KvSpec {
+    /// Store name; components naming the same store share keys. Defaults to
+    /// the component name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store: Option<String>,
+    /// Exchange writes with other nodes hosting a component on this store
+    #[serde(default)]
+    pub replicate: bool,
+}
+
+impl KvSpec {
+    /// Build from CLI/form input; `None` when nothing differs from the default.
+    pub fn from_options(store: Option<&str>, replicate: bool) -> Result<Option<Self>, String> {
+        let store = store.map(str::trim).filter(|s| !s.is_empty());
+        if let Some(name) = store {
+            if name.len() > 64
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            {
+                return Err(format!(
+                    "invalid kv store name '{name}' (use up to 64 of A-Z a-z 0-9 . _ -)"
+                ));
+            }
+        }
+        if store.is_none() && !replicate {
+            return Ok(None);
+        }
+        Ok(Some(KvSpec {
+            store: store.map(str::to_string),
+            replicate,
+        }))
+    }
+}
+
+/// One key of a replicated kv store as sent between nodes. `value_b64` is
+/// `None` for a deleted key; the highest `(updated_ms, origin)` wins.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KvEntry {
+    #[serde(default)]
+    pub value_b64: Option<String>,
+    #[serde(default)]
+    pub expires_at_ms: Option<u64>,
+    pub updated_ms: u64,
+    pub origin: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Protocol {
     Tcp,
     Udp,
@@ -364,12 +618,328 @@ fn default_protocol() -> Protocol {
     Protocol::Tcp
 }
 
+impl std::str::FromStr for ServicePort {
+    type Err = String;
+
+    /// Parse `[HOST_PORT:]PORT[/tcp|/udp]`, e.g. `8080`, `9090/udp` or `18080:8080/tcp`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (ports, proto) = match s.split_once('/') {
+            Some((p, proto)) => (p.trim(), proto.trim()),
+            None => (s, "tcp"),
+        };
+        let protocol = if proto.eq_ignore_ascii_case("udp") {
+            Protocol::Udp
+        } else if proto.eq_ignore_ascii_case("tcp") {
+            Protocol::Tcp
+        } else {
+            return Err(format!("invalid protocol '{proto}' (expected tcp or udp)"));
+        };
+        let (host_port, port) = match ports.split_once(':') {
+            Some((h, p)) => (Some(h.trim()), p.trim()),
+            None => (None, ports),
+        };
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| format!("invalid port number '{port}'"))?;
+        let host_port = host_port
+            .map(|h| {
+                h.parse::<u16>()
+                    .map_err(|_| format!("invalid host port '{h}'"))
+            })
+            .transpose()?;
+        Ok(ServicePort {
+            name: None,
+            port,
+            protocol,
+            host_port,
+        })
+    }
+}
+
 fn default_start_true() -> bool {
     true
 }
 
 // Removed legacy static file routing; HTTP is now handled via WASI HTTP.
 
+/// Gateway admission limits for an HTTP component. Unset fields are unlimited,
+/// except `max_body_bytes`, which falls back to the node default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpLimits {
+    /// Requests per second across all clients (burst of one second)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_rps: Option<u32>,
+    /// Requests per second from one client IP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_rate_limit_rps: Option<u32>,
+    /// Largest accepted request body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_bytes: Option<u64>,
+    /// Requests handled at once; extra requests get 503
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<u32>,
+}
+
+impl HttpLimits {
+    /// `None` when every field is unset, to keep specs and signatures minimal.
+    pub fn non_empty(self) -> Option<Self> {
+        (self != Self::default()).then_some(self)
+    }
+}
+
+/// Outbound network allowlist for a component or wasm job. `None` on a spec keeps
+/// the defaults (unrestricted outgoing HTTP, no outbound sockets); an empty
+/// allowlist denies all egress.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EgressPolicy {
+    #[serde(default)]
+    pub allow: Vec<EgressRule>,
+}
+
+impl EgressPolicy {
+    /// Build a policy from CLI/form entries. No entries means no policy; the single
+    /// entry `none` means deny all.
+    pub fn from_entries(entries: &[String]) -> Result<Option<Self>, String> {
+        let entries: Vec<&str> = entries
+            .iter()
+            .map(|e| e.trim())
+            .filter(|e| !e.is_empty())
+            .collect();
+        match entries.as_slice() {
+            [] => Ok(None),
+            ["none"] => Ok(Some(Self::default())),
+            _ => Ok(Some(Self {
+                allow: entries
+                    .iter()
+                    .map(|e| e.parse())
+                    .collect::<Result<_, _>>()?,
+            })),
+        }
+    }
+
+    /// Whether a connection to `host:port` over `scheme` (http, https, tcp, udp) is allowed.
+    pub fn allows(&self, scheme: &str, host: &str, port: u16) -> bool {
+        self.allow.iter().any(|r| r.matches(scheme, host, port))
+    }
+}
+
+/// One allowlist entry: `[SCHEME://]HOST[:PORT]`. `HOST` may be `*` or
+/// `*.example.com` (subdomains only). Without a port, http and https rules
+/// default to 80 and 443; other rules match any port. Without a scheme, any
+/// scheme matches.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct EgressRule {
+    pub scheme: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl EgressRule {
+    pub fn matches(&self, scheme: &str, host: &str, port: u16) -> bool {
+        if let Some(s) = &self.scheme {
+            if !s.eq_ignore_ascii_case(scheme) {
+                return false;
+            }
+        }
+        if let Some(p) = self.port {
+            if p != port {
+                return false;
+            }
+        }
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        match self.host.strip_prefix("*.") {
+            _ if self.host == "*" => true,
+            Some(domain) => host
+                .to_ascii_lowercase()
+                .ends_with(&format!(".{}", domain.to_ascii_lowercase())),
+            None => self.host.eq_ignore_ascii_case(host),
+        }
+    }
+}
+
+impl std::str::FromStr for EgressRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (scheme, rest) = match s.split_once("://") {
+            Some((scheme, rest)) => {
+                let scheme = scheme.to_ascii_lowercase();
+                if !matches!(scheme.as_str(), "http" | "https" | "tcp" | "udp") {
+                    return Err(format!(
+                        "invalid egress scheme '{scheme}' (expected http, https, tcp or udp)"
+                    ));
+                }
+                (Some(scheme), rest)
+            }
+            None => (None, s),
+        };
+        let rest = rest.trim_end_matches('/');
+        // `[v6]:port`, `host:port` or a bare host (including a bare IPv6 address)
+        let (host, port) = if let Some(v6) = rest.strip_prefix('[') {
+            let (host, after) = v6
+                .split_once(']')
+                .ok_or_else(|| format!("invalid egress host '{rest}'"))?;
+            (host, after.strip_prefix(':'))
+        } else if rest.matches(':').count() == 1 {
+            let (h, p) = rest.split_once(':').unwrap_or((rest, ""));
+            (h, Some(p))
+        } else {
+            (rest, None)
+        };
+        if host.is_empty() || host.contains('/') {
+            return Err(format!("invalid egress host '{rest}'"));
+        }
+        let port = match port {
+            Some(p) => Some(
+                p.parse::<u16>()
+                    .map_err(|_| format!("invalid egress port '{p}'"))?,
+            ),
+            None => match scheme.as_deref() {
+                Some("http") => Some(80),
+                Some("https") => Some(443),
+                _ => None,
+            },
+        };
+        Ok(EgressRule {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for EgressRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{scheme}://")?;
+        }
+        if self.host.contains(':') {
+            write!(f, "[{}]", self.host)?;
+        } else {
+            f.write_str(&self.host)?;
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{port}")?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for EgressRule {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<EgressRule> for String {
+    fn from(rule: EgressRule) -> Self {
+        rule.to_string()
+    }
+}
+
+/// Gateway route owned by an HTTP component.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteSpec {
+    /// Hostnames served by this route (`*.example.com` wildcards allowed); empty = any host
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default = "default_route_prefix")]
+    pub path_prefix: String,
+    /// Remove `path_prefix` before handing the path to the component
+    #[serde(default)]
+    pub strip_prefix: bool,
+    /// Higher wins when several routes match
+    #[serde(default)]
+    pub priority: i32,
+    /// Serve this route's hosts on the TLS listener
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<RouteTls>,
+}
+
+/// Certificate source for a route's hosts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteTls {
+    /// PEM certificate chain: `file:/path` or `cas:<sha256>`
+    #[serde(default)]
+    pub cert: Option<String>,
+    /// PEM private key: `file:/path` or `cas:<sha256>`
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Provision and renew a certificate via ACME instead
+    #[serde(default)]
+    pub acme: bool,
+}
+
+fn default_route_prefix() -> String {
+    "/".to_string()
+}
+
+impl std::str::FromStr for RouteSpec {
+    type Err = String;
+
+    /// Parse `host=a.example.com,prefix=/hook[,strip=true][,priority=10]`, optionally
+    /// with TLS as `cert=file:/c.pem,key=cas:<sha256>` or `acme=true`.
+    /// `host` may repeat; at least one of `host` or `prefix` is required.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut route = RouteSpec {
+            hosts: Vec::new(),
+            path_prefix: default_route_prefix(),
+            strip_prefix: false,
+            priority: 0,
+            tls: None,
+        };
+        let mut has_prefix = false;
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (k, v) = part
+                .split_once('=')
+                .ok_or_else(|| format!("invalid route part '{part}'"))?;
+            let v = v.trim();
+            match k.trim() {
+                "host" => route.hosts.push(v.to_ascii_lowercase()),
+                "prefix" => {
+                    if !v.starts_with('/') {
+                        return Err(format!("route prefix must start with '/': '{v}'"));
+                    }
+                    route.path_prefix = v.to_string();
+                    has_prefix = true;
+                }
+                "strip" => route.strip_prefix = v.eq_ignore_ascii_case("true"),
+                "priority" => {
+                    route.priority = v
+                        .parse()
+                        .map_err(|_| format!("invalid route priority '{v}'"))?
+                }
+                "cert" => route.tls.get_or_insert_with(RouteTls::default).cert = Some(v.into()),
+                "key" => route.tls.get_or_insert_with(RouteTls::default).key = Some(v.into()),
+                "acme" => {
+                    route.tls.get_or_insert_with(RouteTls::default).acme =
+                        v.eq_ignore_ascii_case("true")
+                }
+                other => return Err(format!("unknown route key '{other}'")),
+            }
+        }
+        if route.hosts.is_empty() && !has_prefix {
+            return Err(format!("route '{s}' needs host= or prefix="));
+        }
+        if let Some(tls) = &route.tls {
+            if route.hosts.is_empty() {
+                return Err(format!("route '{s}' sets TLS but has no host="));
+            }
+            if !tls.acme && (tls.cert.is_none() || tls.key.is_none()) {
+                return Err(format!(
+                    "route '{s}' needs both cert= and key=, or acme=true"
+                ));
+            }
+        }
+        Ok(route)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Visibility {
     Local,
@@ -419,6 +989,22 @@ pub fn verify_bytes_ed25519(
     Ok(vk.verify(data, &sig).is_ok())
 }
 
+/// Parse `KEY=VALUE` entries (CLI flags, form lines) into a map. Blank entries are skipped.
+pub fn parse_env_entries(entries: &[String]) -> Result<BTreeMap<String, String>, String> {
+    let mut out = BTreeMap::new();
+    for entry in entries.iter().map(|e| e.trim()).filter(|e| !e.is_empty()) {
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("invalid env entry '{entry}' (expected KEY=VALUE)"))?;
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!("invalid env name '{key}'"));
+        }
+        out.insert(key.to_string(), value.to_string());
+    }
+    Ok(out)
+}
+
 pub fn sha256_hex(bytes: &[u8]) -> String {
     use sha2::{Digest, Sha256};
     let mut h = Sha256::new();
@@ -464,12 +1050,179 @@ pub struct JobSpec {
     pub execution: JobExecution,
     #[serde(default)]
     pub targeting: Option<JobTargeting>,
+    #[serde(default)]
+    pub resources: Option<JobResources>,
+    /// Supervision for `service` jobs; defaults apply when unset
+    #[serde(default)]
+    pub service: Option<JobService>,
+    /// How `recurring` jobs turn their `schedule` into runs
+    #[serde(default)]
+    pub recurring: Option<RecurringPolicy>,
+    /// Order among jobs waiting for a slot on a node; higher goes first
+    #[serde(default)]
+    pub priority: i32,
+    /// Let a waiting job with a higher priority stop this one and take its
+    /// slot; the job is requeued on its node and runs again from the start
+    #[serde(default)]
+    pub preemptible: bool,
+}
+
+/// Scheduling options of a `recurring` job. The node that claims the job
+/// schedules it and submits each run as a one-shot child job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringPolicy {
+    /// IANA timezone the schedule is read in (e.g. "Europe/Berlin"); UTC when unset
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub catch_up: CatchUp,
+    #[serde(default)]
+    pub concurrency: Concurrency,
+    /// Child runs remembered on the job; older finished ones are dropped
+    #[serde(default = "default_recurring_history")]
+    pub history: usize,
+}
+
+impl Default for RecurringPolicy {
+    fn default() -> Self {
+        Self {
+            timezone: None,
+            catch_up: CatchUp::default(),
+            concurrency: Concurrency::default(),
+            history: default_recurring_history(),
+        }
+    }
+}
+
+fn default_recurring_history() -> usize {
+    20
+}
+
+/// What happens to runs that came due while no scheduler was up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CatchUp {
+    /// Drop them
+    #[default]
+    Skip,
+    /// Run once for all of them
+    RunOnce,
+    /// Run each of them
+    RunAll,
+}
+
+/// What happens when a run comes due while an earlier one is still active.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Concurrency {
+    /// Start it anyway
+    #[default]
+    Allow,
+    /// Skip the new run
+    Forbid,
+    /// Cancel the active run and start the new one
+    Replace,
 }
 
 fn default_job_type() -> JobType {
     JobType::OneShot
 }
 
+/// How a `service` job is kept running: restarts, health probing, exposed
+/// ports and relocation when its node goes away.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobService {
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// Consecutive failed runs tolerated before the job is marked failed;
+    /// unlimited when unset
+    #[serde(default)]
+    pub max_restarts: Option<u32>,
+    /// Delay before a restart, doubled for each consecutive failure (capped
+    /// at five minutes)
+    #[serde(default = "default_restart_backoff_secs")]
+    pub backoff_secs: u64,
+    #[serde(default)]
+    pub health: Option<HealthCheck>,
+    /// Ports the service listens on at 127.0.0.1; exposed and advertised to
+    /// the mesh like component ports, under the job's name
+    #[serde(default)]
+    pub ports: Vec<ServicePort>,
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+    /// Let another eligible node take the job over when this one stops
+    /// sending heartbeats
+    #[serde(default = "default_relocate")]
+    pub relocate: bool,
+}
+
+impl Default for JobService {
+    fn default() -> Self {
+        Self {
+            restart: RestartPolicy::default(),
+            max_restarts: None,
+            backoff_secs: default_restart_backoff_secs(),
+            health: None,
+            ports: Vec::new(),
+            visibility: None,
+            relocate: default_relocate(),
+        }
+    }
+}
+
+fn default_restart_backoff_secs() -> u64 {
+    1
+}
+fn default_relocate() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Restart whenever the service exits
+    #[default]
+    Always,
+    /// Restart only after a failed run or health check
+    OnFailure,
+    /// Run once; the job ends with the process
+    Never,
+}
+
+/// Liveness probe against a running service. A run that fails
+/// `failure_threshold` probes in a row is stopped and handled by the
+/// restart policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheck {
+    /// Port probed on 127.0.0.1
+    pub port: u16,
+    /// HTTP path to GET, expecting a 2xx/3xx answer; a TCP connect when unset
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default = "default_health_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_health_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_health_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Time a fresh run gets to start listening before probes count
+    #[serde(default = "default_health_grace_secs")]
+    pub grace_secs: u64,
+}
+
+fn default_health_interval_secs() -> u64 {
+    10
+}
+fn default_health_timeout_secs() -> u64 {
+    2
+}
+fn default_health_failure_threshold() -> u32 {
+    3
+}
+fn default_health_grace_secs() -> u64 {
+    10
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum JobType {
@@ -496,6 +1249,20 @@ pub enum JobRuntime {
         epoch_ms: u64,
         #[serde(default)]
         mounts: Option<Vec<MountSpec>>, // preopened directories for job runtime
+        #[serde(default)]
+        egress: Option<EgressPolicy>, // outbound network allowlist
+        /// Command-line arguments after the program name
+        #[serde(default)]
+        args: Vec<String>,
+        /// Environment variables to set
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        /// Environment variables resolved from file:/cas: secret references
+        #[serde(default)]
+        secret_env: BTreeMap<String, String>,
+        /// cas: reference fed to the guest's stdin
+        #[serde(default)]
+        stdin: Option<String>,
     },
     #[serde(rename = "native")]
     Native {
@@ -511,6 +1278,9 @@ pub enum JobRuntime {
         /// Environment variables to set
         #[serde(default)]
         env: BTreeMap<String, String>,
+        /// Run inside a Linux sandbox (namespaces, cgroup limits, seccomp, landlock)
+        #[serde(default)]
+        sandbox: Option<NativeSandbox>,
     },
     #[serde(rename = "qemu")]
     Qemu {
@@ -531,9 +1301,128 @@ pub enum JobRuntime {
         /// Optional explicit qemu user-mode binary path (e.g., "/usr/bin/qemu-x86_64")
         #[serde(default)]
         qemu_binary: Option<String>,
+        /// Target root filesystem with the dynamic linker and shared libraries,
+        /// passed to qemu as `-L`: "cas:<sha256>" of a (gzipped) tarball, or
+        /// an absolute directory on the node (e.g., "/usr/aarch64-linux-gnu")
+        #[serde(default)]
+        sysroot: Option<String>,
+    },
+    #[serde(rename = "oci")]
+    Oci {
+        /// Image reference (e.g., "alpine:3.20", "localhost:5000/app@sha256:...")
+        /// or "cas:<sha256>" of an OCI image layout tarball
+        image: String,
+        /// Replaces the image's entrypoint
+        #[serde(default)]
+        entrypoint: Option<Vec<String>>,
+        /// Replaces the image's default command
+        #[serde(default)]
+        args: Option<Vec<String>>,
+        /// Environment variables added to the image's own
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        /// Host directories bound into the container
+        #[serde(default)]
+        mounts: Option<Vec<MountSpec>>,
+        /// Share the host network instead of an empty network namespace
+        #[serde(default)]
+        network: bool,
+    },
+    #[serde(rename = "qemu-system")]
+    QemuSystem {
+        /// "cas:<sha256>" of a bootable qcow2 or raw disk image. The image is
+        /// never written; each run boots from its own copy-on-write overlay.
+        image: String,
+        /// Guest architecture (e.g., "x86_64", "aarch64"); defaults to the node's
+        #[serde(default)]
+        arch: Option<String>,
+        /// Command run in the guest through cloud-init once it has booted;
+        /// the VM powers off when it exits. Empty leaves the VM running its
+        /// own services until it powers off.
+        #[serde(default)]
+        args: Vec<String>,
+        /// Environment variables for `args`
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        /// Guest ports forwarded from the node's loopback interface
+        #[serde(default)]
+        ports: Vec<ServicePort>,
+        /// Outbound network access for the guest; forwarded ports work either way
+        #[serde(default)]
+        network: bool,
+        /// Optional explicit qemu-system binary path (e.g., "/usr/bin/qemu-system-x86_64")
+        #[serde(default)]
+        qemu_binary: Option<String>,
+        /// Extra arguments appended to the qemu-system command line
+        #[serde(default)]
+        qemu_args: Vec<String>,
     },
 }
 
+impl JobRuntime {
+    /// Runtime name as used in job specs and for per-node job slots.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobRuntime::Wasm { .. } => "wasm",
+            JobRuntime::Native { .. } => "native",
+            JobRuntime::Qemu { .. } => "qemu",
+            JobRuntime::Oci { .. } => "oci",
+            JobRuntime::QemuSystem { .. } => "qemu-system",
+        }
+    }
+}
+
+/// Sandbox profile for native jobs. The job sees system directories and the
+/// binary read-only, its workspace and `mounts` read-write, a private `/tmp`,
+/// and no network unless `network` is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NativeSandbox {
+    /// Extra host paths visible read-only at the same path
+    #[serde(default)]
+    pub ro_paths: Vec<String>,
+    /// Host directories bound at `guest` (read-only when `ro`)
+    #[serde(default)]
+    pub mounts: Vec<MountSpec>,
+    /// Share the host network instead of an empty network namespace
+    #[serde(default)]
+    pub network: bool,
+}
+
+/// What a job asks of the node. Sandboxed native jobs get `cpus`, `memory_mb`
+/// and `pids` as cgroup v2 limits; `qemu-system` VMs are sized from `cpus` and
+/// `memory_mb`. The remaining fields are per-process rlimits for native and
+/// qemu-user jobs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobResources {
+    /// CPU cores, fractions allowed
+    #[serde(default)]
+    pub cpus: Option<f64>,
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+    /// Maximum number of processes and threads
+    #[serde(default)]
+    pub pids: Option<u64>,
+    /// CPU time per process in seconds (RLIMIT_CPU)
+    #[serde(default)]
+    pub cpu_seconds: Option<u64>,
+    /// Address space per process (RLIMIT_AS)
+    #[serde(default)]
+    pub address_space_mb: Option<u64>,
+    /// Open file descriptors per process (RLIMIT_NOFILE)
+    #[serde(default)]
+    pub open_files: Option<u64>,
+    /// Largest file a process may write (RLIMIT_FSIZE)
+    #[serde(default)]
+    pub file_size_mb: Option<u64>,
+}
+
+impl JobResources {
+    /// Whether any of the limits enforced through a cgroup is set.
+    pub fn has_cgroup_limits(&self) -> bool {
+        self.cpus.is_some() || self.memory_mb.is_some() || self.pids.is_some()
+    }
+}
+
 fn default_mem_mb() -> u64 {
     64
 }
@@ -552,6 +1441,10 @@ pub struct JobExecution {
     /// Optional timeout in minutes
     #[serde(default)]
     pub timeout_minutes: Option<u64>,
+    /// User (name or uid[:gid]) native and qemu jobs run as; needs the
+    /// agent to run as root
+    #[serde(default)]
+    pub user: Option<String>,
     /// Optional list of artifacts to capture after job completion
     #[serde(default)]
     pub artifacts: Option<Vec<ArtifactSpec>>,
@@ -574,7 +1467,8 @@ pub struct JobTargeting {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArtifactSpec {
-    /// Guest path of the artifact (e.g., "/out/app.wasm")
+    /// Path of the artifact; relative paths resolve against the job's
+    /// workspace (e.g., "artifacts/app.wasm")
     pub path: String,
     /// Optional friendly name to use when storing/serving
     #[serde(default)]
@@ -585,7 +1479,8 @@ pub struct ArtifactSpec {
 pub struct PreStageSpec {
     /// Content-addressed source, e.g. "cas:<sha256>"
     pub source: String,
-    /// Absolute or working-dir-relative destination path on host
+    /// Destination path; relative paths land in the job's workspace
+    /// (e.g. "assets/src.tar.gz" ends up under `$REALM_ASSETS`)
     pub dest: String,
 }
 
@@ -607,6 +1502,8 @@ pub struct JobArtifact {
 #[serde(rename_all = "lowercase")]
 pub enum JobStatus {
     Pending,
+    /// Claimed by a node and waiting there for a free job slot
+    Queued,
     Running,
     Completed,
     Failed,
@@ -635,6 +1532,30 @@ pub struct JobInstance {
     pub schedule_next_at: Option<u64>,
     #[serde(default)]
     pub artifacts: Vec<JobArtifact>,
+    /// Times a service job has been restarted on its node
+    #[serde(default)]
+    pub restarts: u32,
+    /// Recurring job this run was spawned by
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Runs spawned by a recurring job, oldest first
+    #[serde(default)]
+    pub children: Vec<String>,
+    /// When the job last started waiting for a slot on its node
+    #[serde(default)]
+    pub queued_at: Option<u64>,
+    /// Earlier runs that were stopped before finishing, oldest first
+    #[serde(default)]
+    pub attempts: Vec<JobAttempt>,
+}
+
+/// A run of a job that did not finish, e.g. because it was preempted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobAttempt {
+    pub node: String,
+    pub started_at: Option<u64>,
+    pub ended_at: u64,
+    pub reason: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -668,9 +1589,33 @@ impl JobInstance {
             last_scheduled_at: None,
             schedule_next_at: None,
             artifacts: Vec::new(),
+            restarts: 0,
+            parent_id: None,
+            children: Vec::new(),
+            queued_at: None,
+            attempts: Vec::new(),
         }
     }
 
+    /// Not finished yet: waiting to be claimed, queued or running.
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self.status,
+            JobStatus::Pending | JobStatus::Queued | JobStatus::Running
+        )
+    }
+
+    pub fn queue(&mut self, node_id: String) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.status = JobStatus::Queued;
+        self.assigned_node = Some(node_id);
+        self.queued_at = Some(now);
+        self.updated_at = now;
+    }
+
     pub fn start(&mut self, node_id: String) {
         self.status = JobStatus::Running;
         self.assigned_node = Some(node_id);
@@ -749,3 +1694,85 @@ impl JobInstance {
             .as_secs();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(s: &str) -> EgressRule {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn egress_rule_parses_bracketed_and_bare_ipv6() {
+        let bracketed = rule("tcp://[2001:db8::1]:5432");
+        assert_eq!(bracketed.scheme.as_deref(), Some("tcp"));
+        assert_eq!(bracketed.host, "2001:db8::1");
+        assert_eq!(bracketed.port, Some(5432));
+        assert_eq!(bracketed.to_string(), "tcp://[2001:db8::1]:5432");
+
+        let bare = rule("2001:db8::1");
+        assert_eq!(bare.scheme, None);
+        assert_eq!(bare.host, "2001:db8::1");
+        assert_eq!(bare.port, None);
+        assert!(bare.matches("udp", "[2001:db8::1]", 53));
+        assert!("[2001:db8::1".parse::<EgressRule>().is_err());
+    }
+
+    #[test]
+    fn egress_rule_defaults_http_ports() {
+        assert_eq!(rule("http://example.com").port, Some(80));
+        assert_eq!(rule("https://example.com/").port, Some(443));
+        assert_eq!(rule("tcp://example.com").port, None);
+        assert_eq!(rule("example.com").port, None);
+
+        let https = rule("https://API.example.com");
+        assert!(https.matches("https", "api.example.com", 443));
+        assert!(!https.matches("https", "api.example.com", 8443));
+        assert!(!https.matches("http", "api.example.com", 443));
+        assert!(rule("example.com").matches("tcp", "example.com", 5432));
+    }
+
+    #[test]
+    fn egress_wildcard_matches_subdomains_only() {
+        let wildcard = rule("https://*.example.com");
+        assert!(wildcard.matches("https", "api.example.com", 443));
+        assert!(wildcard.matches("https", "a.b.EXAMPLE.com", 443));
+        assert!(!wildcard.matches("https", "example.com", 443));
+        assert!(!wildcard.matches("https", "badexample.com", 443));
+        assert!(rule("*").matches("udp", "10.0.0.5", 53));
+    }
+
+    #[test]
+    fn egress_rule_rejects_bad_entries() {
+        assert!("ftp://example.com".parse::<EgressRule>().is_err());
+        assert!("https://example.com:99999".parse::<EgressRule>().is_err());
+        assert!("https://example.com/path".parse::<EgressRule>().is_err());
+        assert!("https://".parse::<EgressRule>().is_err());
+    }
+
+    #[test]
+    fn egress_policy_from_entries() {
+        assert_eq!(EgressPolicy::from_entries(&[]).unwrap(), None);
+        assert_eq!(EgressPolicy::from_entries(&[" ".into()]).unwrap(), None);
+
+        let none = EgressPolicy::from_entries(&["none".into()])
+            .unwrap()
+            .unwrap();
+        assert!(none.allow.is_empty());
+        assert!(!none.allows("https", "example.com", 443));
+
+        let policy = EgressPolicy::from_entries(&[
+            "https://api.github.com".into(),
+            "tcp://10.0.0.5:5432".into(),
+        ])
+        .unwrap()
+        .unwrap();
+        assert!(policy.allows("https", "api.github.com", 443));
+        assert!(policy.allows("tcp", "10.0.0.5", 5432));
+        assert!(!policy.allows("tcp", "10.0.0.5", 5433));
+        assert!(!policy.allows("http", "api.github.com", 80));
+
+        assert!(EgressPolicy::from_entries(&["none".into(), "ftp://x".into()]).is_err());
+    }
+}